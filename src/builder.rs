@@ -0,0 +1,678 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::ir;
+
+/// Mistakes in a program put together with a [`ProgramBuilder`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuilderError {
+    /// An instruction was added before the first label
+    NoLabel,
+    DuplicateLabel {
+        label: String,
+    },
+    /// `org` address lies inside the code in front of it
+    InvalidOrigin {
+        address: u16,
+        end: u16,
+    },
+    UndefinedEntry {
+        label: String,
+    },
+    /// The program reaches beyond the last address
+    AddressOverflow {
+        label: String,
+    },
+    InvalidRegister {
+        register: u8,
+        label: String,
+    },
+    /// Pseudo-instruction uses its scratch register as operand
+    ScratchRegister {
+        register: u8,
+        label: String,
+    },
+    /// `li` added while the 32 bit mode is off
+    Requires32BitMode {
+        label: String,
+    },
+}
+
+impl fmt::Display for BuilderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuilderError::NoLabel => write!(f, "Instruction added before the first label"),
+            BuilderError::DuplicateLabel { label } => {
+                write!(f, "Label '{}' is defined more than once", label)
+            }
+            BuilderError::InvalidOrigin { address, end } => write!(
+                f,
+                "Origin {:#06x} lies inside the code in front of it, which ends at {:#06x}",
+                address, end
+            ),
+            BuilderError::UndefinedEntry { label } => {
+                write!(f, "Start label '{}' is not defined", label)
+            }
+            BuilderError::AddressOverflow { label } => {
+                write!(f, "Code of label '{}' exceeds the address space", label)
+            }
+            BuilderError::InvalidRegister { register, label } => {
+                write!(
+                    f,
+                    "Register {} in label '{}' does not exist",
+                    register, label
+                )
+            }
+            BuilderError::ScratchRegister { register, label } => write!(
+                f,
+                "Register %reg{} in label '{}' is reserved as scratch register",
+                register, label
+            ),
+            BuilderError::Requires32BitMode { label } => {
+                write!(f, "'li' in label '{}' requires 's32b true'", label)
+            }
+        }
+    }
+}
+
+impl BuilderError {
+    /// Stable name of the error kind, like [`crate::parser::ParserError::code`]
+    pub fn code(&self) -> &'static str {
+        match self {
+            BuilderError::NoLabel => "no-label",
+            BuilderError::DuplicateLabel { .. } => "duplicate-label",
+            BuilderError::InvalidOrigin { .. } => "invalid-origin",
+            BuilderError::UndefinedEntry { .. } => "undefined-entry",
+            BuilderError::AddressOverflow { .. } => "address-overflow",
+            BuilderError::InvalidRegister { .. } => "invalid-register",
+            BuilderError::ScratchRegister { .. } => "reserved-register",
+            BuilderError::Requires32BitMode { .. } => "requires-32-bit-mode",
+        }
+    }
+}
+
+impl std::error::Error for BuilderError {}
+
+/// Entry point of the fluent program construction, e.g.
+///
+/// ```
+/// use masm::builder::Program;
+///
+/// let ir = Program::builder()
+///     .label("main")
+///     .ldc(0, 42)
+///     .add(0, 1, 2)
+///     .jump_label("main")
+///     .build()
+///     .unwrap();
+/// assert_eq!(3, masm::generator::generator(ir).unwrap().len());
+/// ```
+pub struct Program;
+
+impl Program {
+    pub fn builder() -> ProgramBuilder {
+        ProgramBuilder::default()
+    }
+}
+
+/// Collects labels and instructions and keeps track of their addresses like
+/// the parser does. Registers are given by their number, `0` for `%reg0`.
+/// Mistakes are reported by [`ProgramBuilder::build`].
+#[derive(Debug)]
+pub struct ProgramBuilder {
+    start_label: Option<String>,
+    labels: Vec<ir::LabelDefinition>,
+    instructions: HashMap<ir::LabelReference, Vec<ir::Instruction>>,
+    /// next free address
+    address: u16,
+    /// `org` or `align` of the next label
+    origin: Option<u16>,
+    align: Option<u16>,
+    scratch: ir::Register,
+    wide_mode: bool,
+    error: Option<BuilderError>,
+}
+
+impl Default for ProgramBuilder {
+    fn default() -> Self {
+        ProgramBuilder {
+            start_label: None,
+            labels: Vec::new(),
+            instructions: HashMap::new(),
+            address: 0,
+            origin: None,
+            align: None,
+            scratch: ir::Register::new(crate::parser::DEFAULT_SCRATCH_REGISTER),
+            wide_mode: false,
+            error: None,
+        }
+    }
+}
+
+fn register(address: u8) -> ir::Register {
+    ir::Register::new(ir::RegisterAddress(address))
+}
+
+fn binary(target: u8, source_a: u8, source_b: u8) -> ir::BinaryExpression {
+    ir::BinaryExpression::new(register(target), register(source_a), register(source_b))
+}
+
+impl ProgramBuilder {
+    /// Keeps the first mistake, the ones after it may be caused by it
+    fn fail(&mut self, error: BuilderError) {
+        self.error.get_or_insert(error);
+    }
+
+    /// Defines the label `name` at the next free address, the following
+    /// instructions belong to it
+    pub fn label(mut self, name: &str) -> Self {
+        let reference = ir::LabelReference::new(name);
+        if self.instructions.contains_key(&reference) {
+            self.fail(BuilderError::DuplicateLabel {
+                label: String::from(name),
+            });
+            return self;
+        }
+        let mut definition = ir::LabelDefinition::new(name, self.address);
+        if let Some(origin) = self.origin.take() {
+            if origin < self.address {
+                self.fail(BuilderError::InvalidOrigin {
+                    address: origin,
+                    end: self.address,
+                });
+            }
+            definition.address = ir::MemoryAddress(origin);
+            definition.fixed = true;
+        }
+        if let Some(align) = self.align.take() {
+            match definition.address.0.checked_next_multiple_of(align) {
+                Some(address) => definition.address = ir::MemoryAddress(address),
+                None => self.fail(BuilderError::AddressOverflow {
+                    label: String::from(name),
+                }),
+            }
+            definition.attributes.align = Some(align);
+        }
+        self.address = definition.address.0;
+        self.labels.push(definition);
+        self.instructions.insert(reference, Vec::new());
+        self
+    }
+
+    /// Places the next label at `address`, like `.org`
+    pub fn org(mut self, address: u16) -> Self {
+        self.origin = Some(address);
+        self
+    }
+
+    /// Aligns the next label to a multiple of `align` words, like
+    /// `[[align=N]]`
+    pub fn align(mut self, align: u16) -> Self {
+        self.align = Some(align.max(1));
+        self
+    }
+
+    /// Starts the program at `label` instead of the first label
+    pub fn entry(mut self, label: &str) -> Self {
+        self.start_label = Some(String::from(label));
+        self
+    }
+
+    /// Scratch register of the following pseudo-instructions, like
+    /// `.scratch`
+    pub fn scratch(mut self, address: u8) -> Self {
+        self.scratch = register(address);
+        self
+    }
+
+    /// Appends any instruction to the current label
+    pub fn instruction(mut self, instruction: ir::Instruction) -> Self {
+        let Some(label) = self.labels.last() else {
+            self.fail(BuilderError::NoLabel);
+            return self;
+        };
+        let label = label.name.clone();
+        let registers = instruction.registers();
+        if let Some(register) = registers.iter().find(|register| register.0 > 7) {
+            self.fail(BuilderError::InvalidRegister {
+                register: register.0,
+                label: label.clone(),
+            });
+        }
+        if let Some(scratch) = instruction.scratch() {
+            if registers.iter().filter(|&&r| r == scratch.address).count() > 1 {
+                self.fail(BuilderError::ScratchRegister {
+                    register: scratch.address.0,
+                    label: label.clone(),
+                });
+            }
+        }
+        if matches!(instruction, ir::Instruction::LoadImmediate { .. }) && !self.wide_mode {
+            self.fail(BuilderError::Requires32BitMode {
+                label: label.clone(),
+            });
+        }
+        match self.address.checked_add(instruction.size()) {
+            Some(address) => self.address = address,
+            None => self.fail(BuilderError::AddressOverflow {
+                label: label.clone(),
+            }),
+        }
+        let reference = ir::LabelReference::new(label);
+        self.instructions
+            .get_mut(&reference)
+            .expect("every label has instructions")
+            .push(instruction);
+        self
+    }
+
+    pub fn ldc(self, target: u8, value: u16) -> Self {
+        self.instruction(ir::Instruction::Load {
+            address: ir::RegisterAddress(target),
+            source: ir::LoadSource::Constant(value),
+        })
+    }
+
+    /// `ldc` of a value computed from label addresses, e.g. `lo(handler)`
+    pub fn ldc_expression(self, target: u8, value: ir::Expression) -> Self {
+        self.instruction(ir::Instruction::Load {
+            address: ir::RegisterAddress(target),
+            source: ir::LoadSource::Expression(value),
+        })
+    }
+
+    /// `ldc` of the address of `label`
+    pub fn ldc_label(self, target: u8, label: &str) -> Self {
+        self.ldc_expression(
+            target,
+            ir::Expression::Label(ir::LabelReference::new(label)),
+        )
+    }
+
+    pub fn ld(self, target: u8, address_register: u8) -> Self {
+        self.instruction(ir::Instruction::Load {
+            address: ir::RegisterAddress(target),
+            source: ir::LoadSource::RAM {
+                address_register: register(address_register),
+            },
+        })
+    }
+
+    pub fn st(self, address_register: u8, data_register: u8) -> Self {
+        self.instruction(ir::Instruction::StoreRAM {
+            address_register: ir::RegisterAddress(address_register),
+            data_register: ir::RegisterAddress(data_register),
+        })
+    }
+
+    pub fn mov(self, target: u8, source: u8) -> Self {
+        self.instruction(ir::Instruction::Move(ir::UnaryExpression::new(
+            register(target),
+            register(source),
+        )))
+    }
+
+    pub fn add(self, target: u8, source_a: u8, source_b: u8) -> Self {
+        self.instruction(ir::Instruction::Add(binary(target, source_a, source_b)))
+    }
+
+    pub fn add3(self, target: u8, source_a: u8, source_b: u8, source_c: u8) -> Self {
+        self.instruction(ir::Instruction::Add3(ir::TernaryExpression::new(
+            register(target),
+            register(source_a),
+            register(source_b),
+            register(source_c),
+        )))
+    }
+
+    pub fn addc(self, target: u8, source_a: u8, source_b: u8) -> Self {
+        self.instruction(ir::Instruction::AddWithCarry(binary(
+            target, source_a, source_b,
+        )))
+    }
+
+    pub fn sub(self, target: u8, source_a: u8, source_b: u8) -> Self {
+        self.instruction(ir::Instruction::Subtract(binary(
+            target, source_a, source_b,
+        )))
+    }
+
+    pub fn subc(self, target: u8, source_a: u8, source_b: u8) -> Self {
+        self.instruction(ir::Instruction::SubtractWithCarry(binary(
+            target, source_a, source_b,
+        )))
+    }
+
+    pub fn inc(self, target: u8) -> Self {
+        self.instruction(ir::Instruction::Increment(ir::UnaryExpression::new(
+            register(target),
+            register(target),
+        )))
+    }
+
+    pub fn dec(self, target: u8) -> Self {
+        self.instruction(ir::Instruction::Decrement(ir::UnaryExpression::new(
+            register(target),
+            register(target),
+        )))
+    }
+
+    pub fn mul(self, target: u8, source_a: u8, source_b: u8) -> Self {
+        self.instruction(ir::Instruction::Multiply(binary(
+            target, source_a, source_b,
+        )))
+    }
+
+    pub fn and(self, target: u8, source_a: u8, source_b: u8) -> Self {
+        self.instruction(ir::Instruction::AND(binary(target, source_a, source_b)))
+    }
+
+    pub fn or(self, target: u8, source_a: u8, source_b: u8) -> Self {
+        self.instruction(ir::Instruction::OR(binary(target, source_a, source_b)))
+    }
+
+    pub fn not(self, target: u8, source: u8) -> Self {
+        self.instruction(ir::Instruction::NOT(ir::UnaryExpression::new(
+            register(target),
+            register(source),
+        )))
+    }
+
+    pub fn neg(self, target: u8, source: u8) -> Self {
+        self.instruction(ir::Instruction::Negate(ir::UnaryExpression::new(
+            register(target),
+            register(source),
+        )))
+    }
+
+    pub fn xor(self, target: u8, source_a: u8, source_b: u8) -> Self {
+        self.instruction(ir::Instruction::XOR(binary(target, source_a, source_b)))
+    }
+
+    pub fn xnor(self, target: u8, source_a: u8, source_b: u8) -> Self {
+        self.instruction(ir::Instruction::XNOR(binary(target, source_a, source_b)))
+    }
+
+    pub fn shl(self, target: u8, source_a: u8, source_b: u8) -> Self {
+        self.instruction(ir::Instruction::ShiftLeft(binary(
+            target, source_a, source_b,
+        )))
+    }
+
+    pub fn shr(self, target: u8, source_a: u8, source_b: u8) -> Self {
+        self.instruction(ir::Instruction::ShiftRight(binary(
+            target, source_a, source_b,
+        )))
+    }
+
+    pub fn tst(self, source_a: u8, source_b: u8) -> Self {
+        self.instruction(ir::Instruction::Test(ir::BinaryStatement::new(
+            register(source_a),
+            register(source_b),
+        )))
+    }
+
+    /// Switches the 32 bit mode, which also sets the width of the following
+    /// rotations
+    pub fn s32b(mut self, enable: bool) -> Self {
+        self.wide_mode = enable;
+        self.instruction(ir::Instruction::Set32BitMode {
+            enable: ir::Boolean(enable),
+        })
+    }
+
+    pub fn hlt(self) -> Self {
+        self.instruction(ir::Instruction::Halt)
+    }
+
+    pub fn dbg(self) -> Self {
+        self.instruction(ir::Instruction::Debug)
+    }
+
+    pub fn nop(self) -> Self {
+        self.instruction(ir::Instruction::Noop)
+    }
+
+    /// Jump to `target` if `condition` holds
+    pub fn jump(self, condition: ir::JumpCondition, target: ir::JumpTarget) -> Self {
+        self.instruction(ir::Instruction::Jump { target, condition })
+    }
+
+    /// `jmp` to the address in register `target`
+    pub fn jmp(self, target: u8) -> Self {
+        self.jump(
+            ir::JumpCondition::True,
+            ir::JumpTarget::Register(register(target)),
+        )
+    }
+
+    /// `jr` by `offset` words relative to the jump
+    pub fn jr(self, offset: i16) -> Self {
+        self.jump(
+            ir::JumpCondition::True,
+            ir::JumpTarget::Constant(offset as u16),
+        )
+    }
+
+    /// `jr` to `label`
+    pub fn jump_label(self, label: &str) -> Self {
+        self.jump_label_if(ir::JumpCondition::True, label)
+    }
+
+    /// Relative jump to `label` if `condition` holds, e.g. `jzr label`
+    pub fn jump_label_if(self, condition: ir::JumpCondition, label: &str) -> Self {
+        self.jump(
+            condition,
+            ir::JumpTarget::Label(ir::LabelReference::new(label)),
+        )
+    }
+
+    /// `ljmp` to `label` through the scratch register
+    pub fn ljmp(self, label: &str) -> Self {
+        let scratch = self.scratch;
+        self.instruction(ir::Instruction::LongJump {
+            target: ir::Expression::Label(ir::LabelReference::new(label)),
+            scratch,
+        })
+    }
+
+    /// `li` of a 32 bit value through the scratch register
+    pub fn li(self, target: u8, value: u32) -> Self {
+        let scratch = self.scratch;
+        self.instruction(ir::Instruction::LoadImmediate {
+            address: ir::RegisterAddress(target),
+            value: ir::Expression::Constant(value),
+            scratch,
+        })
+    }
+
+    /// Immediate form of `operation` writing `source` combined with `value`
+    /// to `target`, e.g. `addi` for [`ir::Operation::Add`] with `target`
+    /// as `source`
+    pub fn immediate(self, operation: ir::Operation, target: u8, source: u8, value: u32) -> Self {
+        let scratch = self.scratch;
+        self.instruction(ir::Instruction::Immediate {
+            operation,
+            target: register(target),
+            source: register(source),
+            value: ir::Expression::Constant(value),
+            scratch,
+        })
+    }
+
+    pub fn rol(self, target: u8, source: u8, amount: u16) -> Self {
+        self.rotate(ir::Direction::Left, target, source, amount)
+    }
+
+    pub fn ror(self, target: u8, source: u8, amount: u16) -> Self {
+        self.rotate(ir::Direction::Right, target, source, amount)
+    }
+
+    fn rotate(self, direction: ir::Direction, target: u8, source: u8, amount: u16) -> Self {
+        let scratch = self.scratch;
+        let width = if self.wide_mode { 32 } else { 16 };
+        self.instruction(ir::Instruction::Rotate {
+            direction,
+            target: register(target),
+            source: register(source),
+            amount,
+            width,
+            scratch,
+        })
+    }
+
+    /// `div` of `source_a` by `source_b`, the remainder is kept in the
+    /// scratch register
+    pub fn div(self, target: u8, source_a: u8, source_b: u8) -> Self {
+        let scratch = self.scratch;
+        self.instruction(ir::Instruction::Divide {
+            expression: binary(target, source_a, source_b),
+            scratch,
+        })
+    }
+
+    pub fn modulo(self, target: u8, source_a: u8, source_b: u8) -> Self {
+        self.instruction(ir::Instruction::Modulo(binary(target, source_a, source_b)))
+    }
+
+    /// `.word` with a raw instruction word
+    pub fn word(self, value: u16) -> Self {
+        self.instruction(ir::Instruction::Data(ir::Constant(value)))
+    }
+
+    /// The program with the entry label selected with [`Self::entry`] or the
+    /// first label, fails with the first mistake made while building it
+    pub fn build(self) -> Result<ir::IR, BuilderError> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        let start_label = self
+            .start_label
+            .or_else(|| self.labels.first().map(|label| label.name.clone()))
+            .ok_or(BuilderError::NoLabel)?;
+        let start_label = ir::LabelReference::new(start_label);
+        let mut label_definitions = ir::LabelLUT::with_capacity(self.labels.len());
+        for label in self.labels {
+            label_definitions
+                .0
+                .insert(ir::LabelReference::new(label.name.as_str()), label);
+        }
+        if !label_definitions.0.contains_key(&start_label) {
+            return Err(BuilderError::UndefinedEntry {
+                label: start_label.name().to_owned(),
+            });
+        }
+        Ok(ir::IR {
+            start_label,
+            label_definitions,
+            instructions: self.instructions,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator;
+
+    #[test]
+    fn builds_program() {
+        let built = Program::builder()
+            .label("main")
+            .ldc(0, 42)
+            .ldc_label(1, "far")
+            .s32b(true)
+            .li(2, 0x12345678)
+            .scratch(5)
+            .ljmp("far")
+            .align(4)
+            .label("loop")
+            .add(0, 1, 2)
+            .immediate(ir::Operation::ShiftLeft, 3, 0, 2)
+            .jump_label_if(ir::JumpCondition::NotZero, "loop")
+            .org(0x20)
+            .label("far")
+            .inc(3)
+            .rol(4, 3, 8)
+            .jump_label("main")
+            .hlt()
+            .build()
+            .unwrap();
+
+        let address = |name: &str| {
+            built.label_definitions.0[&ir::LabelReference::new(name)]
+                .address
+                .0
+        };
+        assert_eq!(
+            (0, 12, 0x20),
+            (address("main"), address("loop"), address("far"))
+        );
+        let source = "main:
+    ldc %reg0 42
+    ldc %reg1 far
+    s32b true
+    li %reg2 0x12345678
+.scratch %reg5
+    ljmp far
+[[align=4]]
+loop:
+    add %reg0 %reg1 %reg2
+    shl %reg3 %reg0 2
+    jnzr loop
+.org 0x20
+far:
+    inc %reg3
+    rol %reg4 %reg3 8
+    jr main
+    hlt
+";
+        assert_eq!(
+            crate::assemble_str(source).unwrap(),
+            generator::generator(built).unwrap()
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let error = |builder: ProgramBuilder| builder.build().err().map(|e| e.code());
+
+        assert_eq!(Some("no-label"), error(Program::builder().hlt()));
+        assert_eq!(Some("no-label"), error(Program::builder()));
+        assert_eq!(
+            Some("duplicate-label"),
+            error(Program::builder().label("main").label("main"))
+        );
+        assert_eq!(
+            Some("invalid-origin"),
+            error(
+                Program::builder()
+                    .label("main")
+                    .nop()
+                    .nop()
+                    .org(1)
+                    .label("late")
+            )
+        );
+        assert_eq!(
+            Some("undefined-entry"),
+            error(Program::builder().label("main").entry("start"))
+        );
+        assert_eq!(
+            Some("invalid-register"),
+            error(Program::builder().label("main").mov(8, 0))
+        );
+        assert_eq!(
+            Some("reserved-register"),
+            error(Program::builder().label("main").s32b(true).li(6, 1))
+        );
+        assert_eq!(
+            Some("requires-32-bit-mode"),
+            error(Program::builder().label("main").li(0, 1))
+        );
+        assert_eq!(
+            Some("address-overflow"),
+            error(Program::builder().org(0xffff).label("main").nop().nop())
+        );
+    }
+}
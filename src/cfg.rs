@@ -0,0 +1,339 @@
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::fmt::Write as _;
+
+use crate::ir;
+
+/// Straight-line code which is only entered at its first instruction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    /// names of the labels at the block, or `label+offset` inside of the
+    /// code of a label
+    pub name: String,
+    pub address: u16,
+    pub instructions: Vec<ir::Instruction>,
+    /// entered from the entry label, an `[[interrupt]]` handler or a label
+    /// whose address is loaded into a register
+    pub reachable: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeKind {
+    /// unconditional jump
+    Jump,
+    /// conditional jump taken
+    Branch,
+    /// execution continues with the next block
+    FallThrough,
+}
+
+/// Edge between the blocks with the indices `from` and `to`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edge {
+    pub from: usize,
+    pub to: usize,
+    pub kind: EdgeKind,
+}
+
+/// Basic blocks of a program and the jumps and fall-throughs between them.
+/// Jumps through registers have no edge, their targets are unknown.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ControlFlowGraph {
+    /// blocks in the order of their addresses
+    pub blocks: Vec<Block>,
+    pub edges: Vec<Edge>,
+}
+
+impl ControlFlowGraph {
+    /// Splits the code of `ir` into basic blocks, pseudo-instructions are
+    /// kept as single instructions
+    pub fn new(ir: &ir::IR) -> Self {
+        let labels = &ir.label_definitions;
+        let mut definitions: Vec<&ir::LabelDefinition> = labels.0.values().collect();
+        definitions.sort_by_key(|label| (label.address, &label.name));
+        let mut names: HashMap<u16, Vec<&str>> = HashMap::new();
+        for label in &definitions {
+            names
+                .entry(label.address.0)
+                .or_default()
+                .push(label.name.as_str());
+        }
+
+        // address, instruction, the label whose code it belongs to and
+        // whether it is the last instruction of that label
+        let mut code: Vec<(u16, &ir::Instruction, &ir::LabelDefinition, bool)> = Vec::new();
+        for label in &definitions {
+            let Some(instructions) = ir.instructions.get(&(*label).clone().into()) else {
+                continue;
+            };
+            let mut address = label.address.0;
+            for (index, instruction) in instructions.iter().enumerate() {
+                code.push((address, instruction, label, index + 1 == instructions.len()));
+                address = address.wrapping_add(instruction.size());
+            }
+        }
+
+        let mut leaders: BTreeSet<u16> = BTreeSet::new();
+        for (address, instruction, label, _) in &code {
+            if *address == label.address.0 {
+                leaders.insert(*address);
+            }
+            if let Some(target) = target(instruction, *address, labels) {
+                leaders.insert(target);
+            }
+            if is_jump(instruction) || !instruction.falls_through() {
+                leaders.insert(address.wrapping_add(instruction.size()));
+            }
+        }
+
+        let mut blocks: Vec<Block> = Vec::new();
+        // last instruction of every block with its address and label
+        let mut ends: Vec<(u16, &ir::Instruction, &ir::LabelDefinition, bool)> = Vec::new();
+        for &(address, instruction, label, last) in &code {
+            if blocks.is_empty() || leaders.contains(&address) {
+                let name = match names.get(&address) {
+                    Some(names) => names.join(", "),
+                    None => format!("{}+{}", label.name, address - label.address.0),
+                };
+                blocks.push(Block {
+                    name,
+                    address,
+                    instructions: Vec::new(),
+                    reachable: false,
+                });
+                ends.push((address, instruction, label, last));
+            }
+            if let Some(block) = blocks.last_mut() {
+                block.instructions.push(instruction.clone());
+            }
+            if let Some(end) = ends.last_mut() {
+                *end = (address, instruction, label, last);
+            }
+        }
+
+        let block_at = |address: u16| blocks.iter().position(|block| block.address == address);
+        let mut edges = Vec::new();
+        for (from, &(address, instruction, label, last)) in ends.iter().enumerate() {
+            if let Some(to) = target(instruction, address, labels).and_then(block_at) {
+                let kind = if instruction.falls_through() {
+                    EdgeKind::Branch
+                } else {
+                    EdgeKind::Jump
+                };
+                edges.push(Edge { from, to, kind });
+            }
+            let noreturn = last && label.attributes.noreturn;
+            if instruction.falls_through() && !noreturn && from + 1 < blocks.len() {
+                edges.push(Edge {
+                    from,
+                    to: from + 1,
+                    kind: EdgeKind::FallThrough,
+                });
+            }
+        }
+
+        // labels whose address is taken may be the target of a jump through
+        // a register
+        let mut roots: Vec<u16> = definitions
+            .iter()
+            .filter(|label| label.name == ir.start_label.name() || label.attributes.interrupt)
+            .map(|label| label.address.0)
+            .collect();
+        for (_, instruction, _, _) in &code {
+            if !matches!(
+                instruction,
+                ir::Instruction::Jump { .. } | ir::Instruction::LongJump { .. }
+            ) {
+                roots.extend(
+                    instruction
+                        .label_references()
+                        .into_iter()
+                        .filter_map(|reference| labels.0.get(reference))
+                        .map(|label| label.address.0),
+                );
+            }
+        }
+        let mut queue: VecDeque<usize> = roots.into_iter().filter_map(block_at).collect();
+        while let Some(index) = queue.pop_front() {
+            if blocks[index].reachable {
+                continue;
+            }
+            blocks[index].reachable = true;
+            queue.extend(
+                edges
+                    .iter()
+                    .filter(|edge| edge.from == index)
+                    .map(|edge| edge.to),
+            );
+        }
+
+        ControlFlowGraph { blocks, edges }
+    }
+
+    /// Blocks no execution starting at the entry label can reach
+    pub fn unreachable(&self) -> impl Iterator<Item = &Block> {
+        self.blocks.iter().filter(|block| !block.reachable)
+    }
+
+    /// Graph in the DOT language of Graphviz called `name`. Every block is a
+    /// box with its name, address and instructions. Conditional jumps are
+    /// labelled with their mnemonic, fall-throughs are dashed and
+    /// unreachable blocks grey.
+    pub fn to_dot(&self, name: &str) -> String {
+        let mut dot = format!(
+            "digraph \"{}\" {{\n    node [shape=box, fontname=\"monospace\"];\n",
+            escape(name)
+        );
+        for (index, block) in self.blocks.iter().enumerate() {
+            let mut label = format!("{} ({:#06x})\\l", escape(&block.name), block.address);
+            for instruction in &block.instructions {
+                label.push_str(&format!("    {}\\l", escape(&instruction.to_string())));
+            }
+            let style = if block.reachable {
+                ""
+            } else {
+                ", style=dashed, color=gray, fontcolor=gray"
+            };
+            let _ = writeln!(dot, "    b{} [label=\"{}\"{}];", index, label, style);
+        }
+        for edge in &self.edges {
+            let attributes = match edge.kind {
+                EdgeKind::Jump => String::new(),
+                EdgeKind::Branch => {
+                    let jump = self.blocks[edge.from]
+                        .instructions
+                        .last()
+                        .map(ToString::to_string)
+                        .unwrap_or_default();
+                    let mnemonic = jump.split_whitespace().next().unwrap_or_default();
+                    format!(" [label=\"{}\"]", escape(mnemonic))
+                }
+                EdgeKind::FallThrough => String::from(" [style=dashed]"),
+            };
+            let _ = writeln!(dot, "    b{} -> b{}{};", edge.from, edge.to, attributes);
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+fn is_jump(instruction: &ir::Instruction) -> bool {
+    matches!(
+        instruction,
+        ir::Instruction::Jump { .. } | ir::Instruction::LongJump { .. }
+    )
+}
+
+/// Address a jump at `address` continues at, `None` for jumps through
+/// registers and other instructions
+fn target(instruction: &ir::Instruction, address: u16, labels: &ir::LabelLUT) -> Option<u16> {
+    let label_address =
+        |label: &ir::LabelReference| labels.0.get(label).map(|label| label.address.0);
+    match instruction {
+        ir::Instruction::Jump { target, .. } => match target {
+            ir::JumpTarget::Constant(offset) => Some(address.wrapping_add(*offset)),
+            ir::JumpTarget::Register(_) => None,
+            ir::JumpTarget::Label(label) => label_address(label),
+            ir::JumpTarget::LabelOffset { label, offset } => {
+                label_address(label).map(|target| target.wrapping_add(*offset as u16))
+            }
+        },
+        ir::Instruction::LongJump { target, .. } => target
+            .evaluate(labels, address)
+            .ok()
+            .map(|target| target as u16),
+        _ => None,
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph(source: &str) -> ControlFlowGraph {
+        let lexed = crate::lexer::lexer_from_str(source, &Default::default()).unwrap();
+        ControlFlowGraph::new(&crate::parser::parser(lexed).unwrap())
+    }
+
+    #[test]
+    fn blocks_and_edges() {
+        let cfg = graph(
+            "main:
+    ldc %reg0 3
+loop:
+    dec %reg0
+    jnzr loop
+    ljmp done
+dead:
+    nop
+done:
+    hlt
+",
+        );
+        let blocks: Vec<(&str, u16, bool)> = cfg
+            .blocks
+            .iter()
+            .map(|block| (block.name.as_str(), block.address, block.reachable))
+            .collect();
+        assert_eq!(
+            vec![
+                ("main", 0, true),
+                ("loop", 1, true),
+                ("loop+2", 3, true),
+                ("dead", 5, false),
+                ("done", 6, true),
+            ],
+            blocks
+        );
+        let edge = |from, to, kind| Edge { from, to, kind };
+        assert_eq!(
+            vec![
+                edge(0, 1, EdgeKind::FallThrough),
+                edge(1, 1, EdgeKind::Branch),
+                edge(1, 2, EdgeKind::FallThrough),
+                edge(2, 4, EdgeKind::Jump),
+                edge(3, 4, EdgeKind::FallThrough),
+            ],
+            cfg.edges
+        );
+        assert_eq!(
+            vec!["dead"],
+            cfg.unreachable()
+                .map(|block| block.name.as_str())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn address_taken() {
+        let cfg = graph(
+            "main:
+    ldc %reg0 handler
+    jmp %reg0
+handler:
+    hlt
+",
+        );
+        assert_eq!(0, cfg.unreachable().count());
+        assert!(cfg.edges.is_empty());
+    }
+
+    #[test]
+    fn dot() {
+        let cfg = graph("main:\n    jzr end\nend:\n    hlt\n");
+        assert_eq!(
+            "digraph \"main.s\" {
+    node [shape=box, fontname=\"monospace\"];
+    b0 [label=\"main (0x0000)\\l    jzr end\\l\"];
+    b1 [label=\"end (0x0001)\\l    hlt\\l\"];
+    b0 -> b1 [label=\"jzr\"];
+    b0 -> b1 [style=dashed];
+}
+",
+            cfg.to_dot("main.s")
+        );
+    }
+}
@@ -0,0 +1,303 @@
+use std::fmt;
+
+use crate::emulator::{Emulator, EmulatorError, Event};
+
+/// Errors of reading a hardware trace
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CosimError {
+    /// The line does not start with the program counter
+    InvalidCycle { line_number: usize, line: String },
+    /// A `%regN=VALUE` token has no valid register or value
+    InvalidWrite { line_number: usize, write: String },
+}
+
+impl fmt::Display for CosimError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CosimError::InvalidCycle { line_number, line } => write!(
+                f,
+                "Line {} '{}' does not start with the program counter",
+                line_number, line
+            ),
+            CosimError::InvalidWrite { line_number, write } => write!(
+                f,
+                "Line {} has an invalid register write '{}'",
+                line_number, write
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CosimError {}
+
+/// Cycle of a trace of the hardware
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cycle {
+    /// line of the cycle in the trace, counted from 1
+    pub line_number: usize,
+    /// address of the executed instruction
+    pub pc: u16,
+    /// written registers with their new value
+    pub registers: Vec<(u8, u32)>,
+}
+
+/// Reads a trace with one line per cycle, starting with the program counter
+/// of the executed instruction followed by its register writes like
+/// `%reg1=0x0002`. Other words are ignored, so the trace of `masm run
+/// --trace` is read as well. Empty lines and lines starting with `#` are
+/// skipped.
+pub fn parse_trace(text: &str) -> Result<Vec<Cycle>, CosimError> {
+    let mut cycles = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let mut words = line.split_whitespace();
+        let Some(pc) = words.next().filter(|word| !word.starts_with('#')) else {
+            continue;
+        };
+        let pc = parse_number(pc)
+            .and_then(|pc| u16::try_from(pc).ok())
+            .ok_or(CosimError::InvalidCycle {
+                line_number,
+                line: line.to_string(),
+            })?;
+        let registers = words
+            .filter(|word| word.contains('='))
+            .filter_map(|word| Some((word, word.strip_prefix("%reg")?)))
+            .map(|(word, write)| {
+                write
+                    .split_once('=')
+                    .and_then(|(register, value)| {
+                        Some((register.parse().ok()?, parse_number(value)?))
+                    })
+                    .ok_or(CosimError::InvalidWrite {
+                        line_number,
+                        write: word.to_string(),
+                    })
+            })
+            .collect::<Result<_, _>>()?;
+        cycles.push(Cycle {
+            line_number,
+            pc,
+            registers,
+        });
+    }
+    Ok(cycles)
+}
+
+/// Hexadecimal with `0x`, decimal otherwise
+fn parse_number(word: &str) -> Option<u32> {
+    match word.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => word.parse().ok(),
+    }
+}
+
+/// First cycle in which the emulator and the hardware disagree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// number of cycles both agreed on before
+    pub cycle: usize,
+    /// line of the cycle in the hardware trace
+    pub line_number: usize,
+    pub hardware: String,
+    pub emulator: String,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Cycle {} (line {} of the trace): hardware {}, emulator {}",
+            self.cycle, self.line_number, self.hardware, self.emulator
+        )
+    }
+}
+
+/// Runs the emulator along the `hardware` trace and compares the program
+/// counter and register writes of every cycle. Returns the first
+/// divergence, `None` if all cycles of the trace agree.
+pub fn compare(
+    emulator: &mut Emulator,
+    hardware: &[Cycle],
+) -> Result<Option<Divergence>, EmulatorError> {
+    for (cycle, expected) in hardware.iter().enumerate() {
+        let divergence = |hardware: String, emulator: String| {
+            Some(Divergence {
+                cycle,
+                line_number: expected.line_number,
+                hardware,
+                emulator,
+            })
+        };
+        let (event, entry) = emulator.step_traced()?;
+        let Some(entry) = entry else {
+            return Ok(divergence(
+                format!("executed {:#06x}", expected.pc),
+                String::from("halted"),
+            ));
+        };
+        if entry.address != expected.pc {
+            return Ok(divergence(
+                format!("executed {:#06x}", expected.pc),
+                format!("executed {:#06x} {}", entry.address, entry.instruction),
+            ));
+        }
+        let found = written(&entry.registers);
+        if written(&expected.registers) != found {
+            return Ok(divergence(
+                writes(&expected.registers, entry.wide_mode),
+                writes(&found, entry.wide_mode),
+            ));
+        }
+        if event == Event::Halt {
+            return Ok(hardware.get(cycle + 1).map(|next| Divergence {
+                cycle: cycle + 1,
+                line_number: next.line_number,
+                hardware: format!("executed {:#06x}", next.pc),
+                emulator: String::from("halted"),
+            }));
+        }
+    }
+    Ok(None)
+}
+
+/// Last value written to every register, sorted by register
+fn written(registers: &[(u8, u32)]) -> Vec<(u8, u32)> {
+    let mut last: Vec<(u8, u32)> = Vec::new();
+    for &(register, value) in registers.iter().rev() {
+        if !last.iter().any(|(written, _)| *written == register) {
+            last.push((register, value));
+        }
+    }
+    last.sort();
+    last
+}
+
+fn writes(registers: &[(u8, u32)], wide_mode: bool) -> String {
+    if registers.is_empty() {
+        return String::from("wrote no register");
+    }
+    let width = if wide_mode { 10 } else { 6 };
+    let writes: Vec<String> = registers
+        .iter()
+        .map(|(register, value)| format!("%reg{}={:#0width$x}", register, value, width = width))
+        .collect();
+    format!("wrote {}", writes.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROGRAM: &str = "main:
+    ldc %reg1 2
+loop:
+    dec %reg1
+    jnzr loop
+    hlt
+";
+
+    fn emulator() -> Emulator {
+        Emulator::new(crate::assemble_str(PROGRAM).unwrap())
+    }
+
+    #[test]
+    fn read_trace() {
+        let trace = parse_trace(
+            "# pc and writes
+0x0000 %reg1=0x0002
+
+0x0001  20106  dec %reg1                %reg1=0x0001 z=0 c=0 o=0
+2
+",
+        )
+        .unwrap();
+        assert_eq!(
+            vec![
+                Cycle {
+                    line_number: 2,
+                    pc: 0,
+                    registers: vec![(1, 2)]
+                },
+                Cycle {
+                    line_number: 4,
+                    pc: 1,
+                    registers: vec![(1, 1)]
+                },
+                Cycle {
+                    line_number: 5,
+                    pc: 2,
+                    registers: vec![]
+                },
+            ],
+            trace
+        );
+        assert_eq!(
+            Err(CosimError::InvalidCycle {
+                line_number: 1,
+                line: String::from("pc 0x1")
+            }),
+            parse_trace("pc 0x1")
+        );
+        assert_eq!(
+            Err(CosimError::InvalidWrite {
+                line_number: 1,
+                write: String::from("%reg1=x")
+            }),
+            parse_trace("0x1 %reg1=x")
+        );
+    }
+
+    #[test]
+    fn agreeing_trace() {
+        // the trace of the emulator agrees with itself
+        let mut traced = emulator();
+        let mut trace = String::new();
+        traced
+            .run_traced(100, |_, entry| trace += &format!("{}\n", entry))
+            .unwrap();
+        let hardware = parse_trace(&trace).unwrap();
+        assert_eq!(Ok(None), compare(&mut emulator(), &hardware));
+        // a trace ending early agrees as far as it goes
+        assert_eq!(Ok(None), compare(&mut emulator(), &hardware[..2]));
+    }
+
+    #[test]
+    fn divergences() {
+        let hardware = parse_trace("0x0000 %reg1=0x0002\n0x0001 %reg1=0x0000\n").unwrap();
+        let divergence = compare(&mut emulator(), &hardware).unwrap().unwrap();
+        assert_eq!(
+            "Cycle 1 (line 2 of the trace): hardware wrote %reg1=0x0000, emulator wrote %reg1=0x0001",
+            divergence.to_string()
+        );
+
+        let hardware = parse_trace("0x0\n").unwrap();
+        let divergence = compare(&mut emulator(), &hardware).unwrap().unwrap();
+        assert_eq!(
+            "hardware wrote no register, emulator wrote %reg1=0x0002",
+            format!(
+                "hardware {}, emulator {}",
+                divergence.hardware, divergence.emulator
+            )
+        );
+
+        let hardware = parse_trace("0x0 %reg1=2\n0x1 %reg1=1\n0x3\n").unwrap();
+        let divergence = compare(&mut emulator(), &hardware).unwrap().unwrap();
+        assert_eq!(
+            "Cycle 2 (line 3 of the trace): hardware executed 0x0003, emulator executed 0x0002 jnzr -0x1",
+            divergence.to_string()
+        );
+
+        let mut trace = String::new();
+        emulator()
+            .run_traced(100, |_, entry| trace += &format!("{}\n", entry))
+            .unwrap();
+        trace += "0x0005\n";
+        let hardware = parse_trace(&trace).unwrap();
+        let divergence = compare(&mut emulator(), &hardware).unwrap().unwrap();
+        assert_eq!(
+            (String::from("executed 0x0005"), String::from("halted")),
+            (divergence.hardware, divergence.emulator)
+        );
+    }
+}
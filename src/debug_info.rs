@@ -0,0 +1,183 @@
+use std::path::{Path, PathBuf};
+
+use crate::diagnostic::{display_line, json_string};
+use crate::ir;
+
+/// Words generated for a statement of the source
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    /// address of the first word
+    pub address: u16,
+    /// number of words, pseudo-instructions and data directives have more
+    /// than one
+    pub size: u16,
+    pub line_number: u16,
+    pub column: u16,
+    /// index of the included file in [`DebugInfo::files`], `None` for the
+    /// assembled file
+    pub file: Option<usize>,
+}
+
+impl Span {
+    pub fn contains(&self, address: u16) -> bool {
+        address >= self.address
+            && u32::from(address) < u32::from(self.address) + u32::from(self.size)
+    }
+}
+
+/// Source positions of the generated words, collected by
+/// [`crate::parser::parser_with_debug_info`]. Line numbers of statements
+/// from included files refer to those files.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DebugInfo {
+    /// one span per statement in the order of the source
+    pub spans: Vec<Span>,
+    /// paths of the included files relative to the directory of the
+    /// assembled file, in the order of their first `.include`
+    pub files: Vec<PathBuf>,
+    /// file and line of the first `.include` of every file in `files`, the
+    /// file is `None` for the assembled file
+    pub included_at: Vec<(Option<usize>, u16)>,
+    /// qualified name, file and line of every label definition in the order
+    /// of the source
+    pub labels: Vec<(String, Option<usize>, u16)>,
+}
+
+impl DebugInfo {
+    /// Span of the statement the word at `address` was generated for
+    pub fn span(&self, address: u16) -> Option<&Span> {
+        self.spans.iter().find(|span| span.contains(address))
+    }
+
+    /// Address of the first word generated for line `line_number` of
+    /// `file`, `None` for the assembled file
+    pub fn address(&self, file: Option<usize>, line_number: u16) -> Option<u16> {
+        self.spans
+            .iter()
+            .filter(|span| span.file == file && span.line_number == line_number)
+            .map(|span| span.address)
+            .min()
+    }
+    /// Moves the spans along with the labels they were generated under,
+    /// e.g. after [`crate::layout::place_entry_first`] changed the label
+    /// addresses from `old` to `new`
+    pub fn relocate(&mut self, old: &ir::LabelLUT, new: &ir::LabelLUT) {
+        for span in &mut self.spans {
+            let moved = old
+                .0
+                .iter()
+                .filter(|(_, label)| label.address.0 <= span.address)
+                .max_by_key(|(_, label)| label.address)
+                .and_then(|(reference, label)| {
+                    let address = new.0.get(reference)?.address.0;
+                    Some(address.wrapping_sub(label.address.0))
+                });
+            span.address = span.address.wrapping_add(moved.unwrap_or(0));
+        }
+    }
+
+    /// Serializes one object per generated word with its address, the file,
+    /// line and column of its statement, the label it belongs to and the
+    /// text of the line, e.g.
+    /// ```json
+    /// {
+    ///   "words": [
+    ///     {"address": 0, "file": "main.s", "line": 2, "column": 4, "label": "main", "text": "ldc %reg0 0x1"}
+    ///   ]
+    /// }
+    /// ```
+    /// Files are named relative to the directory of `input`, the assembled
+    /// file. Lines are counted from 1 like in the diagnostics, columns from
+    /// 0. `line(file, line_number)` returns the text of a source line,
+    /// `file` is `None` for `input`. Words filling gaps have no entry.
+    pub fn to_json(
+        &self,
+        input: &Path,
+        ir: &ir::IR,
+        line: impl Fn(Option<&Path>, u16) -> Option<String>,
+    ) -> String {
+        let mut labels: Vec<(u16, bool, &str)> = ir
+            .label_definitions
+            .0
+            .iter()
+            .map(|(reference, label)| {
+                let code = ir
+                    .instructions
+                    .get(reference)
+                    .is_some_and(|code| !code.is_empty());
+                (label.address.0, code, label.name.as_str())
+            })
+            .collect();
+        // aliases of a label sort in front of the one holding the code
+        labels.sort();
+        let input_name = input.file_name().map(Path::new).unwrap_or(input);
+        let mut spans: Vec<&Span> = self.spans.iter().collect();
+        spans.sort_by_key(|span| span.address);
+        let mut words = Vec::new();
+        for span in spans {
+            let file = span.file.and_then(|file| self.files.get(file));
+            let text = line(file.map(PathBuf::as_path), span.line_number)
+                .map(|text| json_string(text.trim()))
+                .unwrap_or_else(|| String::from("null"));
+            let file = json_string(
+                &file
+                    .map_or(input_name, PathBuf::as_path)
+                    .display()
+                    .to_string(),
+            );
+            for offset in 0..span.size {
+                let address = span.address.wrapping_add(offset);
+                let label = labels
+                    .iter()
+                    .rev()
+                    .find(|(label_address, _, _)| *label_address <= address)
+                    .map_or_else(|| String::from("null"), |(_, _, name)| json_string(name));
+                words.push(format!(
+                    "    {{\"address\": {}, \"file\": {}, \"line\": {}, \"column\": {}, \"label\": {}, \"text\": {}}}",
+                    address,
+                    file,
+                    display_line(span.line_number),
+                    span.column,
+                    label,
+                    text
+                ));
+            }
+        }
+        format!("{{\n  \"words\": [\n{}\n  ]\n}}\n", words.join(",\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json() {
+        let path = Path::new("tests/include/main.s");
+        let lexed = crate::lexer::lexer(path).unwrap();
+        let (ir, debug_info) =
+            crate::parser::parser_with_debug_info(lexed, &Default::default()).unwrap();
+        let json = debug_info.to_json(path, &ir, |file, line_number| {
+            let file = file.map_or(path.to_path_buf(), |file| {
+                Path::new("tests/include").join(file)
+            });
+            let source = std::fs::read_to_string(file).ok()?;
+            source
+                .lines()
+                .nth(usize::from(line_number))
+                .map(String::from)
+        });
+        assert_eq!(
+            r#"{
+  "words": [
+    {"address": 0, "file": "main.s", "line": 2, "column": 4, "label": "main", "text": "nop"},
+    {"address": 1, "file": "lib.s", "line": 3, "column": 4, "label": "lib", "text": "inc %reg0"},
+    {"address": 2, "file": "main.s", "line": 4, "column": 4, "label": "lib", "text": "jr lib"},
+    {"address": 3, "file": "main.s", "line": 5, "column": 0, "label": "lib", "text": null}
+  ]
+}
+"#,
+            json
+        );
+    }
+}
@@ -0,0 +1,809 @@
+use std::collections::BTreeSet;
+use std::fmt::{self, Write};
+use std::path::Path;
+
+use crate::{
+    debug_info::DebugInfo,
+    diagnostic::display_line,
+    disassembler,
+    emulator::{Emulator, EmulatorError, Event, TraceEntry},
+    generator::InstructionWord,
+    ir,
+};
+
+/// Lines of source and disassembly shown around the program counter
+const CONTEXT: u16 = 5;
+/// Words of the memory inspector
+const MEMORY_ROWS: u16 = 4;
+const MEMORY_COLUMNS: u16 = 8;
+/// Instructions `rs` and `rc` can undo
+const HISTORY: usize = 100_000;
+
+/// Commands of the debugger prompt, see [`HELP`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Step(u32),
+    /// step one or N source lines
+    Next(u32),
+    /// undo one or N instructions
+    ReverseStep(u32),
+    /// undo instructions until a breakpoint
+    ReverseContinue,
+    Continue,
+    /// run until the target is reached, like a breakpoint used once
+    Until(String),
+    Break(String),
+    /// stop when the RAM word or register of the target is accessed
+    Watch(String),
+    Delete(String),
+    /// show the RAM from the target address on
+    Memory(String),
+    Help,
+    Quit,
+}
+
+pub const HELP: &str = "\
+s [N]        step one or N instructions, an empty line steps once
+n [N]        step one or N source lines, expansions of a line are one step
+c            continue until a breakpoint, dbg or hlt
+rs [N]       step back one or N instructions
+rc           continue backwards until a breakpoint
+u TARGET     run until TARGET is reached
+b TARGET     set a breakpoint, TARGET is a label, an address or :LINE
+w TARGET     stop when the RAM word at TARGET is read or written, or %REG is written
+d TARGET     delete a breakpoint or watchpoint
+m ADDRESS    show the RAM from ADDRESS on
+h            show this help
+q            quit";
+
+/// Count of `s`, `n` and `rs`, one without argument
+fn steps(argument: Option<&str>) -> Result<u32, String> {
+    match argument {
+        None => Ok(1),
+        Some(count) => count
+            .parse()
+            .map_err(|_| format!("'{}' is no number of steps", count)),
+    }
+}
+
+/// Parses a line typed at the debugger prompt
+pub fn parse_command(line: &str) -> Result<Command, String> {
+    let mut words = line.split_whitespace();
+    let command = words.next().unwrap_or("s");
+    let argument = words.next();
+    if let Some(extra) = words.next() {
+        return Err(format!("Unexpected argument '{}'", extra));
+    }
+    let target = |name: &str| {
+        argument
+            .map(String::from)
+            .ok_or(format!("'{}' needs a target", name))
+    };
+    match command {
+        "s" | "step" => steps(argument).map(Command::Step),
+        "n" | "next" => steps(argument).map(Command::Next),
+        "c" | "continue" => Ok(Command::Continue),
+        "rs" | "reverse-step" => steps(argument).map(Command::ReverseStep),
+        "rc" | "reverse-continue" => Ok(Command::ReverseContinue),
+        "u" | "until" => target("until").map(Command::Until),
+        "b" | "break" => target("break").map(Command::Break),
+        "w" | "watch" => target("watch").map(Command::Watch),
+        "d" | "delete" => target("delete").map(Command::Delete),
+        "m" | "memory" => target("memory").map(Command::Memory),
+        "h" | "help" => Ok(Command::Help),
+        "q" | "quit" => Ok(Command::Quit),
+        _ => Err(format!(
+            "Unknown command '{}', 'h' lists all commands",
+            command
+        )),
+    }
+}
+
+/// RAM word or register stopping the debugger when it is accessed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Watchpoint {
+    /// `ld` or `st` of the address
+    Ram(u16),
+    /// write of the register
+    Register(u8),
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Watchpoint::Ram(address) => write!(f, "ram[{:#06x}]", address),
+            Watchpoint::Register(register) => write!(f, "%reg{}", register),
+        }
+    }
+}
+
+/// Interactive session over an [`Emulator`], which shows the source line of
+/// the program counter with the spans in `debug_info`
+pub struct Debugger {
+    pub emulator: Emulator,
+    source: Vec<String>,
+    /// lines of the files in [`DebugInfo::files`]
+    included: Vec<Vec<String>>,
+    debug_info: DebugInfo,
+    /// labels in the order of their addresses
+    labels: Vec<(u16, String)>,
+    pub breakpoints: BTreeSet<u16>,
+    pub watchpoints: BTreeSet<Watchpoint>,
+    /// first address of the memory inspector
+    memory_address: u16,
+    /// cycles after which `c` and `u` give up
+    max_cycles: u64,
+    /// result of the last command
+    message: String,
+}
+
+impl Debugger {
+    /// Debugs `program` assembled from `source`, `read` returns the text of
+    /// the included files
+    pub fn new(
+        program: Vec<InstructionWord>,
+        source: &str,
+        debug_info: DebugInfo,
+        labels: &ir::LabelLUT,
+        max_cycles: u64,
+        read: impl Fn(&Path) -> Option<String>,
+    ) -> Self {
+        let mut labels: Vec<(u16, String)> = labels
+            .0
+            .values()
+            .map(|label| (label.address.0, label.name.clone()))
+            .collect();
+        labels.sort();
+        let lines = |source: &str| source.lines().map(String::from).collect();
+        let mut emulator = Emulator::new(program);
+        emulator.history_size = HISTORY;
+        Debugger {
+            emulator,
+            source: lines(source),
+            included: debug_info
+                .files
+                .iter()
+                .map(|file| read(file).as_deref().map(lines).unwrap_or_default())
+                .collect(),
+            debug_info,
+            labels,
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeSet::new(),
+            memory_address: 0,
+            max_cycles,
+            message: String::from("'h' lists all commands"),
+        }
+    }
+
+    /// Address of a label, of the first word of `:LINE` or a number. Lines
+    /// are counted from 1 like in the diagnostics.
+    pub fn address(&self, target: &str) -> Result<u16, String> {
+        if let Some(line) = target.strip_prefix(':') {
+            let line_number = line
+                .parse::<u16>()
+                .ok()
+                .and_then(|line_number| line_number.checked_sub(1))
+                .ok_or(format!("'{}' is no line number", line))?;
+            return self
+                .debug_info
+                .address(None, line_number)
+                .ok_or(format!("No code at line {}", line));
+        }
+        let number = match target.strip_prefix("0x") {
+            Some(hex) => u16::from_str_radix(hex, 16).ok(),
+            None => target.parse().ok(),
+        };
+        number
+            .or_else(|| {
+                self.labels
+                    .iter()
+                    .find(|(_, name)| name == target)
+                    .map(|(address, _)| *address)
+            })
+            .ok_or(format!("Unknown label '{}'", target))
+    }
+
+    /// Watchpoint of a register like `%reg1` or of the RAM word at the
+    /// address of [`Debugger::address`]
+    pub fn watchpoint(&self, target: &str) -> Result<Watchpoint, String> {
+        match target.to_lowercase().strip_prefix("%reg") {
+            Some(register) => register
+                .parse()
+                .ok()
+                .filter(|&register| register < ir::REGISTER_COUNT)
+                .map(Watchpoint::Register)
+                .ok_or(format!("Unknown register '{}'", target)),
+            None => self.address(target).map(Watchpoint::Ram),
+        }
+    }
+
+    /// Parses and executes a line typed at the prompt, returns `false` once
+    /// the session ends
+    pub fn prompt(&mut self, line: &str) -> bool {
+        match parse_command(line) {
+            Ok(command) => self.execute(command),
+            Err(message) => {
+                self.message = message;
+                true
+            }
+        }
+    }
+
+    /// Executes `command`, returns `false` once the session ends
+    pub fn execute(&mut self, command: Command) -> bool {
+        let result = match command {
+            Command::Step(count) => Ok(self.step(count)),
+            Command::Next(count) => Ok(self.next(count)),
+            Command::Continue => Ok(self.resume(None)),
+            Command::ReverseStep(count) => Ok(self.step_back(count)),
+            Command::ReverseContinue => Ok(self.resume_back()),
+            Command::Until(target) => self
+                .address(&target)
+                .map(|address| self.resume(Some(address))),
+            Command::Break(target) => self.address(&target).map(|address| {
+                self.breakpoints.insert(address);
+                format!("Breakpoint at {:#06x}", address)
+            }),
+            Command::Watch(target) => self.watchpoint(&target).map(|watchpoint| {
+                self.watchpoints.insert(watchpoint);
+                format!("Watchpoint on {}", watchpoint)
+            }),
+            Command::Delete(target) => self.watchpoint(&target).and_then(|watchpoint| {
+                let mut deleted = Vec::new();
+                if let Watchpoint::Ram(address) = watchpoint {
+                    if self.breakpoints.remove(&address) {
+                        deleted.push(format!("breakpoint at {:#06x}", address));
+                    }
+                }
+                if self.watchpoints.remove(&watchpoint) {
+                    deleted.push(format!("watchpoint on {}", watchpoint));
+                }
+                if deleted.is_empty() {
+                    Err(format!("No breakpoint or watchpoint at {}", target))
+                } else {
+                    Ok(format!("Deleted {}", deleted.join(" and ")))
+                }
+            }),
+            Command::Memory(target) => self.address(&target).map(|address| {
+                self.memory_address = address;
+                format!("Memory from {:#06x}", address)
+            }),
+            Command::Help => Ok(String::from(HELP)),
+            Command::Quit => return false,
+        };
+        self.message = result.unwrap_or_else(|message| message);
+        true
+    }
+
+    fn step(&mut self, count: u32) -> String {
+        for _ in 0..count {
+            match self.step_watched() {
+                Ok((Event::Halt, _)) => return String::from("Halted"),
+                Ok((_, Some(report))) => return report,
+                Ok(_) => {}
+                Err(err) => return err.to_string(),
+            }
+        }
+        format!("Stepped to {:#06x}", self.emulator.pc)
+    }
+
+    fn step_back(&mut self, count: u32) -> String {
+        for _ in 0..count {
+            if !self.emulator.step_back() {
+                return format!("No history before {:#06x}", self.emulator.pc);
+            }
+        }
+        format!("Stepped back to {:#06x}", self.emulator.pc)
+    }
+
+    /// Undoes instructions until a breakpoint or the start of the history
+    fn resume_back(&mut self) -> String {
+        while self.emulator.step_back() {
+            let pc = self.emulator.pc;
+            if self.breakpoints.contains(&pc) {
+                return format!("Breakpoint at {:#06x}", pc);
+            }
+        }
+        format!("No history before {:#06x}", self.emulator.pc)
+    }
+
+    /// Runs until a breakpoint, `until`, `dbg`, `hlt` or an error
+    fn resume(&mut self, until: Option<u16>) -> String {
+        self.run_until(|_, pc| (until == Some(pc)).then(|| format!("Reached {:#06x}", pc)))
+            .unwrap_or_else(|message| message)
+    }
+
+    /// Steps until the program counter reaches the first word of another
+    /// source line or jumps back to the first word of the current one, so
+    /// the words of a pseudo-instruction or macro expansion are one step
+    fn next(&mut self, count: u32) -> String {
+        let mut message = String::new();
+        for _ in 0..count {
+            let span = self.debug_info.span(self.emulator.pc).copied();
+            let line = span.map(|span| (span.file, span.line_number));
+            let start = span.map(|span| span.address);
+            let stepped = self.run_until(|debugger, pc| {
+                let next = debugger.debug_info.span(pc);
+                if next.is_some_and(|next| next.address != pc) {
+                    return None;
+                }
+                let next_line = next.map(|next| (next.file, next.line_number));
+                if next_line == line && start != Some(pc) {
+                    return None;
+                }
+                Some(match next {
+                    Some(next) => match next
+                        .file
+                        .and_then(|file| debugger.debug_info.files.get(file))
+                    {
+                        Some(file) => format!(
+                            "Stepped to line {} of {}",
+                            display_line(next.line_number),
+                            file.display()
+                        ),
+                        None => format!("Stepped to line {}", display_line(next.line_number)),
+                    },
+                    None => format!("Stepped to {:#06x}", pc),
+                })
+            });
+            match stepped {
+                Ok(stepped) => message = stepped,
+                Err(stopped) => return stopped,
+            }
+        }
+        message
+    }
+
+    /// Runs until `stop` returns a message for the new program counter,
+    /// which is `Ok`, or until a breakpoint, watchpoint, `dbg`, `hlt` or an
+    /// error, which is `Err`
+    fn run_until(&mut self, stop: impl Fn(&Self, u16) -> Option<String>) -> Result<String, String> {
+        for _ in 0..self.max_cycles {
+            match self.step_watched() {
+                Ok((Event::Halt, _)) => return Err(String::from("Halted")),
+                Ok((Event::Debug, _)) => {
+                    return Err(format!("dbg at {:#06x}", self.emulator.pc.wrapping_sub(1)))
+                }
+                Ok((Event::Continue, Some(report))) => return Err(report),
+                Ok((Event::Continue, None)) => {}
+                Err(err) => return Err(err.to_string()),
+            }
+            let pc = self.emulator.pc;
+            if let Some(message) = stop(self, pc) {
+                return Ok(message);
+            }
+            if self.breakpoints.contains(&pc) {
+                return Err(format!("Breakpoint at {:#06x}", pc));
+            }
+        }
+        Err(format!("Stopped after {} cycles", self.max_cycles))
+    }
+
+    /// Steps once and reports the first watchpoint the instruction hit with
+    /// the old and new value
+    fn step_watched(&mut self) -> Result<(Event, Option<String>), EmulatorError> {
+        if self.watchpoints.is_empty() {
+            return self.emulator.step().map(|event| (event, None));
+        }
+        let old: Vec<(Watchpoint, u32)> = self
+            .watchpoints
+            .iter()
+            .map(|&watchpoint| match watchpoint {
+                Watchpoint::Ram(address) => (watchpoint, self.emulator.ram[usize::from(address)]),
+                Watchpoint::Register(register) => (watchpoint, self.emulator.register(register)),
+            })
+            .collect();
+        let (event, entry) = self.emulator.step_traced()?;
+        let report = entry.and_then(|entry| {
+            old.iter()
+                .find_map(|&(watchpoint, old)| watched(&entry, watchpoint, old))
+        });
+        Ok((event, report))
+    }
+
+    /// Screen with the source and disassembly around the program counter,
+    /// the registers, the memory inspector and the result of the last
+    /// command. With `color` the current lines are highlighted.
+    pub fn render(&self, color: bool) -> String {
+        let mut screen = String::new();
+        let highlight = |line: String, current: bool| {
+            if current && color {
+                format!("\x1b[7m{}\x1b[0m", line)
+            } else {
+                line
+            }
+        };
+        let header = |screen: &mut String, title: &str| {
+            if color {
+                let _ = writeln!(screen, "\x1b[1m{}\x1b[0m", title);
+            } else {
+                let _ = writeln!(screen, "{}", title);
+            }
+        };
+        let pc = self.emulator.pc;
+
+        let span = self.debug_info.span(pc);
+        // lines of included files are shown with their file
+        match span.and_then(|span| self.debug_info.files.get(span.file?)) {
+            Some(file) => header(&mut screen, &format!("Source {}", file.display())),
+            None => header(&mut screen, "Source"),
+        }
+        match span {
+            Some(span) => {
+                let source = match span.file {
+                    Some(file) => &self.included[file],
+                    None => &self.source,
+                };
+                let first = span.line_number.saturating_sub(CONTEXT);
+                let last = span.line_number.saturating_add(CONTEXT);
+                for line_number in first..=last {
+                    let Some(text) = source.get(usize::from(line_number)) else {
+                        break;
+                    };
+                    let breakpoint = self
+                        .debug_info
+                        .address(span.file, line_number)
+                        .is_some_and(|address| self.breakpoints.contains(&address));
+                    let current = line_number == span.line_number;
+                    let line = format!(
+                        "{}{}{:>4} | {}",
+                        if breakpoint { '*' } else { ' ' },
+                        if current { '>' } else { ' ' },
+                        display_line(line_number),
+                        text
+                    );
+                    let _ = writeln!(screen, "{}", highlight(line, current));
+                }
+            }
+            None => {
+                let _ = writeln!(screen, "  no source line for {:#06x}", pc);
+            }
+        }
+
+        header(&mut screen, "Disassembly");
+        let program = self.emulator.program();
+        let first = pc.saturating_sub(CONTEXT);
+        for address in first..pc.saturating_add(CONTEXT + 1) {
+            let Some(word) = program.get(usize::from(address)) else {
+                break;
+            };
+            for (_, name) in self.labels.iter().filter(|(a, _)| *a == address) {
+                let _ = writeln!(screen, "        {}:", name);
+            }
+            let text = disassembler::decode(word)
+                .map_or(String::from("; no instruction"), |instruction| {
+                    instruction.to_string()
+                });
+            let current = address == pc;
+            let line = format!(
+                "{}{} {:#06x}  {}  {}",
+                if self.breakpoints.contains(&address) {
+                    '*'
+                } else {
+                    ' '
+                },
+                if current { '>' } else { ' ' },
+                address,
+                word,
+                text
+            );
+            let _ = writeln!(screen, "{}", highlight(line, current));
+        }
+
+        header(&mut screen, "Registers");
+        let digits = if self.emulator.wide_mode { 8 } else { 4 };
+        for row in 0..ir::REGISTER_COUNT / 4 {
+            let registers: Vec<String> = (row * 4..row * 4 + 4)
+                .map(|address| {
+                    let value = self.emulator.register(address);
+                    format!("%reg{} {:#0width$x}", address, value, width = digits + 2)
+                })
+                .collect();
+            let _ = writeln!(screen, "  {}", registers.join("  "));
+        }
+        let flags = self.emulator.flags;
+        let _ = writeln!(
+            screen,
+            "  pc {:#06x}  cycles {}  zero={} carry={} overflow={}  {} bit",
+            pc,
+            self.emulator.cycles,
+            u8::from(flags.zero),
+            u8::from(flags.carry),
+            u8::from(flags.overflow),
+            if self.emulator.wide_mode { 32 } else { 16 }
+        );
+
+        header(&mut screen, "Memory");
+        for row in 0..MEMORY_ROWS {
+            let start = self.memory_address.wrapping_add(row * MEMORY_COLUMNS);
+            let words: Vec<String> = (0..MEMORY_COLUMNS)
+                .map(|column| {
+                    let address = usize::from(start.wrapping_add(column));
+                    format!("{:0width$x}", self.emulator.ram[address], width = digits)
+                })
+                .collect();
+            let _ = writeln!(screen, "  {:#06x}  {}", start, words.join(" "));
+        }
+
+        if !self.emulator.bus.is_empty() {
+            header(&mut screen, "Devices");
+            let _ = write!(screen, "{}", self.emulator.bus);
+        }
+
+        let _ = writeln!(screen);
+        let _ = writeln!(screen, "{}", self.message);
+        screen
+    }
+}
+
+/// Report of the access of `entry` to `watchpoint`, whose value was `old`
+/// before the instruction
+fn watched(entry: &TraceEntry, watchpoint: Watchpoint, old: u32) -> Option<String> {
+    let width = if entry.wide_mode { 10 } else { 6 };
+    let access = match watchpoint {
+        Watchpoint::Ram(address) => {
+            let name = format!("{}[{:#06x}]", entry.device.unwrap_or("ram"), address);
+            match (entry.load, entry.store) {
+                (_, Some((stored, value))) if stored == address => match entry.device {
+                    Some(_) => format!("wrote {} {:#0width$x}", name, value),
+                    None => format!("wrote {} {:#0width$x} -> {:#0width$x}", name, old, value),
+                },
+                (Some((loaded, value)), _) if loaded == address => {
+                    format!("read {} {:#0width$x}", name, value)
+                }
+                _ => return None,
+            }
+        }
+        Watchpoint::Register(register) => {
+            let &(_, value) = entry
+                .registers
+                .iter()
+                .rev()
+                .find(|(written, _)| *written == register)?;
+            format!(
+                "wrote %reg{} {:#0width$x} -> {:#0width$x}",
+                register, old, value
+            )
+        }
+    };
+    Some(format!(
+        "Watchpoint at {:#06x}: {} {}",
+        entry.address, entry.instruction, access
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer, parser, preprocessor};
+
+    const SOURCE: &str = "main:
+    ldc %reg1 3
+loop:
+    dec %reg1
+    jnzr loop
+    ldc %reg2 0x20
+    st %reg2 %reg2
+    hlt
+";
+
+    fn debugger() -> Debugger {
+        debugger_of(SOURCE)
+    }
+
+    fn debugger_of(source: &str) -> Debugger {
+        let keywords = lexer::lexer_from_str(source, &lexer::LexerOptions::default()).unwrap();
+        let keywords = preprocessor::preprocessor(keywords).unwrap();
+        let (ir, debug_info) =
+            parser::parser_with_debug_info(keywords, &Default::default()).unwrap();
+        let labels = ir.label_definitions.clone();
+        let program = crate::generator::generator(ir).unwrap();
+        Debugger::new(program, source, debug_info, &labels, 1000, |_| None)
+    }
+
+    #[test]
+    fn commands() {
+        assert_eq!(Ok(Command::Step(1)), parse_command(""));
+        assert_eq!(Ok(Command::Step(3)), parse_command("s 3"));
+        assert_eq!(
+            Ok(Command::Break(String::from("loop"))),
+            parse_command("b loop")
+        );
+        assert!(parse_command("b").is_err());
+        assert!(parse_command("s x").is_err());
+        assert!(parse_command("x").is_err());
+
+        let debugger = debugger();
+        assert_eq!(Ok(1), debugger.address("loop"));
+        assert_eq!(Ok(3), debugger.address(":6"));
+        assert_eq!(Ok(0x10), debugger.address("0x10"));
+        assert!(debugger.address("missing").is_err());
+        assert!(debugger.address(":0").is_err());
+    }
+
+    #[test]
+    fn breakpoints_and_stepping() {
+        let mut debugger = debugger();
+        assert!(debugger.prompt("b :6"));
+        debugger.execute(Command::Continue);
+        assert_eq!(3, debugger.emulator.pc);
+        assert_eq!(0, debugger.emulator.register(1));
+
+        debugger.execute(Command::Until(String::from("0x5")));
+        assert_eq!(5, debugger.emulator.pc);
+        debugger.execute(Command::Step(1));
+        assert_eq!(0x20, debugger.emulator.ram[0x20]);
+        debugger.execute(Command::Continue);
+        assert!(debugger.emulator.halted);
+        assert!(debugger.prompt("x"));
+        assert!(debugger
+            .render(false)
+            .ends_with("\nUnknown command 'x', 'h' lists all commands\n"));
+        assert!(!debugger.prompt("q"));
+    }
+
+    #[test]
+    fn source_stepping() {
+        let mut debugger = debugger_of(
+            "main:
+    s32b true
+    ldc %reg1 2
+loop:
+    li %reg0 0x12345678
+    dec %reg1
+    jnzr loop
+    hlt
+",
+        );
+        // the five words of `li` are one step, the loop runs twice
+        for (count, pc, message) in [
+            (1, 1, "Stepped to line 3"),
+            (1, 2, "Stepped to line 5"),
+            (1, 7, "Stepped to line 6"),
+            (1, 8, "Stepped to line 7"),
+            (1, 2, "Stepped to line 5"),
+            (3, 9, "Stepped to line 8"),
+            (1, 9, "Halted"),
+        ] {
+            debugger.execute(Command::Next(count));
+            assert_eq!(pc, debugger.emulator.pc);
+            assert!(debugger
+                .render(false)
+                .ends_with(&format!("\n{}\n", message)));
+        }
+        assert_eq!(Ok(Command::Next(2)), parse_command("n 2"));
+    }
+
+    #[test]
+    fn reverse_stepping() {
+        let mut debugger = debugger();
+        assert_eq!(Ok(Command::ReverseStep(2)), parse_command("rs 2"));
+        assert_eq!(Ok(Command::ReverseContinue), parse_command("rc"));
+        debugger.execute(Command::Continue);
+        assert!(debugger.emulator.halted);
+        assert_eq!(0x20, debugger.emulator.ram[0x20]);
+
+        debugger.execute(Command::ReverseStep(2));
+        assert_eq!(4, debugger.emulator.pc);
+        assert_eq!(0, debugger.emulator.ram[0x20]);
+        assert!(debugger
+            .render(false)
+            .ends_with("\nStepped back to 0x0004\n"));
+
+        // the loop body ran three times, the last run is found first
+        debugger.execute(Command::Break(String::from("loop")));
+        debugger.execute(Command::ReverseContinue);
+        assert_eq!(
+            (1, 1),
+            (debugger.emulator.pc, debugger.emulator.register(1))
+        );
+        debugger.execute(Command::Delete(String::from("loop")));
+        debugger.execute(Command::ReverseContinue);
+        assert_eq!((0, 0), (debugger.emulator.pc, debugger.emulator.cycles));
+        assert!(debugger
+            .render(false)
+            .ends_with("\nNo history before 0x0000\n"));
+        debugger.execute(Command::Continue);
+        assert_eq!(0x20, debugger.emulator.ram[0x20]);
+    }
+
+    #[test]
+    fn watchpoints() {
+        let mut debugger = debugger();
+        assert!(debugger.watchpoint("%reg8").is_err());
+        assert_eq!(Ok(Watchpoint::Register(1)), debugger.watchpoint("%REG1"));
+        assert_eq!(Ok(Watchpoint::Ram(0x20)), debugger.watchpoint("0x20"));
+
+        debugger.execute(Command::Watch(String::from("%reg1")));
+        debugger.execute(Command::Continue);
+        assert!(debugger
+            .render(false)
+            .ends_with("\nWatchpoint at 0x0000: ldc %reg1 0x3 wrote %reg1 0x0000 -> 0x0003\n"));
+        debugger.execute(Command::Step(5));
+        assert_eq!(2, debugger.emulator.pc);
+        assert!(debugger
+            .render(false)
+            .ends_with("\nWatchpoint at 0x0001: dec %reg1 wrote %reg1 0x0003 -> 0x0002\n"));
+
+        debugger.execute(Command::Delete(String::from("%reg1")));
+        debugger.execute(Command::Watch(String::from("0x20")));
+        debugger.execute(Command::Continue);
+        assert_eq!(5, debugger.emulator.pc);
+        assert!(debugger.render(false).ends_with(
+            "\nWatchpoint at 0x0004: st %reg2 %reg2 wrote ram[0x0020] 0x0000 -> 0x0020\n"
+        ));
+        debugger.execute(Command::Delete(String::from("0x20")));
+        assert!(debugger
+            .render(false)
+            .ends_with("\nDeleted watchpoint on ram[0x0020]\n"));
+        debugger.execute(Command::Delete(String::from("0x20")));
+        assert!(debugger
+            .render(false)
+            .ends_with("\nNo breakpoint or watchpoint at 0x20\n"));
+    }
+
+    #[test]
+    fn screen() {
+        let mut debugger = debugger();
+        debugger.execute(Command::Break(String::from("loop")));
+        debugger.execute(Command::Step(2));
+
+        let screen = debugger.render(false);
+        assert!(
+            screen.contains("\n     3 | loop:\n*    4 |     dec %reg1\n >   5 |     jnzr loop\n")
+        );
+        assert!(screen.contains(
+            "\n        loop:\n*  0x0001  20106  dec %reg1\n > 0x0002  ffe5a  jnzr -0x1\n"
+        ));
+        assert!(screen.contains("  %reg0 0x0000  %reg1 0x0002  %reg2 0x0000  %reg3 0x0000\n"));
+        assert!(screen.ends_with("\nStepped to 0x0002\n"));
+        assert!(!screen.contains("Devices"));
+        let (address, gpio) = crate::device::parse_spec("gpio@0x20").unwrap();
+        debugger.emulator.bus.attach(address, gpio).unwrap();
+        assert!(debugger
+            .render(false)
+            .contains("Devices\ngpio@0x0020\n  output  "));
+        assert!(debugger
+            .render(true)
+            .contains("\x1b[7m >   5 |     jnzr loop\x1b[0m"));
+    }
+
+    #[test]
+    fn included_source() {
+        let path = Path::new("tests/include/main.s");
+        let keywords = preprocessor::preprocessor(lexer::lexer(path).unwrap()).unwrap();
+        let (ir, debug_info) =
+            parser::parser_with_debug_info(keywords, &Default::default()).unwrap();
+        let labels = ir.label_definitions.clone();
+        let program = crate::generator::generator(ir).unwrap();
+        let source = std::fs::read_to_string(path).unwrap();
+        let mut debugger = Debugger::new(program, &source, debug_info, &labels, 1000, |file| {
+            std::fs::read_to_string(Path::new("tests/include").join(file)).ok()
+        });
+        assert!(debugger.prompt("b :4"));
+
+        debugger.execute(Command::Step(1));
+        let screen = debugger.render(false);
+        assert!(screen.starts_with("Source lib.s\n     1 | ;; Increments register 0\n"));
+        assert!(screen.contains("\n     2 | lib:\n >   3 |     inc %reg0\nDisassembly\n"));
+        debugger.execute(Command::Step(1));
+        let screen = debugger.render(false);
+        assert!(screen.starts_with("Source\n     1 | main:\n"));
+        assert!(screen.contains("\n     3 | .include \"lib.s\"\n*>   4 |     jr lib\n"));
+    }
+
+    #[test]
+    fn debug_at_last_address() {
+        // nops up to the `dbg` at 0xffff, after which the program counter wraps
+        let mut program = vec![InstructionWord::from_raw(0x6c); 0xffff];
+        program.extend(crate::assemble_str("main:\n    dbg\n").unwrap());
+        program.truncate(0x10000);
+        let labels = ir::LabelLUT::new();
+        let mut debugger =
+            Debugger::new(program, "", DebugInfo::default(), &labels, 0x10000, |_| {
+                None
+            });
+        debugger.execute(Command::Continue);
+        assert!(debugger.render(false).ends_with("\ndbg at 0xffff\n"));
+    }
+}
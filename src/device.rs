@@ -0,0 +1,649 @@
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use crate::emulator::RAM_SIZE;
+
+/// Errors of describing and attaching devices
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceError {
+    /// `--device` is not of the form `KIND@ADDRESS[,OPTION=VALUE...]`
+    InvalidSpec {
+        spec: String,
+    },
+    UnknownDevice {
+        kind: String,
+    },
+    UnknownOption {
+        kind: String,
+        option: String,
+    },
+    InvalidOption {
+        kind: String,
+        option: String,
+        value: u32,
+    },
+    /// The words of the device do not fit below the end of the RAM
+    OutOfRange {
+        device: String,
+        address: u16,
+    },
+    /// The device is attached to words of another device
+    Overlap {
+        device: String,
+        address: u16,
+        other: String,
+    },
+}
+
+impl fmt::Display for DeviceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeviceError::InvalidSpec { spec } => write!(
+                f,
+                "Invalid device '{}', expected KIND@ADDRESS[,OPTION=VALUE...]",
+                spec
+            ),
+            DeviceError::UnknownDevice { kind } => write!(
+                f,
+                "Unknown device '{}', expected one of: {}",
+                kind,
+                KINDS.join(", ")
+            ),
+            DeviceError::UnknownOption { kind, option } => {
+                write!(f, "Device '{}' has no option '{}'", kind, option)
+            }
+            DeviceError::InvalidOption {
+                kind,
+                option,
+                value,
+            } => write!(
+                f,
+                "Invalid value {} of option '{}' of device '{}'",
+                value, option, kind
+            ),
+            DeviceError::OutOfRange { device, address } => write!(
+                f,
+                "Device '{}' at address {:#06x} does not fit into the address space",
+                device, address
+            ),
+            DeviceError::Overlap {
+                device,
+                address,
+                other,
+            } => write!(
+                f,
+                "Device '{}' at address {:#06x} overlaps device '{}'",
+                device, address, other
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DeviceError {}
+
+impl DeviceError {
+    /// Stable name of the error kind, e.g. for machine readable diagnostics
+    pub fn code(&self) -> &'static str {
+        match self {
+            DeviceError::InvalidSpec { .. } => "invalid-device",
+            DeviceError::UnknownDevice { .. } => "unknown-device",
+            DeviceError::UnknownOption { .. } => "unknown-option",
+            DeviceError::InvalidOption { .. } => "invalid-option",
+            DeviceError::OutOfRange { .. } => "device-out-of-range",
+            DeviceError::Overlap { .. } => "overlapping-devices",
+        }
+    }
+}
+
+/// Peripheral attached to a range of RAM addresses, which `ld` and `st`
+/// reach instead of the RAM. Offsets are relative to the first address of
+/// the device. The [`fmt::Display`] of a device is its state, one line
+/// indented by two spaces per value.
+pub trait Device: fmt::Debug + fmt::Display {
+    /// Kind of the device, as used by [`create`]
+    fn kind(&self) -> &'static str;
+    /// Number of addressed words
+    fn size(&self) -> u16;
+    fn read(&mut self, offset: u16) -> u32;
+    fn write(&mut self, offset: u16, value: u32);
+    /// Called after every cycle of the emulator
+    fn tick(&mut self) {}
+}
+
+/// Kinds of devices [`create`] knows
+pub const KINDS: [&str; 4] = ["timer", "gpio", "seven-segment", "console"];
+
+/// Device of `kind` configured with `options`, which are
+/// - `divider` of the `timer`, default 1, 0 stops it
+/// - `input` pins of the `gpio`, default 0
+/// - `digits` of the `seven-segment` display from 1 to 8, default 4
+///
+/// The `console` has no options and uses stdin and stdout.
+pub fn create(kind: &str, options: &[(String, u32)]) -> Result<Box<dyn Device>, DeviceError> {
+    let known = match kind {
+        "timer" => Some("divider"),
+        "gpio" => Some("input"),
+        "seven-segment" => Some("digits"),
+        "console" => None,
+        _ => {
+            return Err(DeviceError::UnknownDevice {
+                kind: kind.to_string(),
+            })
+        }
+    };
+    if let Some((option, _)) = options
+        .iter()
+        .find(|(option, _)| Some(option.as_str()) != known)
+    {
+        return Err(DeviceError::UnknownOption {
+            kind: kind.to_string(),
+            option: option.clone(),
+        });
+    }
+    let value = options.last().map(|(_, value)| *value);
+    let invalid = |value: u32| DeviceError::InvalidOption {
+        kind: kind.to_string(),
+        option: known.unwrap_or_default().to_string(),
+        value,
+    };
+    Ok(match (kind, value) {
+        ("timer", divider) => Box::new(Timer {
+            divider: divider.unwrap_or(1),
+            ..Timer::default()
+        }),
+        ("gpio", input) => Box::new(Gpio {
+            input: input.unwrap_or(0),
+            ..Gpio::default()
+        }),
+        ("console", _) => Box::new(Console::new(io::stdin(), io::stdout())),
+        (_, Some(digits)) if !(1..=8).contains(&digits) => return Err(invalid(digits)),
+        (_, digits) => Box::new(SevenSegment {
+            digits: vec![0; digits.unwrap_or(4) as usize],
+        }),
+    })
+}
+
+/// Parses a number with an optional `0x` or `0b` prefix and `_` separators
+pub(crate) fn parse_value(text: &str) -> Option<u32> {
+    let text = text.replace('_', "");
+    if let Some(hex) = text.strip_prefix("0x") {
+        u32::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = text.strip_prefix("0b") {
+        u32::from_str_radix(binary, 2).ok()
+    } else {
+        text.parse().ok()
+    }
+}
+
+/// Parses a device given as `KIND@ADDRESS[,OPTION=VALUE...]`, e.g.
+/// `seven-segment@0xff20,digits=6`
+pub fn parse_spec(spec: &str) -> Result<(u16, Box<dyn Device>), DeviceError> {
+    let invalid = || DeviceError::InvalidSpec {
+        spec: spec.to_string(),
+    };
+    let mut parts = spec.split(',');
+    let (kind, address) = parts
+        .next()
+        .and_then(|device| device.split_once('@'))
+        .ok_or_else(invalid)?;
+    let address = parse_value(address)
+        .and_then(|address| u16::try_from(address).ok())
+        .ok_or_else(invalid)?;
+    let options = parts
+        .map(|option| {
+            let (name, value) = option.split_once('=').ok_or_else(invalid)?;
+            Ok((name.to_string(), parse_value(value).ok_or_else(invalid)?))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok((address, create(kind, &options)?))
+}
+
+/// Counter at offset 0 incremented every `divider` cycles, the divider is
+/// at offset 1. Both can be written, a divider of 0 stops the timer.
+#[derive(Debug)]
+pub struct Timer {
+    counter: u32,
+    divider: u32,
+    /// cycles since the last increment
+    elapsed: u32,
+}
+
+impl Default for Timer {
+    fn default() -> Self {
+        Timer {
+            counter: 0,
+            divider: 1,
+            elapsed: 0,
+        }
+    }
+}
+
+impl Device for Timer {
+    fn kind(&self) -> &'static str {
+        "timer"
+    }
+
+    fn size(&self) -> u16 {
+        2
+    }
+
+    fn read(&mut self, offset: u16) -> u32 {
+        match offset {
+            0 => self.counter,
+            _ => self.divider,
+        }
+    }
+
+    fn write(&mut self, offset: u16, value: u32) {
+        match offset {
+            0 => self.counter = value,
+            _ => self.divider = value,
+        }
+        self.elapsed = 0;
+    }
+
+    fn tick(&mut self) {
+        if self.divider == 0 {
+            return;
+        }
+        self.elapsed += 1;
+        if self.elapsed >= self.divider {
+            self.elapsed = 0;
+            self.counter = self.counter.wrapping_add(1);
+        }
+    }
+}
+
+impl fmt::Display for Timer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "  counter {:#06x}", self.counter)?;
+        writeln!(f, "  divider {:#06x}", self.divider)
+    }
+}
+
+/// Output pins at offset 0, which read back what was written, and input
+/// pins at offset 1, which keep the value they were configured with
+#[derive(Debug, Default)]
+pub struct Gpio {
+    output: u32,
+    input: u32,
+}
+
+impl Device for Gpio {
+    fn kind(&self) -> &'static str {
+        "gpio"
+    }
+
+    fn size(&self) -> u16 {
+        2
+    }
+
+    fn read(&mut self, offset: u16) -> u32 {
+        match offset {
+            0 => self.output,
+            _ => self.input,
+        }
+    }
+
+    fn write(&mut self, offset: u16, value: u32) {
+        if offset == 0 {
+            self.output = value;
+        }
+    }
+}
+
+impl fmt::Display for Gpio {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "  output  {:016b}", self.output & 0xffff)?;
+        writeln!(f, "  input   {:016b}", self.input & 0xffff)
+    }
+}
+
+/// Display with one word per digit, the leftmost first. Bits 0 to 6 light
+/// the segments a to g, bit 7 the decimal point.
+#[derive(Debug)]
+pub struct SevenSegment {
+    digits: Vec<u32>,
+}
+
+impl Default for SevenSegment {
+    fn default() -> Self {
+        SevenSegment { digits: vec![0; 4] }
+    }
+}
+
+impl Device for SevenSegment {
+    fn kind(&self) -> &'static str {
+        "seven-segment"
+    }
+
+    fn size(&self) -> u16 {
+        self.digits.len() as u16
+    }
+
+    fn read(&mut self, offset: u16) -> u32 {
+        self.digits[usize::from(offset)]
+    }
+
+    fn write(&mut self, offset: u16, value: u32) {
+        self.digits[usize::from(offset)] = value & 0xff;
+    }
+}
+
+/// The digits drawn with `_` and `|` over three lines
+impl fmt::Display for SevenSegment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let segment = |digit: u32, bit: u32, lit: char| {
+            if digit & (1 << bit) != 0 {
+                lit
+            } else {
+                ' '
+            }
+        };
+        let rows: [Vec<[char; 4]>; 3] = [
+            self.digits
+                .iter()
+                .map(|&d| [' ', segment(d, 0, '_'), ' ', ' '])
+                .collect(),
+            self.digits
+                .iter()
+                .map(|&d| {
+                    [
+                        segment(d, 5, '|'),
+                        segment(d, 6, '_'),
+                        segment(d, 1, '|'),
+                        ' ',
+                    ]
+                })
+                .collect(),
+            self.digits
+                .iter()
+                .map(|&d| {
+                    [
+                        segment(d, 4, '|'),
+                        segment(d, 3, '_'),
+                        segment(d, 2, '|'),
+                        segment(d, 7, '.'),
+                    ]
+                })
+                .collect(),
+        ];
+        for row in rows {
+            let line: String = row.into_iter().flatten().collect();
+            writeln!(f, "  {}", line.trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+/// Character console: storing to offset 0 writes the low byte of the value
+/// to the output, loading from it reads the next byte of the input or -1 at
+/// its end. Bit 0 of the status at offset 1 is set while there is input
+/// left, which blocks until input is available.
+pub struct Console {
+    input: Box<dyn Read>,
+    output: Box<dyn Write>,
+    /// byte read ahead by the status, `Some(None)` at the end of the input
+    peeked: Option<Option<u8>>,
+    read: u64,
+    written: u64,
+}
+
+impl Console {
+    pub fn new(input: impl Read + 'static, output: impl Write + 'static) -> Self {
+        Console {
+            input: Box::new(input),
+            output: Box::new(output),
+            peeked: None,
+            read: 0,
+            written: 0,
+        }
+    }
+
+    /// Next byte of the input, errors end the input like its end
+    fn next(&mut self) -> Option<u8> {
+        if let Some(byte) = self.peeked.take() {
+            return byte;
+        }
+        let mut byte = [0];
+        match self.input.read_exact(&mut byte) {
+            Ok(()) => Some(byte[0]),
+            Err(_) => None,
+        }
+    }
+}
+
+impl fmt::Debug for Console {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Console")
+            .field("read", &self.read)
+            .field("written", &self.written)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Device for Console {
+    fn kind(&self) -> &'static str {
+        "console"
+    }
+
+    fn size(&self) -> u16 {
+        2
+    }
+
+    fn read(&mut self, offset: u16) -> u32 {
+        match offset {
+            0 => match self.next() {
+                Some(byte) => {
+                    self.read += 1;
+                    u32::from(byte)
+                }
+                None => u32::MAX,
+            },
+            _ => {
+                let byte = self.next();
+                self.peeked = Some(byte);
+                u32::from(byte.is_some())
+            }
+        }
+    }
+
+    fn write(&mut self, offset: u16, value: u32) {
+        if offset == 0 {
+            // programs have no way to notice a failed write
+            let _ = self.output.write_all(&[value as u8]);
+            let _ = self.output.flush();
+            self.written += 1;
+        }
+    }
+}
+
+impl fmt::Display for Console {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "  read    {} bytes", self.read)?;
+        writeln!(f, "  written {} bytes", self.written)
+    }
+}
+
+/// Device attached at `address`
+#[derive(Debug)]
+struct Mapping {
+    address: u16,
+    device: Box<dyn Device>,
+}
+
+impl Mapping {
+    fn contains(&self, address: u16) -> bool {
+        address >= self.address && address - self.address < self.device.size()
+    }
+}
+
+/// Address decoder routing `ld` and `st` to the attached devices, the RAM
+/// answers every other address
+#[derive(Debug, Default)]
+pub struct Bus {
+    mappings: Vec<Mapping>,
+}
+
+impl Bus {
+    pub fn new() -> Self {
+        Bus::default()
+    }
+
+    /// Attaches `device` to the addresses from `address` on
+    pub fn attach(&mut self, address: u16, device: Box<dyn Device>) -> Result<(), DeviceError> {
+        let end = usize::from(address) + usize::from(device.size());
+        if end > RAM_SIZE {
+            return Err(DeviceError::OutOfRange {
+                device: device.kind().to_string(),
+                address,
+            });
+        }
+        if let Some(other) = self.mappings.iter().find(|mapping| {
+            usize::from(mapping.address) < end && address < mapping.address + mapping.device.size()
+        }) {
+            return Err(DeviceError::Overlap {
+                device: device.kind().to_string(),
+                address,
+                other: format!("{}@{:#06x}", other.device.kind(), other.address),
+            });
+        }
+        self.mappings.push(Mapping { address, device });
+        self.mappings.sort_by_key(|mapping| mapping.address);
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mappings.is_empty()
+    }
+
+    /// Kind of the device at `address`, `None` for RAM addresses
+    pub fn device(&self, address: u16) -> Option<&'static str> {
+        self.mappings
+            .iter()
+            .find(|m| m.contains(address))
+            .map(|mapping| mapping.device.kind())
+    }
+
+    /// Word of the device at `address`, `None` for RAM addresses
+    pub fn read(&mut self, address: u16) -> Option<u32> {
+        let mapping = self.mappings.iter_mut().find(|m| m.contains(address))?;
+        Some(mapping.device.read(address - mapping.address))
+    }
+
+    /// Writes to the device at `address`, returns `false` for RAM addresses
+    pub fn write(&mut self, address: u16, value: u32) -> bool {
+        match self.mappings.iter_mut().find(|m| m.contains(address)) {
+            Some(mapping) => {
+                mapping.device.write(address - mapping.address, value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn tick(&mut self) {
+        for mapping in &mut self.mappings {
+            mapping.device.tick();
+        }
+    }
+}
+
+/// The state of every device below a `KIND@ADDRESS` line
+impl fmt::Display for Bus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for mapping in &self.mappings {
+            writeln!(f, "{}@{:#06x}", mapping.device.kind(), mapping.address)?;
+            write!(f, "{}", mapping.device)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+
+    #[test]
+    fn devices() {
+        let (address, mut timer) = parse_spec("timer@0xff00,divider=2").unwrap();
+        assert_eq!(0xff00, address);
+        for _ in 0..5 {
+            timer.tick();
+        }
+        assert_eq!(2, timer.read(0));
+        timer.write(0, 7);
+        assert_eq!(7, timer.read(0));
+
+        let (_, mut gpio) = parse_spec("gpio@16,input=0b1010").unwrap();
+        gpio.write(0, 5);
+        gpio.write(1, 5);
+        assert_eq!((5, 0b1010), (gpio.read(0), gpio.read(1)));
+
+        let (_, mut display) = parse_spec("seven-segment@0x20,digits=2").unwrap();
+        // 1 with the decimal point and 2
+        display.write(0, 0x86);
+        display.write(1, 0x5b);
+        assert_eq!("       _\n    |  _|\n    |.|_\n", display.to_string());
+    }
+
+    #[test]
+    fn console() {
+        #[derive(Clone, Default)]
+        struct Output(Rc<RefCell<Vec<u8>>>);
+        impl Write for Output {
+            fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+                self.0.borrow_mut().write(bytes)
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        let output = Output::default();
+        let mut console = Console::new(&b"hi"[..], output.clone());
+
+        assert_eq!(1, console.read(1));
+        assert_eq!(u32::from(b'h'), console.read(0));
+        assert_eq!(u32::from(b'i'), console.read(0));
+        assert_eq!(0, console.read(1));
+        assert_eq!(u32::MAX, console.read(0));
+        for byte in b"ok\n" {
+            console.write(0, u32::from(*byte) | 0x100);
+        }
+        assert_eq!(b"ok\n", output.0.borrow().as_slice());
+        assert_eq!(
+            "  read    2 bytes\n  written 3 bytes\n",
+            console.to_string()
+        );
+        assert_eq!(
+            "unknown-option",
+            create("console", &[(String::from("input"), 1)])
+                .unwrap_err()
+                .code()
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let code = |spec: &str| parse_spec(spec).unwrap_err().code();
+        assert_eq!("invalid-device", code("timer"));
+        assert_eq!("invalid-device", code("timer@0x10000"));
+        assert_eq!("unknown-device", code("uart@0x10"));
+        assert_eq!("unknown-option", code("timer@0x10,digits=2"));
+        assert_eq!("invalid-option", code("seven-segment@0x10,digits=9"));
+
+        let mut bus = Bus::new();
+        bus.attach(0x10, create("timer", &[]).unwrap()).unwrap();
+        let overlap = bus.attach(0x11, create("gpio", &[]).unwrap()).unwrap_err();
+        assert_eq!("overlapping-devices", overlap.code());
+        let end = bus.attach(0xfffe, create("seven-segment", &[]).unwrap());
+        assert_eq!("device-out-of-range", end.unwrap_err().code());
+        bus.attach(0x12, create("gpio", &[]).unwrap()).unwrap();
+        assert_eq!(None, bus.read(0x14));
+        assert!(bus.write(0x12, 3));
+        assert_eq!(Some(3), bus.read(0x12));
+    }
+}
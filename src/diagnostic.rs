@@ -0,0 +1,249 @@
+use std::fmt;
+use std::path::Path;
+
+/// Line and, if known, column of the token a diagnostic points at, both
+/// counted from 0
+pub type Position = (u16, Option<u16>);
+
+/// Line `line_number` as shown in diagnostics, which count lines from 1
+/// like editors do
+pub fn display_line(line_number: u16) -> u32 {
+    u32::from(line_number) + 1
+}
+
+/// Severity of a diagnostic, selects the color it is highlighted with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    fn name(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+
+    /// ANSI color of the header and the caret
+    fn color(self) -> &'static str {
+        match self {
+            Severity::Error => "1;31",
+            Severity::Warning => "1;33",
+        }
+    }
+}
+
+/// ANSI color of the line number gutter
+const GUTTER_COLOR: &str = "1;34";
+
+/// Renders diagnostics, highlighted with ANSI colors if `color` is set
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Renderer {
+    pub color: bool,
+}
+
+impl Renderer {
+    /// `label: message` with the label colored after `severity`
+    pub fn header(&self, severity: Severity, label: &str, message: &dyn fmt::Display) -> String {
+        format!(
+            "{} {}",
+            self.paint(&format!("{}:", label), severity.color()),
+            message
+        )
+    }
+
+    /// Renders the line of `source` at `position` with a caret under the
+    /// token starting at its column, e.g.
+    ///
+    /// ```text
+    ///    |
+    /// 12 |     ldc %reg0 0x1 }
+    ///    |                   ^
+    /// ```
+    pub fn snippet(
+        &self,
+        severity: Severity,
+        source: &str,
+        (line_number, column): Position,
+    ) -> Option<String> {
+        let line = source.lines().nth(usize::from(line_number))?;
+        let number = display_line(line_number).to_string();
+        let gutter = self.paint(&format!("{} |", " ".repeat(number.len())), GUTTER_COLOR);
+        let mut snippet = format!(
+            "{}\n{} {}",
+            gutter,
+            self.paint(&format!("{} |", number), GUTTER_COLOR),
+            line
+        );
+        if let Some(column) = column {
+            let (before, token) = token_at(line, column)?;
+            // tabs stay tabs so the caret lines up with the token
+            let padding: String = before
+                .chars()
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect();
+            snippet.push_str(&format!(
+                "\n{} {}{}",
+                gutter,
+                padding,
+                self.paint(&"^".repeat(token.chars().count().max(1)), severity.color())
+            ));
+        }
+        Some(snippet)
+    }
+
+    fn paint(&self, text: &str, color: &str) -> String {
+        if self.color {
+            format!("\x1b[{}m{}\x1b[0m", color, text)
+        } else {
+            String::from(text)
+        }
+    }
+}
+
+/// Plain [`Renderer::snippet`] of an error
+pub fn snippet(source: &str, position: Position) -> Option<String> {
+    Renderer::default().snippet(Severity::Error, source, position)
+}
+
+/// Byte range of the token at `position` in its line of `source`
+pub fn span(source: &str, (line_number, column): Position) -> Option<(usize, usize)> {
+    let line = source.lines().nth(usize::from(line_number))?;
+    let (before, token) = token_at(line, column?)?;
+    Some((before.len(), before.len() + token.len().max(1)))
+}
+
+/// Splits `line` in front of `column`, the token reaches up to the next
+/// whitespace
+fn token_at(line: &str, column: u16) -> Option<(&str, &str)> {
+    let (before, rest) = line.split_at_checked(usize::from(column))?;
+    let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+    Some((before, &rest[..end]))
+}
+
+/// Diagnostic written as JSON by `--diagnostics-format json`
+#[derive(Debug, Clone)]
+pub struct Diagnostic<'a> {
+    pub severity: Severity,
+    pub code: &'a str,
+    pub message: String,
+    pub file: Option<&'a Path>,
+    pub position: Option<Position>,
+}
+
+impl Diagnostic<'_> {
+    /// JSON object on a single line, the span of the token is looked up in
+    /// `source`. Unknown values are `null`.
+    pub fn to_json(&self, source: Option<&str>) -> String {
+        let (line, column) = match self.position {
+            Some((line, column)) => (Some(display_line(line)), column),
+            None => (None, None),
+        };
+        let span = source
+            .zip(self.position)
+            .and_then(|(source, position)| span(source, position))
+            .map(|(start, end)| format!("{{\"start\":{},\"end\":{}}}", start, end));
+        format!(
+            "{{\"severity\":\"{}\",\"code\":{},\"message\":{},\"file\":{},\"line\":{},\"column\":{},\"span\":{}}}",
+            self.severity.name(),
+            json_string(self.code),
+            json_string(&self.message),
+            json_or_null(self.file.map(|file| json_string(&file.display().to_string()))),
+            json_or_null(line),
+            json_or_null(column),
+            json_or_null(span)
+        )
+    }
+}
+
+pub(crate) fn json_string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", u32::from(c))),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn json_or_null(value: Option<impl fmt::Display>) -> String {
+    value.map_or_else(|| String::from("null"), |value| value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn caret() {
+        let source = "main:\n    ldc %reg0 0x1\n\tjr  loop\n";
+
+        assert_eq!(
+            Some(String::from(
+                "  |\n2 |     ldc %reg0 0x1\n  |         ^^^^^"
+            )),
+            snippet(source, (1, Some(8)))
+        );
+        assert_eq!(
+            Some(String::from("  |\n3 | \tjr  loop\n  | \t    ^^^^")),
+            snippet(source, (2, Some(5)))
+        );
+        assert_eq!(
+            Some(String::from("  |\n1 | main:")),
+            snippet(source, (0, None))
+        );
+        assert_eq!(None, snippet(source, (3, None)));
+    }
+
+    #[test]
+    fn colors() {
+        let renderer = Renderer { color: true };
+
+        assert_eq!(
+            "\x1b[1;33mWarning:\x1b[0m unused",
+            renderer.header(Severity::Warning, "Warning", &"unused")
+        );
+        assert_eq!(
+            Some(String::from(
+                "\x1b[1;34m  |\x1b[0m\n\x1b[1;34m1 |\x1b[0m     hlt\n\x1b[1;34m  |\x1b[0m     \x1b[1;31m^^^\x1b[0m"
+            )),
+            renderer.snippet(Severity::Error, "    hlt", (0, Some(4)))
+        );
+    }
+
+    #[test]
+    fn json() {
+        let diagnostic = Diagnostic {
+            severity: Severity::Error,
+            code: "unknown-command",
+            message: String::from("Unknown command: 'foo' at line 2, column 4"),
+            file: Some(Path::new("dir/main.s")),
+            position: Some((1, Some(4))),
+        };
+
+        assert_eq!(
+            r#"{"severity":"error","code":"unknown-command","message":"Unknown command: 'foo' at line 2, column 4","file":"dir/main.s","line":2,"column":4,"span":{"start":4,"end":7}}"#,
+            diagnostic.to_json(Some("main:\n    foo %reg0\n"))
+        );
+        let diagnostic = Diagnostic {
+            severity: Severity::Warning,
+            code: "unused-label",
+            message: String::from("Label \"a\\b\"\n"),
+            file: None,
+            position: None,
+        };
+        assert_eq!(
+            r#"{"severity":"warning","code":"unused-label","message":"Label \"a\\b\"\n","file":null,"line":null,"column":null,"span":null}"#,
+            diagnostic.to_json(None)
+        );
+    }
+}
@@ -0,0 +1,287 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::{generator::InstructionWord, ir};
+
+/// Bits of the word holding each field, see [`InstructionWord`]
+const OPCODE: u32 = 0x000ff;
+const TARGET: u32 = 0xe0000;
+const OP_A: u32 = 0x00700;
+const OP_B: u32 = 0x03800;
+const OP_C: u32 = 0x1c000;
+const CONSTANT12: u32 = 0xfff00;
+
+/// Name of the label reconstructed at the entry of the image
+pub const ENTRY_LABEL: &str = "main";
+
+fn register(raw: u32, field: u32) -> ir::Register {
+    ir::Register::new(ir::RegisterAddress(
+        ((raw & field) >> field.trailing_zeros()) as u8,
+    ))
+}
+
+fn binary(raw: u32) -> ir::BinaryExpression {
+    ir::BinaryExpression::new(
+        register(raw, TARGET),
+        register(raw, OP_A),
+        register(raw, OP_B),
+    )
+}
+
+fn unary(raw: u32) -> ir::UnaryExpression {
+    ir::UnaryExpression::new(register(raw, TARGET), register(raw, OP_A))
+}
+
+/// Condition of the jump opcodes, the inverse of the generator's
+/// condition codes
+fn condition(code: u32) -> Option<ir::JumpCondition> {
+    match code {
+        0 => Some(ir::JumpCondition::True),
+        1 => Some(ir::JumpCondition::Zero),
+        2 => Some(ir::JumpCondition::NotZero),
+        3 => Some(ir::JumpCondition::Less),
+        4 => Some(ir::JumpCondition::Overflow),
+        _ => None,
+    }
+}
+
+/// Instruction the generator encodes as `word`. Words with an unknown
+/// opcode or with bits set outside of the fields of their opcode are no
+/// instruction and return `None`. Relative jumps keep their offset from
+/// the jump as [`ir::JumpTarget::Constant`].
+pub fn decode(word: &InstructionWord) -> Option<ir::Instruction> {
+    let raw = word.raw();
+    // `ldc` is the only instruction with bit 7 set and uses all other bits
+    if raw & 0x80 != 0 {
+        return Some(ir::Instruction::Load {
+            address: ir::RegisterAddress(((raw >> 4) & 0x7) as u8),
+            source: ir::LoadSource::Constant(((raw >> 4) & 0xfff0 | raw & 0xf) as u16),
+        });
+    }
+    let (instruction, fields) = match raw & OPCODE {
+        0x00 => (ir::Instruction::Add(binary(raw)), TARGET | OP_A | OP_B),
+        0x01 => (
+            ir::Instruction::Add3(ir::TernaryExpression::new(
+                register(raw, TARGET),
+                register(raw, OP_A),
+                register(raw, OP_B),
+                register(raw, OP_C),
+            )),
+            TARGET | OP_A | OP_B | OP_C,
+        ),
+        0x02 => (
+            ir::Instruction::AddWithCarry(binary(raw)),
+            TARGET | OP_A | OP_B,
+        ),
+        0x03 => (ir::Instruction::Subtract(binary(raw)), TARGET | OP_A | OP_B),
+        0x04 => (
+            ir::Instruction::SubtractWithCarry(binary(raw)),
+            TARGET | OP_A | OP_B,
+        ),
+        // `inc` and `dec` have a single operand in both fields
+        0x05 | 0x06 if register(raw, TARGET) != register(raw, OP_A) => return None,
+        0x05 => (ir::Instruction::Increment(unary(raw)), TARGET | OP_A),
+        0x06 => (ir::Instruction::Decrement(unary(raw)), TARGET | OP_A),
+        0x07 => (ir::Instruction::Multiply(binary(raw)), TARGET | OP_A | OP_B),
+        0x08 => (
+            ir::Instruction::Test(ir::BinaryStatement::new(
+                register(raw, OP_A),
+                register(raw, OP_B),
+            )),
+            OP_A | OP_B,
+        ),
+        0x09 => (ir::Instruction::AND(binary(raw)), TARGET | OP_A | OP_B),
+        0x0a => (ir::Instruction::OR(binary(raw)), TARGET | OP_A | OP_B),
+        0x0b => (ir::Instruction::NOT(unary(raw)), TARGET | OP_A),
+        0x0c => (ir::Instruction::Negate(unary(raw)), TARGET | OP_A),
+        0x0d => (ir::Instruction::XOR(binary(raw)), TARGET | OP_A | OP_B),
+        0x0e => (ir::Instruction::XNOR(binary(raw)), TARGET | OP_A | OP_B),
+        0x0f => (
+            ir::Instruction::ShiftLeft(binary(raw)),
+            TARGET | OP_A | OP_B,
+        ),
+        0x10 => (
+            ir::Instruction::ShiftRight(binary(raw)),
+            TARGET | OP_A | OP_B,
+        ),
+        0x48 => (ir::Instruction::Move(unary(raw)), TARGET | OP_A),
+        0x4a => {
+            let enable = match raw & CONSTANT12 {
+                0x00000 => false,
+                0x0ff00 => true,
+                _ => return None,
+            };
+            let enable = ir::Boolean(enable);
+            (ir::Instruction::Set32BitMode { enable }, CONSTANT12)
+        }
+        opcode @ 0x50..=0x54 => (
+            ir::Instruction::Jump {
+                target: ir::JumpTarget::Register(register(raw, OP_A)),
+                condition: condition(opcode - 0x50)?,
+            },
+            OP_A,
+        ),
+        opcode @ 0x58..=0x5c => {
+            // 12 bit offset relative to the next instruction
+            let offset = ((raw << 12) as i32 >> 20) as i16;
+            (
+                ir::Instruction::Jump {
+                    target: ir::JumpTarget::Constant(offset.wrapping_add(1) as u16),
+                    condition: condition(opcode - 0x58)?,
+                },
+                CONSTANT12,
+            )
+        }
+        0x68 => (
+            ir::Instruction::StoreRAM {
+                address_register: register(raw, OP_B).address,
+                data_register: register(raw, OP_A).address,
+            },
+            OP_A | OP_B,
+        ),
+        0x69 => (
+            ir::Instruction::Load {
+                address: register(raw, TARGET).address,
+                source: ir::LoadSource::RAM {
+                    address_register: register(raw, OP_B),
+                },
+            },
+            TARGET | OP_B,
+        ),
+        0x6c => (ir::Instruction::Noop, 0),
+        0x7e => (ir::Instruction::Debug, 0),
+        0x7f => (ir::Instruction::Halt, 0),
+        _ => return None,
+    };
+    if raw & !(OPCODE | fields) != 0 {
+        return None;
+    }
+    Some(instruction)
+}
+
+/// Decoded image, printed as masm listing with the address and the word
+/// of every instruction in a comment
+pub struct Disassembly {
+    /// address and word of the image with the instruction decoded from it
+    pub words: Vec<(u16, InstructionWord, Option<ir::Instruction>)>,
+    /// names of the labels at addresses, at least [`ENTRY_LABEL`] at 0
+    pub labels: BTreeMap<u16, String>,
+}
+
+/// Decodes every word of `image`. With `labels`, the targets of relative
+/// jumps inside of the image get a label `label_XXXX` named after their
+/// address, which the jumps refer to.
+pub fn disassemble(image: &[InstructionWord], labels: bool) -> Disassembly {
+    let mut words: Vec<(u16, InstructionWord, Option<ir::Instruction>)> = (0..=u16::MAX)
+        .zip(image)
+        .map(|(address, word)| (address, word.clone(), decode(word)))
+        .collect();
+    let mut names = BTreeMap::from([(0, String::from(ENTRY_LABEL))]);
+    if labels {
+        for (address, _, instruction) in words.iter_mut() {
+            let Some(ir::Instruction::Jump { target, .. }) = instruction else {
+                continue;
+            };
+            let ir::JumpTarget::Constant(offset) = *target else {
+                continue;
+            };
+            let destination = address.wrapping_add(offset);
+            if usize::from(destination) >= image.len() {
+                continue;
+            }
+            let name = names
+                .entry(destination)
+                .or_insert_with(|| format!("label_{:04x}", destination));
+            *target = ir::JumpTarget::Label(ir::LabelReference::new(name.as_str()));
+        }
+    }
+    Disassembly {
+        words,
+        labels: names,
+    }
+}
+
+/// Listing that assembles back to the image, except for words which are no
+/// instruction and do not fit into `.word`
+impl fmt::Display for Disassembly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (address, word, instruction) in &self.words {
+            if let Some(label) = self.labels.get(address) {
+                writeln!(f, "{}:", label)?;
+            }
+            let text = match instruction {
+                Some(instruction) => instruction.to_string(),
+                None if word.raw() <= 0xffff => format!(".word {:#x}", word.raw()),
+                None => String::from("; no instruction"),
+            };
+            writeln!(f, "    {:<28} ; {:#06x}  {}", text, address, word)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_all_instructions() {
+        let source = std::fs::read_to_string("tests/all_instructions.s").unwrap();
+        let image = crate::assemble_str(&source).unwrap();
+
+        for word in &image {
+            let instruction = decode(word).unwrap_or_else(|| panic!("{:?} not decoded", word));
+            let program = format!("main:\n    {}\n", instruction);
+            let found = crate::assemble_str(&program).unwrap();
+            assert_eq!(word, &found[0], "{}", instruction);
+        }
+        assert_eq!(None, decode(&InstructionWord::from_raw(0x0006c | 0x00100)));
+        assert_eq!(None, decode(&InstructionWord::from_raw(0x00305)));
+        assert_eq!(None, decode(&InstructionWord::from_raw(0x0007d)));
+    }
+
+    #[test]
+    fn listing_with_labels() {
+        let image = crate::assemble_str(
+            "main:
+    ldc %reg0 0x3
+loop:
+    dec %reg0
+    jnzr loop
+    jr end
+    .word 0x1234
+end:
+    hlt
+",
+        )
+        .unwrap();
+
+        let listing = disassemble(&image, true).to_string();
+
+        assert_eq!(
+            "main:
+    ldc %reg0 0x3                ; 0x0000  00083
+label_0001:
+    dec %reg0                    ; 0x0001  00006
+    jnzr label_0001              ; 0x0002  ffe5a
+    jr label_0005                ; 0x0003  00158
+    .word 0x1234                 ; 0x0004  01234
+label_0005:
+    hlt                          ; 0x0005  0007f
+",
+            listing
+        );
+        assert_eq!(image, crate::assemble_str(&listing).unwrap());
+        let plain = disassemble(&image, false).to_string();
+        assert!(plain.contains("    jnzr -0x1 "));
+        assert_eq!(image, crate::assemble_str(&plain).unwrap());
+
+        // the last word of the address space
+        let image = vec![InstructionWord::from_raw(0x6c); 0x10000];
+        let disassembly = disassemble(&image, true);
+        assert_eq!(
+            Some(&0xffff),
+            disassembly.words.last().map(|(address, ..)| address)
+        );
+    }
+}
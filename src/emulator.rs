@@ -0,0 +1,1002 @@
+use std::{collections::VecDeque, fmt, ops::Range};
+
+use crate::{device::Bus, disassembler, generator::InstructionWord, ir, recording::Recording};
+
+/// Number of words of the data RAM, addressed by the lower 16 bits of the
+/// address register
+pub const RAM_SIZE: usize = 0x10000;
+
+/// Errors stopping the emulation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EmulatorError {
+    /// The word at `address` is no instruction, e.g. data run into
+    InvalidInstruction { address: u16, word: InstructionWord },
+    /// The program counter left the program memory
+    OutOfProgram { address: u16 },
+    /// Pseudo-instructions and label operands have to be lowered and
+    /// resolved before they can be executed
+    Unresolved { instruction: String },
+    /// [`Emulator::run`] did not halt within its cycle limit
+    CycleLimit { cycles: u64 },
+    /// The replayed recording has no value for the device read with the
+    /// index `read`
+    ReplayDiverged { address: u16, read: usize },
+}
+
+impl fmt::Display for EmulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmulatorError::InvalidInstruction { address, word } => write!(
+                f,
+                "Word {} at address {:#06x} is no instruction",
+                word, address
+            ),
+            EmulatorError::OutOfProgram { address } => write!(
+                f,
+                "Program counter {:#06x} is outside of the program",
+                address
+            ),
+            EmulatorError::Unresolved { instruction } => {
+                write!(f, "Instruction '{}' can not be executed", instruction)
+            }
+            EmulatorError::CycleLimit { cycles } => {
+                write!(f, "Program did not halt within {} cycles", cycles)
+            }
+            EmulatorError::ReplayDiverged { address, read } => write!(
+                f,
+                "Read {} from device address {:#06x} is not in the replayed recording",
+                read, address
+            ),
+        }
+    }
+}
+
+impl std::error::Error for EmulatorError {}
+
+impl EmulatorError {
+    /// Stable name of the error kind, e.g. for machine readable diagnostics
+    pub fn code(&self) -> &'static str {
+        match self {
+            EmulatorError::InvalidInstruction { .. } => "invalid-instruction",
+            EmulatorError::OutOfProgram { .. } => "out-of-program",
+            EmulatorError::Unresolved { .. } => "unresolved-instruction",
+            EmulatorError::CycleLimit { .. } => "cycle-limit",
+            EmulatorError::ReplayDiverged { .. } => "replay-diverged",
+        }
+    }
+}
+
+/// Status flags, set by the arithmetic and logic instructions and `tst`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Flags {
+    /// result is zero, for `tst` both operands are equal
+    pub zero: bool,
+    /// unsigned carry or borrow, for `tst` the first operand is less
+    /// than the second, tested by `jc`
+    pub carry: bool,
+    /// signed overflow, tested by `jo`
+    pub overflow: bool,
+}
+
+/// What the executed instruction asks the caller to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    Continue,
+    /// `dbg` was executed
+    Debug,
+    /// `hlt` was executed, the program counter stays at the `hlt`
+    Halt,
+}
+
+/// Effects of an instruction executed by [`Emulator::step_traced`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub address: u16,
+    pub word: InstructionWord,
+    pub instruction: ir::Instruction,
+    /// written registers in the order of the writes with their new value
+    pub registers: Vec<(u8, u32)>,
+    /// address and value of a `ld` from RAM or a device
+    pub load: Option<(u16, u32)>,
+    /// address and value of a `st`, RAM or device
+    pub store: Option<(u16, u32)>,
+    /// kind of the device reached by `load` or `store`, `None` for RAM
+    pub device: Option<&'static str>,
+    /// flags after the instruction if it changed them
+    pub flags: Option<Flags>,
+    /// values are 32 bits wide
+    pub wide_mode: bool,
+}
+
+/// One line with the address, word and disassembly of the instruction
+/// followed by its effects, e.g.
+/// `0x0001  20106  dec %reg1                %reg1=0x0002 z=0 c=0 o=0`
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = if self.wide_mode { 10 } else { 6 };
+        let mut line = format!(
+            "{:#06x}  {}  {:<24}",
+            self.address,
+            self.word,
+            self.instruction.to_string()
+        );
+        for (register, value) in &self.registers {
+            line += &format!(" %reg{}={:#0width$x}", register, value, width = width);
+        }
+        if let Some((address, value)) = self.store {
+            line += &format!(
+                " {}[{:#06x}]={:#0width$x}",
+                self.device.unwrap_or("ram"),
+                address,
+                value,
+                width = width
+            );
+        }
+        if let Some(flags) = self.flags {
+            line += &format!(
+                " z={} c={} o={}",
+                u8::from(flags.zero),
+                u8::from(flags.carry),
+                u8::from(flags.overflow)
+            );
+        }
+        write!(f, "{}", line.trim_end())
+    }
+}
+
+/// State an instruction changed, restored by [`Emulator::step_back`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Delta {
+    pc: u16,
+    flags: Flags,
+    wide_mode: bool,
+    /// old values of the written registers
+    registers: Vec<(u8, u32)>,
+    /// address and old value of the RAM word written by `st`
+    ram: Option<(u16, u32)>,
+    /// `ld` read a device
+    device_read: bool,
+}
+
+/// CPU executing an assembled image. Every instruction takes a single
+/// cycle. Registers are 16 bits wide and 32 bits after `s32b true`, results
+/// are truncated to the width of the current mode. `ldc` loads a zero
+/// extended 16 bit constant.
+#[derive(Debug)]
+pub struct Emulator {
+    program: Vec<InstructionWord>,
+    pub registers: [u32; ir::REGISTER_COUNT as usize],
+    pub flags: Flags,
+    pub pc: u16,
+    pub ram: Vec<u32>,
+    /// devices answering `ld` and `st` instead of the RAM
+    pub bus: Bus,
+    /// `s32b true` is active
+    pub wide_mode: bool,
+    /// number of executed instructions
+    pub cycles: u64,
+    pub halted: bool,
+    /// number of instructions [`Emulator::step_back`] can undo, none are
+    /// recorded by default
+    pub history_size: usize,
+    history: VecDeque<Delta>,
+    /// device reads since [`Emulator::record`]
+    recorded: Option<Recording>,
+    /// recording answering the device reads, see [`Emulator::replay`]
+    replayed: Option<Recording>,
+    /// registers written by the last instruction, for its [`TraceEntry`]
+    writes: Vec<(u8, u32)>,
+    loaded: Option<(u16, u32)>,
+    stored: Option<(u16, u32)>,
+    accessed: Option<&'static str>,
+    overwritten: Option<(u16, u32)>,
+}
+
+impl Emulator {
+    /// Emulator with cleared registers and RAM starting the program at
+    /// address 0
+    pub fn new(program: Vec<InstructionWord>) -> Self {
+        Emulator {
+            program,
+            registers: [0; ir::REGISTER_COUNT as usize],
+            flags: Flags::default(),
+            pc: 0,
+            ram: vec![0; RAM_SIZE],
+            bus: Bus::new(),
+            wide_mode: false,
+            cycles: 0,
+            halted: false,
+            history_size: 0,
+            history: VecDeque::new(),
+            recorded: None,
+            replayed: None,
+            writes: Vec::new(),
+            loaded: None,
+            stored: None,
+            accessed: None,
+            overwritten: None,
+        }
+    }
+
+    pub fn program(&self) -> &[InstructionWord] {
+        &self.program
+    }
+
+    /// Swaps the program memory, e.g. for a re-assembled program. The
+    /// registers, flags, RAM and program counter keep their values.
+    pub fn load(&mut self, program: Vec<InstructionWord>) {
+        self.program = program;
+        self.history.clear();
+    }
+
+    /// Value of register `address` within the width of the current mode
+    pub fn register(&self, address: u8) -> u32 {
+        self.registers[usize::from(address)] & self.mask()
+    }
+
+    fn set_register(&mut self, register: ir::RegisterAddress, value: u32) {
+        self.registers[usize::from(register.0)] = value & self.mask();
+        self.writes.push((register.0, value & self.mask()));
+    }
+
+    fn mask(&self) -> u32 {
+        if self.wide_mode {
+            0xffff_ffff
+        } else {
+            0xffff
+        }
+    }
+
+    fn width(&self) -> u32 {
+        if self.wide_mode {
+            32
+        } else {
+            16
+        }
+    }
+
+    /// Decodes and executes the instruction at the program counter. Once
+    /// halted, every step returns [`Event::Halt`] without executing anything.
+    pub fn step(&mut self) -> Result<Event, EmulatorError> {
+        self.step_traced().map(|(event, _)| event)
+    }
+
+    /// Steps like [`Emulator::step`] and returns the effects of the executed
+    /// instruction, which are `None` once halted
+    pub fn step_traced(&mut self) -> Result<(Event, Option<TraceEntry>), EmulatorError> {
+        if self.halted {
+            return Ok((Event::Halt, None));
+        }
+        let address = self.pc;
+        let word = self
+            .program
+            .get(usize::from(address))
+            .ok_or(EmulatorError::OutOfProgram { address })?
+            .clone();
+        let instruction = disassembler::decode(&word).ok_or(EmulatorError::InvalidInstruction {
+            address,
+            word: word.clone(),
+        })?;
+        let flags = self.flags;
+        let (registers, wide_mode) = (self.registers, self.wide_mode);
+        let event = self.execute(&instruction)?;
+        if self.history_size > 0 {
+            if self.history.len() >= self.history_size {
+                self.history.pop_front();
+            }
+            self.history.push_back(Delta {
+                pc: address,
+                flags,
+                wide_mode,
+                registers: self
+                    .writes
+                    .iter()
+                    .map(|&(register, _)| (register, registers[usize::from(register)]))
+                    .collect(),
+                ram: self.overwritten.take(),
+                device_read: self.loaded.is_some() && self.accessed.is_some(),
+            });
+        }
+        let entry = TraceEntry {
+            address,
+            word,
+            instruction,
+            registers: std::mem::take(&mut self.writes),
+            load: self.loaded.take(),
+            store: self.stored.take(),
+            device: self.accessed.take(),
+            flags: Some(self.flags).filter(|&new| new != flags),
+            wide_mode: self.wide_mode,
+        };
+        Ok((event, Some(entry)))
+    }
+
+    /// Undoes the last instruction recorded in the history of
+    /// [`Emulator::history_size`] instructions, returns `false` once the
+    /// history is empty. Devices keep their state.
+    pub fn step_back(&mut self) -> bool {
+        let Some(delta) = self.history.pop_back() else {
+            return false;
+        };
+        for (register, value) in delta.registers.into_iter().rev() {
+            self.registers[usize::from(register)] = value;
+        }
+        if let Some((address, value)) = delta.ram {
+            self.ram[usize::from(address)] = value;
+        }
+        if delta.device_read {
+            if let Some(recorded) = &mut self.recorded {
+                recorded.reads.pop();
+            }
+            if let Some(replayed) = &mut self.replayed {
+                replayed.rewind();
+            }
+        }
+        self.pc = delta.pc;
+        self.flags = delta.flags;
+        self.wide_mode = delta.wide_mode;
+        self.cycles -= 1;
+        self.halted = false;
+        true
+    }
+
+    /// Records the value of every device read from now on
+    pub fn record(&mut self) {
+        self.recorded = Some(Recording::default());
+    }
+
+    /// Device reads recorded since [`Emulator::record`]
+    pub fn recording(&self) -> Option<&Recording> {
+        self.recorded.as_ref()
+    }
+
+    /// Answers the device reads from now on with the values of `recording`
+    /// instead of asking the devices, a read which is not recorded next is
+    /// an [`EmulatorError::ReplayDiverged`]
+    pub fn replay(&mut self, recording: Recording) {
+        self.replayed = Some(recording);
+    }
+
+    /// Steps until the program halts, at most `max_cycles` times. `dbg`
+    /// does not stop the run.
+    pub fn run(&mut self, max_cycles: u64) -> Result<(), EmulatorError> {
+        self.run_traced(max_cycles, |_, _| {})
+    }
+
+    /// Runs like [`Emulator::run`] and calls `hook` after every cycle with
+    /// the new state and the effects of the executed instruction
+    pub fn run_traced(
+        &mut self,
+        max_cycles: u64,
+        mut hook: impl FnMut(&Emulator, &TraceEntry),
+    ) -> Result<(), EmulatorError> {
+        for _ in 0..max_cycles {
+            let (event, entry) = self.step_traced()?;
+            if let Some(entry) = entry {
+                hook(self, &entry);
+            }
+            if event == Event::Halt {
+                return Ok(());
+            }
+        }
+        Err(EmulatorError::CycleLimit { cycles: max_cycles })
+    }
+
+    /// Executes `instruction` as if it was stored at the program counter.
+    /// Only machine instructions with constant jump targets can be executed,
+    /// see [`crate::lowering`].
+    pub fn execute(&mut self, instruction: &ir::Instruction) -> Result<Event, EmulatorError> {
+        let mut next = self.pc.wrapping_add(1);
+        let mut event = Event::Continue;
+        self.writes.clear();
+        self.loaded = None;
+        self.stored = None;
+        self.accessed = None;
+        self.overwritten = None;
+        match instruction {
+            ir::Instruction::Move(e) => self.set_register(e.target.address, self.read(e.source_a)),
+            ir::Instruction::Set32BitMode { enable } => self.wide_mode = enable.0,
+            ir::Instruction::Load {
+                address,
+                source: ir::LoadSource::Constant(value),
+            } => self.set_register(*address, u32::from(*value)),
+            ir::Instruction::Load {
+                address,
+                source: ir::LoadSource::RAM { address_register },
+            } => {
+                let index = self.ram_address(*address_register);
+                let bus_address = index as u16;
+                let value = match (&mut self.replayed, self.bus.device(bus_address)) {
+                    (Some(replayed), Some(_)) => {
+                        replayed
+                            .next(bus_address)
+                            .ok_or(EmulatorError::ReplayDiverged {
+                                address: bus_address,
+                                read: replayed.replayed(),
+                            })?
+                    }
+                    _ => match self.bus.read(bus_address) {
+                        Some(value) => {
+                            if let Some(recorded) = &mut self.recorded {
+                                recorded.reads.push((bus_address, value));
+                            }
+                            value
+                        }
+                        None => self.ram[index],
+                    },
+                };
+                self.loaded = Some((bus_address, value));
+                self.accessed = self.bus.device(bus_address);
+                self.set_register(*address, value);
+            }
+            ir::Instruction::StoreRAM {
+                address_register,
+                data_register,
+            } => {
+                let index = self.ram_address(ir::Register::new(*address_register));
+                let value = self.register(data_register.0);
+                if !self.bus.write(index as u16, value) {
+                    self.overwritten = Some((index as u16, self.ram[index]));
+                    self.ram[index] = value;
+                }
+                self.stored = Some((index as u16, value));
+                self.accessed = self.bus.device(index as u16);
+            }
+            ir::Instruction::Halt => {
+                self.halted = true;
+                next = self.pc;
+                event = Event::Halt;
+            }
+            ir::Instruction::Debug => event = Event::Debug,
+            ir::Instruction::Noop => {}
+            ir::Instruction::Jump { target, condition } => {
+                let taken = match condition {
+                    ir::JumpCondition::True => true,
+                    ir::JumpCondition::Zero => self.flags.zero,
+                    ir::JumpCondition::NotZero => !self.flags.zero,
+                    ir::JumpCondition::Less => self.flags.carry,
+                    ir::JumpCondition::Overflow => self.flags.overflow,
+                    _ => return Err(self.unresolved(instruction)),
+                };
+                let destination = match target {
+                    ir::JumpTarget::Register(register) => self.read(*register) as u16,
+                    ir::JumpTarget::Constant(offset) => self.pc.wrapping_add(*offset),
+                    _ => return Err(self.unresolved(instruction)),
+                };
+                if taken {
+                    next = destination;
+                }
+            }
+            ir::Instruction::Add(e) => {
+                let result = self.add(self.read(e.source_a), self.read(e.source_b), 0);
+                self.set_register(e.target.address, result);
+            }
+            ir::Instruction::Add3(e) => {
+                let sum = u64::from(self.read(e.source_a))
+                    + u64::from(self.read(e.source_b))
+                    + u64::from(self.read(e.source_c));
+                let result = sum as u32 & self.mask();
+                self.flags = Flags {
+                    zero: result == 0,
+                    carry: sum > u64::from(self.mask()),
+                    overflow: false,
+                };
+                self.set_register(e.target.address, result);
+            }
+            ir::Instruction::AddWithCarry(e) => {
+                let carry = u32::from(self.flags.carry);
+                let result = self.add(self.read(e.source_a), self.read(e.source_b), carry);
+                self.set_register(e.target.address, result);
+            }
+            ir::Instruction::Subtract(e) => {
+                let result = self.subtract(self.read(e.source_a), self.read(e.source_b), 0);
+                self.set_register(e.target.address, result);
+            }
+            ir::Instruction::SubtractWithCarry(e) => {
+                let borrow = u32::from(self.flags.carry);
+                let result = self.subtract(self.read(e.source_a), self.read(e.source_b), borrow);
+                self.set_register(e.target.address, result);
+            }
+            ir::Instruction::Increment(e) => {
+                let result = self.add(self.read(e.source_a), 1, 0);
+                self.set_register(e.target.address, result);
+            }
+            ir::Instruction::Decrement(e) => {
+                let result = self.subtract(self.read(e.source_a), 1, 0);
+                self.set_register(e.target.address, result);
+            }
+            ir::Instruction::Multiply(e) => {
+                let product = u64::from(self.read(e.source_a)) * u64::from(self.read(e.source_b));
+                let result = product as u32 & self.mask();
+                let carry = product > u64::from(self.mask());
+                self.flags = Flags {
+                    zero: result == 0,
+                    carry,
+                    overflow: carry,
+                };
+                self.set_register(e.target.address, result);
+            }
+            ir::Instruction::Test(s) => {
+                self.subtract(self.read(s.source_a), self.read(s.source_b), 0);
+            }
+            ir::Instruction::AND(e) => self.logic(e, |a, b| a & b),
+            ir::Instruction::OR(e) => self.logic(e, |a, b| a | b),
+            ir::Instruction::XOR(e) => self.logic(e, |a, b| a ^ b),
+            ir::Instruction::XNOR(e) => self.logic(e, |a, b| !(a ^ b)),
+            ir::Instruction::ShiftLeft(e) => self.logic(e, |a, b| a.checked_shl(b).unwrap_or(0)),
+            ir::Instruction::ShiftRight(e) => self.logic(e, |a, b| a.checked_shr(b).unwrap_or(0)),
+            ir::Instruction::NOT(e) => {
+                let expression = ir::BinaryExpression::new(e.target, e.source_a, e.source_a);
+                self.logic(&expression, |a, _| !a);
+            }
+            ir::Instruction::Negate(e) => {
+                let result = self.subtract(0, self.read(e.source_a), 0);
+                self.set_register(e.target.address, result);
+            }
+            ir::Instruction::Load {
+                source: ir::LoadSource::Expression(_) | ir::LoadSource::Pgm,
+                ..
+            }
+            | ir::Instruction::Data(_)
+            | ir::Instruction::LongJump { .. }
+            | ir::Instruction::LoadImmediate { .. }
+            | ir::Instruction::Immediate { .. }
+            | ir::Instruction::Rotate { .. }
+            | ir::Instruction::Divide { .. }
+            | ir::Instruction::Modulo(_) => return Err(self.unresolved(instruction)),
+        }
+        self.pc = next;
+        self.cycles += 1;
+        self.bus.tick();
+        Ok(event)
+    }
+
+    fn read(&self, register: ir::Register) -> u32 {
+        self.register(register.address.0)
+    }
+
+    fn ram_address(&self, register: ir::Register) -> usize {
+        (self.read(register) as usize) % RAM_SIZE
+    }
+
+    fn unresolved(&self, instruction: &ir::Instruction) -> EmulatorError {
+        EmulatorError::Unresolved {
+            instruction: instruction.to_string(),
+        }
+    }
+
+    /// `a + b + carry` setting all flags
+    fn add(&mut self, a: u32, b: u32, carry: u32) -> u32 {
+        let sum = u64::from(a) + u64::from(b) + u64::from(carry);
+        let result = sum as u32 & self.mask();
+        let sign = 1 << (self.width() - 1);
+        self.flags = Flags {
+            zero: result == 0,
+            carry: sum > u64::from(self.mask()),
+            overflow: (a ^ result) & (b ^ result) & sign != 0,
+        };
+        result
+    }
+
+    /// `a - b - borrow` setting all flags, the carry is the borrow
+    fn subtract(&mut self, a: u32, b: u32, borrow: u32) -> u32 {
+        let result = a.wrapping_sub(b).wrapping_sub(borrow) & self.mask();
+        let sign = 1 << (self.width() - 1);
+        self.flags = Flags {
+            zero: result == 0,
+            carry: u64::from(a) < u64::from(b) + u64::from(borrow),
+            overflow: (a ^ b) & (a ^ result) & sign != 0,
+        };
+        result
+    }
+
+    /// Bitwise operations and shifts only set the zero flag
+    fn logic(&mut self, e: &ir::BinaryExpression, operation: fn(u32, u32) -> u32) {
+        let result = operation(self.read(e.source_a), self.read(e.source_b)) & self.mask();
+        self.flags = Flags {
+            zero: result == 0,
+            ..Flags::default()
+        };
+        self.set_register(e.target.address, result);
+    }
+}
+
+/// State of the CPU: program counter, cycle count, flags, the registers in
+/// the width of the current mode, every RAM word which is not zero and the
+/// attached devices
+impl Emulator {
+    /// Serializes the registers within the width of the current mode, the
+    /// flags and the RAM words at the addresses in `ram` as JSON, e.g.
+    /// ```json
+    /// {
+    ///   "pc": 6,
+    ///   "cycles": 7,
+    ///   "halted": true,
+    ///   "wide_mode": false,
+    ///   "flags": { "zero": false, "carry": true, "overflow": false },
+    ///   "registers": [0, 65535, 1, 0, 1, 65535, 0, 0],
+    ///   "ram": { "start": 32, "words": [5, 0] }
+    /// }
+    /// ```
+    pub fn to_json(&self, ram: Range<u16>) -> String {
+        let registers: Vec<String> = (0..ir::REGISTER_COUNT)
+            .map(|address| self.register(address).to_string())
+            .collect();
+        let words: Vec<String> = self.ram[usize::from(ram.start)..usize::from(ram.end)]
+            .iter()
+            .map(|word| word.to_string())
+            .collect();
+        format!(
+            "{{
+  \"pc\": {},
+  \"cycles\": {},
+  \"halted\": {},
+  \"wide_mode\": {},
+  \"flags\": {{ \"zero\": {}, \"carry\": {}, \"overflow\": {} }},
+  \"registers\": [{}],
+  \"ram\": {{ \"start\": {}, \"words\": [{}] }}
+}}
+",
+            self.pc,
+            self.cycles,
+            self.halted,
+            self.wide_mode,
+            self.flags.zero,
+            self.flags.carry,
+            self.flags.overflow,
+            registers.join(", "),
+            ram.start,
+            words.join(", ")
+        )
+    }
+}
+
+impl fmt::Display for Emulator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let digits = if self.wide_mode { 8 } else { 4 };
+        writeln!(f, "pc      {:#06x}", self.pc)?;
+        writeln!(f, "cycles  {}", self.cycles)?;
+        writeln!(
+            f,
+            "flags   zero={} carry={} overflow={}",
+            u8::from(self.flags.zero),
+            u8::from(self.flags.carry),
+            u8::from(self.flags.overflow)
+        )?;
+        for address in 0..ir::REGISTER_COUNT {
+            let value = self.register(address);
+            writeln!(
+                f,
+                "%reg{}   {:#0width$x}",
+                address,
+                value,
+                width = digits + 2
+            )?;
+        }
+        for (address, value) in self.ram.iter().enumerate().filter(|(_, &v)| v != 0) {
+            writeln!(
+                f,
+                "ram[{:#06x}] {:#0width$x}",
+                address,
+                value,
+                width = digits + 2
+            )?;
+        }
+        write!(f, "{}", self.bus)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(source: &str) -> Emulator {
+        let mut emulator = Emulator::new(crate::assemble_str(source).unwrap());
+        emulator.run(10_000).unwrap();
+        emulator
+    }
+
+    #[test]
+    fn arithmetic_and_flags() {
+        let emulator = run("main:
+    ldc %reg1 0xffff
+    ldc %reg2 0x1
+    add %reg3 %reg1 %reg2
+    addc %reg4 %reg0 %reg0
+    sub %reg5 %reg0 %reg2
+    tst %reg2 %reg1
+    hlt
+");
+        assert_eq!(0, emulator.register(3));
+        assert_eq!(1, emulator.register(4));
+        assert_eq!(0xffff, emulator.register(5));
+        assert_eq!(
+            Flags {
+                zero: false,
+                carry: true,
+                overflow: false,
+            },
+            emulator.flags
+        );
+        assert_eq!(7, emulator.cycles);
+        assert_eq!(6, emulator.pc);
+        assert!(emulator.halted);
+    }
+
+    #[test]
+    fn json_dump() {
+        let emulator = run("main:
+    ldc %reg0 0x21
+    ldc %reg1 0x7
+    st %reg0 %reg1
+    tst %reg1 %reg0
+    hlt
+");
+        assert_eq!(
+            "{
+  \"pc\": 4,
+  \"cycles\": 5,
+  \"halted\": true,
+  \"wide_mode\": false,
+  \"flags\": { \"zero\": false, \"carry\": true, \"overflow\": false },
+  \"registers\": [33, 7, 0, 0, 0, 0, 0, 0],
+  \"ram\": { \"start\": 32, \"words\": [0, 7] }
+}
+",
+            emulator.to_json(0x20..0x22)
+        );
+        assert!(emulator.to_json(0..0).contains("\"words\": []"));
+    }
+
+    #[test]
+    fn loops_and_memory() {
+        // sum of 1..=10 stored at RAM address 0x20
+        let emulator = run("main:
+    ldc %reg1 10
+    ldc %reg2 0
+loop:
+    add %reg2 %reg2 %reg1
+    dec %reg1
+    jnzr loop
+    ldc %reg3 0x20
+    st %reg3 %reg2
+    ld %reg4 %reg3
+    hlt
+");
+        assert_eq!(55, emulator.ram[0x20]);
+        assert_eq!(55, emulator.register(4));
+        assert_eq!(2 + 3 * 10 + 4, emulator.cycles);
+        assert_eq!(
+            "pc      0x0008
+cycles  36
+flags   zero=1 carry=0 overflow=0
+%reg0   0x0000
+%reg1   0x0000
+%reg2   0x0037
+%reg3   0x0020
+%reg4   0x0037
+%reg5   0x0000
+%reg6   0x0000
+%reg7   0x0000
+ram[0x0020] 0x0037
+",
+            emulator.to_string()
+        );
+    }
+
+    #[test]
+    fn pseudo_instructions() {
+        let emulator = run("main:
+    s32b true
+    li %reg0 0x12345678
+    rol %reg1 %reg0 8
+    ldc %reg2 3
+    ldc %reg3 7
+    tst %reg3 %reg2
+    jger bigger
+    hlt
+bigger:
+    ljmp end
+    hlt
+end:
+    addi %reg2 2
+    hlt
+");
+        assert_eq!(0x12345678, emulator.register(0));
+        assert_eq!(0x34567812, emulator.register(1));
+        assert_eq!(5, emulator.register(2));
+    }
+
+    #[test]
+    fn load() {
+        let mut emulator =
+            Emulator::new(crate::assemble_str("main:\n    inc %reg0\n    jr main\n").unwrap());
+        emulator.run(3).unwrap_err();
+        assert_eq!((1, 2), (emulator.pc, emulator.register(0)));
+
+        // the new program continues at the program counter of the old one
+        emulator
+            .load(crate::assemble_str("main:\n    dec %reg0\n    dec %reg0\n    hlt\n").unwrap());
+        emulator.run(10).unwrap();
+        assert_eq!((2, 1), (emulator.pc, emulator.register(0)));
+    }
+
+    #[test]
+    fn step_back() {
+        let program = crate::assemble_str(
+            "main:
+    ldc %reg0 0x20
+    ldc %reg1 0x7
+    st %reg0 %reg1
+    dec %reg1
+    hlt
+",
+        )
+        .unwrap();
+        let mut emulator = Emulator::new(program);
+        emulator.history_size = 3;
+        emulator.run(100).unwrap();
+        assert!(emulator.halted);
+
+        assert!(emulator.step_back());
+        assert!(!emulator.halted);
+        assert_eq!(4, emulator.pc);
+        assert!(emulator.step_back());
+        assert_eq!(
+            (3, 7, 3),
+            (emulator.pc, emulator.register(1), emulator.cycles)
+        );
+        assert_eq!(Flags::default(), emulator.flags);
+        assert!(emulator.step_back());
+        assert_eq!((2, 0), (emulator.pc, emulator.ram[0x20]));
+        // the first instruction fell out of the history
+        assert!(!emulator.step_back());
+        assert_eq!(0x20, emulator.register(0));
+
+        emulator.run(100).unwrap();
+        assert_eq!(6, emulator.register(1));
+        assert_eq!(7, emulator.ram[0x20]);
+    }
+
+    #[test]
+    fn record_and_replay() {
+        let program = crate::assemble_str(
+            "main:
+    ldc %reg0 0xfff0
+    ld %reg1 %reg0
+    ld %reg2 %reg0
+    st %reg0 %reg1
+",
+        )
+        .unwrap();
+        let console = |input: &'static [u8]| {
+            let mut bus = Bus::new();
+            let console = crate::device::Console::new(input, std::io::sink());
+            bus.attach(0xfff0, Box::new(console)).unwrap();
+            bus
+        };
+        let mut emulator = Emulator::new(program.clone());
+        emulator.bus = console(b"hi");
+        emulator.record();
+        emulator.run(100).unwrap();
+        let recording = emulator.recording().unwrap().clone();
+        assert_eq!(vec![(0xfff0, 0x68), (0xfff0, 0x69)], recording.reads);
+
+        // the console has no input left, the reads are answered by the recording
+        let mut replayed = Emulator::new(program.clone());
+        replayed.bus = console(b"");
+        replayed.replay(recording);
+        replayed.run(100).unwrap();
+        assert_eq!(emulator.registers, replayed.registers);
+
+        let mut diverged = Emulator::new(program);
+        diverged.bus = console(b"");
+        diverged.replay(Recording::new(vec![(0xfff0, 0x68)]));
+        assert_eq!(
+            Err(EmulatorError::ReplayDiverged {
+                address: 0xfff0,
+                read: 1
+            }),
+            diverged.run(100)
+        );
+    }
+
+    #[test]
+    fn devices() {
+        let program = crate::assemble_str(
+            "main:
+    ldc %reg0 0xff00
+    ldc %reg1 0x5
+    st %reg0 %reg1
+    ldc %reg2 0xff10
+    ld %reg3 %reg2
+",
+        )
+        .unwrap();
+        let mut emulator = Emulator::new(program);
+        for spec in ["gpio@0xff00", "timer@0xff10"] {
+            let (address, device) = crate::device::parse_spec(spec).unwrap();
+            emulator.bus.attach(address, device).unwrap();
+        }
+        let mut trace = Vec::new();
+        emulator
+            .run_traced(100, |_, entry| trace.push(entry.clone()))
+            .unwrap();
+
+        assert_eq!(
+            "0x0002  00168  st %reg0 %reg1           gpio[0xff00]=0x0005",
+            trace[2].to_string()
+        );
+        assert_eq!(Some("timer"), trace[4].device);
+        assert_eq!(4, emulator.register(3));
+        assert_eq!(0, emulator.ram[0xff00]);
+        assert!(emulator.to_string().ends_with(
+            "gpio@0xff00
+  output  0000000000000101
+  input   0000000000000000
+timer@0xff10
+  counter 0x0006
+  divider 0x0001
+"
+        ));
+    }
+
+    #[test]
+    fn trace() {
+        let program = crate::assemble_str(
+            "main:
+    ldc %reg0 0x20
+    ldc %reg1 0x1
+    dec %reg1
+    st %reg0 %reg1
+",
+        )
+        .unwrap();
+        let mut emulator = Emulator::new(program);
+        let mut trace = Vec::new();
+        emulator
+            .run_traced(100, |_, entry| trace.push(entry.to_string()))
+            .unwrap();
+
+        assert_eq!(
+            vec![
+                "0x0000  00280  ldc %reg0 0x20           %reg0=0x0020",
+                "0x0001  00091  ldc %reg1 0x1            %reg1=0x0001",
+                "0x0002  20106  dec %reg1                %reg1=0x0000 z=1 c=0 o=0",
+                "0x0003  00168  st %reg0 %reg1           ram[0x0020]=0x0000",
+                "0x0004  0007f  hlt",
+            ],
+            trace
+        );
+        assert_eq!((Event::Halt, None), emulator.step_traced().unwrap());
+    }
+
+    #[test]
+    fn errors() {
+        let mut emulator = Emulator::new(crate::assemble_str("main:\n    .word 0x7d\n").unwrap());
+        assert_eq!(
+            Err(EmulatorError::InvalidInstruction {
+                address: 0,
+                word: InstructionWord::from_raw(0x7d),
+            }),
+            emulator.step()
+        );
+
+        let mut emulator = Emulator::new(crate::assemble_str("main:\n    jr main\n").unwrap());
+        assert_eq!(
+            Err(EmulatorError::CycleLimit { cycles: 100 }),
+            emulator.run(100)
+        );
+
+        let mut emulator = Emulator::new(Vec::new());
+        assert_eq!("out-of-program", emulator.step().unwrap_err().code());
+        let long_jump = ir::Instruction::LongJump {
+            target: ir::Expression::Constant(0),
+            scratch: ir::Register::new(ir::RegisterAddress(6)),
+        };
+        assert_eq!(
+            "unresolved-instruction",
+            emulator.execute(&long_jump).unwrap_err().code()
+        );
+    }
+}
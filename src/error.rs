@@ -0,0 +1,184 @@
+use std::fmt;
+
+use crate::{
+    diagnostic, generator::GeneratorError, layout::LayoutError, lexer::LexerError,
+    output::OutputError, parser::ParserError, preprocessor::PreprocessorError,
+};
+
+/// Error of any stage of the assembler, so a whole assembly can be run with
+/// `?`. The lexer and the parser report all errors of a program at once.
+#[derive(Debug)]
+pub enum MasmError {
+    Lexer(Vec<LexerError>),
+    Preprocessor(PreprocessorError),
+    Parser(Vec<ParserError>),
+    Layout(LayoutError),
+    Generator(GeneratorError),
+    Output(OutputError),
+}
+
+impl fmt::Display for MasmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MasmError::Lexer(errors) => write_all(f, errors),
+            MasmError::Preprocessor(error) => write!(f, "{}", error),
+            MasmError::Parser(errors) => write_all(f, errors),
+            MasmError::Layout(error) => write!(f, "{}", error),
+            MasmError::Generator(error) => write!(f, "{}", error),
+            MasmError::Output(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+/// One error per line
+fn write_all(f: &mut fmt::Formatter<'_>, errors: &[impl fmt::Display]) -> fmt::Result {
+    for (i, error) in errors.iter().enumerate() {
+        if i > 0 {
+            writeln!(f)?;
+        }
+        write!(f, "{}", error)?;
+    }
+    Ok(())
+}
+
+impl std::error::Error for MasmError {
+    /// The first error of the stage
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MasmError::Lexer(errors) => errors.first().map(|error| error as _),
+            MasmError::Preprocessor(error) => Some(error),
+            MasmError::Parser(errors) => errors.first().map(|error| error as _),
+            MasmError::Layout(error) => Some(error),
+            MasmError::Generator(error) => Some(error),
+            MasmError::Output(error) => Some(error),
+        }
+    }
+}
+
+impl MasmError {
+    /// Name of the stage that failed
+    pub fn stage(&self) -> &'static str {
+        match self {
+            MasmError::Lexer(_) => "Lexer",
+            MasmError::Preprocessor(_) => "Preprocessor",
+            MasmError::Parser(_) => "Parser",
+            MasmError::Layout(_) => "Layout",
+            MasmError::Generator(_) => "Generator",
+            MasmError::Output(_) => "Output",
+        }
+    }
+
+    /// Code and position of every single error, see [`diagnostic`]
+    pub fn diagnostics(&self) -> Vec<(&'static str, String, Option<diagnostic::Position>)> {
+        match self {
+            MasmError::Lexer(errors) => errors
+                .iter()
+                .map(|error| (error.code(), error.to_string(), error.position()))
+                .collect(),
+            MasmError::Preprocessor(error) => {
+                vec![(error.code(), error.to_string(), error.position())]
+            }
+            MasmError::Parser(errors) => errors
+                .iter()
+                .map(|error| (error.code(), error.to_string(), error.position()))
+                .collect(),
+            MasmError::Layout(error) => vec![(error.code(), error.to_string(), None)],
+            MasmError::Generator(error) => vec![(error.code(), error.to_string(), None)],
+            MasmError::Output(error) => vec![(error.code(), error.to_string(), None)],
+        }
+    }
+}
+
+impl From<Vec<LexerError>> for MasmError {
+    fn from(errors: Vec<LexerError>) -> Self {
+        MasmError::Lexer(errors)
+    }
+}
+
+impl From<LexerError> for MasmError {
+    fn from(error: LexerError) -> Self {
+        MasmError::Lexer(vec![error])
+    }
+}
+
+impl From<PreprocessorError> for MasmError {
+    fn from(error: PreprocessorError) -> Self {
+        MasmError::Preprocessor(error)
+    }
+}
+
+impl From<Vec<ParserError>> for MasmError {
+    fn from(errors: Vec<ParserError>) -> Self {
+        MasmError::Parser(errors)
+    }
+}
+
+impl From<ParserError> for MasmError {
+    fn from(error: ParserError) -> Self {
+        MasmError::Parser(vec![error])
+    }
+}
+
+impl From<LayoutError> for MasmError {
+    fn from(error: LayoutError) -> Self {
+        MasmError::Layout(error)
+    }
+}
+
+impl From<GeneratorError> for MasmError {
+    fn from(error: GeneratorError) -> Self {
+        MasmError::Generator(error)
+    }
+}
+
+impl From<OutputError> for MasmError {
+    fn from(error: OutputError) -> Self {
+        MasmError::Output(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use super::*;
+    use crate::lexer::Keyword;
+    use crate::{generator, parser};
+
+    fn assemble(keywords: Vec<Keyword>) -> Result<usize, MasmError> {
+        let ir = parser::parser(keywords)?;
+        Ok(generator::generator(ir)?.len())
+    }
+
+    #[test]
+    fn question_mark() {
+        assert_eq!(
+            1,
+            assemble(vec![Keyword::label("main", 0), Keyword::mmenonic("hlt", 1)]).unwrap()
+        );
+
+        let error = assemble(vec![
+            Keyword::label("main", 0),
+            Keyword::mmenonic("foo", 1),
+            Keyword::mmenonic("bar", 2),
+        ])
+        .unwrap_err();
+        assert_eq!("Parser", error.stage());
+        assert_eq!(
+            "Unknown command: 'foo' at line 2, column 0\nUnknown command: 'bar' at line 3, column 0",
+            error.to_string()
+        );
+        assert_eq!(
+            "Unknown command: 'foo' at line 2, column 0",
+            error.source().unwrap().to_string()
+        );
+        assert_eq!(
+            vec!["unknown-command", "unknown-command"],
+            error
+                .diagnostics()
+                .into_iter()
+                .map(|(code, _, _)| code)
+                .collect::<Vec<_>>()
+        );
+    }
+}
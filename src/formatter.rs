@@ -0,0 +1,348 @@
+use crate::lexer::{self, Keyword, LexerError, LineNumber};
+
+/// Indentation of instructions and indented directives
+const INDENT: &str = "    ";
+
+/// Radix constants are written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Radix {
+    /// radix as written, hex digits in lowercase
+    #[default]
+    Keep,
+    Hex,
+    Decimal,
+}
+
+impl std::str::FromStr for Radix {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "keep" => Ok(Radix::Keep),
+            "hex" => Ok(Radix::Hex),
+            "dec" => Ok(Radix::Decimal),
+            _ => Err(format!("unknown radix '{name}'")),
+        }
+    }
+}
+
+/// Settings of the formatter
+#[derive(Debug, Clone, Default)]
+pub struct FormatterOptions {
+    pub radix: Radix,
+}
+
+/// Formatted line of the source
+enum Line {
+    /// statement with its trailing comment, which is aligned with the
+    /// comments of the surrounding lines
+    Code {
+        text: String,
+        comment: Option<String>,
+    },
+    /// line written as it is, e.g. a comment on its own
+    Verbatim(String),
+}
+
+/// Formats the program `source`:
+/// - labels and attributes start at column 0, instructions are indented
+///   by four spaces, directives stay at column 0 unless they are indented
+/// - operands are separated by a single space
+/// - trailing comments of lines not separated by a blank line start at the
+///   same column, comments on their own line at column 0 or indented like
+///   instructions
+/// - constants are written in the radix of `options`
+/// - trailing whitespace and blank lines at the end are removed
+///
+/// Lines the formatted text would lex differently, e.g. ignored text like
+/// `loop: ; comment`, are kept as they are. Included files are not
+/// formatted.
+pub fn format(source: &str, options: &FormatterOptions) -> Result<String, Vec<LexerError>> {
+    let keywords = lexer::lexer_with_comments(source)?;
+    let lines: Vec<&str> = source.lines().collect();
+    let mut by_line: Vec<Vec<&Keyword>> = vec![Vec::new(); lines.len()];
+    for keyword in &keywords {
+        if let Some(on_line) = by_line.get_mut(usize::from(keyword.get_line_number())) {
+            on_line.push(keyword);
+        }
+    }
+    let formatted: Vec<Line> = lines
+        .iter()
+        .zip(&by_line)
+        .enumerate()
+        .map(|(line_number, (text, keywords))| {
+            format_line(text, keywords, line_number as u16, options)
+        })
+        .collect();
+
+    let mut output: Vec<String> = Vec::with_capacity(formatted.len());
+    // lines up to the next blank line share the column of their comments
+    for paragraph in formatted.split(|line| matches!(line, Line::Verbatim(text) if text.is_empty()))
+    {
+        let column = paragraph
+            .iter()
+            .filter_map(|line| match line {
+                Line::Code {
+                    text,
+                    comment: Some(_),
+                } => Some(text.len()),
+                _ => None,
+            })
+            .max()
+            .unwrap_or_default();
+        for line in paragraph {
+            output.push(match line {
+                Line::Code {
+                    text,
+                    comment: Some(comment),
+                } => format!("{:<column$} {}", text, comment),
+                Line::Code {
+                    text,
+                    comment: None,
+                } => text.clone(),
+                Line::Verbatim(text) => text.clone(),
+            });
+        }
+        output.push(String::new());
+    }
+    while output.last().is_some_and(String::is_empty) {
+        output.pop();
+    }
+    if output.is_empty() {
+        return Ok(String::new());
+    }
+    output.push(String::new());
+    Ok(output.join("\n"))
+}
+
+/// Index of the first line `formatted` differs from `source` in, `None`
+/// if `source` is formatted already
+pub fn first_difference(source: &str, formatted: &str) -> Option<usize> {
+    let mut source_lines = source.lines();
+    let mut formatted_lines = formatted.lines();
+    let mut line_number: usize = 0;
+    loop {
+        match (source_lines.next(), formatted_lines.next()) {
+            (None, None) if source == formatted => return None,
+            // a missing or extra newline at the end
+            (None, None) => return Some(line_number.saturating_sub(1)),
+            (source_line, formatted_line) if source_line != formatted_line => {
+                return Some(line_number)
+            }
+            _ => line_number += 1,
+        }
+    }
+}
+
+fn format_line(
+    text: &str,
+    keywords: &[&Keyword],
+    line_number: u16,
+    options: &FormatterOptions,
+) -> Line {
+    let verbatim = || Line::Verbatim(text.trim_end().to_string());
+    let comment = keywords.iter().find_map(|keyword| match keyword {
+        Keyword::Comment { text, column, .. } => Some((text.clone(), *column)),
+        _ => None,
+    });
+    let code: Vec<&Keyword> = keywords
+        .iter()
+        .copied()
+        .filter(|keyword| !matches!(keyword, Keyword::Comment { .. } | Keyword::BlankLine { .. }))
+        .collect();
+
+    let formatted = match code.as_slice() {
+        [] => {
+            let code_end = comment
+                .as_ref()
+                .map_or(text.len(), |(_, column)| usize::from(*column));
+            return match comment {
+                // ignored by the lexer, e.g. an instruction at column 0
+                _ if !text[..code_end].trim().is_empty() => verbatim(),
+                Some((comment, 0)) => Line::Verbatim(comment),
+                Some((comment, _)) => Line::Verbatim(format!("{INDENT}{comment}")),
+                None => Line::Verbatim(String::new()),
+            };
+        }
+        [Keyword::Label { name, .. }] => format!("{name}:"),
+        attributes
+            if attributes
+                .iter()
+                .all(|keyword| matches!(keyword, Keyword::Attribute { .. })) =>
+        {
+            let attributes: Vec<String> = attributes
+                .iter()
+                .map(|attribute| {
+                    let attribute = attribute.get_original_string();
+                    attribute[2..attribute.len() - 2].to_string()
+                })
+                .collect();
+            format!("[[{}]]", attributes.join(", "))
+        }
+        [command @ (Keyword::Mmenonic { .. } | Keyword::Directive { .. }), operands @ ..] => {
+            let indent = match command {
+                Keyword::Directive { column: 0, .. } => "",
+                _ => INDENT,
+            };
+            let mut words = vec![command.get_original_string()];
+            words.extend(
+                operands
+                    .iter()
+                    .map(|operand| word(text, operand, options.radix)),
+            );
+            format!("{}{}", indent, words.join(" "))
+        }
+        _ => return verbatim(),
+    };
+
+    // e.g. a label indented like an instruction
+    let mut relexed: Vec<Keyword> = Vec::with_capacity(code.len());
+    let line = match &comment {
+        Some((comment, _)) => format!("{formatted} {comment}"),
+        None => formatted.clone(),
+    };
+    if lexer::lex_line(&mut relexed, line, line_number).is_err()
+        || relexed.len() != code.len()
+        || !relexed.iter().zip(&code).all(|(a, b)| same(a, b))
+    {
+        return verbatim();
+    }
+    Line::Code {
+        text: formatted,
+        comment: comment.map(|(comment, _)| comment),
+    }
+}
+
+/// Operand `keyword` as written in `text`, constants in `radix`
+fn word(text: &str, keyword: &Keyword, radix: Radix) -> String {
+    match keyword {
+        Keyword::Constant { origin, .. } => constant(origin, radix),
+        // references keep their leading dot
+        Keyword::Label { column, .. } => text[usize::from(*column)..]
+            .split(|c: char| c.is_whitespace() || c == ';')
+            .next()
+            .unwrap_or_default()
+            .to_string(),
+        keyword => keyword.get_original_string(),
+    }
+}
+
+/// Number literal `origin` written in `radix`
+fn constant(origin: &str, radix: Radix) -> String {
+    let Some((digits, written, negative)) = lexer::split_number(origin) else {
+        return origin.to_string();
+    };
+    let Ok(magnitude) = u16::from_str_radix(digits, written) else {
+        return origin.to_string();
+    };
+    let sign = if negative { "-" } else { "" };
+    match (radix, written) {
+        (Radix::Hex, _) | (Radix::Keep, 16) => format!("{sign}0x{}", {
+            // keeps leading zeros of hex literals
+            let width = if written == 16 { digits.len() } else { 0 };
+            format!("{magnitude:0width$x}")
+        }),
+        (Radix::Decimal, _) => format!("{sign}{magnitude}"),
+        (Radix::Keep, _) => origin.to_string(),
+    }
+}
+
+/// Whether `a` and `b` are the same keyword, constants may be written in
+/// another radix
+fn same(a: &Keyword, b: &Keyword) -> bool {
+    match (a, b) {
+        (Keyword::Constant { value: a, .. }, Keyword::Constant { value: b, .. }) => a == b,
+        (a, b) => a == b,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format_str(source: &str, radix: Radix) -> String {
+        format(source, &FormatterOptions { radix }).unwrap()
+    }
+
+    #[test]
+    fn layout() {
+        let source = "\
+;; Entry point
+  [[noreturn,align=2]]
+main:
+\tldc   %reg0\t0xFF ; limit
+  .word 1 2   ;data
+    jnzr .loop     ; back
+
+
+.org 0x10
+loop: ; ignored by the lexer
+ldc %reg0 1
+   ; indented comment
+.loop:
+    hlt
+
+";
+        assert_eq!(
+            "\
+;; Entry point
+[[noreturn, align=2]]
+main:
+    ldc %reg0 0xff ; limit
+    .word 1 2      ;data
+    jnzr .loop     ; back
+
+
+.org 0x10
+loop: ; ignored by the lexer
+ldc %reg0 1
+    ; indented comment
+.loop:
+    hlt
+",
+            format_str(source, Radix::Keep)
+        );
+    }
+
+    #[test]
+    fn radix() {
+        let source = "main:\n    ldc %reg0 0x00FF\n    ldc %reg1 -10\n    ldc %reg2 0b101\n";
+        assert_eq!(
+            "main:\n    ldc %reg0 0x00ff\n    ldc %reg1 -10\n    ldc %reg2 0b101\n",
+            format_str(source, Radix::Keep)
+        );
+        assert_eq!(
+            "main:\n    ldc %reg0 0x00ff\n    ldc %reg1 -0xa\n    ldc %reg2 0x5\n",
+            format_str(source, Radix::Hex)
+        );
+        assert_eq!(
+            "main:\n    ldc %reg0 255\n    ldc %reg1 -10\n    ldc %reg2 5\n",
+            format_str(source, Radix::Decimal)
+        );
+    }
+
+    #[test]
+    fn formatted_files() {
+        for file in [
+            "tests/test.s",
+            "tests/macros.s",
+            "tests/docs.s",
+            "tests/whitespace.s",
+        ] {
+            let source = std::fs::read_to_string(file).unwrap();
+            let formatted = format_str(&source, Radix::Keep);
+            assert_eq!(formatted, format_str(&formatted, Radix::Keep), "{file}");
+            assert_eq!(
+                crate::assemble_str(&source).unwrap(),
+                crate::assemble_str(&formatted).unwrap(),
+                "{file}"
+            );
+        }
+        assert_eq!(None, first_difference("main:\n", "main:\n"));
+        assert_eq!(
+            Some(1),
+            first_difference("main:\n hlt\n", "main:\n    hlt\n")
+        );
+        assert_eq!(Some(0), first_difference("main:", "main:\n"));
+    }
+}
@@ -1,8 +1,11 @@
-use std::fmt;
+use std::{fmt, io};
 
-use crate::ir;
+use crate::{ir, lowering};
 
-#[derive(Clone)]
+/// Header line of the Logisim `v3.0 hex words plain` image format
+pub const LOGISIM_HEADER: &str = "v3.0 hex words plain";
+
+#[derive(Clone, PartialEq, Eq)]
 pub struct InstructionWord {
     buffer: [bool; 20],
 }
@@ -13,6 +16,19 @@ impl InstructionWord {
             buffer: [false; 20],
         }
     }
+    /// Creates an instruction word from the lower 20 bits of `raw`
+    pub fn from_raw(raw: u32) -> Self {
+        let mut instruction_word = InstructionWord::new();
+        set_bits(&mut instruction_word.buffer, raw);
+        instruction_word
+    }
+    /// Returns the instruction word as an integer
+    pub fn raw(&self) -> u32 {
+        self.buffer
+            .iter()
+            .rev()
+            .fold(0, |raw, &bit| (raw << 1) | u32::from(bit))
+    }
     fn clear(&mut self) {
         self.buffer.fill(false);
     }
@@ -48,9 +64,6 @@ impl InstructionWord {
     fn set_constant12(&mut self, constant: u16) {
         set_bits(&mut self.buffer[8..=19], constant as u32);
     }
-    fn set_unary_statement(&mut self, u_stat: &ir::UnaryStatement) {
-        self.set_op_a(u_stat.source_a.addr());
-    }
     fn set_unary_expression(&mut self, u_expr: &ir::UnaryExpression) {
         self.set_target(u_expr.target.addr());
         self.set_op_a(u_expr.source_a.addr());
@@ -115,8 +128,13 @@ fn set_bits(buffer: &mut [bool], int: u32) {
     }
 }
 
+#[derive(Debug)]
 pub enum GeneratorError {
     UndefinedLabel { label_name: String },
+    JumpOutOfRange { label_name: String, distance: i16 },
+    ConstantOutOfRange { label_name: String, value: u32 },
+    InvalidRegister { label_name: String, register: u8 },
+    IoError(io::Error),
 }
 
 impl fmt::Display for GeneratorError {
@@ -125,111 +143,232 @@ impl fmt::Display for GeneratorError {
             GeneratorError::UndefinedLabel { label_name } => {
                 write!(f, "Could not find definition of label '{}'", label_name,)
             }
+            GeneratorError::JumpOutOfRange {
+                label_name,
+                distance,
+            } => write!(
+                f,
+                "Relative jump by {} words in label '{}' does not fit into 12 bits, \
+                 use an absolute jump like 'ljmp' instead",
+                distance, label_name
+            ),
+            GeneratorError::InvalidRegister {
+                label_name,
+                register,
+            } => write!(
+                f,
+                "Register {} used in label '{}' does not exist, there are only {} registers",
+                register,
+                label_name,
+                ir::REGISTER_COUNT
+            ),
+            GeneratorError::ConstantOutOfRange { label_name, value } => write!(
+                f,
+                "Constant {:#x} loaded in label '{}' does not fit into 16 bits",
+                value, label_name
+            ),
+            GeneratorError::IoError(io_error) => write!(f, "IO error '{}'", io_error),
+        }
+    }
+}
+
+impl std::error::Error for GeneratorError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GeneratorError::IoError(io_error) => Some(io_error),
+            _ => None,
+        }
+    }
+}
+
+/// Receives the generated instruction words in address order, e.g. a `Vec`
+/// or a writer encoding every word as soon as it is generated
+pub trait WordSink {
+    /// Address of the next word, the number of words received so far
+    fn address(&self) -> usize;
+    fn push(&mut self, word: InstructionWord) -> io::Result<()>;
+}
+
+impl WordSink for Vec<InstructionWord> {
+    fn address(&self) -> usize {
+        self.len()
+    }
+    fn push(&mut self, word: InstructionWord) -> io::Result<()> {
+        Vec::push(self, word);
+        Ok(())
+    }
+}
+
+fn emit(sink: &mut dyn WordSink, word: InstructionWord) -> Result<(), GeneratorError> {
+    sink.push(word).map_err(GeneratorError::IoError)
+}
+
+impl GeneratorError {
+    /// Stable name of the error kind, e.g. for machine readable diagnostics
+    pub fn code(&self) -> &'static str {
+        match self {
+            GeneratorError::UndefinedLabel { .. } => "undefined-label",
+            GeneratorError::JumpOutOfRange { .. } => "jump-out-of-range",
+            GeneratorError::ConstantOutOfRange { .. } => "constant-out-of-range",
+            GeneratorError::InvalidRegister { .. } => "invalid-register",
+            GeneratorError::IoError(_) => "io-error",
+        }
+    }
+}
+
+/// Offset of the jump opcodes for `condition` from the unconditional jump
+fn condition_code(condition: &ir::JumpCondition) -> u8 {
+    match condition {
+        ir::JumpCondition::True => 0,
+        ir::JumpCondition::Zero => 1,
+        ir::JumpCondition::NotZero => 2,
+        ir::JumpCondition::Less => 3,
+        ir::JumpCondition::Overflow => 4,
+        ir::JumpCondition::GreaterEqual
+        | ir::JumpCondition::LessEqual
+        | ir::JumpCondition::Greater => {
+            unreachable!("{:?} jumps are lowered before generation", condition)
         }
     }
 }
 
 pub fn generator(ir: ir::IR) -> Result<Vec<InstructionWord>, GeneratorError> {
-    let mut labels: Vec<&ir::LabelDefinition> = ir.label_definitions.0.values().collect();
-    labels.sort_by(|&a, &b| a.address.cmp(&b.address));
+    generator_with_gap_fill(ir, &InstructionWord::from_raw(0x6c))
+}
 
+/// Like [`generator`], but fills the gaps in front of labels placed with
+/// `.org` or `[[align=N]]` with `gap_fill` instead of nops
+pub fn generator_with_gap_fill(
+    ir: ir::IR,
+    gap_fill: &InstructionWord,
+) -> Result<Vec<InstructionWord>, GeneratorError> {
     let mut binary: Vec<InstructionWord> = Vec::with_capacity(32);
+    generate_into(ir, gap_fill, &mut binary)?;
+    Ok(binary)
+}
+
+/// Like [`generator_with_gap_fill`], but hands every word to `sink` as soon
+/// as it is generated instead of collecting the image
+pub fn generate_into(
+    ir: ir::IR,
+    gap_fill: &InstructionWord,
+    sink: &mut dyn WordSink,
+) -> Result<(), GeneratorError> {
+    let ir = lowering::lower(ir);
+    let mut labels: Vec<&ir::LabelDefinition> = ir.label_definitions.0.values().collect();
+    labels.sort_by_key(|label| label.address);
+
     let mut instruction_word = InstructionWord::new();
 
     for label in labels {
+        // fill the gap in front of placed labels
+        while sink.address() < usize::from(label.address.0) {
+            emit(sink, gap_fill.clone())?;
+        }
         if let Some(instructions) = ir.instructions.get(&label.clone().into()) {
             for (idx, instr) in instructions.iter().enumerate() {
                 instruction_word.clear();
+                // register fields are 3 bits wide and would be truncated
+                if let Some(register) = instr
+                    .registers()
+                    .into_iter()
+                    .find(|register| register.0 >= ir::REGISTER_COUNT)
+                {
+                    return Err(GeneratorError::InvalidRegister {
+                        label_name: label.name.clone(),
+                        register: register.0,
+                    });
+                }
                 match instr {
                     ir::Instruction::Add(binary_expression) => {
                         instruction_word.set_opcode(0x0);
                         instruction_word.set_binary_expression(binary_expression);
-                        binary.push(instruction_word.clone());
+                        emit(sink, instruction_word.clone())?;
                     }
                     ir::Instruction::Add3(ternary_expression) => {
                         instruction_word.set_opcode(0x1);
                         instruction_word.set_ternary_expression(ternary_expression);
-                        binary.push(instruction_word.clone());
+                        emit(sink, instruction_word.clone())?;
                     }
                     ir::Instruction::AddWithCarry(binary_expression) => {
                         instruction_word.set_opcode(0x2);
                         instruction_word.set_binary_expression(binary_expression);
-                        binary.push(instruction_word.clone());
+                        emit(sink, instruction_word.clone())?;
                     }
                     ir::Instruction::Subtract(binary_expression) => {
                         instruction_word.set_opcode(0x3);
                         instruction_word.set_binary_expression(binary_expression);
-                        binary.push(instruction_word.clone());
+                        emit(sink, instruction_word.clone())?;
                     }
                     ir::Instruction::SubtractWithCarry(binary_expression) => {
                         instruction_word.set_opcode(0x4);
                         instruction_word.set_binary_expression(binary_expression);
-                        binary.push(instruction_word.clone());
+                        emit(sink, instruction_word.clone())?;
                     }
                     ir::Instruction::Increment(unary_expression) => {
                         instruction_word.set_opcode(0x5);
                         instruction_word.set_unary_expression(unary_expression);
-                        binary.push(instruction_word.clone());
+                        emit(sink, instruction_word.clone())?;
                     }
                     ir::Instruction::Decrement(unary_expression) => {
                         instruction_word.set_opcode(0x6);
                         instruction_word.set_unary_expression(unary_expression);
-                        binary.push(instruction_word.clone());
+                        emit(sink, instruction_word.clone())?;
                     }
                     ir::Instruction::Multiply(binary_expression) => {
                         instruction_word.set_opcode(0x7);
                         instruction_word.set_binary_expression(binary_expression);
-                        binary.push(instruction_word.clone());
+                        emit(sink, instruction_word.clone())?;
                     }
                     ir::Instruction::Test(binary_statement) => {
                         instruction_word.set_opcode(0x8);
                         instruction_word.set_binary_statement(binary_statement);
-                        binary.push(instruction_word.clone());
+                        emit(sink, instruction_word.clone())?;
                     }
                     ir::Instruction::AND(binary_expression) => {
                         instruction_word.set_opcode(0x9);
                         instruction_word.set_binary_expression(binary_expression);
-                        binary.push(instruction_word.clone());
+                        emit(sink, instruction_word.clone())?;
                     }
                     ir::Instruction::OR(binary_expression) => {
                         instruction_word.set_opcode(0xa);
                         instruction_word.set_binary_expression(binary_expression);
-                        binary.push(instruction_word.clone());
+                        emit(sink, instruction_word.clone())?;
                     }
                     ir::Instruction::NOT(unary_expression) => {
                         instruction_word.set_opcode(0xb);
                         instruction_word.set_unary_expression(unary_expression);
-                        binary.push(instruction_word.clone());
+                        emit(sink, instruction_word.clone())?;
                     }
                     ir::Instruction::Negate(unary_expression) => {
-                        instruction_word.set_opcode(0xb);
+                        instruction_word.set_opcode(0xc);
                         instruction_word.set_unary_expression(unary_expression);
-                        binary.push(instruction_word.clone());
+                        emit(sink, instruction_word.clone())?;
                     }
                     ir::Instruction::XOR(binary_expression) => {
                         instruction_word.set_opcode(0xd);
                         instruction_word.set_binary_expression(binary_expression);
-                        binary.push(instruction_word.clone());
+                        emit(sink, instruction_word.clone())?;
                     }
                     ir::Instruction::XNOR(binary_expression) => {
                         instruction_word.set_opcode(0xe);
                         instruction_word.set_binary_expression(binary_expression);
-                        binary.push(instruction_word.clone());
+                        emit(sink, instruction_word.clone())?;
                     }
                     ir::Instruction::ShiftLeft(binary_expression) => {
                         instruction_word.set_opcode(0xf);
                         instruction_word.set_binary_expression(binary_expression);
-                        binary.push(instruction_word.clone());
+                        emit(sink, instruction_word.clone())?;
                     }
                     ir::Instruction::ShiftRight(binary_expression) => {
                         instruction_word.set_opcode(0x10);
                         instruction_word.set_binary_expression(binary_expression);
-                        binary.push(instruction_word.clone());
+                        emit(sink, instruction_word.clone())?;
                     }
                     ir::Instruction::Move(unary_expression) => {
                         instruction_word.set_opcode(0x48);
                         instruction_word.set_unary_expression(unary_expression);
-                        binary.push(instruction_word.clone());
+                        emit(sink, instruction_word.clone())?;
                     }
                     ir::Instruction::Set32BitMode { enable } => {
                         instruction_word.set_opcode(0x4a);
@@ -237,43 +376,38 @@ pub fn generator(ir: ir::IR) -> Result<Vec<InstructionWord>, GeneratorError> {
                             ir::Boolean(true) => instruction_word.set_constant12(0xff),
                             ir::Boolean(false) => instruction_word.set_constant12(0x00),
                         };
-                        binary.push(instruction_word.clone());
+                        emit(sink, instruction_word.clone())?;
                     }
                     // Absolute jumps
                     ir::Instruction::Jump {
                         target: ir::JumpTarget::Register(reg),
                         condition,
                     } => {
-                        let opcode = 0x50
-                            + match condition {
-                                ir::JumpCondition::True => 0,
-                                ir::JumpCondition::Zero => 1,
-                                ir::JumpCondition::NotZero => 2,
-                                ir::JumpCondition::Less => 3,
-                                ir::JumpCondition::Overflow => 4,
-                            };
+                        let opcode = 0x50 + condition_code(condition);
                         instruction_word.set_opcode(opcode);
                         instruction_word.set_op_a(reg.addr());
-                        binary.push(instruction_word.clone());
+                        emit(sink, instruction_word.clone())?;
                     }
                     // Relative Jumps
                     ir::Instruction::Jump { target, condition } => {
-                        let opcode = 0x58
-                            + match condition {
-                                ir::JumpCondition::True => 0,
-                                ir::JumpCondition::Zero => 1,
-                                ir::JumpCondition::NotZero => 2,
-                                ir::JumpCondition::Less => 3,
-                                ir::JumpCondition::Overflow => 4,
-                            };
+                        let opcode = 0x58 + condition_code(condition);
                         instruction_word.set_opcode(opcode);
                         let offset = match target {
-                            ir::JumpTarget::Label(jump_label_ref) => {
+                            ir::JumpTarget::Label(jump_label_ref)
+                            | ir::JumpTarget::LabelOffset {
+                                label: jump_label_ref,
+                                ..
+                            } => {
+                                let label_offset = match target {
+                                    ir::JumpTarget::LabelOffset { offset, .. } => *offset,
+                                    _ => 0,
+                                };
                                 if let Some(jump_label) = ir.label_definitions.0.get(jump_label_ref)
                                 {
                                     jump_label
                                         .address
                                         .0
+                                        .wrapping_add_signed(label_offset)
                                         .wrapping_sub(label.address.0 + (idx as u16) + 1)
                                 } else {
                                     return Err(GeneratorError::UndefinedLabel {
@@ -281,19 +415,26 @@ pub fn generator(ir: ir::IR) -> Result<Vec<InstructionWord>, GeneratorError> {
                                     });
                                 }
                             }
-                            ir::JumpTarget::Constant(c) => *c - 1,
+                            ir::JumpTarget::Constant(c) => c.wrapping_sub(1),
                             _ => 0,
                         };
+                        // the offset is relative to the next instruction
+                        if !(-2048..=2047).contains(&(offset as i16)) {
+                            return Err(GeneratorError::JumpOutOfRange {
+                                label_name: label.name.clone(),
+                                distance: offset.wrapping_add(1) as i16,
+                            });
+                        }
                         instruction_word.set_constant12(offset);
-                        binary.push(instruction_word.clone());
+                        emit(sink, instruction_word.clone())?;
                     }
                     ir::Instruction::Debug => {
                         instruction_word.set_opcode(0x7e);
-                        binary.push(instruction_word.clone());
+                        emit(sink, instruction_word.clone())?;
                     }
                     ir::Instruction::Halt => {
                         instruction_word.set_opcode(0x7f);
-                        binary.push(instruction_word.clone());
+                        emit(sink, instruction_word.clone())?;
                     }
                     ir::Instruction::Load {
                         address,
@@ -302,7 +443,28 @@ pub fn generator(ir: ir::IR) -> Result<Vec<InstructionWord>, GeneratorError> {
                         instruction_word.set_load();
                         instruction_word.set_load_address(address.0);
                         instruction_word.set_constant16(*c);
-                        binary.push(instruction_word.clone());
+                        emit(sink, instruction_word.clone())?;
+                    }
+                    ir::Instruction::Load {
+                        address,
+                        source: ir::LoadSource::Expression(expression),
+                    } => {
+                        let location = label.address.0 + idx as u16;
+                        let value = expression
+                            .evaluate(&ir.label_definitions, location)
+                            .map_err(|reference| GeneratorError::UndefinedLabel {
+                                label_name: reference.name().to_string(),
+                            })?;
+                        if !ir::fits_16_bits(value) {
+                            return Err(GeneratorError::ConstantOutOfRange {
+                                label_name: label.name.clone(),
+                                value,
+                            });
+                        }
+                        instruction_word.set_load();
+                        instruction_word.set_load_address(address.0);
+                        instruction_word.set_constant16(value as u16);
+                        emit(sink, instruction_word.clone())?;
                     }
                     ir::Instruction::StoreRAM {
                         address_register,
@@ -311,7 +473,7 @@ pub fn generator(ir: ir::IR) -> Result<Vec<InstructionWord>, GeneratorError> {
                         instruction_word.set_opcode(0x68);
                         instruction_word.set_op_a(data_register.0);
                         instruction_word.set_op_b(address_register.0);
-                        binary.push(instruction_word.clone());
+                        emit(sink, instruction_word.clone())?;
                     }
                     ir::Instruction::Load {
                         address,
@@ -320,11 +482,14 @@ pub fn generator(ir: ir::IR) -> Result<Vec<InstructionWord>, GeneratorError> {
                         instruction_word.set_opcode(0x69);
                         instruction_word.set_op_b(address_register.addr());
                         instruction_word.set_target(address.0);
-                        binary.push(instruction_word.clone());
+                        emit(sink, instruction_word.clone())?;
                     }
                     ir::Instruction::Noop => {
                         instruction_word.set_opcode(0x6c);
-                        binary.push(instruction_word.clone());
+                        emit(sink, instruction_word.clone())?;
+                    }
+                    ir::Instruction::Data(constant) => {
+                        emit(sink, InstructionWord::from_raw(u32::from(constant.0)))?;
                     }
                     _ => (),
                 }
@@ -332,5 +497,89 @@ pub fn generator(ir: ir::IR) -> Result<Vec<InstructionWord>, GeneratorError> {
         }
     }
 
-    Ok(binary)
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn jump(target: ir::JumpTarget, distance: u16) -> ir::IR {
+        let main = ir::LabelReference::new("main");
+        let mut label_definitions = ir::LabelLUT::new();
+        label_definitions
+            .0
+            .insert(main.clone(), ir::LabelDefinition::new("main", 0));
+        label_definitions.0.insert(
+            ir::LabelReference::new("far"),
+            ir::LabelDefinition::new("far", distance),
+        );
+        ir::IR {
+            start_label: main.clone(),
+            label_definitions,
+            instructions: HashMap::from([(
+                main,
+                vec![ir::Instruction::Jump {
+                    target,
+                    condition: ir::JumpCondition::True,
+                }],
+            )]),
+        }
+    }
+
+    #[test]
+    fn jump_range() {
+        let far = || ir::JumpTarget::Label(ir::LabelReference::new("far"));
+        assert!(generator(jump(far(), 2048)).is_ok());
+        assert!(generator(jump(ir::JumpTarget::Constant(-2047i16 as u16), 0)).is_ok());
+
+        match generator(jump(far(), 2049)) {
+            Err(GeneratorError::JumpOutOfRange { distance, .. }) => assert_eq!(2049, distance),
+            _ => panic!("expected jump out of range"),
+        }
+        match generator(jump(ir::JumpTarget::Constant(-2048i16 as u16), 0)) {
+            Err(GeneratorError::JumpOutOfRange { distance, .. }) => assert_eq!(-2048, distance),
+            _ => panic!("expected jump out of range"),
+        }
+    }
+
+    #[test]
+    fn constant_range() {
+        let mut ir = jump(ir::JumpTarget::Constant(1), 0xfff0);
+        let far = Box::new(ir::Expression::Label(ir::LabelReference::new("far")));
+        ir.instructions.insert(
+            ir::LabelReference::new("main"),
+            vec![ir::Instruction::Load {
+                address: ir::RegisterAddress(0),
+                source: ir::LoadSource::Expression(ir::Expression::Sum(
+                    far,
+                    Box::new(ir::Expression::Constant(0x10)),
+                )),
+            }],
+        );
+
+        match generator(ir) {
+            Err(GeneratorError::ConstantOutOfRange { value, .. }) => assert_eq!(0x10000, value),
+            _ => panic!("expected constant out of range"),
+        }
+    }
+
+    #[test]
+    fn register_range() {
+        let mut ir = jump(ir::JumpTarget::Constant(1), 0);
+        let register = ir::Register::new(ir::RegisterAddress(8));
+        ir.instructions.insert(
+            ir::LabelReference::new("main"),
+            vec![ir::Instruction::Move(ir::UnaryExpression::new(
+                register, register,
+            ))],
+        );
+
+        match generator(ir) {
+            Err(GeneratorError::InvalidRegister { register, .. }) => assert_eq!(8, register),
+            _ => panic!("expected invalid register"),
+        }
+    }
 }
@@ -1,5 +1,6 @@
 use std::{
     collections::HashMap,
+    fmt,
     hash::{Hash, Hasher},
 };
 
@@ -10,12 +11,102 @@ use std::{
 ///         of the LabelDefinition referenced by a LabelReference
 ///     - `instructions` - collection which stores for each label the associated instructions
 ///         in a `Vec`
+#[derive(Clone)]
 pub struct IR {
     pub start_label: LabelReference,
     pub label_definitions: LabelLUT,
     pub instructions: HashMap<LabelReference, Vec<Instruction>>,
 }
 
+impl IR {
+    /// Number of words of the generated image, from address 0 to the end of
+    /// the last instruction
+    pub fn size(&self) -> usize {
+        let mut labels: Vec<&LabelDefinition> = self.label_definitions.0.values().collect();
+        labels.sort_by_key(|label| label.address);
+        labels.into_iter().fold(0, |end, label| {
+            let start = end.max(usize::from(label.address.0));
+            start + usize::from(self.block_size(&label.clone().into()))
+        })
+    }
+
+    /// Number of words of the code following `label` up to the next label,
+    /// 0 for labels aliasing the block of the label after them
+    pub fn block_size(&self, label: &LabelReference) -> u16 {
+        self.instructions.get(label).map_or(0, |instructions| {
+            instructions.iter().map(Instruction::size).sum()
+        })
+    }
+}
+
+/// Listing of the program in masm syntax, the labels in the order of their
+/// addresses, each with its doc comment, `.org` and attributes. Local labels
+/// are written as `.name:` below their global label and expressions the
+/// lexer can't read back, like `.equ` values substituted into a sum, as
+/// their value, so the listing assembles to the same program. A `.scratch`
+/// directive is written whenever a pseudo-instruction uses another scratch
+/// register than the one before. The alternate form `{:#}` precedes every
+/// label with a comment line holding its address, which assembles like the
+/// plain form.
+impl fmt::Display for IR {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut scratch = Register::new(crate::parser::DEFAULT_SCRATCH_REGISTER);
+        let mut labels: Vec<&LabelDefinition> = self.label_definitions.0.values().collect();
+        labels.sort_by_key(|label| (label.address, &label.name));
+        // global label local labels are written under
+        let mut scope: Option<&str> = None;
+        for label in labels {
+            // in front of the doc comment, which has to be right above the label
+            if f.alternate() {
+                writeln!(f, "; {:#06x}", label.address.0)?;
+            }
+            if let Some(doc) = &label.doc {
+                for line in doc.lines() {
+                    writeln!(f, ";; {}", line)?;
+                }
+            }
+            if label.fixed {
+                writeln!(f, ".org {:#06x}", label.address.0)?;
+            }
+            let attributes = label.attributes.names();
+            if !attributes.is_empty() {
+                writeln!(f, "[[{}]]", attributes.join(", "))?;
+            }
+            match label.name.split_once('.') {
+                Some((global, local)) if scope == Some(global) => writeln!(f, ".{}:", local)?,
+                Some(_) => writeln!(f, "{}:", label.name)?,
+                None => {
+                    scope = Some(&label.name);
+                    writeln!(f, "{}:", label.name)?;
+                }
+            }
+            let mut location = label.address.0;
+            for instruction in self
+                .instructions
+                .get(&label.clone().into())
+                .into_iter()
+                .flatten()
+            {
+                if let Some(register) = instruction.scratch().filter(|&r| r != scratch) {
+                    writeln!(f, ".scratch {}", register)?;
+                    scratch = register;
+                }
+                let mut instruction = instruction.clone();
+                for expression in instruction.expressions_mut() {
+                    if !expression.is_printable() {
+                        if let Ok(value) = expression.evaluate(&self.label_definitions, location) {
+                            *expression = Expression::Constant(value);
+                        }
+                    }
+                }
+                writeln!(f, "    {}", instruction)?;
+                location = location.wrapping_add(instruction.size());
+            }
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct LabelLUT(pub HashMap<LabelReference, LabelDefinition>);
 
@@ -38,6 +129,40 @@ impl Default for LabelLUT {
 pub struct LabelDefinition {
     pub name: String,
     pub address: MemoryAddress,
+    /// Text of the `;;` doc comment written above the label
+    pub doc: Option<String>,
+    pub attributes: LabelAttributes,
+    /// Placed at its address with `.org`
+    pub fixed: bool,
+}
+
+/// Metadata attached to a label with `[[attribute]]` lines
+/// in front of its definition
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LabelAttributes {
+    /// `[[noreturn]]` - code at this label never falls through or returns
+    pub noreturn: bool,
+    /// `[[interrupt]]` - label is an interrupt handler
+    pub interrupt: bool,
+    /// `[[align=N]]` - label address is a multiple of `N`
+    pub align: Option<u16>,
+}
+
+impl LabelAttributes {
+    /// Attributes as written between `[[` and `]]`
+    pub fn names(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        if self.noreturn {
+            names.push(String::from("noreturn"));
+        }
+        if self.interrupt {
+            names.push(String::from("interrupt"));
+        }
+        if let Some(align) = self.align {
+            names.push(format!("align={}", align));
+        }
+        names
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -48,6 +173,9 @@ impl LabelDefinition {
         LabelDefinition {
             name: name.into(),
             address: MemoryAddress(address),
+            doc: None,
+            attributes: LabelAttributes::default(),
+            fixed: false,
         }
     }
 }
@@ -81,9 +209,15 @@ impl PartialEq for LabelReference {
 
 impl Eq for LabelReference {}
 
+impl fmt::Display for LabelReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
 /// Enum which represents all possible instructions
 /// and its metadata for the assembled language
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Instruction {
     Move(UnaryExpression),
     Set32BitMode {
@@ -121,9 +255,418 @@ pub enum Instruction {
     ShiftLeft(BinaryExpression),
     ShiftRight(BinaryExpression),
     Negate(UnaryExpression),
+    /// Raw word emitted by `.word` or `.byte`
+    Data(Constant),
+    /// `ljmp` pseudo-instruction, lowered to an `ldc` of the target address
+    /// into `scratch` and a `jmp` through it, see [`crate::lowering`]
+    LongJump {
+        target: Expression,
+        scratch: Register,
+    },
+    /// `li` pseudo-instruction for 32 bit values, lowered to `ldc` of the
+    /// upper half, a shift by 16 and an `or` of the lower half through
+    /// `scratch`, see [`crate::lowering`]
+    LoadImmediate {
+        address: RegisterAddress,
+        value: Expression,
+        scratch: Register,
+    },
+    /// `addi`, `subi`, ... and shifts by a constant, lowered to an `ldc` of
+    /// the value into `scratch` and the register form of the operation
+    Immediate {
+        operation: Operation,
+        target: Register,
+        source: Register,
+        value: Expression,
+        scratch: Register,
+    },
+    /// `rol`/`ror` pseudo-instructions, lowered to shifts of `source` by the
+    /// constant `amount` and `width - amount` combined with `or`
+    Rotate {
+        direction: Direction,
+        target: Register,
+        source: Register,
+        amount: u16,
+        /// register width of the mode the rotation was written in
+        width: u16,
+        scratch: Register,
+    },
+    /// `div` pseudo-instruction, lowered to a loop subtracting `source_b`
+    /// from the remainder in `scratch` and counting in `target`
+    Divide {
+        expression: BinaryExpression,
+        scratch: Register,
+    },
+    /// `mod` pseudo-instruction, lowered to a loop subtracting `source_b`
+    /// from `target`
+    Modulo(BinaryExpression),
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+/// Direction of a `rol`/`ror` rotation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Left,
+    Right,
+}
+
+impl Direction {
+    /// Amount of a left rotation equivalent to rotating by `amount`
+    /// in this direction within a `width` bit register
+    pub fn left_amount(self, amount: u16, width: u16) -> u16 {
+        match self {
+            Direction::Left => amount % width,
+            Direction::Right => (width - amount % width) % width,
+        }
+    }
+}
+
+/// Register-register operations which have an immediate form
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Add,
+    Subtract,
+    AND,
+    OR,
+    XOR,
+    ShiftLeft,
+    ShiftRight,
+}
+
+impl Operation {
+    /// Instruction applying the operation to the registers of `expression`
+    pub fn instruction(self, expression: BinaryExpression) -> Instruction {
+        match self {
+            Operation::Add => Instruction::Add(expression),
+            Operation::Subtract => Instruction::Subtract(expression),
+            Operation::AND => Instruction::AND(expression),
+            Operation::OR => Instruction::OR(expression),
+            Operation::XOR => Instruction::XOR(expression),
+            Operation::ShiftLeft => Instruction::ShiftLeft(expression),
+            Operation::ShiftRight => Instruction::ShiftRight(expression),
+        }
+    }
+}
+
+impl Instruction {
+    /// Scratch register a pseudo-instruction is lowered through
+    pub fn scratch(&self) -> Option<Register> {
+        match self {
+            Instruction::LongJump { scratch, .. }
+            | Instruction::LoadImmediate { scratch, .. }
+            | Instruction::Immediate { scratch, .. }
+            | Instruction::Rotate { scratch, .. }
+            | Instruction::Divide { scratch, .. } => Some(*scratch),
+            _ => None,
+        }
+    }
+
+    /// Number of words the instruction occupies in the program
+    pub fn size(&self) -> u16 {
+        match self {
+            Instruction::LongJump { .. } => 2,
+            Instruction::LoadImmediate { .. } => 5,
+            Instruction::Immediate { .. } => 2,
+            Instruction::Rotate {
+                direction,
+                target,
+                source,
+                amount,
+                width,
+                ..
+            } => match direction.left_amount(*amount, *width) {
+                0 => 1,
+                _ if target != source => 5,
+                // the left shift is done by repeated doubling
+                left => 3 + left,
+            },
+            Instruction::Divide { .. } => 7,
+            Instruction::Modulo(_) => 5,
+            Instruction::Jump {
+                condition: JumpCondition::GreaterEqual | JumpCondition::LessEqual,
+                ..
+            } => 2,
+            Instruction::Jump {
+                condition: JumpCondition::Greater,
+                ..
+            } => 3,
+            _ => 1,
+        }
+    }
+
+    /// Whether execution may continue with the instruction behind this one
+    pub fn falls_through(&self) -> bool {
+        !matches!(
+            self,
+            Instruction::Halt
+                | Instruction::Data(_)
+                | Instruction::LongJump { .. }
+                | Instruction::Jump {
+                    condition: JumpCondition::True,
+                    ..
+                }
+        )
+    }
+
+    /// Returns all labels referenced by the instruction
+    pub fn label_references(&self) -> Vec<&LabelReference> {
+        match self {
+            Instruction::Jump {
+                target: JumpTarget::Label(label) | JumpTarget::LabelOffset { label, .. },
+                ..
+            } => vec![label],
+            Instruction::Load {
+                source: LoadSource::Expression(expression),
+                ..
+            }
+            | Instruction::LongJump {
+                target: expression, ..
+            }
+            | Instruction::LoadImmediate {
+                value: expression, ..
+            }
+            | Instruction::Immediate {
+                value: expression, ..
+            } => expression.label_references(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Returns all labels referenced by the instruction for renaming them
+    pub fn label_references_mut(&mut self) -> Vec<&mut LabelReference> {
+        match self {
+            Instruction::Jump {
+                target: JumpTarget::Label(label) | JumpTarget::LabelOffset { label, .. },
+                ..
+            } => vec![label],
+            Instruction::Load {
+                source: LoadSource::Expression(expression),
+                ..
+            }
+            | Instruction::LongJump {
+                target: expression, ..
+            }
+            | Instruction::LoadImmediate {
+                value: expression, ..
+            }
+            | Instruction::Immediate {
+                value: expression, ..
+            } => expression.label_references_mut(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Returns all expressions of the instruction for resolving them
+    pub fn expressions_mut(&mut self) -> Vec<&mut Expression> {
+        match self {
+            Instruction::Load {
+                source: LoadSource::Expression(expression),
+                ..
+            }
+            | Instruction::LongJump {
+                target: expression, ..
+            }
+            | Instruction::LoadImmediate {
+                value: expression, ..
+            }
+            | Instruction::Immediate {
+                value: expression, ..
+            } => vec![expression],
+            _ => Vec::new(),
+        }
+    }
+
+    /// Returns all registers the instruction reads or writes
+    pub fn registers(&self) -> Vec<RegisterAddress> {
+        match self {
+            Instruction::Move(u_expr)
+            | Instruction::Increment(u_expr)
+            | Instruction::Decrement(u_expr)
+            | Instruction::NOT(u_expr)
+            | Instruction::Negate(u_expr) => vec![u_expr.target.address, u_expr.source_a.address],
+            Instruction::Add(b_expr)
+            | Instruction::AddWithCarry(b_expr)
+            | Instruction::Subtract(b_expr)
+            | Instruction::SubtractWithCarry(b_expr)
+            | Instruction::Multiply(b_expr)
+            | Instruction::AND(b_expr)
+            | Instruction::OR(b_expr)
+            | Instruction::XOR(b_expr)
+            | Instruction::XNOR(b_expr)
+            | Instruction::ShiftLeft(b_expr)
+            | Instruction::ShiftRight(b_expr)
+            | Instruction::Modulo(b_expr) => vec![
+                b_expr.target.address,
+                b_expr.source_a.address,
+                b_expr.source_b.address,
+            ],
+            Instruction::Add3(t_expr) => vec![
+                t_expr.target.address,
+                t_expr.source_a.address,
+                t_expr.source_b.address,
+                t_expr.source_c.address,
+            ],
+            Instruction::Test(b_stat) => vec![b_stat.source_a.address, b_stat.source_b.address],
+            Instruction::Load {
+                address,
+                source: LoadSource::RAM { address_register },
+            } => vec![*address, address_register.address],
+            Instruction::Load { address, .. } => vec![*address],
+            Instruction::StoreRAM {
+                address_register,
+                data_register,
+            } => vec![*address_register, *data_register],
+            Instruction::Jump {
+                target: JumpTarget::Register(register),
+                ..
+            } => vec![register.address],
+            Instruction::LongJump { scratch, .. } => vec![scratch.address],
+            Instruction::LoadImmediate {
+                address, scratch, ..
+            } => vec![*address, scratch.address],
+            Instruction::Immediate {
+                target,
+                source,
+                scratch,
+                ..
+            }
+            | Instruction::Rotate {
+                target,
+                source,
+                scratch,
+                ..
+            } => vec![target.address, source.address, scratch.address],
+            Instruction::Divide {
+                expression,
+                scratch,
+            } => vec![
+                expression.target.address,
+                expression.source_a.address,
+                expression.source_b.address,
+                scratch.address,
+            ],
+            Instruction::Jump { .. }
+            | Instruction::Set32BitMode { .. }
+            | Instruction::Halt
+            | Instruction::Debug
+            | Instruction::Noop
+            | Instruction::Data(_) => Vec::new(),
+        }
+    }
+}
+
+/// Canonical masm syntax of the instruction, e.g. `add %reg0 %reg1 %reg2`.
+/// Scratch registers of pseudo-instructions are chosen with `.scratch` and
+/// are not part of the instruction.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::Move(e) => write!(f, "mov {} {}", e.target, e.source_a),
+            Instruction::Set32BitMode { enable } => write!(f, "s32b {}", enable.0),
+            Instruction::Load { address, source } => match source {
+                LoadSource::Constant(value) => write!(f, "ldc {} {:#x}", address, value),
+                LoadSource::Expression(expression) => write!(f, "ldc {} {}", address, expression),
+                LoadSource::RAM { address_register } => {
+                    write!(f, "ld {} {}", address, address_register)
+                }
+                // no syntax of its own, kept visible as a comment
+                LoadSource::Pgm => write!(f, "; ld {} pgm", address),
+            },
+            Instruction::StoreRAM {
+                address_register,
+                data_register,
+            } => write!(f, "st {} {}", address_register, data_register),
+            Instruction::Halt => write!(f, "hlt"),
+            Instruction::Debug => write!(f, "dbg"),
+            Instruction::Noop => write!(f, "nop"),
+            Instruction::Jump { target, condition } => {
+                let mnemonic = match (target, condition) {
+                    (JumpTarget::Register(_), JumpCondition::True) => "jmp",
+                    (JumpTarget::Register(_), JumpCondition::Zero) => "jz",
+                    (JumpTarget::Register(_), JumpCondition::NotZero) => "jnz",
+                    (JumpTarget::Register(_), JumpCondition::Less) => "jc",
+                    (JumpTarget::Register(_), JumpCondition::Overflow) => "jo",
+                    (JumpTarget::Register(_), JumpCondition::GreaterEqual) => "jge",
+                    (JumpTarget::Register(_), JumpCondition::LessEqual) => "jle",
+                    (JumpTarget::Register(_), JumpCondition::Greater) => "jgt",
+                    (_, JumpCondition::True) => "jr",
+                    (_, JumpCondition::Zero) => "jzr",
+                    (_, JumpCondition::NotZero) => "jnzr",
+                    (_, JumpCondition::Less) => "jcr",
+                    (_, JumpCondition::Overflow) => "jor",
+                    (_, JumpCondition::GreaterEqual) => "jger",
+                    (_, JumpCondition::LessEqual) => "jler",
+                    (_, JumpCondition::Greater) => "jgtr",
+                };
+                write!(f, "{} {}", mnemonic, target)
+            }
+            Instruction::Add(e) => write_binary(f, "add", e),
+            Instruction::Add3(e) => write!(
+                f,
+                "add3 {} {} {} {}",
+                e.target, e.source_a, e.source_b, e.source_c
+            ),
+            Instruction::AddWithCarry(e) => write_binary(f, "addc", e),
+            Instruction::Subtract(e) => write_binary(f, "sub", e),
+            Instruction::SubtractWithCarry(e) => write_binary(f, "subc", e),
+            Instruction::Increment(e) => write!(f, "inc {}", e.target),
+            Instruction::Decrement(e) => write!(f, "dec {}", e.target),
+            Instruction::Multiply(e) => write_binary(f, "mul", e),
+            Instruction::Test(s) => write!(f, "tst {} {}", s.source_a, s.source_b),
+            Instruction::AND(e) => write_binary(f, "and", e),
+            Instruction::OR(e) => write_binary(f, "or", e),
+            Instruction::NOT(e) => write!(f, "not {} {}", e.target, e.source_a),
+            Instruction::XOR(e) => write_binary(f, "xor", e),
+            Instruction::XNOR(e) => write_binary(f, "xnor", e),
+            Instruction::ShiftLeft(e) => write_binary(f, "shl", e),
+            Instruction::ShiftRight(e) => write_binary(f, "shr", e),
+            Instruction::Negate(e) => write!(f, "neg {} {}", e.target, e.source_a),
+            Instruction::Data(constant) => write!(f, ".word {:#x}", constant.0),
+            Instruction::LongJump { target, .. } => write!(f, "ljmp {}", target),
+            Instruction::LoadImmediate { address, value, .. } => {
+                write!(f, "li {} {}", address, value)
+            }
+            Instruction::Immediate {
+                operation,
+                target,
+                source,
+                value,
+                ..
+            } => match operation {
+                Operation::ShiftLeft => write!(f, "shl {} {} {}", target, source, value),
+                Operation::ShiftRight => write!(f, "shr {} {} {}", target, source, value),
+                // the other immediate forms operate on `target` in place
+                Operation::Add => write!(f, "addi {} {}", target, value),
+                Operation::Subtract => write!(f, "subi {} {}", target, value),
+                Operation::AND => write!(f, "andi {} {}", target, value),
+                Operation::OR => write!(f, "ori {} {}", target, value),
+                Operation::XOR => write!(f, "xori {} {}", target, value),
+            },
+            Instruction::Rotate {
+                direction,
+                target,
+                source,
+                amount,
+                ..
+            } => {
+                let mnemonic = match direction {
+                    Direction::Left => "rol",
+                    Direction::Right => "ror",
+                };
+                write!(f, "{} {} {} {}", mnemonic, target, source, amount)
+            }
+            Instruction::Divide { expression, .. } => write_binary(f, "div", expression),
+            Instruction::Modulo(e) => write_binary(f, "mod", e),
+        }
+    }
+}
+
+fn write_binary(f: &mut fmt::Formatter<'_>, mnemonic: &str, e: &BinaryExpression) -> fmt::Result {
+    write!(f, "{} {} {} {}", mnemonic, e.target, e.source_a, e.source_b)
+}
+/// Number of registers in the register file, `%reg0` .. `%reg7`
+pub const REGISTER_COUNT: u8 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct RegisterAddress(pub u8);
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct MemoryAddress(pub u16);
@@ -147,7 +690,19 @@ impl Register {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+impl fmt::Display for RegisterAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "%reg{}", self.0)
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.address)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct UnaryExpression {
     pub target: Register,
     pub source_a: Register,
@@ -159,7 +714,7 @@ impl UnaryExpression {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct UnaryStatement {
     pub source_a: Register,
 }
@@ -170,7 +725,7 @@ impl UnaryStatement {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct BinaryExpression {
     pub target: Register,
     pub source_a: Register,
@@ -187,7 +742,7 @@ impl BinaryExpression {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct BinaryStatement {
     pub source_a: Register,
     pub source_b: Register,
@@ -199,7 +754,7 @@ impl BinaryStatement {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TernaryExpression {
     pub target: Register,
     pub source_a: Register,
@@ -223,25 +778,178 @@ impl TernaryExpression {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum LoadSource {
     Constant(u16),
-    RAM { address_register: Register },
+    /// Constant computed by the generator, see [`Expression::evaluate`]
+    Expression(Expression),
+    RAM {
+        address_register: Register,
+    },
     Pgm,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum JumpTarget {
     Constant(u16),
     Register(Register),
     Label(LabelReference),
+    /// `label+offset` / `label-offset` - target is `offset` words after the label
+    LabelOffset {
+        label: LabelReference,
+        offset: i16,
+    },
+}
+
+/// Relative targets are written as signed word offsets, e.g. `-0x2`
+impl fmt::Display for JumpTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JumpTarget::Constant(offset) => write_signed(f, "", *offset as i16),
+            JumpTarget::Register(register) => write!(f, "{}", register),
+            JumpTarget::Label(label) => write!(f, "{}", label),
+            JumpTarget::LabelOffset { label, offset } => write_signed(f, label.name(), *offset),
+        }
+    }
+}
+
+/// `base+0xN` or `base-0xN`, a positive value without base has no sign
+fn write_signed(f: &mut fmt::Formatter<'_>, base: &str, value: i16) -> fmt::Result {
+    let sign = if value < 0 {
+        "-"
+    } else if base.is_empty() {
+        ""
+    } else {
+        "+"
+    };
+    write!(f, "{}{}{:#x}", base, sign, value.unsigned_abs())
+}
+
+/// Operand value that depends on label addresses
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expression {
+    Constant(u32),
+    /// Address of the label
+    Label(LabelReference),
+    /// `$` - address of the instruction the expression belongs to
+    Location,
+    /// `lo(expression)` - lower 16 bits
+    Low(Box<Expression>),
+    /// `hi(expression)` - upper 16 bits
+    High(Box<Expression>),
+    /// `a+b`
+    Sum(Box<Expression>, Box<Expression>),
+    /// `a-b`
+    Difference(Box<Expression>, Box<Expression>),
+}
+
+impl Expression {
+    /// Returns all label references of the expression
+    pub fn label_references(&self) -> Vec<&LabelReference> {
+        match self {
+            Expression::Label(reference) => vec![reference],
+            Expression::Low(expression) | Expression::High(expression) => {
+                expression.label_references()
+            }
+            Expression::Sum(a, b) | Expression::Difference(a, b) => {
+                let mut references = a.label_references();
+                references.extend(b.label_references());
+                references
+            }
+            Expression::Constant(_) | Expression::Location => Vec::new(),
+        }
+    }
+
+    /// Returns all label references of the expression for renaming them
+    pub fn label_references_mut(&mut self) -> Vec<&mut LabelReference> {
+        match self {
+            Expression::Label(reference) => vec![reference],
+            Expression::Low(expression) | Expression::High(expression) => {
+                expression.label_references_mut()
+            }
+            Expression::Sum(a, b) | Expression::Difference(a, b) => {
+                let mut references = a.label_references_mut();
+                references.extend(b.label_references_mut());
+                references
+            }
+            Expression::Constant(_) | Expression::Location => Vec::new(),
+        }
+    }
+
+    /// Whether the lexer reads the printed expression back: a number, `$`
+    /// or label, one operator between two of them, or `lo()`/`hi()` of such
+    /// an expression
+    pub fn is_printable(&self) -> bool {
+        let atom = |expression: &Expression| {
+            matches!(
+                expression,
+                Expression::Constant(_) | Expression::Label(_) | Expression::Location
+            )
+        };
+        match self {
+            Expression::Low(expression) | Expression::High(expression) => expression.is_printable(),
+            Expression::Sum(a, b) | Expression::Difference(a, b) => atom(a) && atom(b),
+            expression => atom(expression),
+        }
+    }
+
+    /// Computes the value of the expression for the instruction at
+    /// `location`, fails with the first label that is not defined in `labels`.
+    pub fn evaluate(&self, labels: &LabelLUT, location: u16) -> Result<u32, LabelReference> {
+        match self {
+            Expression::Constant(value) => Ok(*value),
+            Expression::Label(reference) => labels
+                .0
+                .get(reference)
+                .map(|label| u32::from(label.address.0))
+                .ok_or_else(|| reference.clone()),
+            Expression::Location => Ok(u32::from(location)),
+            Expression::Low(expression) => Ok(expression.evaluate(labels, location)? & 0xffff),
+            Expression::High(expression) => Ok(expression.evaluate(labels, location)? >> 16),
+            Expression::Sum(a, b) => Ok(a
+                .evaluate(labels, location)?
+                .wrapping_add(b.evaluate(labels, location)?)),
+            Expression::Difference(a, b) => Ok(a
+                .evaluate(labels, location)?
+                .wrapping_sub(b.evaluate(labels, location)?)),
+        }
+    }
+}
+
+/// Expressions are read back only up to one operator below `lo()`/`hi()`,
+/// deeper ones like `.equ` values substituted into a sum are printed as is,
+/// see [`Expression::is_printable`]
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expression::Constant(value) => write!(f, "{:#x}", value),
+            Expression::Label(label) => write!(f, "{}", label),
+            Expression::Location => write!(f, "$"),
+            Expression::Low(expression) => write!(f, "lo({})", expression),
+            Expression::High(expression) => write!(f, "hi({})", expression),
+            Expression::Sum(a, b) => write!(f, "{}+{}", a, b),
+            Expression::Difference(a, b) => write!(f, "{}-{}", a, b),
+        }
+    }
+}
+
+/// Whether `value` fits into a 16 bit constant, either unsigned or as
+/// two's complement like the result of `label-4` for a label below 4
+pub fn fits_16_bits(value: u32) -> bool {
+    value <= 0xffff || value >= 0xffff_8000
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum JumpCondition {
     True,
     Zero,
     NotZero,
     Less,
     Overflow,
+    /// not `Less`, lowered to a `Less` jump over an unconditional jump
+    GreaterEqual,
+    /// `Less` or `Zero`, lowered to a jump for each of them
+    LessEqual,
+    /// neither `Less` nor `Zero`, lowered to jumps over an unconditional jump
+    Greater,
 }
@@ -0,0 +1,1198 @@
+use std::fmt;
+
+use crate::diagnostic::{self, display_line, json_string};
+use crate::ir;
+
+/// Errors of reading a program serialized with [`to_json`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IrJsonError {
+    /// The input is no valid JSON
+    Syntax {
+        message: String,
+        line_number: u16,
+        column: u16,
+    },
+    /// The JSON does not describe a program, `path` leads to the offending
+    /// value, e.g. `labels[0].instructions[2].target`
+    InvalidValue {
+        path: String,
+        message: String,
+    },
+    DuplicateLabel {
+        label: String,
+    },
+    UndefinedEntry {
+        label: String,
+    },
+}
+
+impl fmt::Display for IrJsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IrJsonError::Syntax {
+                message,
+                line_number,
+                column,
+            } => write!(
+                f,
+                "Invalid JSON at line {}, column {}: {}",
+                display_line(*line_number),
+                column,
+                message
+            ),
+            IrJsonError::InvalidValue { path, message } => {
+                write!(f, "Invalid value of '{}': {}", path, message)
+            }
+            IrJsonError::DuplicateLabel { label } => {
+                write!(f, "Label '{}' is defined more than once", label)
+            }
+            IrJsonError::UndefinedEntry { label } => {
+                write!(f, "Start label '{}' is not defined", label)
+            }
+        }
+    }
+}
+
+impl std::error::Error for IrJsonError {}
+
+impl IrJsonError {
+    /// Position of malformed JSON in the input
+    pub fn position(&self) -> Option<diagnostic::Position> {
+        match *self {
+            IrJsonError::Syntax {
+                line_number,
+                column,
+                ..
+            } => Some((line_number, Some(column))),
+            _ => None,
+        }
+    }
+
+    /// Stable name of the error kind, e.g. for machine readable diagnostics
+    pub fn code(&self) -> &'static str {
+        match self {
+            IrJsonError::Syntax { .. } => "json-syntax",
+            IrJsonError::InvalidValue { .. } => "invalid-value",
+            IrJsonError::DuplicateLabel { .. } => "duplicate-label",
+            IrJsonError::UndefinedEntry { .. } => "undefined-entry",
+        }
+    }
+}
+
+/// Serializes `ir` as JSON, one instruction per line. The labels are
+/// written in the order of their addresses:
+///
+/// ```text
+/// {
+///   "start": "main",
+///   "labels": [
+///     {
+///       "name": "main",
+///       "address": 0,
+///       "instructions": [
+///         {"instruction": "load", "address": 0, "source": {"constant": 5}},
+///         {"instruction": "halt"}
+///       ]
+///     }
+///   ]
+/// }
+/// ```
+pub fn to_json(ir: &ir::IR) -> String {
+    let mut labels: Vec<&ir::LabelDefinition> = ir.label_definitions.0.values().collect();
+    labels.sort_by_key(|label| (label.address, &label.name));
+    let labels: Vec<String> = labels
+        .into_iter()
+        .map(|label| {
+            let mut fields = vec![
+                format!("\"name\": {}", json_string(&label.name)),
+                format!("\"address\": {}", label.address.0),
+            ];
+            if label.fixed {
+                fields.push(String::from("\"fixed\": true"));
+            }
+            if let Some(doc) = &label.doc {
+                fields.push(format!("\"doc\": {}", json_string(doc)));
+            }
+            if label.attributes.noreturn {
+                fields.push(String::from("\"noreturn\": true"));
+            }
+            if label.attributes.interrupt {
+                fields.push(String::from("\"interrupt\": true"));
+            }
+            if let Some(align) = label.attributes.align {
+                fields.push(format!("\"align\": {}", align));
+            }
+            let instructions: Vec<String> = ir
+                .instructions
+                .get(&label.clone().into())
+                .into_iter()
+                .flatten()
+                .map(|instruction| format!("        {}", instruction_json(instruction)))
+                .collect();
+            fields.push(if instructions.is_empty() {
+                String::from("\"instructions\": []")
+            } else {
+                format!("\"instructions\": [\n{}\n      ]", instructions.join(",\n"))
+            });
+            format!("    {{\n      {}\n    }}", fields.join(",\n      "))
+        })
+        .collect();
+    format!(
+        "{{\n  \"start\": {},\n  \"labels\": [\n{}\n  ]\n}}\n",
+        json_string(ir.start_label.name()),
+        labels.join(",\n")
+    )
+}
+
+fn object(fields: &[(&str, String)]) -> String {
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|(key, value)| format!("{}: {}", json_string(key), value))
+        .collect();
+    format!("{{{}}}", fields.join(", "))
+}
+
+fn register_json(register: &ir::Register) -> String {
+    register.addr().to_string()
+}
+
+fn instruction_json(instruction: &ir::Instruction) -> String {
+    let name = |name: &str| ("instruction", json_string(name));
+    let unary = |instruction: &str, e: &ir::UnaryExpression| {
+        object(&[
+            name(instruction),
+            ("target", register_json(&e.target)),
+            ("source_a", register_json(&e.source_a)),
+        ])
+    };
+    let binary = |instruction: &str, e: &ir::BinaryExpression| {
+        object(&[
+            name(instruction),
+            ("target", register_json(&e.target)),
+            ("source_a", register_json(&e.source_a)),
+            ("source_b", register_json(&e.source_b)),
+        ])
+    };
+    match instruction {
+        ir::Instruction::Move(e) => unary("move", e),
+        ir::Instruction::Set32BitMode { enable } => {
+            object(&[name("set-32-bit-mode"), ("enable", enable.0.to_string())])
+        }
+        ir::Instruction::Load { address, source } => object(&[
+            name("load"),
+            ("address", address.0.to_string()),
+            ("source", load_source_json(source)),
+        ]),
+        ir::Instruction::StoreRAM {
+            address_register,
+            data_register,
+        } => object(&[
+            name("store-ram"),
+            ("address_register", address_register.0.to_string()),
+            ("data_register", data_register.0.to_string()),
+        ]),
+        ir::Instruction::Halt => object(&[name("halt")]),
+        ir::Instruction::Debug => object(&[name("debug")]),
+        ir::Instruction::Noop => object(&[name("noop")]),
+        ir::Instruction::Jump { target, condition } => object(&[
+            name("jump"),
+            ("target", jump_target_json(target)),
+            ("condition", json_string(condition_name(condition))),
+        ]),
+        ir::Instruction::Add(e) => binary("add", e),
+        ir::Instruction::Add3(e) => object(&[
+            name("add3"),
+            ("target", register_json(&e.target)),
+            ("source_a", register_json(&e.source_a)),
+            ("source_b", register_json(&e.source_b)),
+            ("source_c", register_json(&e.source_c)),
+        ]),
+        ir::Instruction::AddWithCarry(e) => binary("add-with-carry", e),
+        ir::Instruction::Subtract(e) => binary("subtract", e),
+        ir::Instruction::SubtractWithCarry(e) => binary("subtract-with-carry", e),
+        ir::Instruction::Increment(e) => unary("increment", e),
+        ir::Instruction::Decrement(e) => unary("decrement", e),
+        ir::Instruction::Multiply(e) => binary("multiply", e),
+        ir::Instruction::Test(s) => object(&[
+            name("test"),
+            ("source_a", register_json(&s.source_a)),
+            ("source_b", register_json(&s.source_b)),
+        ]),
+        ir::Instruction::AND(e) => binary("and", e),
+        ir::Instruction::OR(e) => binary("or", e),
+        ir::Instruction::NOT(e) => unary("not", e),
+        ir::Instruction::XOR(e) => binary("xor", e),
+        ir::Instruction::XNOR(e) => binary("xnor", e),
+        ir::Instruction::ShiftLeft(e) => binary("shift-left", e),
+        ir::Instruction::ShiftRight(e) => binary("shift-right", e),
+        ir::Instruction::Negate(e) => unary("negate", e),
+        ir::Instruction::Data(constant) => {
+            object(&[name("data"), ("value", constant.0.to_string())])
+        }
+        ir::Instruction::LongJump { target, scratch } => object(&[
+            name("long-jump"),
+            ("target", expression_json(target)),
+            ("scratch", register_json(scratch)),
+        ]),
+        ir::Instruction::LoadImmediate {
+            address,
+            value,
+            scratch,
+        } => object(&[
+            name("load-immediate"),
+            ("address", address.0.to_string()),
+            ("value", expression_json(value)),
+            ("scratch", register_json(scratch)),
+        ]),
+        ir::Instruction::Immediate {
+            operation,
+            target,
+            source,
+            value,
+            scratch,
+        } => object(&[
+            name("immediate"),
+            ("operation", json_string(operation_name(operation))),
+            ("target", register_json(target)),
+            ("source", register_json(source)),
+            ("value", expression_json(value)),
+            ("scratch", register_json(scratch)),
+        ]),
+        ir::Instruction::Rotate {
+            direction,
+            target,
+            source,
+            amount,
+            width,
+            scratch,
+        } => object(&[
+            name("rotate"),
+            ("direction", json_string(direction_name(direction))),
+            ("target", register_json(target)),
+            ("source", register_json(source)),
+            ("amount", amount.to_string()),
+            ("width", width.to_string()),
+            ("scratch", register_json(scratch)),
+        ]),
+        ir::Instruction::Divide {
+            expression,
+            scratch,
+        } => object(&[
+            name("divide"),
+            ("target", register_json(&expression.target)),
+            ("source_a", register_json(&expression.source_a)),
+            ("source_b", register_json(&expression.source_b)),
+            ("scratch", register_json(scratch)),
+        ]),
+        ir::Instruction::Modulo(e) => binary("modulo", e),
+    }
+}
+
+/// `{"constant": N}`, `{"expression": EXPRESSION}`, `{"ram": REGISTER}` or
+/// `"pgm"`
+fn load_source_json(source: &ir::LoadSource) -> String {
+    match source {
+        ir::LoadSource::Constant(value) => object(&[("constant", value.to_string())]),
+        ir::LoadSource::Expression(expression) => {
+            object(&[("expression", expression_json(expression))])
+        }
+        ir::LoadSource::RAM { address_register } => {
+            object(&[("ram", register_json(address_register))])
+        }
+        ir::LoadSource::Pgm => json_string("pgm"),
+    }
+}
+
+/// `{"register": REGISTER}`, `{"offset": N}` relative to the jump,
+/// `{"label": NAME}` or `{"label": NAME, "offset": N}`
+fn jump_target_json(target: &ir::JumpTarget) -> String {
+    match target {
+        ir::JumpTarget::Constant(offset) => object(&[("offset", (*offset as i16).to_string())]),
+        ir::JumpTarget::Register(register) => object(&[("register", register_json(register))]),
+        ir::JumpTarget::Label(label) => object(&[("label", json_string(label.name()))]),
+        ir::JumpTarget::LabelOffset { label, offset } => object(&[
+            ("label", json_string(label.name())),
+            ("offset", offset.to_string()),
+        ]),
+    }
+}
+
+/// A number, `"$"`, the name of a label, `{"lo": EXPRESSION}`,
+/// `{"hi": EXPRESSION}`, `{"sum": [A, B]}` or `{"difference": [A, B]}`
+fn expression_json(expression: &ir::Expression) -> String {
+    match expression {
+        ir::Expression::Constant(value) => value.to_string(),
+        ir::Expression::Label(label) => json_string(label.name()),
+        ir::Expression::Location => json_string("$"),
+        ir::Expression::Low(e) => object(&[("lo", expression_json(e))]),
+        ir::Expression::High(e) => object(&[("hi", expression_json(e))]),
+        ir::Expression::Sum(a, b) => object(&[(
+            "sum",
+            format!("[{}, {}]", expression_json(a), expression_json(b)),
+        )]),
+        ir::Expression::Difference(a, b) => object(&[(
+            "difference",
+            format!("[{}, {}]", expression_json(a), expression_json(b)),
+        )]),
+    }
+}
+
+const CONDITIONS: &[(&str, ir::JumpCondition)] = &[
+    ("always", ir::JumpCondition::True),
+    ("zero", ir::JumpCondition::Zero),
+    ("not-zero", ir::JumpCondition::NotZero),
+    ("less", ir::JumpCondition::Less),
+    ("overflow", ir::JumpCondition::Overflow),
+    ("greater-equal", ir::JumpCondition::GreaterEqual),
+    ("less-equal", ir::JumpCondition::LessEqual),
+    ("greater", ir::JumpCondition::Greater),
+];
+
+const OPERATIONS: &[(&str, ir::Operation)] = &[
+    ("add", ir::Operation::Add),
+    ("subtract", ir::Operation::Subtract),
+    ("and", ir::Operation::AND),
+    ("or", ir::Operation::OR),
+    ("xor", ir::Operation::XOR),
+    ("shift-left", ir::Operation::ShiftLeft),
+    ("shift-right", ir::Operation::ShiftRight),
+];
+
+const DIRECTIONS: &[(&str, ir::Direction)] = &[
+    ("left", ir::Direction::Left),
+    ("right", ir::Direction::Right),
+];
+
+fn condition_name(condition: &ir::JumpCondition) -> &'static str {
+    name_of(CONDITIONS, condition)
+}
+
+fn operation_name(operation: &ir::Operation) -> &'static str {
+    name_of(OPERATIONS, operation)
+}
+
+fn direction_name(direction: &ir::Direction) -> &'static str {
+    name_of(DIRECTIONS, direction)
+}
+
+fn name_of<T: PartialEq>(names: &[(&'static str, T)], value: &T) -> &'static str {
+    names
+        .iter()
+        .find(|(_, candidate)| candidate == value)
+        .map(|(name, _)| *name)
+        .expect("every value has a name")
+}
+
+/// Reads a program written by [`to_json`]. Labels without an `address` are
+/// placed right behind the code of the label in front of them, aligned to
+/// their `align`. Without `start` the program starts at the first label,
+/// without `scratch` pseudo-instructions use the default scratch register.
+pub fn from_json(text: &str) -> Result<ir::IR, IrJsonError> {
+    let value = Reader::new(text).document()?;
+    let program = Fields::new(String::new(), &value)?;
+
+    let labels = program.array("labels")?;
+    let mut ir = ir::IR {
+        start_label: ir::LabelReference::new(""),
+        label_definitions: ir::LabelLUT::with_capacity(labels.len()),
+        instructions: std::collections::HashMap::with_capacity(labels.len()),
+    };
+    let mut end: u16 = 0;
+    for (idx, label) in labels.iter().enumerate() {
+        let label = Fields::new(format!("labels[{}]", idx), label)?;
+        let name = label.string("name")?;
+        let align = label.optional("align", |key| {
+            label
+                .number(key, 1, i64::from(u16::MAX))
+                .map(|align| align as u16)
+        })?;
+        let address = match label.optional("address", |key| label.u16(key))? {
+            Some(address) => address,
+            None => align
+                .map_or(Some(end), |align| end.checked_next_multiple_of(align))
+                .ok_or_else(|| label.invalid("address", "label does not fit into memory"))?,
+        };
+        let instructions = label
+            .array("instructions")?
+            .iter()
+            .enumerate()
+            .map(|(idx, instruction)| {
+                instruction_from_json(Fields::new(
+                    format!("{}.instructions[{}]", label.path, idx),
+                    instruction,
+                )?)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let size: u32 = instructions
+            .iter()
+            .map(|instruction| u32::from(instruction.size()))
+            .sum();
+        end = u16::try_from(u32::from(address) + size)
+            .map_err(|_| label.invalid("instructions", "label does not fit into memory"))?;
+
+        let definition = ir::LabelDefinition {
+            name: name.to_owned(),
+            address: ir::MemoryAddress(address),
+            doc: label
+                .optional("doc", |key| label.string(key))?
+                .map(String::from),
+            attributes: ir::LabelAttributes {
+                noreturn: label
+                    .optional("noreturn", |key| label.bool(key))?
+                    .unwrap_or(false),
+                interrupt: label
+                    .optional("interrupt", |key| label.bool(key))?
+                    .unwrap_or(false),
+                align,
+            },
+            fixed: label
+                .optional("fixed", |key| label.bool(key))?
+                .unwrap_or(false),
+        };
+        let reference = ir::LabelReference::new(name);
+        if idx == 0 {
+            ir.start_label = reference.clone();
+        }
+        if ir
+            .label_definitions
+            .0
+            .insert(reference.clone(), definition)
+            .is_some()
+        {
+            return Err(IrJsonError::DuplicateLabel {
+                label: name.to_owned(),
+            });
+        }
+        ir.instructions.insert(reference, instructions);
+    }
+
+    match program.optional("start", |key| program.string(key))? {
+        Some(start) => ir.start_label = ir::LabelReference::new(start),
+        None if labels.is_empty() => return Err(program.invalid("labels", "program has no labels")),
+        None => (),
+    }
+    if !ir.label_definitions.0.contains_key(&ir.start_label) {
+        return Err(IrJsonError::UndefinedEntry {
+            label: ir.start_label.name().to_owned(),
+        });
+    }
+    Ok(ir)
+}
+
+fn instruction_from_json(fields: Fields) -> Result<ir::Instruction, IrJsonError> {
+    let register = |key: &str| fields.register(key);
+    let unary = || -> Result<_, IrJsonError> {
+        Ok(ir::UnaryExpression::new(
+            register("target")?,
+            register("source_a")?,
+        ))
+    };
+    let binary = || -> Result<_, IrJsonError> {
+        Ok(ir::BinaryExpression::new(
+            register("target")?,
+            register("source_a")?,
+            register("source_b")?,
+        ))
+    };
+    let scratch = || -> Result<_, IrJsonError> {
+        Ok(fields
+            .optional("scratch", |key| fields.register(key))?
+            .unwrap_or(ir::Register::new(crate::parser::DEFAULT_SCRATCH_REGISTER)))
+    };
+    Ok(match fields.string("instruction")? {
+        "move" => ir::Instruction::Move(unary()?),
+        "set-32-bit-mode" => ir::Instruction::Set32BitMode {
+            enable: ir::Boolean(fields.bool("enable")?),
+        },
+        "load" => ir::Instruction::Load {
+            address: register("address")?.address,
+            source: load_source_from_json(&fields)?,
+        },
+        "store-ram" => ir::Instruction::StoreRAM {
+            address_register: register("address_register")?.address,
+            data_register: register("data_register")?.address,
+        },
+        "halt" => ir::Instruction::Halt,
+        "debug" => ir::Instruction::Debug,
+        "noop" => ir::Instruction::Noop,
+        "jump" => ir::Instruction::Jump {
+            target: jump_target_from_json(&Fields::new(
+                fields.path("target"),
+                fields.required("target")?,
+            )?)?,
+            condition: fields.name("condition", CONDITIONS)?,
+        },
+        "add" => ir::Instruction::Add(binary()?),
+        "add3" => ir::Instruction::Add3(ir::TernaryExpression::new(
+            register("target")?,
+            register("source_a")?,
+            register("source_b")?,
+            register("source_c")?,
+        )),
+        "add-with-carry" => ir::Instruction::AddWithCarry(binary()?),
+        "subtract" => ir::Instruction::Subtract(binary()?),
+        "subtract-with-carry" => ir::Instruction::SubtractWithCarry(binary()?),
+        "increment" => ir::Instruction::Increment(unary()?),
+        "decrement" => ir::Instruction::Decrement(unary()?),
+        "multiply" => ir::Instruction::Multiply(binary()?),
+        "test" => ir::Instruction::Test(ir::BinaryStatement::new(
+            register("source_a")?,
+            register("source_b")?,
+        )),
+        "and" => ir::Instruction::AND(binary()?),
+        "or" => ir::Instruction::OR(binary()?),
+        "not" => ir::Instruction::NOT(unary()?),
+        "xor" => ir::Instruction::XOR(binary()?),
+        "xnor" => ir::Instruction::XNOR(binary()?),
+        "shift-left" => ir::Instruction::ShiftLeft(binary()?),
+        "shift-right" => ir::Instruction::ShiftRight(binary()?),
+        "negate" => ir::Instruction::Negate(unary()?),
+        "data" => ir::Instruction::Data(ir::Constant(fields.word("value")?)),
+        "long-jump" => ir::Instruction::LongJump {
+            target: fields.expression("target")?,
+            scratch: scratch()?,
+        },
+        "load-immediate" => ir::Instruction::LoadImmediate {
+            address: register("address")?.address,
+            value: fields.expression("value")?,
+            scratch: scratch()?,
+        },
+        "immediate" => ir::Instruction::Immediate {
+            operation: fields.name("operation", OPERATIONS)?,
+            target: register("target")?,
+            source: register("source")?,
+            value: fields.expression("value")?,
+            scratch: scratch()?,
+        },
+        "rotate" => ir::Instruction::Rotate {
+            direction: fields.name("direction", DIRECTIONS)?,
+            target: register("target")?,
+            source: register("source")?,
+            amount: fields.u16("amount")?,
+            width: match fields.optional("width", |key| fields.u16(key))? {
+                None => 16,
+                Some(width @ (16 | 32)) => width,
+                Some(_) => return Err(fields.invalid("width", "expected 16 or 32")),
+            },
+            scratch: scratch()?,
+        },
+        "divide" => ir::Instruction::Divide {
+            expression: binary()?,
+            scratch: scratch()?,
+        },
+        "modulo" => ir::Instruction::Modulo(binary()?),
+        _ => return Err(fields.invalid("instruction", "unknown instruction")),
+    })
+}
+
+fn load_source_from_json(fields: &Fields) -> Result<ir::LoadSource, IrJsonError> {
+    let value = fields.required("source")?;
+    if value == &Value::String(String::from("pgm")) {
+        return Ok(ir::LoadSource::Pgm);
+    }
+    let source = Fields::new(fields.path("source"), value)?;
+    if source.get("constant").is_some() {
+        Ok(ir::LoadSource::Constant(source.word("constant")?))
+    } else if source.get("expression").is_some() {
+        Ok(ir::LoadSource::Expression(source.expression("expression")?))
+    } else if source.get("ram").is_some() {
+        Ok(ir::LoadSource::RAM {
+            address_register: source.register("ram")?,
+        })
+    } else {
+        Err(fields.invalid(
+            "source",
+            "expected 'constant', 'expression', 'ram' or \"pgm\"",
+        ))
+    }
+}
+
+fn jump_target_from_json(target: &Fields) -> Result<ir::JumpTarget, IrJsonError> {
+    let offset = target.optional("offset", |key| {
+        target.number(key, i64::from(i16::MIN), i64::from(i16::MAX))
+    })?;
+    if target.get("register").is_some() {
+        return Ok(ir::JumpTarget::Register(target.register("register")?));
+    }
+    Ok(
+        match (target.optional("label", |key| target.string(key))?, offset) {
+            (Some(label), None) => ir::JumpTarget::Label(ir::LabelReference::new(label)),
+            (Some(label), Some(offset)) => ir::JumpTarget::LabelOffset {
+                label: ir::LabelReference::new(label),
+                offset: offset as i16,
+            },
+            (None, Some(offset)) => ir::JumpTarget::Constant(offset as u16),
+            (None, None) => {
+                return Err(IrJsonError::InvalidValue {
+                    path: target.path.clone(),
+                    message: String::from("expected 'register', 'label' or 'offset'"),
+                })
+            }
+        },
+    )
+}
+
+fn expression_from_json(path: String, value: &Value) -> Result<ir::Expression, IrJsonError> {
+    let invalid = |path: String| IrJsonError::InvalidValue {
+        path,
+        message: String::from(
+            "expected a number, \"$\", a label or one of 'lo', 'hi', 'sum' and 'difference'",
+        ),
+    };
+    match value {
+        Value::Number(number) => u32::try_from(*number)
+            .or_else(|_| i32::try_from(*number).map(|number| number as u32))
+            .map(ir::Expression::Constant)
+            .map_err(|_| IrJsonError::InvalidValue {
+                path,
+                message: String::from("does not fit into 32 bits"),
+            }),
+        Value::String(name) if name == "$" => Ok(ir::Expression::Location),
+        Value::String(name) => Ok(ir::Expression::Label(ir::LabelReference::new(name))),
+        Value::Object(entries) => match entries.as_slice() {
+            [(function, argument)] if function == "lo" || function == "hi" => {
+                let argument = Box::new(expression_from_json(
+                    format!("{}.{}", path, function),
+                    argument,
+                )?);
+                Ok(if function == "lo" {
+                    ir::Expression::Low(argument)
+                } else {
+                    ir::Expression::High(argument)
+                })
+            }
+            [(operator, Value::Array(operands))]
+                if operator == "sum" || operator == "difference" =>
+            {
+                let [a, b] = operands.as_slice() else {
+                    return Err(IrJsonError::InvalidValue {
+                        path: format!("{}.{}", path, operator),
+                        message: String::from("expected two operands"),
+                    });
+                };
+                let a = Box::new(expression_from_json(
+                    format!("{}.{}[0]", path, operator),
+                    a,
+                )?);
+                let b = Box::new(expression_from_json(
+                    format!("{}.{}[1]", path, operator),
+                    b,
+                )?);
+                Ok(if operator == "sum" {
+                    ir::Expression::Sum(a, b)
+                } else {
+                    ir::Expression::Difference(a, b)
+                })
+            }
+            _ => Err(invalid(path)),
+        },
+        _ => Err(invalid(path)),
+    }
+}
+
+/// Members of a JSON object, reached through `path`
+struct Fields<'a> {
+    path: String,
+    entries: &'a [(String, Value)],
+}
+
+impl<'a> Fields<'a> {
+    fn new(path: String, value: &'a Value) -> Result<Self, IrJsonError> {
+        match value {
+            Value::Object(entries) => Ok(Fields { path, entries }),
+            _ => Err(IrJsonError::InvalidValue {
+                path: if path.is_empty() {
+                    String::from("$")
+                } else {
+                    path
+                },
+                message: String::from("expected an object"),
+            }),
+        }
+    }
+
+    fn path(&self, key: &str) -> String {
+        if self.path.is_empty() {
+            String::from(key)
+        } else {
+            format!("{}.{}", self.path, key)
+        }
+    }
+
+    fn invalid(&self, key: &str, message: &str) -> IrJsonError {
+        IrJsonError::InvalidValue {
+            path: self.path(key),
+            message: String::from(message),
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<&'a Value> {
+        self.entries
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value)
+    }
+
+    fn required(&self, key: &str) -> Result<&'a Value, IrJsonError> {
+        self.get(key).ok_or_else(|| self.invalid(key, "missing"))
+    }
+
+    /// `None` if the member `key` is missing or `null`, read with `read`
+    /// otherwise
+    fn optional<T>(
+        &self,
+        key: &str,
+        read: impl FnOnce(&str) -> Result<T, IrJsonError>,
+    ) -> Result<Option<T>, IrJsonError> {
+        match self.get(key) {
+            None | Some(Value::Null) => Ok(None),
+            Some(_) => read(key).map(Some),
+        }
+    }
+
+    fn string(&self, key: &str) -> Result<&'a str, IrJsonError> {
+        match self.required(key)? {
+            Value::String(value) => Ok(value),
+            _ => Err(self.invalid(key, "expected a string")),
+        }
+    }
+
+    fn bool(&self, key: &str) -> Result<bool, IrJsonError> {
+        match self.required(key)? {
+            Value::Bool(value) => Ok(*value),
+            _ => Err(self.invalid(key, "expected true or false")),
+        }
+    }
+
+    fn array(&self, key: &str) -> Result<&'a [Value], IrJsonError> {
+        match self.required(key)? {
+            Value::Array(values) => Ok(values),
+            _ => Err(self.invalid(key, "expected an array")),
+        }
+    }
+
+    /// Integer within `min..=max`
+    fn number(&self, key: &str, min: i64, max: i64) -> Result<i64, IrJsonError> {
+        match self.required(key)? {
+            Value::Number(value) if (min..=max).contains(value) => Ok(*value),
+            _ => Err(self.invalid(key, &format!("expected an integer from {} to {}", min, max))),
+        }
+    }
+
+    fn u16(&self, key: &str) -> Result<u16, IrJsonError> {
+        self.number(key, 0, i64::from(u16::MAX))
+            .map(|value| value as u16)
+    }
+
+    /// 16 bit word, negative values are stored as two's complement
+    fn word(&self, key: &str) -> Result<u16, IrJsonError> {
+        self.number(key, i64::from(i16::MIN), i64::from(u16::MAX))
+            .map(|value| value as u16)
+    }
+
+    fn register(&self, key: &str) -> Result<ir::Register, IrJsonError> {
+        self.number(key, 0, i64::from(ir::REGISTER_COUNT - 1))
+            .map(|value| ir::Register::new(ir::RegisterAddress(value as u8)))
+    }
+
+    fn expression(&self, key: &str) -> Result<ir::Expression, IrJsonError> {
+        expression_from_json(self.path(key), self.required(key)?)
+    }
+
+    /// Value of the string among `names`
+    fn name<T: Copy>(&self, key: &str, names: &[(&str, T)]) -> Result<T, IrJsonError> {
+        let name = self.string(key)?;
+        names
+            .iter()
+            .find(|(candidate, _)| *candidate == name)
+            .map(|(_, value)| *value)
+            .ok_or_else(|| {
+                let names: Vec<&str> = names.iter().map(|(name, _)| *name).collect();
+                self.invalid(key, &format!("expected one of {}", names.join(", ")))
+            })
+    }
+}
+
+/// Parsed JSON document, numbers are integers only
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Value {
+    Null,
+    Bool(bool),
+    Number(i64),
+    String(String),
+    Array(Vec<Value>),
+    /// members in the order they are written
+    Object(Vec<(String, Value)>),
+}
+
+/// Deepest nesting of arrays and objects [`Reader`] accepts, far more than
+/// any IR needs, but few enough not to overflow the stack
+const MAX_DEPTH: usize = 128;
+
+/// Recursive descent parser of a JSON document
+struct Reader<'a> {
+    text: &'a str,
+    offset: usize,
+    /// arrays and objects the current value is nested in
+    depth: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(text: &'a str) -> Self {
+        Reader {
+            text,
+            offset: 0,
+            depth: 0,
+        }
+    }
+
+    /// The single value making up the whole text
+    fn document(mut self) -> Result<Value, IrJsonError> {
+        let value = self.value()?;
+        self.skip_whitespace();
+        match self.peek() {
+            None => Ok(value),
+            Some(_) => Err(self.error("expected the end of the document")),
+        }
+    }
+
+    fn error(&self, message: &str) -> IrJsonError {
+        let before = &self.text[..self.offset];
+        let line_start = before.rfind('\n').map_or(0, |idx| idx + 1);
+        IrJsonError::Syntax {
+            message: String::from(message),
+            line_number: before.matches('\n').count() as u16,
+            column: (self.offset - line_start) as u16,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.offset..].chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.offset += c.len_utf8();
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self
+            .peek()
+            .is_some_and(|c| matches!(c, ' ' | '\t' | '\n' | '\r'))
+        {
+            self.offset += 1;
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), IrJsonError> {
+        self.skip_whitespace();
+        if self.peek() == Some(expected) {
+            self.offset += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", expected)))
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, IrJsonError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{' | '[') if self.depth == MAX_DEPTH => {
+                Err(self.error("arrays and objects are nested too deeply"))
+            }
+            Some(c @ ('{' | '[')) => {
+                self.depth += 1;
+                let value = if c == '{' {
+                    self.object()
+                } else {
+                    self.array()
+                };
+                self.depth -= 1;
+                value
+            }
+            Some('"') => self.string().map(Value::String),
+            Some('-' | '0'..='9') => self.number(),
+            Some(_) => {
+                for (literal, value) in [
+                    ("true", Value::Bool(true)),
+                    ("false", Value::Bool(false)),
+                    ("null", Value::Null),
+                ] {
+                    if self.text[self.offset..].starts_with(literal) {
+                        self.offset += literal.len();
+                        return Ok(value);
+                    }
+                }
+                Err(self.error("expected a value"))
+            }
+            None => Err(self.error("unexpected end of the document")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, IrJsonError> {
+        self.expect('{')?;
+        let mut entries = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.offset += 1;
+            return Ok(Value::Object(entries));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some('"') {
+                return Err(self.error("expected a member name"));
+            }
+            let key = self.string()?;
+            self.expect(':')?;
+            entries.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.offset += 1,
+                Some('}') => {
+                    self.offset += 1;
+                    return Ok(Value::Object(entries));
+                }
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, IrJsonError> {
+        self.expect('[')?;
+        let mut values = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.offset += 1;
+            return Ok(Value::Array(values));
+        }
+        loop {
+            values.push(self.value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.offset += 1,
+                Some(']') => {
+                    self.offset += 1;
+                    return Ok(Value::Array(values));
+                }
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, IrJsonError> {
+        self.expect('"')?;
+        let mut string = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(string),
+                Some('\\') => {
+                    let c = match self.next() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => self.unicode_escape()?,
+                        _ => return Err(self.error("invalid escape sequence")),
+                    };
+                    string.push(c);
+                }
+                Some(c) if c.is_control() => return Err(self.error("control character in string")),
+                Some(c) => string.push(c),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    /// `XXXX` of a `\uXXXX` escape, characters outside of the basic plane
+    /// are written as a surrogate pair of two escapes
+    fn unicode_escape(&mut self) -> Result<char, IrJsonError> {
+        let high = self.hex_digits()?;
+        let code = if (0xd800..0xdc00).contains(&high) {
+            if !self.text[self.offset..].starts_with("\\u") {
+                return Err(self.error("unpaired surrogate"));
+            }
+            self.offset += 2;
+            let low = self.hex_digits()?;
+            if !(0xdc00..0xe000).contains(&low) {
+                return Err(self.error("unpaired surrogate"));
+            }
+            0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(|| self.error("invalid unicode escape"))
+    }
+
+    fn hex_digits(&mut self) -> Result<u32, IrJsonError> {
+        let digits = self
+            .text
+            .get(self.offset..self.offset + 4)
+            .filter(|digits| digits.chars().all(|c| c.is_ascii_hexdigit()))
+            .ok_or_else(|| self.error("expected four hex digits"))?;
+        self.offset += 4;
+        Ok(u32::from_str_radix(digits, 16).expect("checked hex digits"))
+    }
+
+    fn number(&mut self) -> Result<Value, IrJsonError> {
+        let start = self.offset;
+        if self.peek() == Some('-') {
+            self.offset += 1;
+        }
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.offset += 1;
+        }
+        if self.peek().is_some_and(|c| matches!(c, '.' | 'e' | 'E')) {
+            return Err(self.error("expected an integer"));
+        }
+        self.text[start..self.offset]
+            .parse()
+            .map(Value::Number)
+            .map_err(|_| {
+                self.offset = start;
+                self.error("invalid integer")
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> ir::IR {
+        let options = crate::lexer::LexerOptions { auto_halt: false };
+        crate::parser::parser(crate::lexer::lexer_from_str(source, &options).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let parsed = parse(
+            ";; Entry
+main:
+    ldc %reg0 lo(far+2)
+    ld %reg1 %reg0
+    jnzr main-1
+    jr -3
+    jz %reg2
+.scratch %reg5
+    ror %reg2 %reg3 4
+    subi %reg4 -1
+.org 0x0100
+[[noreturn, align=4]]
+far:
+    .word 0x1234
+    hlt
+",
+        );
+
+        let json = to_json(&parsed);
+        let read = from_json(&json).unwrap();
+        assert_eq!(parsed.start_label, read.start_label);
+        assert_eq!(parsed.instructions, read.instructions);
+        let far = &read.label_definitions.0[&ir::LabelReference::new("far")];
+        assert_eq!(ir::MemoryAddress(0x100), far.address);
+        assert!(far.fixed && far.attributes.noreturn);
+        assert_eq!(Some(4), far.attributes.align);
+        assert_eq!(
+            Some("Entry"),
+            read.label_definitions.0[&read.start_label].doc.as_deref()
+        );
+        assert_eq!(json, to_json(&read));
+    }
+
+    #[test]
+    fn addresses() {
+        let read = from_json(
+            r#"{"start": "end", "labels": [
+                {"name": "main", "instructions": [
+                    {"instruction": "long-jump", "target": "end"},
+                    {"instruction": "jump", "target": {"offset": -1}, "condition": "less"}
+                ]},
+                {"name": "data", "align": 4, "instructions": [{"instruction": "data", "value": -1}]},
+                {"name": "end", "instructions": [{"instruction": "halt"}]}
+            ]}"#,
+        )
+        .unwrap();
+
+        let address = |name: &str| {
+            read.label_definitions.0[&ir::LabelReference::new(name)]
+                .address
+                .0
+        };
+        assert_eq!(
+            (0, 4, 5),
+            (address("main"), address("data"), address("end"))
+        );
+        assert_eq!("end", read.start_label.name());
+        assert_eq!(
+            vec![
+                ir::Instruction::LongJump {
+                    target: ir::Expression::Label(ir::LabelReference::new("end")),
+                    scratch: ir::Register::new(crate::parser::DEFAULT_SCRATCH_REGISTER),
+                },
+                ir::Instruction::Jump {
+                    target: ir::JumpTarget::Constant(1u16.wrapping_neg()),
+                    condition: ir::JumpCondition::Less,
+                },
+            ],
+            read.instructions[&ir::LabelReference::new("main")]
+        );
+        assert_eq!(
+            vec![ir::Instruction::Data(ir::Constant(0xffff))],
+            read.instructions[&ir::LabelReference::new("data")]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            Err(IrJsonError::Syntax {
+                message: String::from("arrays and objects are nested too deeply"),
+                line_number: 0,
+                column: MAX_DEPTH as u16,
+            }),
+            from_json(&"[".repeat(200_000)).map(|_| ())
+        );
+        assert_eq!(
+            Err(IrJsonError::Syntax {
+                message: String::from("expected ',' or '}'"),
+                line_number: 1,
+                column: 18,
+            }),
+            from_json("{\"labels\": [\n  {\"name\": \"main\" \"instructions\": []}]}").map(|_| ())
+        );
+        assert_eq!(
+            Err(String::from(
+                "Invalid value of 'labels[0].instructions[1].target': expected an integer from 0 to 7"
+            )),
+            from_json(
+                r#"{"labels": [{"name": "main", "instructions": [
+                    {"instruction": "halt"},
+                    {"instruction": "increment", "target": 8, "source_a": 8}
+                ]}]}"#
+            )
+            .map(|_| ())
+            .map_err(|err| err.to_string())
+        );
+        assert_eq!(
+            Err(IrJsonError::DuplicateLabel {
+                label: String::from("main"),
+            }),
+            from_json(
+                r#"{"labels": [{"name": "main", "instructions": []}, {"name": "main", "instructions": []}]}"#
+            )
+            .map(|_| ())
+        );
+        assert_eq!(
+            Err(IrJsonError::UndefinedEntry {
+                label: String::from("start"),
+            }),
+            from_json(r#"{"start": "start", "labels": [{"name": "main", "instructions": []}]}"#)
+                .map(|_| ())
+        );
+    }
+}
@@ -0,0 +1,384 @@
+use std::{collections::HashMap, fmt};
+
+use crate::ir;
+
+/// Reasons why the entry label can not be moved to address 0, or the code
+/// can not be ordered by a profile
+#[derive(Debug)]
+pub enum LayoutError {
+    Gap { label: String },
+    FixedLabel { label: String },
+    Misaligned { label: String, align: u16 },
+    FallThrough { label: String, next: String },
+    RelativeJump { label: String },
+}
+
+impl fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LayoutError::Gap { label } => write!(
+                f,
+                "Label '{}' does not follow the code in front of it without a gap",
+                label
+            ),
+            LayoutError::FixedLabel { label } => {
+                write!(
+                    f,
+                    "Label '{}' is placed with '.org' and can not move",
+                    label
+                )
+            }
+            LayoutError::Misaligned { label, align } => write!(
+                f,
+                "Label '{}' would no longer be aligned to {} words",
+                label, align
+            ),
+            LayoutError::FallThrough { label, next } => write!(
+                f,
+                "Code of label '{}' falls through into '{}', which would no longer follow it",
+                label, next
+            ),
+            LayoutError::RelativeJump { label } => write!(
+                f,
+                "Relative jump with a constant offset in label '{}' leaves the code it moves with",
+                label
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LayoutError {}
+
+impl LayoutError {
+    /// Stable name of the error kind, e.g. for machine readable diagnostics
+    pub fn code(&self) -> &'static str {
+        match self {
+            LayoutError::Gap { .. } => "gap",
+            LayoutError::FixedLabel { .. } => "fixed-label",
+            LayoutError::Misaligned { .. } => "misaligned",
+            LayoutError::FallThrough { .. } => "fall-through",
+            LayoutError::RelativeJump { .. } => "relative-jump",
+        }
+    }
+}
+
+/// Moves the code from the entry label to the end of the program in front
+/// of the code before the entry label, so the CPU starting at address 0
+/// starts at the entry label.
+///
+/// All label addresses change, label references stay valid. The code in
+/// front of the entry label and the end of the program must not fall
+/// through, as they are no longer followed by the same code afterwards.
+pub fn place_entry_first(mut ir: ir::IR) -> Result<ir::IR, LayoutError> {
+    let entry = match ir.label_definitions.0.get(&ir.start_label) {
+        Some(definition) if definition.address.0 != 0 => definition.clone(),
+        _ => return Ok(ir),
+    };
+
+    let mut labels: Vec<(&ir::LabelDefinition, &[ir::Instruction])> = ir
+        .label_definitions
+        .0
+        .values()
+        .map(|label| {
+            let instructions = ir
+                .instructions
+                .get(&label.clone().into())
+                .map_or(&[][..], Vec::as_slice);
+            (label, instructions)
+        })
+        .collect();
+    labels.sort_by_key(|(label, instructions)| (label.address, !instructions.is_empty()));
+
+    let mut end = 0;
+    // last label with code in front of the entry label and of the end
+    let mut in_front_of_entry: Option<(&ir::LabelDefinition, &ir::Instruction)> = None;
+    let mut last: Option<(&ir::LabelDefinition, &ir::Instruction)> = None;
+    for (label, instructions) in &labels {
+        if label.fixed {
+            return Err(LayoutError::FixedLabel {
+                label: label.name.clone(),
+            });
+        }
+        let Some(last_instruction) = instructions.last() else {
+            continue;
+        };
+        if label.address.0 != end {
+            return Err(LayoutError::Gap {
+                label: label.name.clone(),
+            });
+        }
+        for instruction in instructions.iter() {
+            if let ir::Instruction::Jump {
+                target: ir::JumpTarget::Constant(offset),
+                ..
+            } = instruction
+            {
+                let target = end.wrapping_add(*offset);
+                if (end < entry.address.0) != (target < entry.address.0) {
+                    return Err(LayoutError::RelativeJump {
+                        label: label.name.clone(),
+                    });
+                }
+            }
+            end += instruction.size();
+        }
+        if label.address < entry.address {
+            in_front_of_entry = Some((label, last_instruction));
+        }
+        last = Some((label, last_instruction));
+    }
+
+    let falls_through = |(label, instruction): (&ir::LabelDefinition, &ir::Instruction)| {
+        !label.attributes.noreturn && instruction.falls_through()
+    };
+    if let Some((label, instruction)) = in_front_of_entry {
+        if falls_through((label, instruction)) {
+            return Err(LayoutError::FallThrough {
+                label: label.name.clone(),
+                next: entry.name.clone(),
+            });
+        }
+    }
+    if let Some((label, instruction)) = last {
+        if falls_through((label, instruction)) {
+            let first = labels
+                .first()
+                .map_or_else(String::new, |(label, _)| label.name.clone());
+            return Err(LayoutError::FallThrough {
+                label: label.name.clone(),
+                next: first,
+            });
+        }
+    }
+
+    let moved = end - entry.address.0;
+    for label in ir.label_definitions.0.values_mut() {
+        label.address.0 = if label.address >= entry.address {
+            label.address.0 - entry.address.0
+        } else {
+            label.address.0 + moved
+        };
+        if let Some(align) = label.attributes.align {
+            if label.address.0 % align != 0 {
+                return Err(LayoutError::Misaligned {
+                    label: label.name.clone(),
+                    align,
+                });
+            }
+        }
+    }
+    Ok(ir)
+}
+
+/// Orders the code by the number of cycles `profile` counted in each label,
+/// so the hot code is contiguous and its relative jumps stay short.
+///
+/// Labels connected by falling through from one into the next form a chain,
+/// which moves as a whole. The chain at address 0, where the CPU starts,
+/// stays first and a chain falling through the end of the program stays
+/// last. The other chains follow the first one from the most to the least
+/// cycles, chains with the same number keep their order. Labels missing in
+/// `profile` were not executed.
+pub fn place_hot_first(
+    mut ir: ir::IR,
+    profile: &HashMap<String, u64>,
+) -> Result<ir::IR, LayoutError> {
+    let mut labels: Vec<(&ir::LabelDefinition, &[ir::Instruction])> = ir
+        .label_definitions
+        .0
+        .values()
+        .map(|label| {
+            let instructions = ir
+                .instructions
+                .get(&label.clone().into())
+                .map_or(&[][..], Vec::as_slice);
+            (label, instructions)
+        })
+        .collect();
+    labels.sort_by_key(|(label, instructions)| (label.address, !instructions.is_empty()));
+
+    // address, size and cycles of every chain
+    let mut chains: Vec<(u16, u16, u64)> = Vec::new();
+    // targets of relative jumps with a constant offset and the jumping label
+    let mut jumps: Vec<(u16, u16, &str)> = Vec::new();
+    let mut end = 0;
+    let mut falls_through = false;
+    for (label, instructions) in &labels {
+        if label.fixed {
+            return Err(LayoutError::FixedLabel {
+                label: label.name.clone(),
+            });
+        }
+        let cycles = profile.get(&label.name).copied().unwrap_or(0);
+        let Some(last_instruction) = instructions.last() else {
+            if let Some(chain) = chains.last_mut().filter(|_| falls_through) {
+                chain.2 += cycles;
+            }
+            continue;
+        };
+        if label.address.0 != end {
+            return Err(LayoutError::Gap {
+                label: label.name.clone(),
+            });
+        }
+        match chains.last_mut().filter(|_| falls_through) {
+            Some(chain) => chain.2 += cycles,
+            None => chains.push((end, 0, cycles)),
+        }
+        for instruction in instructions.iter() {
+            if let ir::Instruction::Jump {
+                target: ir::JumpTarget::Constant(offset),
+                ..
+            } = instruction
+            {
+                jumps.push((end, end.wrapping_add(*offset), &label.name));
+            }
+            end += instruction.size();
+        }
+        falls_through = !label.attributes.noreturn && last_instruction.falls_through();
+        if let Some(chain) = chains.last_mut() {
+            chain.1 = end - chain.0;
+        }
+    }
+
+    let chain_of = |address: u16| {
+        chains
+            .iter()
+            .position(|&(start, size, _)| address >= start && address - start < size)
+    };
+    if let Some((_, _, label)) = jumps
+        .iter()
+        .find(|(address, target, _)| chain_of(*address) != chain_of(*target))
+    {
+        return Err(LayoutError::RelativeJump {
+            label: label.to_string(),
+        });
+    }
+
+    let fixed_end = usize::from(falls_through && chains.len() > 1);
+    let mut order: Vec<usize> = (0..chains.len()).collect();
+    if chains.len() > 1 {
+        let last = chains.len() - fixed_end;
+        order[1..last].sort_by_key(|&chain| std::cmp::Reverse(chains[chain].2));
+    }
+    let mut moved: HashMap<u16, u16> = HashMap::new();
+    let mut address = 0;
+    for chain in order {
+        let (start, size, _) = chains[chain];
+        moved.insert(start, address);
+        address += size;
+    }
+
+    for label in ir.label_definitions.0.values_mut() {
+        if let Some(chain) = chain_of(label.address.0) {
+            let start = chains[chain].0;
+            label.address.0 = moved[&start] + (label.address.0 - start);
+        }
+        if let Some(align) = label.attributes.align {
+            if label.address.0 % align != 0 {
+                return Err(LayoutError::Misaligned {
+                    label: label.name.clone(),
+                    align,
+                });
+            }
+        }
+    }
+    Ok(ir)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn program(labels: Vec<(&str, u16, Vec<ir::Instruction>)>, entry: &str) -> ir::IR {
+        let mut label_definitions = ir::LabelLUT::new();
+        let mut instructions = HashMap::new();
+        for (name, address, code) in labels {
+            let reference = ir::LabelReference::new(name);
+            label_definitions
+                .0
+                .insert(reference.clone(), ir::LabelDefinition::new(name, address));
+            instructions.insert(reference, code);
+        }
+        ir::IR {
+            start_label: ir::LabelReference::new(entry),
+            label_definitions,
+            instructions,
+        }
+    }
+
+    fn address(ir: &ir::IR, name: &str) -> u16 {
+        ir.label_definitions.0[&ir::LabelReference::new(name)]
+            .address
+            .0
+    }
+
+    #[test]
+    fn entry_first() {
+        let placed = place_entry_first(program(
+            vec![
+                (
+                    "table",
+                    0,
+                    vec![
+                        ir::Instruction::Data(ir::Constant(1)),
+                        ir::Instruction::Data(ir::Constant(2)),
+                    ],
+                ),
+                (
+                    "main",
+                    2,
+                    vec![ir::Instruction::Noop, ir::Instruction::Halt],
+                ),
+            ],
+            "main",
+        ))
+        .unwrap();
+
+        assert_eq!(0, address(&placed, "main"));
+        assert_eq!(2, address(&placed, "table"));
+    }
+
+    #[test]
+    fn fall_through() {
+        match place_entry_first(program(
+            vec![
+                ("setup", 0, vec![ir::Instruction::Noop]),
+                ("main", 1, vec![ir::Instruction::Halt]),
+            ],
+            "main",
+        )) {
+            Err(LayoutError::FallThrough { label, next }) => {
+                assert_eq!(("setup", "main"), (label.as_str(), next.as_str()))
+            }
+            found => panic!("expected fall through, found {:?}", found.err()),
+        }
+    }
+
+    #[test]
+    fn hot_first() {
+        let jump = |label: &str| ir::Instruction::Jump {
+            target: ir::JumpTarget::Label(ir::LabelReference::new(label)),
+            condition: ir::JumpCondition::True,
+        };
+        let program = program(
+            vec![
+                ("main", 0, vec![jump("hot")]),
+                ("cold", 1, vec![ir::Instruction::Noop]),
+                ("cold_end", 2, vec![ir::Instruction::Halt]),
+                ("hot", 3, vec![ir::Instruction::Noop, jump("main")]),
+            ],
+            "main",
+        );
+        let profile = HashMap::from([(String::from("main"), 1), (String::from("hot"), 8)]);
+        let placed = place_hot_first(program, &profile).unwrap();
+
+        assert_eq!(0, address(&placed, "main"));
+        assert_eq!(1, address(&placed, "hot"));
+        // falls through into 'cold_end', so both move together
+        assert_eq!(3, address(&placed, "cold"));
+        assert_eq!(4, address(&placed, "cold_end"));
+    }
+}
@@ -2,39 +2,92 @@ use std::{
     collections::VecDeque,
     fs::File,
     io::{self, BufRead},
-    path::Path,
+    path::{Path, PathBuf},
 };
 
+use crate::diagnostic::{self, display_line};
+use crate::ir;
+
 pub trait LineNumber {
     fn get_line_number(&self) -> u16;
 }
 
 /// Keywords are the Tokens, that the lexer creates from the
-/// input character stream
-#[derive(Debug)]
+/// input character stream. `column` is the byte offset of the
+/// keyword in its line.
+#[derive(Debug, Clone)]
 pub enum Keyword {
     Mmenonic {
         name: String,
         line_number: u16,
+        column: u16,
     },
     RegisterAddress {
         name: String,
         line_number: u16,
+        column: u16,
     },
     Constant {
         value: u16,
         line_number: u16,
+        column: u16,
         origin: String,
     },
     Boolean {
         value: bool,
         line_number: u16,
+        column: u16,
         origin: String,
     },
     Label {
         name: String,
         line_number: u16,
+        column: u16,
+    },
+    /// Documentation from the `;;` comment lines directly above a label definition
+    Doc {
+        text: String,
+        line_number: u16,
+        column: u16,
+    },
+    /// Assembler directive like `.reserve`, stored without the leading dot
+    Directive {
+        name: String,
+        line_number: u16,
+        column: u16,
+    },
+    /// Quoted string with its escape sequences already resolved
+    StringLiteral {
+        value: String,
+        line_number: u16,
+        column: u16,
+        origin: String,
+    },
+    /// Label attribute like `[[noreturn]]` or `[[align=4]]`
+    Attribute {
+        name: String,
+        value: Option<String>,
+        line_number: u16,
+        column: u16,
+    },
+    /// Operand like `lo(handler)` whose value the generator computes
+    /// once all label addresses are known
+    Expression {
+        expression: ir::Expression,
+        line_number: u16,
+        column: u16,
+        origin: String,
+    },
+    /// `;` comment including its semicolons, only lexed by
+    /// [`Tokens::with_comments`]
+    Comment {
+        text: String,
+        line_number: u16,
+        column: u16,
     },
+    /// Line without keywords and comments, only lexed by
+    /// [`Tokens::with_comments`]
+    BlankLine { line_number: u16, column: u16 },
 }
 
 impl Keyword {
@@ -42,12 +95,14 @@ impl Keyword {
         Keyword::Mmenonic {
             name: name.to_string(),
             line_number,
+            column: 0,
         }
     }
     pub fn register_address(name: &str, line_number: u16) -> Keyword {
         Keyword::RegisterAddress {
             name: name.to_string(),
             line_number,
+            column: 0,
         }
     }
     pub fn constant(origin: &str, value: u16, line_number: u16) -> Keyword {
@@ -55,12 +110,14 @@ impl Keyword {
             origin: origin.to_string(),
             value,
             line_number,
+            column: 0,
         }
     }
     pub fn boolean(origin: &str, value: bool, line_number: u16) -> Keyword {
         Keyword::Boolean {
             value,
             line_number,
+            column: 0,
             origin: origin.to_string(),
         }
     }
@@ -68,6 +125,139 @@ impl Keyword {
         Keyword::Label {
             name: name.to_string(),
             line_number,
+            column: 0,
+        }
+    }
+    pub fn doc(text: &str, line_number: u16) -> Keyword {
+        Keyword::Doc {
+            text: text.to_string(),
+            line_number,
+            column: 0,
+        }
+    }
+    pub fn directive(name: &str, line_number: u16) -> Keyword {
+        Keyword::Directive {
+            name: name.to_string(),
+            line_number,
+            column: 0,
+        }
+    }
+    pub fn string_literal(origin: &str, value: &str, line_number: u16) -> Keyword {
+        Keyword::StringLiteral {
+            value: value.to_string(),
+            line_number,
+            column: 0,
+            origin: origin.to_string(),
+        }
+    }
+    pub fn attribute(name: &str, value: Option<&str>, line_number: u16) -> Keyword {
+        Keyword::Attribute {
+            name: name.to_string(),
+            value: value.map(String::from),
+            line_number,
+            column: 0,
+        }
+    }
+    pub fn expression(origin: &str, expression: ir::Expression, line_number: u16) -> Keyword {
+        Keyword::Expression {
+            expression,
+            line_number,
+            column: 0,
+            origin: origin.to_string(),
+        }
+    }
+    /// Moves the keyword to `line_number`, used for keywords expanded
+    /// somewhere else than where they were written
+    pub fn with_line_number(mut self, line_number: u16) -> Keyword {
+        match &mut self {
+            Keyword::Mmenonic {
+                line_number: ln, ..
+            }
+            | Keyword::RegisterAddress {
+                line_number: ln, ..
+            }
+            | Keyword::Constant {
+                line_number: ln, ..
+            }
+            | Keyword::Boolean {
+                line_number: ln, ..
+            }
+            | Keyword::Label {
+                line_number: ln, ..
+            }
+            | Keyword::Doc {
+                line_number: ln, ..
+            }
+            | Keyword::Directive {
+                line_number: ln, ..
+            }
+            | Keyword::StringLiteral {
+                line_number: ln, ..
+            }
+            | Keyword::Attribute {
+                line_number: ln, ..
+            }
+            | Keyword::Expression {
+                line_number: ln, ..
+            }
+            | Keyword::Comment {
+                line_number: ln, ..
+            }
+            | Keyword::BlankLine {
+                line_number: ln, ..
+            } => *ln = line_number,
+        }
+        self
+    }
+    /// Moves the keyword to `column` of its line
+    pub fn with_column(mut self, column: u16) -> Keyword {
+        match &mut self {
+            Keyword::Mmenonic { column: col, .. }
+            | Keyword::RegisterAddress { column: col, .. }
+            | Keyword::Constant { column: col, .. }
+            | Keyword::Boolean { column: col, .. }
+            | Keyword::Label { column: col, .. }
+            | Keyword::Doc { column: col, .. }
+            | Keyword::Directive { column: col, .. }
+            | Keyword::StringLiteral { column: col, .. }
+            | Keyword::Attribute { column: col, .. }
+            | Keyword::Expression { column: col, .. }
+            | Keyword::Comment { column: col, .. }
+            | Keyword::BlankLine { column: col, .. } => *col = column,
+        }
+        self
+    }
+    pub fn get_column(&self) -> u16 {
+        match *self {
+            Keyword::Mmenonic { column, .. }
+            | Keyword::RegisterAddress { column, .. }
+            | Keyword::Constant { column, .. }
+            | Keyword::Boolean { column, .. }
+            | Keyword::Label { column, .. }
+            | Keyword::Doc { column, .. }
+            | Keyword::Directive { column, .. }
+            | Keyword::StringLiteral { column, .. }
+            | Keyword::Attribute { column, .. }
+            | Keyword::Expression { column, .. }
+            | Keyword::Comment { column, .. }
+            | Keyword::BlankLine { column, .. } => column,
+        }
+    }
+    /// Name of the keyword type, e.g. `mnemonic` or `label`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Keyword::Mmenonic { .. } => "mnemonic",
+            Keyword::RegisterAddress { .. } => "register",
+            Keyword::Constant { .. } => "constant",
+            Keyword::Boolean { .. } => "boolean",
+            Keyword::Label { .. } => "label",
+            Keyword::Doc { .. } => "doc",
+            Keyword::Directive { .. } => "directive",
+            Keyword::StringLiteral { .. } => "string",
+            Keyword::Attribute { .. } => "attribute",
+            Keyword::Expression { .. } => "expression",
+            Keyword::Comment { .. } => "comment",
+            Keyword::BlankLine { .. } => "blank",
         }
     }
     pub fn get_original_string(&self) -> String {
@@ -77,6 +267,18 @@ impl Keyword {
             Keyword::Label { name, .. } => format!(".{}", name.clone()),
             Keyword::Constant { origin, .. } => origin.clone(),
             Keyword::Boolean { origin, .. } => origin.clone(),
+            Keyword::Doc { text, .. } => format!(";; {}", text),
+            Keyword::Directive { name, .. } => format!(".{}", name),
+            Keyword::StringLiteral { origin, .. } => origin.clone(),
+            Keyword::Attribute {
+                name,
+                value: Some(value),
+                ..
+            } => format!("[[{}={}]]", name, value),
+            Keyword::Attribute { name, .. } => format!("[[{}]]", name),
+            Keyword::Expression { origin, .. } => origin.clone(),
+            Keyword::Comment { text, .. } => text.clone(),
+            Keyword::BlankLine { .. } => String::new(),
         }
     }
 }
@@ -132,6 +334,67 @@ impl PartialEq for Keyword {
                     ..
                 },
             ) => value_self == value_other && origin_self == origin_other,
+            (
+                Keyword::Doc {
+                    text: text_self, ..
+                },
+                Keyword::Doc {
+                    text: text_other, ..
+                },
+            ) => text_self == text_other,
+            (
+                Keyword::Directive {
+                    name: name_self, ..
+                },
+                Keyword::Directive {
+                    name: name_other, ..
+                },
+            ) => name_self == name_other,
+            (
+                Keyword::StringLiteral {
+                    value: value_self,
+                    origin: origin_self,
+                    ..
+                },
+                Keyword::StringLiteral {
+                    value: value_other,
+                    origin: origin_other,
+                    ..
+                },
+            ) => value_self == value_other && origin_self == origin_other,
+            (
+                Keyword::Attribute {
+                    name: name_self,
+                    value: value_self,
+                    ..
+                },
+                Keyword::Attribute {
+                    name: name_other,
+                    value: value_other,
+                    ..
+                },
+            ) => name_self == name_other && value_self == value_other,
+            (
+                Keyword::Expression {
+                    expression: expression_self,
+                    origin: origin_self,
+                    ..
+                },
+                Keyword::Expression {
+                    expression: expression_other,
+                    origin: origin_other,
+                    ..
+                },
+            ) => expression_self == expression_other && origin_self == origin_other,
+            (
+                Keyword::Comment {
+                    text: text_self, ..
+                },
+                Keyword::Comment {
+                    text: text_other, ..
+                },
+            ) => text_self == text_other,
+            (Keyword::BlankLine { .. }, Keyword::BlankLine { .. }) => true,
             _ => false,
         }
     }
@@ -145,28 +408,53 @@ impl LineNumber for Keyword {
             Keyword::Constant { line_number, .. } => line_number,
             Keyword::Boolean { line_number, .. } => line_number,
             Keyword::Label { line_number, .. } => line_number,
+            Keyword::Doc { line_number, .. } => line_number,
+            Keyword::Directive { line_number, .. } => line_number,
+            Keyword::StringLiteral { line_number, .. } => line_number,
+            Keyword::Attribute { line_number, .. } => line_number,
+            Keyword::Expression { line_number, .. } => line_number,
+            Keyword::Comment { line_number, .. } => line_number,
+            Keyword::BlankLine { line_number, .. } => line_number,
         }
     }
 }
 
-///
+/// Errors the lexer can run into while tokenizing a source file
 #[derive(Debug)]
 pub enum LexerError {
     InvalidRegisterIdentifier {
         actual: String,
         line_number: u16,
+        column: u16,
     },
     InvalidIdentifier {
         actual: String,
         line_number: u16,
+        column: u16,
     },
     CommandAfterCommand {
         command_name: String,
         line_number: u16,
+        column: u16,
     },
     LabelAfterCommand {
         label_name: String,
         line_number: u16,
+        column: u16,
+    },
+    UnterminatedString {
+        line_number: u16,
+        column: u16,
+    },
+    RecursiveInclude {
+        path: String,
+        line_number: u16,
+    },
+    /// Error inside of a file included at `line_number`
+    InInclude {
+        path: String,
+        line_number: u16,
+        error: Box<LexerError>,
     },
     IoError(io::Error),
 }
@@ -178,79 +466,416 @@ impl std::fmt::Display for LexerError {
             LexerError::InvalidIdentifier {
                 actual,
                 line_number,
+                column,
             } => write!(
                 f,
-                "Invalid identifier '{}' found at line {}",
-                actual, line_number
+                "Invalid identifier '{}' found at line {}, column {}",
+                actual,
+                display_line(*line_number),
+                column
             ),
             LexerError::LabelAfterCommand {
                 label_name,
                 line_number,
+                column,
             } => write!(
                 f,
-                "Found illegal label '{}' after command at line {}",
-                label_name, line_number
+                "Found illegal label '{}' after command at line {}, column {}",
+                label_name,
+                display_line(*line_number),
+                column
             ),
             LexerError::CommandAfterCommand {
                 command_name,
                 line_number,
+                column,
+            } => write!(
+                f,
+                "Found illegal command '{}' after command at line {}, column {}",
+                command_name,
+                display_line(*line_number),
+                column
+            ),
+            LexerError::UnterminatedString {
+                line_number,
+                column,
+            } => write!(
+                f,
+                "Unterminated string found at line {}, column {}",
+                display_line(*line_number),
+                column
+            ),
+            LexerError::RecursiveInclude { path, line_number } => write!(
+                f,
+                "Recursive include of '{}' found at line {}",
+                path,
+                display_line(*line_number)
+            ),
+            LexerError::InInclude {
+                path,
+                line_number,
+                error,
             } => write!(
                 f,
-                "Found illegal command '{}' after command at line {}",
-                command_name, line_number
+                "{} in '{}' included at line {}",
+                error,
+                path,
+                display_line(*line_number)
             ),
             LexerError::InvalidRegisterIdentifier {
                 actual,
                 line_number,
+                column,
             } => write!(
                 f,
-                "Invalid register identifier '{}' found at line {}",
-                actual, line_number
+                "Invalid register identifier '{}' found at line {}, column {}",
+                actual,
+                display_line(*line_number),
+                column
             ),
         }
     }
 }
 
+impl std::error::Error for LexerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LexerError::InInclude { error, .. } => Some(error.as_ref()),
+            LexerError::IoError(io_error) => Some(io_error),
+            _ => None,
+        }
+    }
+}
+
+impl LexerError {
+    /// Position in the lexed file, errors in included files have none
+    pub fn position(&self) -> Option<diagnostic::Position> {
+        match *self {
+            LexerError::InvalidRegisterIdentifier {
+                line_number,
+                column,
+                ..
+            }
+            | LexerError::InvalidIdentifier {
+                line_number,
+                column,
+                ..
+            }
+            | LexerError::CommandAfterCommand {
+                line_number,
+                column,
+                ..
+            }
+            | LexerError::LabelAfterCommand {
+                line_number,
+                column,
+                ..
+            }
+            | LexerError::UnterminatedString {
+                line_number,
+                column,
+            } => Some((line_number, Some(column))),
+            LexerError::RecursiveInclude { line_number, .. } => Some((line_number, None)),
+            LexerError::InInclude { .. } | LexerError::IoError(_) => None,
+        }
+    }
+
+    /// Stable name of the error kind, e.g. for machine readable diagnostics
+    pub fn code(&self) -> &'static str {
+        match self {
+            LexerError::InvalidRegisterIdentifier { .. } => "invalid-register-identifier",
+            LexerError::InvalidIdentifier { .. } => "invalid-identifier",
+            LexerError::CommandAfterCommand { .. } => "command-after-command",
+            LexerError::LabelAfterCommand { .. } => "label-after-command",
+            LexerError::UnterminatedString { .. } => "unterminated-string",
+            LexerError::RecursiveInclude { .. } => "recursive-include",
+            LexerError::InInclude { error, .. } => error.code(),
+            LexerError::IoError(_) => "io-error",
+        }
+    }
+}
+
 /// The lexer reads the provided assembler text file and separate
 /// it into Tokens (Keywords).
 /// Tokens are strings that are separated by whitespace.
+/// A block of `;;` comment lines directly above a label definition
+/// is kept as a `Keyword::Doc` in front of the label.
+/// Files included with `.include "file.s"` are lexed in place, see
+/// [`INCLUDE_END`].
 pub fn lexer(path: &Path) -> Result<Vec<Keyword>, Vec<LexerError>> {
-    let mut errors: Vec<LexerError> = Vec::new();
+    lexer_with_options(path, &LexerOptions::default())
+}
+
+/// Settings of the lexer which are not part of the source
+#[derive(Debug, Clone)]
+pub struct LexerOptions {
+    /// Append a `hlt` to programs not ending with one, see [`append_halt`]
+    pub auto_halt: bool,
+}
+
+impl Default for LexerOptions {
+    fn default() -> Self {
+        LexerOptions { auto_halt: true }
+    }
+}
+
+pub fn lexer_with_options(
+    path: &Path,
+    options: &LexerOptions,
+) -> Result<Vec<Keyword>, Vec<LexerError>> {
+    let mut lexed = lex_file(path, &[])?;
+    if options.auto_halt {
+        append_halt(&mut lexed);
+    }
+    Ok(lexed)
+}
+
+/// Like [`lexer_with_options`], but lexes the program `source` instead of
+/// a file. Included files are relative to the current directory.
+pub fn lexer_from_str(
+    source: &str,
+    options: &LexerOptions,
+) -> Result<Vec<Keyword>, Vec<LexerError>> {
+    lexer_from_reader(source.as_bytes(), options)
+}
+
+/// Like [`lexer_with_options`], but lexes the lines of `reader`, e.g. stdin.
+/// Included files are relative to the current directory.
+pub fn lexer_from_reader(
+    reader: impl BufRead,
+    options: &LexerOptions,
+) -> Result<Vec<Keyword>, Vec<LexerError>> {
+    let mut lexed = collect(Tokens::new(reader, Path::new("")))?;
+    if options.auto_halt {
+        append_halt(&mut lexed);
+    }
+    Ok(lexed)
+}
+
+/// Lexes the program `source` with its comments and blank lines, see
+/// [`Tokens::with_comments`]
+pub fn lexer_with_comments(source: &str) -> Result<Vec<Keyword>, Vec<LexerError>> {
+    collect(Tokens::new(source.as_bytes(), Path::new("")).with_comments())
+}
+
+/// Appends a `hlt` behind the last keyword unless it is one already, so
+/// the CPU does not run into whatever follows the program. Returns whether
+/// the `hlt` was appended.
+pub fn append_halt(keywords: &mut Vec<Keyword>) -> bool {
+    let last_keyword = keywords
+        .iter()
+        .rev()
+        .find(|keyword| !matches!(keyword, Keyword::Directive { name, .. } if name == INCLUDE_END));
+    let line_number = match last_keyword {
+        // `HLT` counts as well for case insensitive parsing
+        Some(Keyword::Mmenonic { name, .. }) if name.eq_ignore_ascii_case("hlt") => return false,
+        Some(last_keyword) => last_keyword.get_line_number() + 1,
+        None => return false,
+    };
+    keywords.push(Keyword::mmenonic("hlt", line_number));
+    true
+}
+
+/// Directive the lexer appends to the keywords of an included file.
+/// Together with the `.include` directive in front of them it keeps the
+/// operands of the surrounding lines apart from the included keywords,
+/// whose line numbers refer to the included file.
+pub const INCLUDE_END: &str = "endinclude";
+
+/// Lexes a single file, `include_stack` holds the files currently being
+/// included to detect recursion.
+fn lex_file(path: &Path, include_stack: &[PathBuf]) -> Result<Vec<Keyword>, Vec<LexerError>> {
     let file: File = File::open(path).map_err(|io_err| vec![LexerError::IoError(io_err)])?;
-    let reader = io::BufReader::new(file);
-    let mut line_number = 0;
+    let mut tokens = Tokens::new(io::BufReader::new(file), path);
+    tokens.include_stack = include_stack.to_vec();
+    tokens
+        .include_stack
+        .push(path.canonicalize().unwrap_or_else(|_| path.to_path_buf()));
+    collect(tokens)
+}
+
+/// All keywords of `tokens`, or all of its errors if there are any
+fn collect(
+    tokens: impl Iterator<Item = Result<Keyword, LexerError>>,
+) -> Result<Vec<Keyword>, Vec<LexerError>> {
     let mut lexed: Vec<Keyword> = Vec::with_capacity(32);
-    let mut keyword_buffer: Vec<Keyword> = Vec::with_capacity(4);
-
-    for line in reader.lines() {
-        match line {
-            Ok(line) => {
-                match lex_line(&mut keyword_buffer, line, line_number) {
-                    Ok(_) => lexed.append(&mut keyword_buffer),
-                    Err(error) => errors.push(error),
-                };
-                line_number += 1;
+    let mut errors: Vec<LexerError> = Vec::new();
+    for token in tokens {
+        match token {
+            Ok(keyword) => lexed.push(keyword),
+            Err(error) => errors.push(error),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok(lexed)
+}
+
+/// Streaming lexer, yields the keywords of the lines of a reader while
+/// reading them. Lines with errors are skipped, the iteration ends after
+/// the last line or when reading fails.
+pub struct Tokens<R> {
+    lines: io::Lines<R>,
+    /// file being lexed, included files are relative to its directory
+    path: PathBuf,
+    /// files currently being included to detect recursion
+    include_stack: Vec<PathBuf>,
+    line_number: u16,
+    doc_buffer: Vec<String>,
+    /// lexed but not yet yielded keywords and errors of the current line
+    pending: VecDeque<Result<Keyword, LexerError>>,
+    done: bool,
+    /// see [`Self::with_comments`]
+    comments: bool,
+}
+
+impl<R: BufRead> Tokens<R> {
+    /// Lexes the lines of `reader`, files it includes are relative to the
+    /// directory of `path`
+    pub fn new(reader: R, path: &Path) -> Self {
+        Tokens {
+            lines: reader.lines(),
+            path: path.to_path_buf(),
+            include_stack: Vec::new(),
+            line_number: 0,
+            doc_buffer: Vec::new(),
+            pending: VecDeque::new(),
+            done: false,
+            comments: false,
+        }
+    }
+
+    /// Keeps the source as written for tools like the formatter: comments
+    /// and blank lines become [`Keyword::Comment`] and [`Keyword::BlankLine`],
+    /// `;;` lines are no [`Keyword::Doc`] and included files are not lexed
+    pub fn with_comments(mut self) -> Self {
+        self.comments = true;
+        self
+    }
+
+    /// Lexes `line` into [`Self::pending`]
+    fn lex(&mut self, line: String) {
+        let line_number = self.line_number;
+        self.line_number += 1;
+        if self.comments {
+            self.lex_with_comments(line, line_number);
+            return;
+        }
+        if let Some(doc_line) = line.trim_start().strip_prefix(";;") {
+            self.doc_buffer
+                .push(doc_line.trim_start_matches(';').trim().to_string());
+            return;
+        }
+        let mut keyword_buffer: Vec<Keyword> = Vec::with_capacity(4);
+        if let Err(error) = lex_line(&mut keyword_buffer, line, line_number) {
+            self.doc_buffer.clear();
+            self.pending.push_back(Err(error));
+            return;
+        }
+        match keyword_buffer.first() {
+            // attributes may sit between the doc comment and its label
+            Some(Keyword::Attribute { .. }) => (),
+            Some(Keyword::Label { line_number, .. }) if !self.doc_buffer.is_empty() => {
+                let doc = Keyword::doc(&self.doc_buffer.join("\n"), *line_number);
+                self.pending.push_back(Ok(doc));
+                self.doc_buffer.clear();
+            }
+            _ => self.doc_buffer.clear(),
+        }
+        let include = match keyword_buffer.as_slice() {
+            [Keyword::Directive { name, .. }, Keyword::StringLiteral { value, .. }]
+                if name == "include" =>
+            {
+                Some(value.clone())
+            }
+            _ => None,
+        };
+        self.pending.extend(keyword_buffer.into_iter().map(Ok));
+        if let Some(include) = include {
+            match lex_include(&self.path, &include, line_number, &self.include_stack) {
+                Ok(included) => self.pending.extend(included.into_iter().map(Ok)),
+                Err(include_errors) => self.pending.extend(include_errors.into_iter().map(Err)),
+            }
+            self.pending
+                .push_back(Ok(Keyword::directive(INCLUDE_END, line_number)));
+        }
+    }
+}
+
+impl<R: BufRead> Tokens<R> {
+    /// Lexes `line` into [`Self::pending`] like [`Self::lex`], followed by
+    /// its comment
+    fn lex_with_comments(&mut self, line: String, line_number: u16) {
+        if line.trim().is_empty() {
+            self.pending.push_back(Ok(Keyword::BlankLine {
+                line_number,
+                column: 0,
+            }));
+            return;
+        }
+        let comment = comment_column(&line).map(|column| Keyword::Comment {
+            text: line[column..].trim_end().to_string(),
+            line_number,
+            column: column as u16,
+        });
+        if !line.trim_start().starts_with(";;") {
+            let mut keyword_buffer: Vec<Keyword> = Vec::with_capacity(4);
+            if let Err(error) = lex_line(&mut keyword_buffer, line, line_number) {
+                self.pending.push_back(Err(error));
+                return;
             }
-            Err(io_err) => {
-                errors.push(LexerError::IoError(io_err));
-                return Err(errors);
+            self.pending.extend(keyword_buffer.into_iter().map(Ok));
+        }
+        self.pending.extend(comment.map(Ok));
+    }
+}
+
+impl<R: BufRead> Iterator for Tokens<R> {
+    type Item = Result<Keyword, LexerError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pending.is_empty() && !self.done {
+            match self.lines.next() {
+                Some(Ok(line)) => self.lex(line),
+                Some(Err(io_err)) => {
+                    self.done = true;
+                    return Some(Err(LexerError::IoError(io_err)));
+                }
+                None => self.done = true,
             }
         }
+        self.pending.pop_front()
     }
+}
 
-    let hlt = Keyword::Mmenonic {
-        name: String::from("hlt"),
+/// Lexes the file `include` referenced at `line_number` of `path`.
+/// Paths are relative to the directory of the including file.
+fn lex_include(
+    path: &Path,
+    include: &str,
+    line_number: u16,
+    include_stack: &[PathBuf],
+) -> Result<Vec<Keyword>, Vec<LexerError>> {
+    let in_include = |error| LexerError::InInclude {
+        path: include.to_string(),
         line_number,
+        error: Box::new(error),
     };
-    if lexed
-        .last()
-        .and_then(|last_keyword| if *last_keyword != hlt { Some(()) } else { None })
-        .is_some()
-    {
-        lexed.push(hlt);
+    let included_path = path
+        .parent()
+        .unwrap_or(Path::new(""))
+        .join(include)
+        .canonicalize()
+        .map_err(|io_err| vec![in_include(LexerError::IoError(io_err))])?;
+    if include_stack.contains(&included_path) {
+        return Err(vec![LexerError::RecursiveInclude {
+            path: include.to_string(),
+            line_number,
+        }]);
     }
-
-    Ok(lexed)
+    lex_file(&included_path, include_stack)
+        .map_err(|errors| errors.into_iter().map(in_include).collect())
 }
 
 pub fn lex_line(
@@ -259,30 +884,80 @@ pub fn lex_line(
     line_number: u16,
 ) -> Result<(), LexerError> {
     let mut line = line;
+    // enclosed in [[ ]] -> label attributes
+    if let Some(attributes) = line
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .strip_prefix("[[")
+        .and_then(|line| line.strip_suffix("]]"))
+    {
+        let mut column = line.find("[[").unwrap_or_default() + 2;
+        for attribute in attributes.split(',') {
+            let attribute_column = (column + attribute.len() - attribute.trim_start().len()) as u16;
+            column += attribute.len() + 1;
+            let attribute = attribute.trim();
+            let (name, value) = match attribute.split_once('=') {
+                Some((name, value)) => (name.trim(), Some(value.trim().to_string())),
+                None => (attribute, None),
+            };
+            if name.is_empty() {
+                return Err(LexerError::InvalidIdentifier {
+                    actual: line.trim().to_string(),
+                    line_number,
+                    column: attribute_column,
+                });
+            }
+            keywords.push(Keyword::Attribute {
+                name: name.to_string(),
+                value,
+                line_number,
+                column: attribute_column,
+            });
+        }
+        return Ok(());
+    }
     // starts with 4 spaces -> instruction
+    // starts with . and is no label definition -> directive
     line = line.trim_end().to_string();
-    if line.starts_with([' ', '\t']) {
+    let indent = (line.len() - line.trim_start().len()) as u16;
+    if line.starts_with([' ', '\t']) || (line.starts_with('.') && !line.ends_with(':')) {
         line = line.trim_start().to_string();
+        let mut args: VecDeque<(String, u16)> = split_words(&line, line_number, indent)?;
         if let Some(semi_idx) = line.find(';') {
             line.truncate(semi_idx);
         }
-        let mut args: VecDeque<&str> = line.split_whitespace().collect();
-        let command = args.pop_front().unwrap_or("");
+        let (command, column) = args.pop_front().unwrap_or_default();
         if command.is_empty() {
             return Ok(());
         }
 
-        keywords.push(Keyword::Mmenonic {
-            name: command.to_string(),
-            line_number,
-        });
+        if let Some(directive) = command.strip_prefix('.') {
+            keywords.push(Keyword::Directive {
+                name: directive.to_string(),
+                line_number,
+                column,
+            });
+        } else {
+            keywords.push(Keyword::Mmenonic {
+                name: command.to_string(),
+                line_number,
+                column,
+            });
+        }
 
-        while let Some(word) = args.pop_front() {
-            match word_type(word, line_number) {
-                Ok(Keyword::Mmenonic { name, line_number }) => {
+        while let Some((word, column)) = args.pop_front() {
+            match word_type(&word, line_number, column) {
+                Ok(Keyword::Mmenonic {
+                    name,
+                    line_number,
+                    column,
+                }) => {
                     return Err(LexerError::CommandAfterCommand {
                         command_name: name,
                         line_number,
+                        column,
                     })
                 }
                 Ok(keyword) => keywords.push(keyword),
@@ -295,65 +970,230 @@ pub fn lex_line(
         keywords.push(Keyword::Label {
             name: label.to_string(),
             line_number,
+            column: indent,
         });
     }
 
     Ok(())
 }
 
-fn word_type(word: &str, line_number: u16) -> Result<Keyword, LexerError> {
+/// Splits a line into whitespace separated words and their columns,
+/// counted from `offset`. Quoted strings stay one word including their
+/// quotes and everything after a `;` outside of a string is a comment.
+fn split_words(
+    line: &str,
+    line_number: u16,
+    offset: u16,
+) -> Result<VecDeque<(String, u16)>, LexerError> {
+    let mut words: VecDeque<(String, u16)> = VecDeque::with_capacity(4);
+    let mut word = String::new();
+    let mut column = offset;
+    let mut chars = line.char_indices();
+    while let Some((idx, c)) = chars.next() {
+        if word.is_empty() {
+            column = offset + idx as u16;
+        }
+        match c {
+            ';' => break,
+            '"' => {
+                let unterminated = LexerError::UnterminatedString {
+                    line_number,
+                    column,
+                };
+                word.push(c);
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => {
+                            word.push('\\');
+                            match chars.next() {
+                                Some((_, c)) => word.push(c),
+                                None => return Err(unterminated),
+                            }
+                        }
+                        Some((_, c)) => word.push(c),
+                        None => return Err(unterminated),
+                    }
+                }
+                word.push('"');
+            }
+            c if c.is_whitespace() => {
+                if !word.is_empty() {
+                    words.push_back((std::mem::take(&mut word), column));
+                }
+            }
+            c => word.push(c),
+        }
+    }
+    if !word.is_empty() {
+        words.push_back((word, column));
+    }
+    Ok(words)
+}
+
+/// Byte offset of the `;` starting the comment of `line`, if it has one
+fn comment_column(line: &str) -> Option<usize> {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (idx, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            ';' if !in_string => return Some(idx),
+            _ => (),
+        }
+    }
+    None
+}
+
+/// Resolves the escape sequences `\n`, `\t`, `\r`, `\0`, `\\` and `\"`
+fn unescape(quoted: &str) -> Option<String> {
+    let mut value = String::with_capacity(quoted.len());
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            value.push(match chars.next()? {
+                'n' => '\n',
+                't' => '\t',
+                'r' => '\r',
+                '0' => '\0',
+                '\\' => '\\',
+                '"' => '"',
+                _ => return None,
+            });
+        } else {
+            value.push(c);
+        }
+    }
+    Some(value)
+}
+
+/// Splits a number literal into its digits, radix and whether it is negative
+pub(crate) fn split_number(word: &str) -> Option<(&str, u32, bool)> {
+    let (unsigned_word, sign) = match word.strip_prefix('-') {
+        Some(unsigned_word) => (unsigned_word, true),
+        None => (word, false),
+    };
+    if let Some(hex_word) = unsigned_word.strip_prefix("0x") {
+        Some((hex_word, 16, sign))
+    } else if let Some(octal_word) = unsigned_word.strip_prefix("0o") {
+        Some((octal_word, 8, sign))
+    } else if let Some(binary_word) = unsigned_word.strip_prefix("0b") {
+        Some((binary_word, 2, sign))
+    } else if unsigned_word.starts_with(|c: char| c.is_ascii_digit()) {
+        Some((unsigned_word, 10, sign))
+    } else {
+        None
+    }
+}
+
+fn is_label_name(word: &str) -> bool {
+    !word.is_empty() && word.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Name of a numeric label like `1`, which may be defined more than once
+pub fn is_numeric_label(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Label name or `global.local` reference to a local label
+fn is_label_reference(word: &str) -> bool {
+    match word.split_once('.') {
+        Some((scope, local)) => is_label_name(scope) && is_label_name(local),
+        None => is_label_name(word),
+    }
+}
+
+/// Parses a 32 bit number literal
+fn parse_number(word: &str) -> Option<u32> {
+    let (digits, radix, sign) = split_number(word)?;
+    u32::from_str_radix(digits, radix)
+        .map(|num| if sign { num.wrapping_neg() } else { num })
+        .ok()
+}
+
+/// Parses a number, the location counter `$` (or `.`) or a label
+/// reference, which may start with a dot
+fn parse_atom(word: &str) -> Option<ir::Expression> {
+    if word == "$" || word == "." {
+        return Some(ir::Expression::Location);
+    }
+    if split_number(word).is_some() {
+        return parse_number(word).map(ir::Expression::Constant);
+    }
+    let name = word.strip_prefix('.').unwrap_or(word);
+    is_label_reference(name).then(|| ir::Expression::Label(ir::LabelReference::new(name)))
+}
+
+/// Builds the expression of an operator from its argument
+type Function = fn(Box<ir::Expression>) -> ir::Expression;
+
+/// Operators applicable to an expression like `lo(handler)`
+const FUNCTIONS: [(&str, Function); 2] =
+    [("lo", ir::Expression::Low), ("hi", ir::Expression::High)];
+
+/// Parses `lo(ARGUMENT)` and `hi(ARGUMENT)` as well as `ATOM+ATOM` and
+/// `ATOM-ATOM`. Arguments are atoms or expressions themselves.
+fn parse_expression(word: &str) -> Option<ir::Expression> {
+    for (name, function) in FUNCTIONS {
+        if let Some(argument) = word
+            .strip_prefix(name)
+            .and_then(|word| word.strip_prefix('('))
+        {
+            let argument = argument.strip_suffix(')')?;
+            let argument = parse_expression(argument).or_else(|| parse_atom(argument))?;
+            return Some(function(Box::new(argument)));
+        }
+    }
+
+    // the first character may be the sign of a number
+    let (operator_idx, operator) = word
+        .char_indices()
+        .skip(1)
+        .find(|(_, c)| *c == '+' || *c == '-')?;
+    let base = Box::new(parse_atom(&word[..operator_idx])?);
+    let offset = Box::new(parse_atom(&word[operator_idx + 1..])?);
+    Some(if operator == '+' {
+        ir::Expression::Sum(base, offset)
+    } else {
+        ir::Expression::Difference(base, offset)
+    })
+}
+
+fn word_type(word: &str, line_number: u16, column: u16) -> Result<Keyword, LexerError> {
+    // string
+    // e.g.: "stack pointer", "Hello\n"
+    if let Some(quoted) = word
+        .strip_prefix('"')
+        .and_then(|word| word.strip_suffix('"'))
+    {
+        return unescape(quoted)
+            .map(|value| Keyword::StringLiteral {
+                value,
+                line_number,
+                column,
+                origin: String::from(word),
+            })
+            .ok_or(LexerError::InvalidIdentifier {
+                actual: String::from(word),
+                line_number,
+                column,
+            });
+    }
+
     // register address
     if let Some(register_identifier) = word.strip_prefix('%') {
         return Ok(Keyword::RegisterAddress {
             name: String::from(register_identifier),
             line_number,
+            column,
         });
     }
 
     // constant
-    // e.g.: 0xa7, 173, 0b0011010
-    if let Some(parsed) = if let Some(signed_hex_word) = word.strip_prefix("-0x") {
-        Some((signed_hex_word, 16, true))
-    } else if let Some(hex_word) = word.strip_prefix("0x") {
-        Some((hex_word, 16, false))
-    } else if let Some(signed_binary_word) = word.strip_prefix("-0b") {
-        Some((signed_binary_word, 2, true))
-    } else if let Some(binary_word) = word.strip_prefix("0b") {
-        Some((binary_word, 2, false))
-    } else if word
-        .chars()
-        .next()
-        .and_then(|first_char| {
-            if first_char.is_ascii_digit() {
-                Some(())
-            } else {
-                None
-            }
-        })
-        .is_some()
-    {
-        Some((word, 10, false))
-    } else if let Some(signed_dec_word) = word.strip_prefix('-') {
-        if signed_dec_word
-            .chars()
-            .next()
-            .and_then(|first_char| {
-                if first_char.is_ascii_digit() {
-                    Some(())
-                } else {
-                    None
-                }
-            })
-            .is_some()
-        {
-            Some((signed_dec_word, 10, true))
-        } else {
-            None
-        }
-    } else {
-        None
-    }
-    .and_then(|(word, radix, sign)| {
+    // e.g.: 0xa7, 173, 0b0011010, 0o777
+    if let Some(parsed) = split_number(word).and_then(|(word, radix, sign)| {
         u16::from_str_radix(word, radix)
             .map(|num| if sign { num.wrapping_neg() } else { num })
             .ok()
@@ -361,43 +1201,58 @@ fn word_type(word: &str, line_number: u16) -> Result<Keyword, LexerError> {
         return Ok(Keyword::Constant {
             value: parsed,
             line_number,
+            column,
             origin: String::from(word),
         });
     }
 
-    // boolean
-    if ["true", "True", "TRUE"]
-        .iter()
-        .any(|&pattern| word == pattern)
+    // expression
+    // e.g.: lo(handler), hi(0x12345678), .loop+2, end-1, $, 0x12345678
+    if let Some(expression) = parse_expression(word)
+        .or_else(|| (word == "$" || word == ".").then_some(ir::Expression::Location))
+        .or_else(|| parse_number(word).map(ir::Expression::Constant))
     {
+        return Ok(Keyword::Expression {
+            expression,
+            line_number,
+            column,
+            origin: String::from(word),
+        });
+    }
+
+    // boolean
+    if ["true", "True", "TRUE"].contains(&word) {
         return Ok(Keyword::Boolean {
             value: true,
             line_number,
+            column,
             origin: String::from(word),
         });
     }
-    if ["false", "False", "FALSE"]
-        .iter()
-        .any(|&pattern| word == pattern)
-    {
+    if ["false", "False", "FALSE"].contains(&word) {
         return Ok(Keyword::Boolean {
             value: false,
             line_number,
+            column,
             origin: String::from(word),
         });
     }
 
-    // label
-    if word.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+    // label, references may start with a dot
+    // e.g.: loop, .handler, main.loop
+    let name = word.strip_prefix('.').unwrap_or(word);
+    if is_label_reference(name) {
         return Ok(Keyword::Label {
-            name: String::from(word),
+            name: String::from(name),
             line_number,
+            column,
         });
     }
 
     Err(LexerError::InvalidIdentifier {
         actual: String::from(word),
         line_number,
+        column,
     })
 }
 
@@ -521,9 +1376,39 @@ mod tests {
         }
     }
 
+    #[test]
+    fn number_literals() {
+        let mut keywords = Vec::new();
+        lex_line(
+            &mut keywords,
+            String::from("    .word 0o17 -0o1 0b101 0x1f 42"),
+            0,
+        )
+        .unwrap();
+        assert_eq!(
+            vec![
+                Keyword::directive("word", 0),
+                Keyword::constant("0o17", 15, 0),
+                Keyword::constant("-0o1", 1u16.wrapping_neg(), 0),
+                Keyword::constant("0b101", 5, 0),
+                Keyword::constant("0x1f", 31, 0),
+                Keyword::constant("42", 42, 0),
+            ],
+            keywords
+        );
+
+        // numbers beyond 16 bits are kept as 32 bit expressions
+        let mut keywords = Vec::new();
+        lex_line(&mut keywords, String::from("    li %reg0 0x12345678"), 0).unwrap();
+        assert_eq!(
+            Keyword::expression("0x12345678", ir::Expression::Constant(0x12345678), 0),
+            keywords[2]
+        );
+    }
+
     #[test]
     fn whitespace() {
-        let expected = vec![
+        let expected = [
             Keyword::mmenonic("ldc", 0),
             Keyword::register_address("reg0", 0),
             Keyword::constant("0x4", 4, 0),
@@ -539,7 +1424,7 @@ mod tests {
 
     #[test]
     fn comments() {
-        let expected = vec![
+        let expected = [
             Keyword::mmenonic("ldc", 0),
             Keyword::register_address("reg0", 0),
             Keyword::constant("0", 0, 0),
@@ -551,4 +1436,299 @@ mod tests {
             assert_eq!(expected_keyword, found_keyword);
         }
     }
+
+    #[test]
+    fn doc_comments() {
+        let expected = [
+            Keyword::doc("Entry point of the program", 3),
+            Keyword::label("main", 3),
+            Keyword::mmenonic("ldc", 4),
+            Keyword::register_address("reg0", 4),
+            Keyword::constant("0x1", 1, 4),
+            Keyword::mmenonic("ldc", 7),
+            Keyword::register_address("reg1", 7),
+            Keyword::constant("0x2", 2, 7),
+            Keyword::doc(
+                "Adds register 0 and register 1\nand stores the result in register 2",
+                10,
+            ),
+            Keyword::label("add", 10),
+            Keyword::mmenonic("add", 11),
+            Keyword::register_address("reg2", 11),
+            Keyword::register_address("reg0", 11),
+            Keyword::register_address("reg1", 11),
+            Keyword::label("done", 12),
+            Keyword::mmenonic("hlt", 13),
+        ];
+
+        let found = lexer(Path::new("tests/docs.s")).unwrap();
+        assert_eq!(expected.len(), found.len());
+        for (expected_keyword, found_keyword) in expected.iter().zip(found.iter()) {
+            assert_eq!(expected_keyword, found_keyword);
+        }
+    }
+
+    #[test]
+    fn attributes() {
+        let expected = [
+            Keyword::mmenonic("nop", 0),
+            Keyword::mmenonic("nop", 1),
+            Keyword::mmenonic("nop", 2),
+            Keyword::attribute("align", Some("4"), 3),
+            Keyword::label("aligned", 4),
+            Keyword::mmenonic("nop", 5),
+            Keyword::attribute("noreturn", None, 7),
+            Keyword::attribute("interrupt", None, 7),
+            Keyword::doc("Stops the program", 8),
+            Keyword::label("done", 8),
+            Keyword::mmenonic("hlt", 9),
+        ];
+
+        let found = lexer(Path::new("tests/attributes.s")).unwrap();
+        assert_eq!(expected.len(), found.len());
+        for (expected_keyword, found_keyword) in expected.iter().zip(found.iter()) {
+            assert_eq!(expected_keyword, found_keyword);
+        }
+    }
+
+    #[test]
+    fn directives_and_strings() {
+        let expected = [
+            Keyword::directive("reserve", 0),
+            Keyword::register_address("reg7", 0),
+            Keyword::string_literal("\"stack pointer\"", "stack pointer", 0),
+            Keyword::directive("reserve", 1),
+            Keyword::register_address("regG", 1),
+            Keyword::string_literal("\"link;register \\\"lr\\\"\"", "link;register \"lr\"", 1),
+            Keyword::mmenonic("ldc", 2),
+            Keyword::register_address("reg0", 2),
+            Keyword::constant("0x1", 1, 2),
+            Keyword::mmenonic("hlt", 3),
+        ];
+
+        let found = lexer(Path::new("tests/reserve.s")).unwrap();
+        assert_eq!(expected.len(), found.len());
+        for (expected_keyword, found_keyword) in expected.iter().zip(found.iter()) {
+            assert_eq!(expected_keyword, found_keyword);
+        }
+
+        let mut keywords = Vec::new();
+        assert!(matches!(
+            lex_line(&mut keywords, String::from("    .reserve \"open"), 0),
+            Err(LexerError::UnterminatedString { .. })
+        ));
+    }
+
+    #[test]
+    fn includes() {
+        let expected = [
+            Keyword::label("main", 0),
+            Keyword::mmenonic("nop", 1),
+            Keyword::directive("include", 2),
+            Keyword::string_literal("\"lib.s\"", "lib.s", 2),
+            Keyword::doc("Increments register 0", 1),
+            Keyword::label("lib", 1),
+            Keyword::mmenonic("inc", 2),
+            Keyword::register_address("reg0", 2),
+            Keyword::directive(INCLUDE_END, 2),
+            Keyword::mmenonic("jr", 3),
+            Keyword::label("lib", 3),
+            Keyword::mmenonic("hlt", 4),
+        ];
+
+        let found = lexer(Path::new("tests/include/main.s")).unwrap();
+        assert_eq!(expected.len(), found.len());
+        for (expected_keyword, found_keyword) in expected.iter().zip(found.iter()) {
+            assert_eq!(expected_keyword, found_keyword);
+            assert_eq!(
+                expected_keyword.get_line_number(),
+                found_keyword.get_line_number()
+            );
+        }
+
+        match lexer(Path::new("tests/include/cycle_a.s"))
+            .unwrap_err()
+            .as_slice()
+        {
+            [LexerError::InInclude { path, error, .. }] => {
+                assert_eq!("cycle_b.s", path);
+                assert!(matches!(
+                    **error,
+                    LexerError::RecursiveInclude { line_number: 2, .. }
+                ));
+            }
+            found => panic!("expected recursive include, found {:?}", found),
+        }
+
+        let errors = lexer(Path::new("tests/include/broken.s")).unwrap_err();
+        assert_eq!(2, errors.len());
+        assert!(matches!(
+            &errors[1],
+            LexerError::InInclude { line_number: 2, error, .. }
+                if matches!(**error, LexerError::InvalidIdentifier { line_number: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn expressions() {
+        let label = |name: &str| Box::new(ir::Expression::Label(ir::LabelReference::new(name)));
+        let expected = [
+            Keyword::label("main", 0),
+            Keyword::mmenonic("ldc", 1),
+            Keyword::register_address("reg0", 1),
+            Keyword::expression(
+                "lo(0x12345678)",
+                ir::Expression::Low(Box::new(ir::Expression::Constant(0x12345678))),
+                1,
+            ),
+            Keyword::mmenonic("ldc", 2),
+            Keyword::register_address("reg1", 2),
+            Keyword::expression(
+                "hi(0x12345678)",
+                ir::Expression::High(Box::new(ir::Expression::Constant(0x12345678))),
+                2,
+            ),
+            Keyword::mmenonic("ldc", 3),
+            Keyword::register_address("reg2", 3),
+            Keyword::expression("lo(handler)", ir::Expression::Low(label("handler")), 3),
+            Keyword::mmenonic("ldc", 4),
+            Keyword::register_address("reg3", 4),
+            Keyword::expression("hi(handler)", ir::Expression::High(label("handler")), 4),
+            Keyword::label("handler", 5),
+            Keyword::mmenonic("hlt", 6),
+        ];
+
+        let found = lexer(Path::new("tests/halves.s")).unwrap();
+        assert_eq!(expected.len(), found.len());
+        for (expected_keyword, found_keyword) in expected.iter().zip(found.iter()) {
+            assert_eq!(expected_keyword, found_keyword);
+        }
+
+        let mut keywords = Vec::new();
+        lex_line(&mut keywords, String::from("    jr .loop+2"), 0).unwrap();
+        lex_line(&mut keywords, String::from("    ldc %reg0 lo(end-1)"), 1).unwrap();
+        lex_line(&mut keywords, String::from("    jr $"), 2).unwrap();
+        assert_eq!(
+            vec![
+                Keyword::mmenonic("jr", 0),
+                Keyword::expression(
+                    ".loop+2",
+                    ir::Expression::Sum(label("loop"), Box::new(ir::Expression::Constant(2))),
+                    0,
+                ),
+                Keyword::mmenonic("ldc", 1),
+                Keyword::register_address("reg0", 1),
+                Keyword::expression(
+                    "lo(end-1)",
+                    ir::Expression::Low(Box::new(ir::Expression::Difference(
+                        label("end"),
+                        Box::new(ir::Expression::Constant(1)),
+                    ))),
+                    1,
+                ),
+                Keyword::mmenonic("jr", 2),
+                Keyword::expression("$", ir::Expression::Location, 2),
+            ],
+            keywords
+        );
+        for invalid in ["lo(0x100000000)", "hi(handler", "lo(%reg0)", "loop+", "+1"] {
+            assert!(matches!(
+                lex_line(&mut keywords, format!("    ldc %reg0 {invalid}"), 0),
+                Err(LexerError::InvalidIdentifier { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn auto_halt() {
+        let path = Path::new("tests/numeric_labels.s");
+        let options = LexerOptions { auto_halt: false };
+
+        let mut lexed = lexer_with_options(path, &options).unwrap();
+        assert!(matches!(lexed.last(), Some(Keyword::Label { name, .. }) if name == "1b"));
+        assert!(append_halt(&mut lexed));
+        assert_eq!(Some(&Keyword::mmenonic("hlt", 15)), lexed.last());
+        assert!(!append_halt(&mut lexed));
+        assert_eq!(lexed, lexer(path).unwrap());
+    }
+    #[test]
+    fn columns() {
+        let mut keywords = Vec::new();
+        lex_line(&mut keywords, String::from("loop:"), 0).unwrap();
+        lex_line(
+            &mut keywords,
+            String::from("    ldc  %reg0 \"a b\" ; 0x1"),
+            1,
+        )
+        .unwrap();
+        lex_line(&mut keywords, String::from("[[align=4, noreturn]]"), 2).unwrap();
+        let columns: Vec<u16> = keywords.iter().map(Keyword::get_column).collect();
+        assert_eq!(vec![0, 4, 9, 15, 2, 11], columns);
+
+        match lex_line(&mut keywords, String::from("    ldc %reg0 0x1 }"), 3) {
+            Err(LexerError::InvalidIdentifier {
+                line_number,
+                column,
+                ..
+            }) => assert_eq!((3, 18), (line_number, column)),
+            found => panic!("expected invalid identifier, found {:?}", found),
+        }
+    }
+
+    #[test]
+    fn streaming() {
+        let source = ";; entry\nmain:\n    ldc %reg0 }\n    hlt\n";
+        let mut tokens = Tokens::new(source.as_bytes(), Path::new(""));
+
+        assert!(matches!(tokens.next(), Some(Ok(Keyword::Doc { .. }))));
+        assert!(matches!(tokens.next(), Some(Ok(Keyword::Label { .. }))));
+        assert!(matches!(
+            tokens.next(),
+            Some(Err(LexerError::InvalidIdentifier { line_number: 2, .. }))
+        ));
+        // the line with the error is skipped
+        assert!(matches!(
+            tokens.next(),
+            Some(Ok(Keyword::Mmenonic { line_number: 3, .. }))
+        ));
+        assert!(tokens.next().is_none());
+
+        assert_eq!(
+            lexer(Path::new("tests/whitespace.s")).unwrap(),
+            lexer_from_reader(
+                io::BufReader::new(File::open("tests/whitespace.s").unwrap()),
+                &LexerOptions::default()
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn kept_comments() {
+        let source = ";; entry\nmain:\n\n    .ascii \"a;b\" ; text\n    .include \"missing.s\"\n";
+        let found = lexer_with_comments(source).unwrap();
+
+        let comment = |text: &str, line_number| Keyword::Comment {
+            text: text.to_string(),
+            line_number,
+            column: 0,
+        };
+        assert_eq!(
+            vec![
+                comment(";; entry", 0),
+                Keyword::label("main", 1),
+                Keyword::BlankLine {
+                    line_number: 2,
+                    column: 0
+                },
+                Keyword::directive("ascii", 3),
+                Keyword::string_literal("\"a;b\"", "a;b", 3),
+                comment("; text", 3),
+                Keyword::directive("include", 4),
+                Keyword::string_literal("\"missing.s\"", "missing.s", 4),
+            ],
+            found
+        );
+        assert_eq!(17, found[5].get_column());
+    }
 }
@@ -1,4 +1,83 @@
+pub mod builder;
+pub mod cfg;
+pub mod cosim;
+pub mod debug_info;
+pub mod debugger;
+pub mod device;
+pub mod diagnostic;
+pub mod disassembler;
+pub mod emulator;
+pub mod error;
+pub mod formatter;
 pub mod generator;
 pub mod ir;
+pub mod ir_json;
+pub mod layout;
 pub mod lexer;
+pub mod listing;
+pub mod loader;
+pub mod lowering;
+pub mod machine;
+pub mod output;
 pub mod parser;
+pub mod preprocessor;
+pub mod profile;
+pub mod recording;
+pub mod stats;
+pub mod vcd;
+pub mod verify;
+pub mod warning;
+
+use std::path::Path;
+
+use error::MasmError;
+use generator::InstructionWord;
+use lexer::{Keyword, LexerOptions};
+
+/// Assembles the program `source` with the default options of all stages,
+/// like `masm` without any flags. Included files are relative to the
+/// current directory and warnings are not reported.
+pub fn assemble_str(source: &str) -> Result<Vec<InstructionWord>, MasmError> {
+    assemble(lexer::lexer_from_str(source, &LexerOptions::default())?)
+}
+
+/// Like [`assemble_str`], but reads the program from the file at `path`
+pub fn assemble_path(path: &Path) -> Result<Vec<InstructionWord>, MasmError> {
+    assemble(lexer::lexer(path)?)
+}
+
+fn assemble(keywords: Vec<Keyword>) -> Result<Vec<InstructionWord>, MasmError> {
+    let keywords = preprocessor::preprocessor(keywords)?;
+    let ir = parser::parser(keywords)?;
+    Ok(generator::generator(ir)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assemble_source() {
+        let binary = assemble_str("main:\n    ldc %reg0 0x1\n    hlt\n").unwrap();
+        assert_eq!(2, binary.len());
+        assert_eq!(binary, assemble_str("main:\n    ldc %reg0 0x1\n").unwrap());
+
+        match assemble_str("main:\n    ladc %reg0 0x1\n") {
+            Err(MasmError::Parser(errors)) => assert_eq!(1, errors.len()),
+            found => panic!("expected parser error, found {:?}", found),
+        }
+    }
+
+    #[test]
+    fn assemble_file() {
+        let source = std::fs::read_to_string("tests/test.s").unwrap();
+        assert_eq!(
+            assemble_str(&source).unwrap(),
+            assemble_path(Path::new("tests/test.s")).unwrap()
+        );
+        assert!(matches!(
+            assemble_path(Path::new("tests/missing.s")),
+            Err(MasmError::Lexer(_))
+        ));
+    }
+}
@@ -0,0 +1,331 @@
+use std::fmt::Write as _;
+use std::path::Path;
+
+use crate::debug_info::{DebugInfo, Span};
+use crate::diagnostic::display_line;
+use crate::disassembler;
+use crate::generator::InstructionWord;
+use crate::ir;
+
+/// Width of the columns in front of the source text
+const LINE_WIDTH: usize = 6;
+const ADDRESS_WIDTH: usize = 7;
+const WORD_WIDTH: usize = 5;
+
+/// Listing interleaving every line of `source` with the addresses and words
+/// generated for it. Statements of a single word share the row of their
+/// line, the words of macros, pseudo-instructions and data directives follow
+/// their line in rows marked with `+`, with the disassembly of each word
+/// unless they are data. Words of no line, like an appended `hlt`, follow
+/// the last line. The lines of included files follow the line of their
+/// first `.include`, `read` returns the text of such a file.
+///
+/// The listing ends with a cross-reference of the labels of `ir`, see
+/// [`cross_reference`].
+pub fn listing(
+    input: &Path,
+    source: &str,
+    ir: &ir::IR,
+    debug_info: &DebugInfo,
+    binary: &[InstructionWord],
+    read: impl Fn(&Path) -> Option<String>,
+) -> String {
+    let mut listing = format!(
+        "; Listing of {} generated by masm\n;{:>width$}  {:<ADDRESS_WIDTH$}  {:<WORD_WIDTH$}    source\n",
+        input.file_name().map(Path::new).unwrap_or(input).display(),
+        "line",
+        "address",
+        "word",
+        width = LINE_WIDTH - 1
+    );
+    list_file(&mut listing, None, source, debug_info, binary, &read);
+    listing.push('\n');
+    listing.push_str(&cross_reference(ir, debug_info));
+    listing
+}
+
+/// Table of every label sorted by address with the line of its definition
+/// and the line and address of every instruction referencing it, e.g.
+/// `loop      0x0001  3        7 (0x0004), lib.s:2 (0x0010)`. Lines are
+/// counted from 1 like in the diagnostics, lines of included files are
+/// prefixed with the file.
+pub fn cross_reference(ir: &ir::IR, debug_info: &DebugInfo) -> String {
+    let position = |file: Option<usize>, line_number: u16| match file {
+        None => display_line(line_number).to_string(),
+        Some(file) => format!(
+            "{}:{}",
+            debug_info.files[file].display(),
+            display_line(line_number)
+        ),
+    };
+    // target, address and line of every reference
+    let mut references: Vec<(&str, u16, String)> = Vec::new();
+    for (label, instructions) in &ir.instructions {
+        let Some(definition) = ir.label_definitions.0.get(label) else {
+            continue;
+        };
+        let mut address = definition.address.0;
+        for instruction in instructions {
+            let line = debug_info.span(address).map_or_else(
+                || String::from("?"),
+                |span| position(span.file, span.line_number),
+            );
+            for target in instruction.label_references() {
+                references.push((target.name(), address, line.clone()));
+            }
+            address = address.wrapping_add(instruction.size());
+        }
+    }
+    references.sort_by_key(|(_, address, _)| *address);
+
+    let mut labels: Vec<&ir::LabelDefinition> = ir.label_definitions.0.values().collect();
+    labels.sort_by_key(|label| (label.address, &label.name));
+    let width = labels
+        .iter()
+        .map(|label| label.name.len())
+        .max()
+        .unwrap_or_default()
+        .max("; label".len());
+    let mut table = format!(
+        "; Cross-reference\n; {:<name_width$}  address  defined  referenced\n",
+        "label",
+        name_width = width - 2
+    );
+    for label in labels {
+        let defined = debug_info
+            .labels
+            .iter()
+            .find(|(name, _, _)| *name == label.name)
+            .map_or_else(
+                || String::from("-"),
+                |(_, file, line_number)| position(*file, *line_number),
+            );
+        let referenced: Vec<String> = references
+            .iter()
+            .filter(|(target, _, _)| *target == label.name)
+            .map(|(_, address, line)| format!("{} ({:#06x})", line, address))
+            .collect();
+        let referenced = if referenced.is_empty() {
+            String::from("-")
+        } else {
+            referenced.join(", ")
+        };
+        let _ = writeln!(
+            table,
+            "{:<width$}  {:#06x}   {:<7}  {}",
+            label.name, label.address.0, defined, referenced
+        );
+    }
+    table
+}
+
+/// Appends the lines of `file`, `None` is the assembled file
+fn list_file(
+    listing: &mut String,
+    file: Option<usize>,
+    source: &str,
+    debug_info: &DebugInfo,
+    binary: &[InstructionWord],
+    read: &impl Fn(&Path) -> Option<String>,
+) {
+    for (line_number, line) in source.lines().enumerate() {
+        let line_number = line_number as u16;
+        let spans: Vec<&Span> = debug_info
+            .spans
+            .iter()
+            .filter(|span| span.file == file && span.line_number == line_number)
+            .collect();
+        let text = line.trim_end();
+        match spans.as_slice() {
+            [span] if span.size == 1 => {
+                row(
+                    listing,
+                    Some(line_number),
+                    Some(span.address),
+                    binary,
+                    ' ',
+                    text,
+                );
+            }
+            spans => {
+                row(listing, Some(line_number), None, binary, ' ', text);
+                // `.word`, `.ascii` and the like
+                let data = text
+                    .split(':')
+                    .next_back()
+                    .unwrap_or(text)
+                    .trim_start()
+                    .starts_with('.');
+                expansion(listing, spans, binary, data);
+            }
+        }
+        for (included, _) in debug_info
+            .included_at
+            .iter()
+            .enumerate()
+            .filter(|(_, at)| **at == (file, line_number))
+        {
+            let path = &debug_info.files[included];
+            let _ = writeln!(listing, "; {}", path.display());
+            match read(path) {
+                Some(included_source) => list_file(
+                    listing,
+                    Some(included),
+                    &included_source,
+                    debug_info,
+                    binary,
+                    read,
+                ),
+                None => listing.push_str("; could not be read\n"),
+            }
+            let _ = writeln!(listing, "; end of {}", path.display());
+        }
+    }
+    // e.g. the `hlt` appended behind the last line
+    let lines = source.lines().count();
+    let appended: Vec<&Span> = debug_info
+        .spans
+        .iter()
+        .filter(|span| span.file == file && usize::from(span.line_number) >= lines)
+        .collect();
+    expansion(listing, &appended, binary, false);
+}
+
+/// Appends a row marked with `+` for every word of `spans`
+fn expansion(listing: &mut String, spans: &[&Span], binary: &[InstructionWord], data: bool) {
+    for span in spans {
+        for address in span.address..span.address.saturating_add(span.size) {
+            let disassembly = binary
+                .get(usize::from(address))
+                .filter(|_| !data)
+                .and_then(disassembler::decode)
+                .map_or_else(String::new, |instruction| format!("    {instruction}"));
+            row(listing, None, Some(address), binary, '+', &disassembly);
+        }
+    }
+}
+
+fn row(
+    listing: &mut String,
+    line_number: Option<u16>,
+    address: Option<u16>,
+    binary: &[InstructionWord],
+    marker: char,
+    text: &str,
+) {
+    let line_number = line_number.map_or_else(String::new, |line| display_line(line).to_string());
+    let word = address
+        .and_then(|address| binary.get(usize::from(address)))
+        .map_or_else(String::new, InstructionWord::to_string);
+    let address = address.map_or_else(String::new, |address| format!("{address:#06x}"));
+    let row = format!(
+        "{line_number:>LINE_WIDTH$}  {address:<ADDRESS_WIDTH$}  {word:<WORD_WIDTH$}  {marker} {text}"
+    );
+    listing.push_str(row.trim_end());
+    listing.push('\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expansions() {
+        let source = "\
+.macro push reg
+    st %reg7 reg
+    dec %reg7
+.endm
+main:
+    push %reg0
+    ldc %reg1 0x2 ; two
+data:
+    .word 0x1 0x2
+";
+        let lexed = crate::lexer::lexer_from_str(source, &Default::default()).unwrap();
+        let keywords = crate::preprocessor::preprocessor(lexed).unwrap();
+        let (ir, debug_info) =
+            crate::parser::parser_with_debug_info(keywords, &Default::default()).unwrap();
+        let binary = crate::generator::generator(ir.clone()).unwrap();
+
+        assert_eq!(
+            "; Listing of main.s generated by masm
+; line  address  word     source
+     1                    .macro push reg
+     2                        st %reg7 reg
+     3                        dec %reg7
+     4                    .endm
+     5                    main:
+     6                        push %reg0
+        0x0000   03868  +     st %reg7 %reg0
+        0x0001   e0706  +     dec %reg7
+     7  0x0002   00092        ldc %reg1 0x2 ; two
+     8                    data:
+     9                        .word 0x1 0x2
+        0x0003   00001  +
+        0x0004   00002  +
+        0x0005   0007f  +     hlt
+
+; Cross-reference
+; label  address  defined  referenced
+main     0x0000   5        -
+data     0x0003   8        -
+",
+            listing(
+                Path::new("/src/main.s"),
+                source,
+                &ir,
+                &debug_info,
+                &binary,
+                |_| None
+            )
+        );
+    }
+
+    #[test]
+    fn included_files() {
+        let path = Path::new("tests/include/main.s");
+        let source = std::fs::read_to_string(path).unwrap();
+        let lexed = crate::lexer::lexer(path).unwrap();
+        let (ir, debug_info) =
+            crate::parser::parser_with_debug_info(lexed, &Default::default()).unwrap();
+        let binary = crate::generator::generator(ir.clone()).unwrap();
+        let found = listing(path, &source, &ir, &debug_info, &binary, |file| {
+            std::fs::read_to_string(Path::new("tests/include").join(file)).ok()
+        });
+
+        assert!(found.contains(
+            "     3                    .include \"lib.s\"
+; lib.s
+     1                    ;; Increments register 0
+     2                    lib:
+     3  0x0001   "
+        ));
+        assert!(found.contains(
+            "; end of lib.s
+     4  0x0002   ffe58        jr lib
+        0x0003   0007f  +     hlt
+"
+        ));
+        assert!(found.ends_with("lib      0x0001   lib.s:2  4 (0x0002)\n"));
+    }
+
+    #[test]
+    fn references() {
+        let lexed = crate::lexer::lexer(Path::new("tests/local_labels.s")).unwrap();
+        let (ir, debug_info) =
+            crate::parser::parser_with_debug_info(lexed, &Default::default()).unwrap();
+
+        assert_eq!(
+            "; Cross-reference
+; label     address  defined  referenced
+main        0x0000   1        -
+main.loop   0x0001   3        5 (0x0002)
+main.done   0x0005   8        6 (0x0003), 15 (0x000a)
+other       0x0007   10       9 (0x0005)
+other.loop  0x0008   12       14 (0x0009)
+",
+            cross_reference(&ir, &debug_info)
+        );
+    }
+}
@@ -0,0 +1,570 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, Read},
+    path::Path,
+};
+
+use crate::generator::{InstructionWord, LOGISIM_HEADER};
+
+/// Largest value that fits into a 20 bit instruction word
+const MAX_WORD: u32 = 0xfffff;
+/// Number of words addressable with 16 bits
+const ADDRESS_SPACE: usize = 0x10000;
+
+/// Formats the loader reads, named like their output backends
+pub const FORMATS: &[&str] = &["hex", "bin", "mif", "coe"];
+
+/// Errors the loader can run into while reading an assembled image
+#[derive(Debug)]
+pub enum LoaderError {
+    MissingHeader {
+        found: String,
+    },
+    InvalidWord {
+        actual: String,
+        line_number: usize,
+    },
+    WordOutOfRange {
+        actual: String,
+        line_number: usize,
+    },
+    /// Words placed beyond address 0xffff
+    ImageTooLarge {
+        actual: String,
+        line_number: usize,
+    },
+    /// Line of a MIF or COE image which is neither a setting nor words
+    InvalidEntry {
+        actual: String,
+        line_number: usize,
+    },
+    /// Radix other than hexadecimal declared by a MIF or COE image
+    UnsupportedRadix {
+        radix: String,
+        line_number: usize,
+    },
+    /// Raw image ending in the middle of a 32 bit integer
+    IncompleteWord {
+        length: usize,
+    },
+    RawWordOutOfRange {
+        value: u32,
+        address: usize,
+    },
+    IoError(io::Error),
+}
+
+impl std::fmt::Display for LoaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self {
+            LoaderError::IoError(io_error) => write!(f, "IO error '{}'", io_error),
+            LoaderError::MissingHeader { found } => write!(
+                f,
+                "Expected image header '{}' found '{}'",
+                LOGISIM_HEADER, found
+            ),
+            LoaderError::InvalidWord {
+                actual,
+                line_number,
+            } => write!(
+                f,
+                "Invalid hex word '{}' found at line {}",
+                actual,
+                line_number + 1
+            ),
+            LoaderError::WordOutOfRange {
+                actual,
+                line_number,
+            } => write!(
+                f,
+                "Hex word '{}' at line {} does not fit into 20 bits",
+                actual,
+                line_number + 1
+            ),
+            LoaderError::ImageTooLarge {
+                actual,
+                line_number,
+            } => write!(
+                f,
+                "Words '{}' at line {} extend the image beyond address 0xffff",
+                actual,
+                line_number + 1
+            ),
+            LoaderError::InvalidEntry {
+                actual,
+                line_number,
+            } => write!(
+                f,
+                "Invalid memory entry '{}' found at line {}",
+                actual,
+                line_number + 1
+            ),
+            LoaderError::UnsupportedRadix { radix, line_number } => write!(
+                f,
+                "Radix '{}' at line {} is not supported, only hexadecimal images are",
+                radix,
+                line_number + 1
+            ),
+            LoaderError::IncompleteWord { length } => write!(
+                f,
+                "Raw image of {} bytes does not consist of 4 byte words",
+                length
+            ),
+            LoaderError::RawWordOutOfRange { value, address } => write!(
+                f,
+                "Word {:#x} at address {:#06x} does not fit into 20 bits",
+                value, address
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LoaderError {}
+
+/// Reads the image stored at `path` back into instruction words, in the
+/// format of its extension or as hex image.
+pub fn loader(path: &Path) -> Result<Vec<InstructionWord>, LoaderError> {
+    let file = File::open(path).map_err(LoaderError::IoError)?;
+    load(
+        detect_format(path).unwrap_or("hex"),
+        io::BufReader::new(file),
+    )
+}
+
+/// Format of [`FORMATS`] matching the extension of `path`
+pub fn detect_format(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_string_lossy().to_lowercase();
+    FORMATS.iter().copied().find(|format| *format == extension)
+}
+
+/// Parses an image in `format`, one of [`FORMATS`]. Other formats are read
+/// as hex image.
+pub fn load(format: &str, reader: impl BufRead) -> Result<Vec<InstructionWord>, LoaderError> {
+    match format {
+        "bin" => load_bin(reader),
+        "mif" => load_mif(reader),
+        "coe" => load_coe(reader),
+        _ => load_hex(reader),
+    }
+}
+
+/// Parses an image in the Logisim `v3.0 hex words plain` format.
+/// Besides plain hex words the body may contain `#` comments and
+/// run-length encoded words of the form `count*word`.
+pub fn load_hex(reader: impl BufRead) -> Result<Vec<InstructionWord>, LoaderError> {
+    let mut lines = reader.lines();
+    let header = lines
+        .next()
+        .transpose()
+        .map_err(LoaderError::IoError)?
+        .unwrap_or_default();
+    if header.trim() != LOGISIM_HEADER {
+        return Err(LoaderError::MissingHeader {
+            found: header.trim().to_string(),
+        });
+    }
+
+    let mut words: Vec<InstructionWord> = Vec::with_capacity(32);
+    for (line_number, line) in (1usize..).zip(lines) {
+        let mut line = line.map_err(LoaderError::IoError)?;
+        if let Some(comment_idx) = line.find('#') {
+            line.truncate(comment_idx);
+        }
+        for word in line.split_whitespace() {
+            let (count, value) = match word.split_once('*') {
+                Some((count, value)) => (
+                    count
+                        .parse::<usize>()
+                        .map_err(|_| LoaderError::InvalidWord {
+                            actual: word.to_string(),
+                            line_number,
+                        })?,
+                    value,
+                ),
+                None => (1, word),
+            };
+            let raw = parse_word(value, word, line_number)?;
+            if count > ADDRESS_SPACE - words.len() {
+                return Err(LoaderError::ImageTooLarge {
+                    actual: word.to_string(),
+                    line_number,
+                });
+            }
+            words.extend(std::iter::repeat_n(raw, count));
+        }
+    }
+
+    Ok(words)
+}
+
+/// Parses an Altera/Quartus memory initialization file as written by
+/// [`crate::output::Mif`]. Entries may set single addresses, consecutive
+/// addresses (`0 : 7f 6c;`) or ranges (`[2..f] : 0;`), `--` starts a
+/// comment. Addresses without an entry below `DEPTH` hold zero words.
+pub fn load_mif(reader: impl BufRead) -> Result<Vec<InstructionWord>, LoaderError> {
+    let mut words: Vec<InstructionWord> = Vec::with_capacity(32);
+    let mut depth = 0;
+    let mut content = false;
+    for (line_number, line) in (0usize..).zip(reader.lines()) {
+        let mut line = line.map_err(LoaderError::IoError)?;
+        if let Some(comment_idx) = line.find("--") {
+            line.truncate(comment_idx);
+        }
+        let statement = line.trim().trim_end_matches(';').trim();
+        let invalid = || LoaderError::InvalidEntry {
+            actual: line.trim().to_string(),
+            line_number,
+        };
+        let too_large = || LoaderError::ImageTooLarge {
+            actual: line.trim().to_string(),
+            line_number,
+        };
+        if statement.is_empty() {
+            continue;
+        }
+        if !content {
+            let upper = statement.to_ascii_uppercase();
+            if upper == "CONTENT" {
+                continue;
+            }
+            if upper == "BEGIN" || upper == "CONTENT BEGIN" {
+                content = true;
+                continue;
+            }
+            let (key, value) = statement.split_once('=').ok_or_else(invalid)?;
+            let value = value.trim();
+            match key.trim().to_ascii_uppercase().as_str() {
+                "DEPTH" => {
+                    depth = value.parse::<usize>().map_err(|_| invalid())?;
+                    if depth > ADDRESS_SPACE {
+                        return Err(too_large());
+                    }
+                }
+                "ADDRESS_RADIX" | "DATA_RADIX" if !value.eq_ignore_ascii_case("HEX") => {
+                    return Err(LoaderError::UnsupportedRadix {
+                        radix: value.to_string(),
+                        line_number,
+                    })
+                }
+                // the width of the words is checked with every word
+                _ => {}
+            }
+            continue;
+        }
+        if statement.eq_ignore_ascii_case("END") {
+            break;
+        }
+
+        let (address, data) = statement.split_once(':').ok_or_else(invalid)?;
+        let parse_address = |address: &str| {
+            let address = usize::from_str_radix(address.trim(), 16).map_err(|_| invalid())?;
+            if address >= ADDRESS_SPACE {
+                return Err(too_large());
+            }
+            Ok(address)
+        };
+        let values: Vec<InstructionWord> = data
+            .split_whitespace()
+            .map(|value| parse_word(value, value, line_number))
+            .collect::<Result<_, _>>()?;
+        if values.is_empty() {
+            return Err(invalid());
+        }
+        let address = address.trim();
+        let (start, values) = match address
+            .strip_prefix('[')
+            .and_then(|range| range.strip_suffix(']'))
+        {
+            Some(range) => {
+                let (start, end) = range.split_once("..").ok_or_else(invalid)?;
+                let (start, end) = (parse_address(start)?, parse_address(end)?);
+                if end < start {
+                    return Err(invalid());
+                }
+                // a range repeats its words until it is filled
+                let filled = values.iter().cycle().take(end + 1 - start);
+                (start, filled.cloned().collect())
+            }
+            None => {
+                let start = parse_address(address)?;
+                if start + values.len() > ADDRESS_SPACE {
+                    return Err(too_large());
+                }
+                (start, values)
+            }
+        };
+        let end = start + values.len();
+        if words.len() < end {
+            words.resize(end, InstructionWord::from_raw(0));
+        }
+        words.splice(start..end, values);
+    }
+    if words.len() < depth {
+        words.resize(depth, InstructionWord::from_raw(0));
+    }
+    Ok(words)
+}
+
+/// Parses a Xilinx coefficient file as written by [`crate::output::Coe`],
+/// the words of `memory_initialization_vector` are separated by commas or
+/// whitespace. Lines starting with `;` are comments.
+pub fn load_coe(reader: impl BufRead) -> Result<Vec<InstructionWord>, LoaderError> {
+    let mut words: Vec<InstructionWord> = Vec::with_capacity(32);
+    let mut vector = false;
+    for (line_number, line) in (0usize..).zip(reader.lines()) {
+        let line = line.map_err(LoaderError::IoError)?;
+        let mut statement = line.trim();
+        if statement.starts_with(';') {
+            continue;
+        }
+        if !vector {
+            if statement.is_empty() {
+                continue;
+            }
+            let (key, value) =
+                statement
+                    .split_once('=')
+                    .ok_or_else(|| LoaderError::InvalidEntry {
+                        actual: statement.to_string(),
+                        line_number,
+                    })?;
+            let value = value.trim();
+            match key.trim().to_ascii_lowercase().as_str() {
+                "memory_initialization_radix" if value.trim_end_matches(';').trim() != "16" => {
+                    return Err(LoaderError::UnsupportedRadix {
+                        radix: value.trim_end_matches(';').trim().to_string(),
+                        line_number,
+                    })
+                }
+                "memory_initialization_vector" => {
+                    vector = true;
+                    statement = value;
+                }
+                _ => continue,
+            }
+        }
+        let (values, end) = match statement.split_once(';') {
+            Some((values, _)) => (values, true),
+            None => (statement, false),
+        };
+        for value in values
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|value| !value.is_empty())
+        {
+            if words.len() == ADDRESS_SPACE {
+                return Err(LoaderError::ImageTooLarge {
+                    actual: value.to_string(),
+                    line_number,
+                });
+            }
+            words.push(parse_word(value, value, line_number)?);
+        }
+        if end {
+            break;
+        }
+    }
+    Ok(words)
+}
+
+/// Parses the hex word `value` written as `word` at `line_number`
+fn parse_word(value: &str, word: &str, line_number: usize) -> Result<InstructionWord, LoaderError> {
+    let raw = u32::from_str_radix(value, 16).map_err(|_| LoaderError::InvalidWord {
+        actual: word.to_string(),
+        line_number,
+    })?;
+    if raw > MAX_WORD {
+        return Err(LoaderError::WordOutOfRange {
+            actual: word.to_string(),
+            line_number,
+        });
+    }
+    Ok(InstructionWord::from_raw(raw))
+}
+
+/// Parses a raw image with one little endian 32 bit integer per word, as
+/// written by [`crate::output::Bin`]
+pub fn load_bin(mut reader: impl Read) -> Result<Vec<InstructionWord>, LoaderError> {
+    let mut bytes = Vec::new();
+    reader
+        .read_to_end(&mut bytes)
+        .map_err(LoaderError::IoError)?;
+    if bytes.len() % 4 != 0 {
+        return Err(LoaderError::IncompleteWord {
+            length: bytes.len(),
+        });
+    }
+    bytes
+        .chunks_exact(4)
+        .enumerate()
+        .map(|(address, chunk)| {
+            let value = u32::from_le_bytes(chunk.try_into().unwrap());
+            if value > MAX_WORD {
+                return Err(LoaderError::RawWordOutOfRange { value, address });
+            }
+            Ok(InstructionWord::from_raw(value))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        generator, lexer,
+        output::{self, OutputBackend},
+        parser,
+    };
+
+    #[test]
+    fn load_generated_image() {
+        let lexed = lexer::lexer(Path::new("tests/test.s")).unwrap();
+        let parsed = parser::parser(lexed).unwrap();
+        let expected = generator::generator(parsed).ok().unwrap();
+
+        let found = loader(Path::new("tests/test.hex")).unwrap();
+
+        assert_eq!(expected, found);
+    }
+
+    #[test]
+    fn comments_and_run_length() {
+        let image = "v3.0 hex words plain\n# program start\n0007f 3*6c # nops\n";
+
+        let found = load_hex(image.as_bytes()).unwrap();
+
+        let raw: Vec<u32> = found.iter().map(InstructionWord::raw).collect();
+        assert_eq!(vec![0x7f, 0x6c, 0x6c, 0x6c], raw);
+    }
+
+    #[test]
+    fn raw_image() {
+        let binary = generator::generator(
+            parser::parser(lexer::lexer(Path::new("tests/test.s")).unwrap()).unwrap(),
+        )
+        .unwrap();
+        let bytes: Vec<u8> = binary
+            .iter()
+            .flat_map(|word| word.raw().to_le_bytes())
+            .collect();
+
+        assert_eq!(binary, load_bin(bytes.as_slice()).unwrap());
+        assert!(matches!(
+            load_bin(&bytes[1..]),
+            Err(LoaderError::IncompleteWord { .. })
+        ));
+        assert!(matches!(
+            load_bin([0, 0, 0x10, 0].as_slice()),
+            Err(LoaderError::RawWordOutOfRange { address: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn mif_and_coe_images() {
+        let binary = generator::generator(
+            parser::parser(lexer::lexer(Path::new("tests/test.s")).unwrap()).unwrap(),
+        )
+        .unwrap();
+        let mut context = output::OutputContext::default();
+        for format in ["mif", "coe"] {
+            let mut image = Vec::new();
+            output::backend(format, &context)
+                .unwrap()
+                .write(&binary, &mut image)
+                .unwrap();
+            assert_eq!(binary, load(format, image.as_slice()).unwrap(), "{format}");
+        }
+
+        // padded to the declared depth
+        context.mif_depth = Some(binary.len() + 4);
+        let mut image = Vec::new();
+        output::Mif(&context).write(&binary, &mut image).unwrap();
+        let found = load_mif(image.as_slice()).unwrap();
+        assert_eq!(binary, found[..binary.len()]);
+        assert_eq!(binary.len() + 4, found.len());
+
+        let image = "DEPTH = 6;\nCONTENT BEGIN\n  0 : 7f 6c; -- two words\n  [3..4] : 1;\nEND;\n";
+        let raw: Vec<u32> = load_mif(image.as_bytes())
+            .unwrap()
+            .iter()
+            .map(InstructionWord::raw)
+            .collect();
+        assert_eq!(vec![0x7f, 0x6c, 0, 1, 1, 0], raw);
+        let image =
+            "; comment\nmemory_initialization_radix=16;\nmemory_initialization_vector=7f 6c,\n1;\n";
+        let raw: Vec<u32> = load_coe(image.as_bytes())
+            .unwrap()
+            .iter()
+            .map(InstructionWord::raw)
+            .collect();
+        assert_eq!(vec![0x7f, 0x6c, 1], raw);
+    }
+
+    #[test]
+    fn full_address_space() {
+        // more lines than fit into 16 bits
+        let binary: Vec<InstructionWord> = (0..ADDRESS_SPACE as u32)
+            .map(|address| InstructionWord::from_raw(address & MAX_WORD))
+            .collect();
+        let mut context = output::OutputContext::default();
+        context.hex_format.words_per_line = 1;
+        for format in ["hex", "mif", "coe"] {
+            let mut image = Vec::new();
+            output::backend(format, &context)
+                .unwrap()
+                .write(&binary, &mut image)
+                .unwrap();
+            assert_eq!(binary, load(format, image.as_slice()).unwrap(), "{format}");
+        }
+
+        let mut image = String::from("v3.0 hex words plain\n");
+        image += &"0\n".repeat(ADDRESS_SPACE);
+        image += "xyz\n";
+        assert!(matches!(
+            load_hex(image.as_bytes()),
+            Err(LoaderError::InvalidWord {
+                line_number: 65537,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn invalid_images() {
+        assert!(matches!(
+            load_hex("0007f\n".as_bytes()),
+            Err(LoaderError::MissingHeader { .. })
+        ));
+        assert!(matches!(
+            load_hex("v3.0 hex words plain\n0007f xyz\n".as_bytes()),
+            Err(LoaderError::InvalidWord { line_number: 1, .. })
+        ));
+        assert!(matches!(
+            load_hex("v3.0 hex words plain\n100000\n".as_bytes()),
+            Err(LoaderError::WordOutOfRange { .. })
+        ));
+        assert!(matches!(
+            load_hex("v3.0 hex words plain\n99999999*0\n".as_bytes()),
+            Err(LoaderError::ImageTooLarge { line_number: 1, .. })
+        ));
+        assert!(matches!(
+            load_hex("v3.0 hex words plain\n40000*0 30000*0\n".as_bytes()),
+            Err(LoaderError::ImageTooLarge { .. })
+        ));
+        assert!(matches!(
+            load_mif("DATA_RADIX = BIN;\n".as_bytes()),
+            Err(LoaderError::UnsupportedRadix { line_number: 0, .. })
+        ));
+        assert!(matches!(
+            load_mif("CONTENT BEGIN\n  [fffe..10000] : 0;\nEND;\n".as_bytes()),
+            Err(LoaderError::ImageTooLarge { line_number: 1, .. })
+        ));
+        assert!(matches!(
+            load_mif("CONTENT BEGIN\n  0 0;\nEND;\n".as_bytes()),
+            Err(LoaderError::InvalidEntry { line_number: 1, .. })
+        ));
+        assert!(matches!(
+            load_coe("memory_initialization_radix=2;\n".as_bytes()),
+            Err(LoaderError::UnsupportedRadix { .. })
+        ));
+    }
+}
@@ -0,0 +1,318 @@
+use crate::ir;
+
+/// Replaces pseudo-instructions by the machine instructions implementing
+/// them.
+///
+/// Every pseudo-instruction expands to exactly [`ir::Instruction::size`]
+/// instructions, so the label addresses computed by the parser stay valid.
+pub fn lower(ir: ir::IR) -> ir::IR {
+    let label_definitions = ir.label_definitions;
+    let instructions = ir
+        .instructions
+        .into_iter()
+        .map(|(label, instructions)| {
+            let mut location = label_definitions
+                .0
+                .get(&label)
+                .map_or(0, |definition| definition.address.0);
+            let lowered = instructions
+                .into_iter()
+                .flat_map(|instruction| {
+                    let instruction_location = location;
+                    location = location.wrapping_add(instruction.size());
+                    lower_instruction(instruction, instruction_location)
+                })
+                .collect();
+            (label, lowered)
+        })
+        .collect();
+    ir::IR {
+        start_label: ir.start_label,
+        label_definitions,
+        instructions,
+    }
+}
+
+fn lower_instruction(instruction: ir::Instruction, location: u16) -> Vec<ir::Instruction> {
+    match instruction {
+        ir::Instruction::LongJump { target, scratch } => vec![
+            ir::Instruction::Load {
+                address: scratch.address,
+                source: ir::LoadSource::Expression(target),
+            },
+            ir::Instruction::Jump {
+                target: ir::JumpTarget::Register(scratch),
+                condition: ir::JumpCondition::True,
+            },
+        ],
+        ir::Instruction::LoadImmediate {
+            address,
+            value,
+            scratch,
+        } => {
+            // `$` is the address of the `li`, not of the expanded `ldc`
+            let value = Box::new(resolve_location(value, location));
+            let target = ir::Register::new(address);
+            vec![
+                ir::Instruction::Load {
+                    address,
+                    source: ir::LoadSource::Expression(ir::Expression::High(value.clone())),
+                },
+                ir::Instruction::Load {
+                    address: scratch.address,
+                    source: ir::LoadSource::Constant(16),
+                },
+                ir::Instruction::ShiftLeft(ir::BinaryExpression::new(target, target, scratch)),
+                ir::Instruction::Load {
+                    address: scratch.address,
+                    source: ir::LoadSource::Expression(ir::Expression::Low(value)),
+                },
+                ir::Instruction::OR(ir::BinaryExpression::new(target, target, scratch)),
+            ]
+        }
+        ir::Instruction::Immediate {
+            operation,
+            target,
+            source,
+            value,
+            scratch,
+        } => vec![
+            ir::Instruction::Load {
+                address: scratch.address,
+                source: ir::LoadSource::Expression(resolve_location(value, location)),
+            },
+            operation.instruction(ir::BinaryExpression::new(target, source, scratch)),
+        ],
+        ir::Instruction::Rotate {
+            direction,
+            target,
+            source,
+            amount,
+            width,
+            scratch,
+        } => {
+            let left = direction.left_amount(amount, width);
+            if left == 0 {
+                return vec![ir::Instruction::Move(ir::UnaryExpression::new(
+                    target, source,
+                ))];
+            }
+            // bits wrapping around to the low end
+            let mut lowered = vec![
+                ir::Instruction::Load {
+                    address: scratch.address,
+                    source: ir::LoadSource::Constant(width - left),
+                },
+                ir::Instruction::ShiftRight(ir::BinaryExpression::new(scratch, source, scratch)),
+            ];
+            if target != source {
+                lowered.push(ir::Instruction::Load {
+                    address: target.address,
+                    source: ir::LoadSource::Constant(left),
+                });
+                lowered.push(ir::Instruction::ShiftLeft(ir::BinaryExpression::new(
+                    target, source, target,
+                )));
+            } else {
+                // no register is left for the shift amount
+                for _ in 0..left {
+                    lowered.push(ir::Instruction::Add(ir::BinaryExpression::new(
+                        target, target, target,
+                    )));
+                }
+            }
+            lowered.push(ir::Instruction::OR(ir::BinaryExpression::new(
+                target, target, scratch,
+            )));
+            lowered
+        }
+        ir::Instruction::Divide {
+            expression,
+            scratch,
+        } => {
+            let ir::BinaryExpression {
+                target,
+                source_a,
+                source_b,
+            } = expression;
+            vec![
+                ir::Instruction::Move(ir::UnaryExpression::new(scratch, source_a)),
+                ir::Instruction::XOR(ir::BinaryExpression::new(target, target, target)),
+                // loop while the remainder is not less than the divisor
+                ir::Instruction::Test(ir::BinaryStatement::new(scratch, source_b)),
+                relative_jump(4, ir::JumpCondition::Less),
+                ir::Instruction::Subtract(ir::BinaryExpression::new(scratch, scratch, source_b)),
+                ir::Instruction::Increment(ir::UnaryExpression::new(target, target)),
+                relative_jump(-4, ir::JumpCondition::True),
+            ]
+        }
+        ir::Instruction::Modulo(ir::BinaryExpression {
+            target,
+            source_a,
+            source_b,
+        }) => vec![
+            ir::Instruction::Move(ir::UnaryExpression::new(target, source_a)),
+            ir::Instruction::Test(ir::BinaryStatement::new(target, source_b)),
+            relative_jump(3, ir::JumpCondition::Less),
+            ir::Instruction::Subtract(ir::BinaryExpression::new(target, target, source_b)),
+            relative_jump(-3, ir::JumpCondition::True),
+        ],
+        ir::Instruction::Jump {
+            target,
+            condition: ir::JumpCondition::GreaterEqual,
+        } => vec![
+            relative_jump(2, ir::JumpCondition::Less),
+            ir::Instruction::Jump {
+                target: shift_jump_target(target, 1),
+                condition: ir::JumpCondition::True,
+            },
+        ],
+        ir::Instruction::Jump {
+            target,
+            condition: ir::JumpCondition::LessEqual,
+        } => vec![
+            ir::Instruction::Jump {
+                target: target.clone(),
+                condition: ir::JumpCondition::Zero,
+            },
+            ir::Instruction::Jump {
+                target: shift_jump_target(target, 1),
+                condition: ir::JumpCondition::Less,
+            },
+        ],
+        ir::Instruction::Jump {
+            target,
+            condition: ir::JumpCondition::Greater,
+        } => vec![
+            relative_jump(3, ir::JumpCondition::Zero),
+            relative_jump(2, ir::JumpCondition::Less),
+            ir::Instruction::Jump {
+                target: shift_jump_target(target, 2),
+                condition: ir::JumpCondition::True,
+            },
+        ],
+        instruction => vec![instruction],
+    }
+}
+
+/// Target of a jump placed `distance` words after the instruction it was
+/// written as. Constant targets are relative and have to be corrected.
+fn shift_jump_target(target: ir::JumpTarget, distance: u16) -> ir::JumpTarget {
+    match target {
+        ir::JumpTarget::Constant(offset) => ir::JumpTarget::Constant(offset.wrapping_sub(distance)),
+        target => target,
+    }
+}
+
+/// Jump by `offset` words relative to the jump itself
+fn relative_jump(offset: i16, condition: ir::JumpCondition) -> ir::Instruction {
+    ir::Instruction::Jump {
+        target: ir::JumpTarget::Constant(offset as u16),
+        condition,
+    }
+}
+
+/// Replaces `$` in `expression` by `location`
+fn resolve_location(expression: ir::Expression, location: u16) -> ir::Expression {
+    let resolve =
+        |expression: Box<ir::Expression>| Box::new(resolve_location(*expression, location));
+    match expression {
+        ir::Expression::Location => ir::Expression::Constant(u32::from(location)),
+        ir::Expression::Low(inner) => ir::Expression::Low(resolve(inner)),
+        ir::Expression::High(inner) => ir::Expression::High(resolve(inner)),
+        ir::Expression::Sum(a, b) => ir::Expression::Sum(resolve(a), resolve(b)),
+        ir::Expression::Difference(a, b) => ir::Expression::Difference(resolve(a), resolve(b)),
+        ir::Expression::Constant(_) | ir::Expression::Label(_) => expression,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn long_jump() {
+        let start = ir::LabelReference::new("_start");
+        let far = ir::Expression::Label(ir::LabelReference::new("far"));
+        let scratch = ir::Register::new(ir::RegisterAddress(6));
+        let lowered = lower(ir::IR {
+            start_label: start.clone(),
+            label_definitions: ir::LabelLUT::new(),
+            instructions: HashMap::from([(
+                start.clone(),
+                vec![
+                    ir::Instruction::LongJump {
+                        target: far.clone(),
+                        scratch,
+                    },
+                    ir::Instruction::Halt,
+                ],
+            )]),
+        });
+
+        assert_eq!(
+            lowered.instructions[&start],
+            vec![
+                ir::Instruction::Load {
+                    address: scratch.address,
+                    source: ir::LoadSource::Expression(far),
+                },
+                ir::Instruction::Jump {
+                    target: ir::JumpTarget::Register(scratch),
+                    condition: ir::JumpCondition::True,
+                },
+                ir::Instruction::Halt,
+            ]
+        );
+    }
+
+    #[test]
+    fn load_immediate() {
+        let start = ir::LabelReference::new("_start");
+        let mut label_definitions = ir::LabelLUT::new();
+        label_definitions
+            .0
+            .insert(start.clone(), ir::LabelDefinition::new("_start", 0x10));
+        let target = ir::Register::new(ir::RegisterAddress(0));
+        let scratch = ir::Register::new(ir::RegisterAddress(6));
+        let lowered = lower(ir::IR {
+            start_label: start.clone(),
+            label_definitions,
+            instructions: HashMap::from([(
+                start.clone(),
+                vec![
+                    ir::Instruction::Noop,
+                    ir::Instruction::LoadImmediate {
+                        address: target.address,
+                        value: ir::Expression::Location,
+                        scratch,
+                    },
+                ],
+            )]),
+        });
+
+        let location = Box::new(ir::Expression::Constant(0x11));
+        assert_eq!(
+            lowered.instructions[&start],
+            vec![
+                ir::Instruction::Noop,
+                ir::Instruction::Load {
+                    address: target.address,
+                    source: ir::LoadSource::Expression(ir::Expression::High(location.clone())),
+                },
+                ir::Instruction::Load {
+                    address: scratch.address,
+                    source: ir::LoadSource::Constant(16),
+                },
+                ir::Instruction::ShiftLeft(ir::BinaryExpression::new(target, target, scratch)),
+                ir::Instruction::Load {
+                    address: scratch.address,
+                    source: ir::LoadSource::Expression(ir::Expression::Low(location)),
+                },
+                ir::Instruction::OR(ir::BinaryExpression::new(target, target, scratch)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,277 @@
+use std::fmt;
+
+use crate::device::{self, Bus, DeviceError};
+use crate::diagnostic::display_line;
+
+/// Errors of reading a machine description with [`parse_machine`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MachineError {
+    /// The input is not in the supported subset of TOML
+    Syntax {
+        message: String,
+        line_number: u16,
+        column: u16,
+    },
+    /// The `[[device]]` table at `line_number` has no `kind` or `address`
+    MissingKey { key: &'static str, line_number: u16 },
+    /// The `[[device]]` table at `line_number` describes no valid device
+    Device {
+        error: DeviceError,
+        line_number: u16,
+    },
+}
+
+impl fmt::Display for MachineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MachineError::Syntax {
+                message,
+                line_number,
+                column,
+            } => write!(
+                f,
+                "Invalid machine description at line {}, column {}: {}",
+                display_line(*line_number),
+                column,
+                message
+            ),
+            MachineError::MissingKey { key, line_number } => {
+                write!(
+                    f,
+                    "Device at line {} has no key '{}'",
+                    display_line(*line_number),
+                    key
+                )
+            }
+            MachineError::Device { error, line_number } => {
+                write!(
+                    f,
+                    "Device at line {}: {}",
+                    display_line(*line_number),
+                    error
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for MachineError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MachineError::Device { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl MachineError {
+    /// Stable name of the error kind, e.g. for machine readable diagnostics
+    pub fn code(&self) -> &'static str {
+        match self {
+            MachineError::Syntax { .. } => "machine-syntax",
+            MachineError::MissingKey { .. } => "missing-key",
+            MachineError::Device { error, .. } => error.code(),
+        }
+    }
+}
+
+enum Value {
+    String(String),
+    Number(u32),
+}
+
+/// `[[device]]` table while it is read
+struct Table {
+    line_number: u16,
+    kind: Option<String>,
+    address: Option<u16>,
+    options: Vec<(String, u32)>,
+}
+
+impl Table {
+    fn attach(self, bus: &mut Bus) -> Result<(), MachineError> {
+        let line_number = self.line_number;
+        let missing = |key| MachineError::MissingKey { key, line_number };
+        let kind = self.kind.ok_or(missing("kind"))?;
+        let address = self.address.ok_or(missing("address"))?;
+        device::create(&kind, &self.options)
+            .and_then(|device| bus.attach(address, device))
+            .map_err(|error| MachineError::Device { error, line_number })
+    }
+}
+
+/// Reads the devices of a machine from a TOML description with one
+/// `[[device]]` table per device, e.g.
+///
+/// ```toml
+/// [[device]]
+/// kind = "seven-segment"
+/// address = 0xff20
+/// digits = 6
+/// ```
+///
+/// Every key besides `kind` and `address` is an option of the device, see
+/// [`device::create`]. Only tables of devices, strings without escapes and
+/// integers are supported.
+pub fn parse_machine(source: &str) -> Result<Bus, MachineError> {
+    let mut bus = Bus::new();
+    let mut table: Option<Table> = None;
+    for (line_number, line) in (0u16..).zip(source.lines()) {
+        let syntax = |message: &str, column: usize| MachineError::Syntax {
+            message: message.to_string(),
+            line_number,
+            column: column as u16,
+        };
+        let line = strip_comment(line);
+        let content = line.trim();
+        if content.is_empty() {
+            continue;
+        }
+        let indent = line.len() - line.trim_start().len();
+        if content.starts_with('[') {
+            if content != "[[device]]" {
+                return Err(syntax("expected the table '[[device]]'", indent));
+            }
+            if let Some(table) = table.take() {
+                table.attach(&mut bus)?;
+            }
+            table = Some(Table {
+                line_number,
+                kind: None,
+                address: None,
+                options: Vec::new(),
+            });
+            continue;
+        }
+
+        let Some((key, value)) = content.split_once('=') else {
+            return Err(syntax("expected 'KEY = VALUE'", indent));
+        };
+        let key = key.trim();
+        let value_column = line.trim_end().len() - value.trim_start().len();
+        if key.is_empty()
+            || !key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return Err(syntax("expected a key", indent));
+        }
+        let Some(table) = table.as_mut() else {
+            return Err(syntax(
+                "keys have to be inside of a '[[device]]' table",
+                indent,
+            ));
+        };
+        let value = value.trim();
+        let value = if let Some(string) = value.strip_prefix('"') {
+            match string.strip_suffix('"') {
+                Some(string) if !string.contains(['"', '\\']) => Value::String(string.to_string()),
+                _ => return Err(syntax("expected a string without escapes", value_column)),
+            }
+        } else {
+            match device::parse_value(value) {
+                Some(number) => Value::Number(number),
+                None => return Err(syntax("expected a string or an integer", value_column)),
+            }
+        };
+
+        let duplicate = match key {
+            "kind" => table.kind.is_some(),
+            "address" => table.address.is_some(),
+            _ => table.options.iter().any(|(option, _)| option == key),
+        };
+        if duplicate {
+            return Err(syntax("duplicate key", indent));
+        }
+        match (key, value) {
+            ("kind", Value::String(kind)) => table.kind = Some(kind),
+            ("kind", Value::Number(_)) => return Err(syntax("expected a string", value_column)),
+            (_, Value::String(_)) => return Err(syntax("expected an integer", value_column)),
+            ("address", Value::Number(address)) => match u16::try_from(address) {
+                Ok(address) => table.address = Some(address),
+                Err(_) => return Err(syntax("expected a 16 bit address", value_column)),
+            },
+            (option, Value::Number(value)) => table.options.push((option.to_string(), value)),
+        }
+    }
+    if let Some(table) = table {
+        table.attach(&mut bus)?;
+    }
+    Ok(bus)
+}
+
+/// `line` up to a `#` outside of a string
+fn strip_comment(line: &str) -> &str {
+    let mut string = false;
+    for (index, c) in line.char_indices() {
+        match c {
+            '"' => string = !string,
+            '#' if !string => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn machine() {
+        let mut bus = parse_machine(
+            "# lab 3
+[[device]]
+kind = \"timer\"    # cycle counter
+address = 0xff00
+divider = 1_000
+
+[[device]]
+  kind = \"gpio\"
+  address = 65296
+  input = 0b101
+",
+        )
+        .unwrap();
+        assert_eq!(Some(1000), bus.read(0xff01));
+        assert_eq!(Some(5), bus.read(0xff11));
+        assert_eq!(None, bus.read(0xff12));
+        assert!(parse_machine("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn errors() {
+        let error = |source: &str| parse_machine(source).unwrap_err();
+        assert_eq!(
+            MachineError::Syntax {
+                message: String::from("expected a string or an integer"),
+                line_number: 1,
+                column: 10,
+            },
+            error("[[device]]\naddress = x")
+        );
+        assert_eq!("machine-syntax", error("[device]").code());
+        assert_eq!("machine-syntax", error("kind = \"timer\"").code());
+        assert_eq!("machine-syntax", error("[[device]]\nkind = 1").code());
+        assert_eq!(
+            "machine-syntax",
+            error("[[device]]\naddress = 1\naddress = 2").code()
+        );
+        assert_eq!(
+            MachineError::MissingKey {
+                key: "address",
+                line_number: 0,
+            },
+            error("[[device]]\nkind = \"timer\"")
+        );
+        assert_eq!(
+            "overlapping-devices",
+            error("[[device]]\nkind = \"gpio\"\naddress = 0\n[[device]]\nkind = \"gpio\"\naddress = 1")
+                .code()
+        );
+        assert_eq!(
+            "unknown-option",
+            error("[[device]]\nkind = \"gpio\"\naddress = 0\ndigits = 2").code()
+        );
+    }
+}
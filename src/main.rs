@@ -1,87 +1,1784 @@
 use std::{
+    cell::Cell,
+    collections::HashMap,
     fs::File,
-    io::{BufWriter, Write},
+    io::{self, BufWriter, IsTerminal, Read, Write},
+    path::{Path, PathBuf},
     process,
 };
 
-use clap::Parser;
+use clap::{
+    builder::{PossibleValue, PossibleValuesParser, TypedValueParser},
+    Args, CommandFactory, Parser, Subcommand,
+};
+
+use masm::{
+    cfg, cosim,
+    debug_info::DebugInfo,
+    debugger, device, diagnostic, disassembler, emulator,
+    error::MasmError,
+    formatter, generator,
+    generator::InstructionWord,
+    ir, ir_json, layout,
+    lexer::{self, LineNumber},
+    listing, loader, lowering, machine,
+    output::{self, Checksum, HexFormat, OutputBackend, OutputContext},
+    parser, preprocessor, profile, recording, stats, vcd, verify, warning,
+};
 
-use masm::{generator, lexer, parser};
+/// Exit status for every kind of failure, so build systems can tell bad
+/// sources from output that could not be written. Invalid arguments exit
+/// with 2.
+const EXIT_FAILURE: i32 = 1;
+const EXIT_LEXER: i32 = 3;
+const EXIT_PARSER: i32 = 4;
+const EXIT_WARNINGS: i32 = 5;
+const EXIT_GENERATOR: i32 = 6;
+const EXIT_IO: i32 = 7;
+const EXIT_EMULATOR: i32 = 8;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about=None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Cli {
-    /// Output file where binary is stored
-    #[arg(short, long = "output")]
-    output_path: Option<std::path::PathBuf>,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Without a subcommand the program is assembled like with `asm`
+    #[command(flatten)]
+    asm: AsmArgs,
+}
+
+#[derive(Args)]
+struct AsmArgs {
+    /// Output file where binary is stored, `-` writes to stdout
+    #[arg(short, long = "output", conflicts_with = "emit")]
+    output_path: Option<PathBuf>,
+    /// Format of the output file [default: detected from the output file extension, hex otherwise]
+    #[arg(long, value_parser = format_parser(), conflicts_with = "emit")]
+    format: Option<String>,
+    /// Artifacts written to the output directory, named after the input file
+    #[arg(long, value_parser = emit_parser(), value_delimiter = ',')]
+    emit: Vec<String>,
+    /// Directory where the artifacts selected with --emit are stored
+    #[arg(long = "out-dir", requires = "emit", default_value = ".")]
+    out_dir: PathBuf,
+    /// Split every word into N equally wide lanes written to separate `_laneK` files
+    #[arg(long = "split-lanes", value_name = "N", value_parser = clap::value_parser!(u8).range(2..=20))]
+    split_lanes: Option<u8>,
+    /// Number of words per line in hex images
+    #[arg(long = "words-per-line", default_value_t = 8, value_parser = clap::value_parser!(u16).range(1..))]
+    words_per_line: u16,
+    /// Use uppercase digits in hex images
+    #[arg(long)]
+    uppercase: bool,
+    /// Number of digits every word in hex images is zero padded to
+    #[arg(long = "hex-digits", default_value_t = 5, value_parser = clap::value_parser!(u8).range(5..=8))]
+    hex_digits: u8,
+    /// Comment every line of hex images with the address of its first word
+    #[arg(long)]
+    annotate: bool,
+    /// Start a new line at every label in hex images, preceded by a comment with its name
+    #[arg(long = "annotate-labels")]
+    annotate_labels: bool,
+    /// Append a record with the word count and checksum of the program
+    #[arg(long, value_parser = PossibleValuesParser::new(["sum16", "xor", "crc16"]).map(|name| name.parse::<Checksum>().unwrap()))]
+    checksum: Option<Checksum>,
+    /// Pad the image with the fill value to this number of words
+    #[arg(long = "pad-to", value_name = "WORDS")]
+    pad_to: Option<usize>,
+    /// Size of the program memory the image has to fit into
+    #[arg(long = "memory-size", value_name = "WORDS", default_value_t = 4096)]
+    memory_size: usize,
+    /// Word used for padding, either a hex word or a single instruction like `hlt`
+    #[arg(
+        long = "fill-value",
+        value_name = "WORD",
+        default_value = "0",
+        requires = "pad_to"
+    )]
+    fill_value: String,
+    /// Word filling the gaps in front of labels placed with `.org` or `[[align=N]]`
+    #[arg(long = "gap-fill", value_name = "WORD", default_value = "nop")]
+    gap_fill: String,
+    #[command(flatten)]
+    assembler: AssemblerArgs,
     /// Enable debug output to stdout
     #[arg(short, long = "debug")]
     debug_enable: bool,
+    /// Markdown file where the reference of all documented labels is stored
+    #[arg(long = "reference")]
+    reference_path: Option<PathBuf>,
+    /// Table of all labels sorted by address with the number of words of their code
+    #[arg(long = "map", value_name = "PATH")]
+    map_path: Option<PathBuf>,
+    /// JSON file where the parsed program is stored, `-` writes to stdout
+    #[arg(long = "ir", value_name = "PATH")]
+    ir_path: Option<PathBuf>,
+    /// Read the input as JSON program stored with --ir and only run the generator on it
+    #[arg(long = "from-ir")]
+    from_ir: bool,
+    /// JSON file mapping every generated word to its source file, line, label and text
+    #[arg(long = "debug-info", value_name = "PATH", conflicts_with = "from_ir")]
+    debug_info_path: Option<PathBuf>,
+    /// Listing of the source lines with the addresses and words generated for them,
+    /// like `--emit listing` but stored at PATH
+    #[arg(long = "listing", value_name = "PATH", conflicts_with = "from_ir")]
+    listing_path: Option<PathBuf>,
+    /// Print the size, words per label, instruction histogram, constant loads and longest
+    /// relative jump of the program, to stderr if the output is written to stdout
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "human",
+        value_parser = ["human", "json"]
+    )]
+    stats: Option<String>,
+    /// Check that the disassembly of the image decodes to the program and assembles to the same image
+    #[arg(long)]
+    verify: bool,
+    /// Memory depth in words declared in MIF images [default: program size]
+    #[arg(long = "mif-depth")]
+    mif_depth: Option<usize>,
+    /// Word width in bits declared in MIF images
+    #[arg(long = "mif-width", default_value_t = 20, value_parser = clap::value_parser!(u8).range(20..=32))]
+    mif_width: u8,
+
+    /// Program to assemble, `-` reads it from stdin
+    #[arg(required = true)]
+    input_path: Option<PathBuf>,
+}
+
+// Options of the assembler shared by `asm`, `run` and `debug`. Not a doc
+// comment, clap would print it as description of `masm --help`.
+#[derive(Args)]
+struct AssemblerArgs {
+    /// Pack two characters of `.ascii`/`.asciiz` strings into every word
+    #[arg(long = "pack-strings")]
+    pack_strings: bool,
+    /// Expand `div` and `mod` to loops of repeated subtraction
+    #[arg(long = "soft-div")]
+    soft_division: bool,
+    /// Accept mnemonics, directives and register names in any case
+    #[arg(long = "case-insensitive")]
+    case_insensitive: bool,
+    /// Treat warnings as errors, same as `-W error`
+    #[arg(long = "strict")]
+    strict: bool,
+    /// Report warnings of a category, `error=WARNING` reports them as errors.
+    /// WARNING is `all` or one of unused-label, deprecated-mnemonic, appended-halt
+    #[arg(short = 'W', value_name = "WARNING", value_parser = parse_warning)]
+    warn: Vec<(String, warning::Level)>,
+    /// Don't report warnings of a category, WARNING is `all` or a category of `-W`
+    #[arg(short = 'A', value_name = "WARNING", value_parser = parse_warning_category)]
+    allow: Vec<String>,
+    /// Don't append a `hlt` to programs not ending with one
+    #[arg(long = "no-auto-halt")]
+    no_auto_halt: bool,
+    /// Start the program at LABEL instead of the one selected with `.start`
+    /// or the first label
+    #[arg(long = "entry", value_name = "LABEL")]
+    entry: Option<String>,
+    /// Move the code of the entry label to address 0
+    #[arg(long = "entry-first")]
+    entry_first: bool,
+    /// Order the code by the cycles per label of the `masm run --profile` at
+    /// PATH, so the hot code is contiguous and its relative jumps stay short
+    #[arg(long = "layout-profile", value_name = "PATH")]
+    layout_profile: Option<PathBuf>,
+    /// Define the symbol NAME for `.if` and operands, VALUE defaults to 1
+    #[arg(short = 'D', value_name = "NAME[=VALUE]", value_parser = parse_define)]
+    defines: Vec<(String, u16)>,
+    /// Name the register REGISTER %NAME, like `.alias`; sp, lr and zero name reg7, reg5 and reg0 by default
+    #[arg(long = "alias", value_name = "NAME=REGISTER", value_parser = parse_alias)]
+    aliases: Vec<(String, ir::RegisterAddress)>,
+    /// Color errors and warnings, `auto` colors them if stderr is a terminal and NO_COLOR is not set
+    #[arg(long, value_name = "WHEN", default_value = "auto", value_parser = ["auto", "always", "never"])]
+    color: String,
+    /// Format of errors and warnings, `json` writes one JSON object per line
+    #[arg(long = "diagnostics-format", value_name = "FORMAT", default_value = "human", value_parser = ["human", "json"])]
+    diagnostics_format: String,
+}
+
+/// Pseudo artifacts of `--emit` printing a stage of the assembly to stdout
+/// and exiting instead of writing a file, see [`check_emit`]
+const PRINTED_STAGES: &[(&str, &str)] = &[
+    (
+        "tokens",
+        "Print the lexed keywords with their line, column and text, then exit",
+    ),
+    (
+        "ir",
+        "Print the expanded program with its label addresses, then exit",
+    ),
+    (
+        "cfg",
+        "Print the basic blocks with their jumps and fall-throughs as Graphviz DOT graph, then exit",
+    ),
+];
+
+/// Artifacts of `--emit` generated from the source besides the image, with
+/// the extension of their file
+const SOURCE_ARTIFACTS: &[(&str, &str, &str)] = &[(
+    "listing",
+    "lst",
+    "Listing of the source lines with the addresses and words generated for them",
+)];
+
+/// Accepts the name of every output backend and lists their descriptions
+/// in the usage.
+fn format_parser() -> PossibleValuesParser {
+    PossibleValuesParser::new(formats())
+}
 
-    input_path: std::path::PathBuf,
+/// Like [`format_parser`], but also accepts the [`SOURCE_ARTIFACTS`] and
+/// [`PRINTED_STAGES`]
+fn emit_parser() -> PossibleValuesParser {
+    let artifacts = SOURCE_ARTIFACTS
+        .iter()
+        .map(|(name, _, help)| PossibleValue::new(*name).help(*help));
+    let stages = PRINTED_STAGES
+        .iter()
+        .map(|(name, help)| PossibleValue::new(*name).help(*help));
+    PossibleValuesParser::new(formats().into_iter().chain(artifacts).chain(stages))
+}
+
+fn formats() -> Vec<PossibleValue> {
+    let context = OutputContext::default();
+    let formats = output::backends(&context)
+        .iter()
+        .map(|backend| PossibleValue::new(backend.name()).help(backend.description()))
+        .collect();
+    formats
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Assemble a program, the default without a subcommand
+    Asm(Box<AsmArgs>),
+    /// Print the assembly of an assembled image
+    Disasm(DisasmArgs),
+    /// Replace a single word of an assembled image
+    Patch(PatchArgs),
+    /// Assemble a program and run it in the emulator
+    Run(RunArgs),
+    /// Assemble a program and step through it in the emulator
+    Debug(DebugArgs),
+    /// Format programs in place
+    Fmt(FmtArgs),
+}
+
+#[derive(Args)]
+struct FmtArgs {
+    /// Programs to format, `-` formats stdin to stdout
+    #[arg(required = true)]
+    input_paths: Vec<PathBuf>,
+    /// Don't write the programs, fail if one of them is not formatted
+    #[arg(long)]
+    check: bool,
+    /// Radix constants are written in, `keep` only lowercases hex digits
+    #[arg(long, default_value = "keep", value_parser = PossibleValuesParser::new(["keep", "hex", "dec"]).map(|name| name.parse::<formatter::Radix>().unwrap()))]
+    radix: formatter::Radix,
+}
+
+#[derive(Args)]
+struct DebugArgs {
+    /// Program to debug, the commands are read from stdin
+    input_path: PathBuf,
+    #[command(flatten)]
+    assembler: AssemblerArgs,
+    #[command(flatten)]
+    machine: MachineArgs,
+    /// Stop `continue` and `until` after this number of cycles
+    #[arg(
+        long = "max-cycles",
+        value_name = "CYCLES",
+        default_value_t = 1_000_000
+    )]
+    max_cycles: u64,
+}
+
+#[derive(Args)]
+struct RunArgs {
+    /// Program to run, `-` reads it from stdin. Images with the extension of
+    /// a loadable format, e.g. `.hex` or `.mif`, are run without assembling them
+    input_path: PathBuf,
+    #[command(flatten)]
+    assembler: AssemblerArgs,
+    #[command(flatten)]
+    machine: MachineArgs,
+    /// Print no state after the run, e.g. to only see the output of a console
+    #[arg(short, long)]
+    quiet: bool,
+    /// Log every executed instruction with its register writes and flag changes
+    /// to stderr or to PATH
+    #[arg(long, value_name = "PATH", require_equals = true)]
+    trace: Option<Option<PathBuf>>,
+    /// Dump registers, flags, PC and bus activity of every cycle as Value
+    /// Change Dump to PATH, e.g. for GTKWave
+    #[arg(long, value_name = "PATH")]
+    vcd: Option<PathBuf>,
+    /// Duration of one cycle in the VCD
+    #[arg(
+        long = "vcd-period",
+        value_name = "NANOSECONDS",
+        default_value_t = 10,
+        requires = "vcd"
+    )]
+    vcd_period: u64,
+    /// Write the registers, flags and the `--dump-ram` words as JSON to PATH
+    /// once the program halted
+    #[arg(long, value_name = "PATH")]
+    dump: Option<PathBuf>,
+    /// RAM words written to the `--dump`, e.g. `0x20..0x30` without the end
+    #[arg(
+        long = "dump-ram",
+        value_name = "START..END",
+        value_parser = parse_address_range,
+        requires = "dump"
+    )]
+    dump_ram: Option<std::ops::Range<u16>>,
+    /// Stop programs which did not halt after this number of cycles
+    #[arg(
+        long = "max-cycles",
+        value_name = "CYCLES",
+        default_value_t = 1_000_000
+    )]
+    max_cycles: u64,
+    /// Write the values of all device reads to PATH, which `--replay` answers
+    /// the reads of another run with
+    #[arg(long = "record", value_name = "PATH", conflicts_with = "replay_path")]
+    record_path: Option<PathBuf>,
+    /// Compare the program counter and register writes of every cycle with
+    /// the trace of the hardware at PATH and report the first divergence
+    #[arg(long = "compare", value_name = "PATH", conflicts_with_all = ["trace", "vcd"])]
+    compare_path: Option<PathBuf>,
+    /// Write the number of cycles spent in the code of every label to PATH,
+    /// which `--layout-profile` orders the code by
+    #[arg(long, value_name = "PATH", conflicts_with = "compare_path")]
+    profile: Option<PathBuf>,
+    /// Keep running and swap in the re-assembled program whenever the source
+    /// changes, until interrupted
+    #[arg(
+        long,
+        conflicts_with_all = ["trace", "vcd", "dump", "record_path", "compare_path", "profile"]
+    )]
+    watch: bool,
+    /// Restart the program with cleared registers and RAM after every reload
+    /// of `--watch` instead of continuing at the program counter
+    #[arg(long, requires = "watch")]
+    reset: bool,
+}
+
+/// Devices attached to the emulator by `run` and `debug`
+#[derive(Args)]
+struct MachineArgs {
+    /// Attach a device, e.g. `timer@0xff00` or `seven-segment@0xff20,digits=6`
+    #[arg(long = "device", value_name = "KIND@ADDRESS", value_parser = parse_device)]
+    devices: Vec<String>,
+    /// TOML description of the devices to attach
+    #[arg(long = "machine", value_name = "PATH")]
+    machine_path: Option<PathBuf>,
+    /// Answer the device reads with the values recorded by `masm run --record`
+    #[arg(long = "replay", value_name = "PATH")]
+    replay_path: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct DisasmArgs {
+    /// Image to disassemble, `-` reads it from stdin
+    image_path: PathBuf,
+    /// Format of the image [default: the format of its extension, hex otherwise]
+    #[arg(long, value_parser = PossibleValuesParser::new(loader::FORMATS))]
+    format: Option<String>,
+    /// Name the targets of relative jumps with labels and jump to them by name
+    #[arg(long)]
+    labels: bool,
+    /// Output file where the assembly is stored, `-` writes to stdout
+    #[arg(short, long = "output", default_value = "-")]
+    output_path: PathBuf,
+}
+
+#[derive(Args)]
+struct PatchArgs {
+    /// Image which gets patched
+    image_path: PathBuf,
+    /// Address of the word which gets replaced
+    #[arg(value_parser = parse_address)]
+    address: u16,
+    #[command(flatten)]
+    value: PatchValue,
+    /// Output file where the patched image is stored [default: IMAGE_PATH]
+    #[arg(short, long = "output")]
+    output_path: Option<PathBuf>,
+    /// Fail if the image has no checksum record
+    #[arg(long)]
+    checksum: bool,
+}
+
+#[derive(Args)]
+#[group(required = true, multiple = false)]
+struct PatchValue {
+    /// Hex word written to the address
+    #[arg(short, long)]
+    word: Option<String>,
+    /// Single assembly instruction written to the address
+    #[arg(short = 'a', long = "asm")]
+    instruction: Option<String>,
 }
 
 fn main() {
     let cli = Cli::parse();
-    let input_path = cli.input_path.canonicalize().unwrap_or_else(|err| {
-        eprintln!("Error: Could not find input file:");
-        eprintln!("{err}");
-        process::exit(1);
-    });
-    let output_path = cli.output_path.unwrap_or("output.hex".into());
+    match cli.command {
+        Some(Command::Asm(args)) => assemble(*args),
+        Some(Command::Disasm(args)) => disassemble(args),
+        Some(Command::Patch(args)) => patch(args),
+        Some(Command::Run(args)) => run(args),
+        Some(Command::Debug(args)) => debug(args),
+        Some(Command::Fmt(args)) => format_programs(args),
+        None => assemble(cli.asm),
+    }
+}
 
-    if cli.debug_enable {
+fn assemble(args: AsmArgs) {
+    check_emit(&args.emit);
+    let input_path = args.input_path.unwrap_or_default();
+    let stdin = input_path == Path::new("-");
+    let (input_path, source) = read_program(&input_path);
+    if args.debug_enable {
         println!("Input: {}", input_path.display());
-        println!("Output: {}", output_path.display());
     }
 
-    let lexed = lexer::lexer(&input_path).unwrap_or_else(|errors| {
-        for err in errors {
-            eprintln!("Lexer: {err}");
+    let reporter = Reporter::new(&args.assembler, input_path.clone(), source);
+    let (parsed, mut debug_info) = if args.from_ir {
+        // both need the source
+        if let Some(artifact) = args
+            .emit
+            .iter()
+            .find(|artifact| *artifact == "tokens" || *artifact == "listing")
+        {
+            let message = format!("Artifact '{artifact}' can not be emitted from an IR input");
+            reporter.error("Error", "unknown-format", &message, None);
+            reporter.exit(EXIT_FAILURE);
         }
-        process::exit(1);
+        (read_ir(&reporter), None)
+    } else {
+        let lexed = lex_program(&reporter, stdin);
+        if args.emit.iter().any(|artifact| artifact == "tokens") {
+            print_tokens(&lexed);
+            return;
+        }
+        let (parsed, debug_info) = parse_program(lexed, &args.assembler, &reporter);
+        (parsed, Some(debug_info))
+    };
+    let parsed = place_entry(parsed, debug_info.as_mut(), &args.assembler, &reporter);
+
+    if args.emit.iter().any(|artifact| artifact == "cfg") {
+        let name = input_path.file_name().unwrap_or_default().to_string_lossy();
+        print!("{}", cfg::ControlFlowGraph::new(&parsed).to_dot(&name));
+        reporter.summary();
+        return;
+    }
+    if args.emit.iter().any(|artifact| artifact == "ir") {
+        print!("{:#}", lowering::lower(parsed));
+        reporter.summary();
+        return;
+    }
+    if args.debug_enable {
+        print!("{}", parsed);
+    }
+
+    let stem = input_path.file_stem().unwrap_or_default().to_string_lossy();
+    let listing_path = args.listing_path.clone().or_else(|| {
+        let (name, extension, _) = SOURCE_ARTIFACTS[0];
+        args.emit
+            .iter()
+            .any(|artifact| artifact == name)
+            .then(|| args.out_dir.join(format!("{stem}.{extension}")))
     });
 
-    let parsed = parser::parser(lexed).unwrap_or_else(|err| {
-        eprintln!("Parser: {err}");
-        process::exit(1);
+    let context = OutputContext {
+        input_path: input_path.clone(),
+        label_definitions: parsed.label_definitions.clone(),
+        block_sizes: parsed
+            .instructions
+            .keys()
+            .map(|label| (label.clone(), parsed.block_size(label)))
+            .collect(),
+        entry_address: parsed
+            .label_definitions
+            .0
+            .get(&parsed.start_label)
+            .map_or(0, |label| label.address.0),
+        mif_depth: args.mif_depth,
+        mif_width: args.mif_width,
+        hex_format: HexFormat {
+            words_per_line: usize::from(args.words_per_line),
+            uppercase: args.uppercase,
+            digits: usize::from(args.hex_digits),
+            addresses: args.annotate,
+            labels: args.annotate_labels,
+        },
+        lane: None,
+    };
+
+    if let Some(reference_path) = args.reference_path {
+        write_file(&reference_path, &output::Reference(&context), &[])
+            .unwrap_or_else(|err| reporter.io_error(&err));
+    }
+    if let Some(map_path) = args.map_path {
+        write_file(&map_path, &output::Map(&context), &[])
+            .unwrap_or_else(|err| reporter.io_error(&err));
+    }
+    if let Some(ir_path) = args.ir_path {
+        write_text(&ir_path, &ir_json::to_json(&parsed))
+            .unwrap_or_else(|err| reporter.io_error(&err));
+    }
+    if let (Some(debug_info_path), Some(debug_info)) = (args.debug_info_path, &debug_info) {
+        let directory = input_path.parent().unwrap_or(Path::new(""));
+        let lines: Vec<&str> = reporter
+            .source
+            .as_deref()
+            .unwrap_or_default()
+            .lines()
+            .collect();
+        let json = debug_info.to_json(&input_path, &parsed, |file, line_number| match file {
+            None => lines
+                .get(usize::from(line_number))
+                .map(|line| line.to_string()),
+            Some(file) => std::fs::read_to_string(directory.join(file))
+                .ok()?
+                .lines()
+                .nth(usize::from(line_number))
+                .map(String::from),
+        });
+        write_text(&debug_info_path, &json).unwrap_or_else(|err| reporter.io_error(&err));
+    }
+    if args.verify {
+        if let Err(err) = verify::verify(&parsed) {
+            let stage = match err {
+                verify::VerifyError::Generator(_) => "Generator",
+                _ => "Verify",
+            };
+            reporter.error(stage, err.code(), &err, None);
+            reporter.exit(EXIT_GENERATOR);
+        }
+    }
+
+    if let Some(format) = &args.stats {
+        let stats = stats::Stats::new(&parsed);
+        let report = if format == "json" {
+            stats.to_json()
+        } else {
+            stats.to_string()
+        };
+        if args.output_path.as_deref().is_some_and(is_stdout) {
+            eprint!("{report}");
+        } else {
+            print!("{report}");
+        }
+    }
+
+    let gap_fill = parse_fill_value(&args.gap_fill);
+    let output = args.emit.is_empty().then(|| {
+        let backend = match &args.format {
+            Some(format) => output::backend(format, &context),
+            None => args
+                .output_path
+                .as_deref()
+                .and_then(|output_path| output::detect_backend(output_path, &context))
+                .or_else(|| output::backend("hex", &context)),
+        }
+        .unwrap_or_else(|| {
+            let format = args.format.clone().unwrap_or_default();
+            let message = format!("Unknown output format '{}'", format);
+            reporter.error("Error", "unknown-format", &message, None);
+            reporter.exit(EXIT_FAILURE);
+        });
+        let output_path = args
+            .output_path
+            .clone()
+            .unwrap_or_else(|| backend.file_name("output").into());
+        if args.debug_enable {
+            println!("Output: {}", output_path.display());
+        }
+        if args.split_lanes.is_some() && is_stdout(&output_path) {
+            let message = "Split lanes can not be written to stdout";
+            reporter.error("Error", "split-lanes-stdout", &message, None);
+            reporter.exit(EXIT_FAILURE);
+        }
+        (backend, output_path)
     });
 
-    if cli.debug_enable {
-        println!("{:#?}", parsed.instructions.keys());
-        println!("{:#?}", parsed.instructions.values());
+    // without changes to the image it is written while being generated
+    let unchanged = args.checksum.is_none()
+        && args.pad_to.is_none()
+        && args.split_lanes.is_none()
+        && listing_path.is_none()
+        && !args.debug_enable;
+    if let (Some((backend, output_path)), true) = (&output, unchanged) {
+        output::check_memory_size(parsed.size(), args.memory_size, &context).unwrap_or_else(
+            |err| {
+                reporter.error("Error", err.code(), &err, None);
+                reporter.exit(EXIT_GENERATOR);
+            },
+        );
+        let mut writer = open_output(output_path).unwrap_or_else(|err| reporter.io_error(&err));
+        let written = output::write_program(parsed, &gap_fill, backend.as_ref(), &mut writer)
+            .and_then(|_| {
+                writer
+                    .flush()
+                    .map_err(|err| output::OutputError::IoError(err).into())
+            });
+        drop(writer);
+        if let Err(err) = written {
+            // no partial images are left behind
+            if !is_stdout(output_path) {
+                let _ = std::fs::remove_file(output_path);
+            }
+            if let MasmError::Generator(generator::GeneratorError::IoError(_))
+            | MasmError::Output(output::OutputError::IoError(_)) = err
+            {
+                let message = format!(
+                    "Could not write to file '{}': {}",
+                    output_path.display(),
+                    err
+                );
+                reporter.io_error(&message);
+            }
+            for (code, message, _) in err.diagnostics() {
+                reporter.error(err.stage(), code, &message, None);
+            }
+            reporter.exit(EXIT_GENERATOR);
+        }
+        reporter.summary();
+        return;
     }
 
-    let binary = generator::generator(parsed).unwrap_or_else(|err| {
-        eprintln!("Generator: {err}");
-        process::exit(1);
+    // the listing cross-references the labels of the program
+    let listed = listing_path.as_ref().map(|_| parsed.clone());
+    let mut binary = generator::generator_with_gap_fill(parsed, &gap_fill).unwrap_or_else(|err| {
+        reporter.error("Generator", err.code(), &err, None);
+        reporter.exit(EXIT_GENERATOR);
+    });
+    if !args.emit.is_empty() {
+        std::fs::create_dir_all(&args.out_dir).unwrap_or_else(|err| {
+            reporter.io_error(&format!(
+                "Could not create output directory '{}': {}",
+                args.out_dir.display(),
+                err
+            ))
+        });
+    }
+    if let (Some(listing_path), Some(listed), Some(debug_info)) =
+        (&listing_path, &listed, &debug_info)
+    {
+        if args.debug_enable && args.listing_path.is_none() {
+            println!("Artifact: {}", listing_path.display());
+        }
+        let directory = input_path.parent().unwrap_or(Path::new(""));
+        let source = reporter.source.as_deref().unwrap_or_default();
+        let listing = listing::listing(&input_path, source, listed, debug_info, &binary, |file| {
+            std::fs::read_to_string(directory.join(file)).ok()
+        });
+        write_text(listing_path, &listing).unwrap_or_else(|err| reporter.io_error(&err));
+    }
+    if let Some(checksum) = args.checksum {
+        output::append_checksum(&mut binary, checksum);
+    }
+    if let Some(pad_to) = args.pad_to {
+        let fill = parse_fill_value(&args.fill_value);
+        output::pad(&mut binary, pad_to, &fill).unwrap_or_else(|err| {
+            reporter.error("Error", err.code(), &err, None);
+            reporter.exit(EXIT_GENERATOR);
+        });
+    }
+    output::check_memory_size(binary.len(), args.memory_size, &context).unwrap_or_else(|err| {
+        reporter.error("Error", err.code(), &err, None);
+        reporter.exit(EXIT_GENERATOR);
     });
 
-    if cli.debug_enable {
+    if args.debug_enable {
         println!("{:#?}", binary);
     }
-    let output = File::create(&output_path).unwrap_or_else(|err| {
-        eprintln!("Error: Could not open output file for writing:");
-        eprintln!("{err}");
-        process::exit(1);
+
+    if let Some((backend, output_path)) = output {
+        write_output(
+            &output_path,
+            backend.as_ref(),
+            &context,
+            &binary,
+            args.split_lanes,
+        )
+        .unwrap_or_else(|err| reporter.io_error(&err));
+        reporter.summary();
+        return;
+    }
+
+    // the artifacts from the source are written already
+    for artifact in args
+        .emit
+        .iter()
+        .filter(|artifact| !SOURCE_ARTIFACTS.iter().any(|(name, ..)| *artifact == name))
+    {
+        let backend = output::backend(artifact, &context).unwrap_or_else(|| {
+            let message = format!("Unknown output format '{artifact}'");
+            reporter.error("Error", "unknown-format", &message, None);
+            reporter.exit(EXIT_FAILURE);
+        });
+        let artifact_path = args.out_dir.join(backend.file_name(&stem));
+        if args.debug_enable {
+            println!("Artifact: {}", artifact_path.display());
+        }
+        write_output(
+            &artifact_path,
+            backend.as_ref(),
+            &context,
+            &binary,
+            args.split_lanes,
+        )
+        .unwrap_or_else(|err| reporter.io_error(&err));
+    }
+    reporter.summary();
+}
+
+/// Rejects a [`PRINTED_STAGES`] entry of `--emit` combined with other
+/// artifacts, which would not be written as the assembly ends with the stage
+fn check_emit(emit: &[String]) {
+    let Some(stage) = emit
+        .iter()
+        .find(|artifact| PRINTED_STAGES.iter().any(|(name, _)| *artifact == name))
+    else {
+        return;
+    };
+    if let Some(other) = emit.iter().find(|artifact| *artifact != stage) {
+        let message = format!(
+            "'--emit {stage}' prints a stage and exits, it can not be combined with '{other}'"
+        );
+        Cli::command()
+            .error(clap::error::ErrorKind::ArgumentConflict, message)
+            .exit();
+    }
+}
+
+/// Reads the program at `input_path`, `-` reads it from stdin. Returns the
+/// path naming the program and its source, `None` if the file is unreadable
+/// and left to the lexer to report.
+fn read_program(input_path: &Path) -> (PathBuf, Option<String>) {
+    if input_path == Path::new("-") {
+        let mut source = String::new();
+        io::stdin()
+            .read_to_string(&mut source)
+            .unwrap_or_else(|err| {
+                eprintln!("Error: Could not read program from stdin:");
+                eprintln!("{err}");
+                process::exit(EXIT_IO);
+            });
+        // names the artifacts and identifiers derived from the input file
+        (PathBuf::from("stdin"), Some(source))
+    } else {
+        let input_path = input_path.canonicalize().unwrap_or_else(|err| {
+            eprintln!("Error: Could not find input file:");
+            eprintln!("{err}");
+            process::exit(EXIT_IO);
+        });
+        let source = std::fs::read_to_string(&input_path).ok();
+        (input_path, source)
+    }
+}
+
+/// Lexes the program of `reporter`, from its source if it was read from stdin
+fn lex_program(reporter: &Reporter, stdin: bool) -> Vec<lexer::Keyword> {
+    let lexer_options = lexer::LexerOptions { auto_halt: false };
+    let lexed = match (stdin, &reporter.source) {
+        (true, Some(source)) => lexer::lexer_from_str(source, &lexer_options),
+        _ => lexer::lexer_with_options(&reporter.input_path, &lexer_options),
+    };
+    lexed.unwrap_or_else(|errors| {
+        for err in errors {
+            reporter.error("Lexer", err.code(), &err, err.position());
+        }
+        reporter.exit(EXIT_LEXER);
+    })
+}
+
+/// Preprocesses and parses `lexed` with the options of `args`, reporting
+/// errors and warnings
+fn parse_program(
+    mut lexed: Vec<lexer::Keyword>,
+    args: &AssemblerArgs,
+    reporter: &Reporter,
+) -> (ir::IR, DebugInfo) {
+    // line numbers of keywords from included files refer to those files
+    let includes = lexed.iter().any(
+        |keyword| matches!(keyword, lexer::Keyword::Directive { name, .. } if name == "include"),
+    );
+    let in_source = |position: Option<diagnostic::Position>| position.filter(|_| !includes);
+    let mut warnings = warning::Diagnostics::new();
+    // a category named on its own overrides `all`
+    let levels = args
+        .allow
+        .iter()
+        .map(|category| (category.clone(), warning::Level::Allow))
+        .chain(args.warn.iter().cloned())
+        .chain(
+            args.strict
+                .then(|| (String::from("all"), warning::Level::Deny)),
+        );
+    let (all, categories): (Vec<_>, Vec<_>) = levels.partition(|(category, _)| category == "all");
+    for (category, level) in all.into_iter().chain(categories) {
+        warnings.set_level(&category, level);
+    }
+    if !args.no_auto_halt && lexer::append_halt(&mut lexed) {
+        warnings.push(warning::Warning::AppendedHalt {
+            line_number: lexed.last().map_or(0, LineNumber::get_line_number),
+        });
+    }
+
+    let lexed = preprocessor::preprocessor_with_options(lexed, &preprocessor_options(args))
+        .unwrap_or_else(|err| {
+            reporter.error("Preprocessor", err.code(), &err, in_source(err.position()));
+            reporter.exit(EXIT_LEXER);
+        });
+    warnings.extend(warning::deprecated_mnemonics(&lexed));
+
+    let parsed =
+        parser::parser_with_debug_info(lexed, &parser_options(args)).unwrap_or_else(|errors| {
+            for err in errors {
+                reporter.error("Parser", err.code(), &err, in_source(err.position()));
+            }
+            reporter.exit(EXIT_PARSER);
+        });
+    warnings.extend(warning::unused_labels(&parsed.0));
+    for (warning, level) in warnings.warnings() {
+        let severity = if *level == warning::Level::Deny {
+            diagnostic::Severity::Error
+        } else {
+            diagnostic::Severity::Warning
+        };
+        reporter.report(
+            severity,
+            warning.code(),
+            warning,
+            in_source(warning.position()),
+        );
+    }
+    // denied warnings fail the assembly
+    if warnings.has_errors() {
+        reporter.exit(EXIT_WARNINGS);
+    }
+    parsed
+}
+
+fn preprocessor_options(args: &AssemblerArgs) -> preprocessor::PreprocessorOptions {
+    preprocessor::PreprocessorOptions {
+        defines: args.defines.iter().cloned().collect(),
+        case_insensitive: args.case_insensitive,
+    }
+}
+
+fn parser_options(args: &AssemblerArgs) -> parser::ParserOptions {
+    parser::ParserOptions {
+        pack_strings: args.pack_strings,
+        soft_division: args.soft_division,
+        register_aliases: args.aliases.iter().cloned().collect(),
+        case_insensitive: args.case_insensitive,
+        entry: args.entry.clone(),
+    }
+}
+
+/// Assembles the file at `input_path` like [`parse_program`] and
+/// [`place_entry`], but returns the first error instead of exiting and
+/// reports no warnings
+fn reassemble(input_path: &Path, args: &AssemblerArgs) -> Result<Vec<InstructionWord>, String> {
+    let lexer_options = lexer::LexerOptions {
+        auto_halt: !args.no_auto_halt,
+    };
+    let lexed = lexer::lexer_with_options(input_path, &lexer_options)
+        .map_err(|errors| format!("Lexer: {}", errors[0]))?;
+    let lexed = preprocessor::preprocessor_with_options(lexed, &preprocessor_options(args))
+        .map_err(|err| format!("Preprocessor: {err}"))?;
+    let (parsed, _) = parser::parser_with_debug_info(lexed, &parser_options(args))
+        .map_err(|errors| format!("Parser: {}", errors[0]))?;
+    let parsed = match &args.layout_profile {
+        Some(path) => {
+            let profile = std::fs::read_to_string(path)
+                .map_err(|err| format!("Could not read profile '{}': {err}", path.display()))?;
+            let profile = profile::parse_profile(&profile)
+                .map_err(|err| format!("{err} of '{}'", path.display()))?;
+            layout::place_hot_first(parsed, &profile).map_err(|err| format!("Layout: {err}"))?
+        }
+        None => parsed,
+    };
+    let parsed = if args.entry_first {
+        layout::place_entry_first(parsed).map_err(|err| format!("Layout: {err}"))?
+    } else {
+        parsed
+    };
+    generator::generator(parsed).map_err(|err| format!("Generator: {err}"))
+}
+
+/// Orders the code by the profile of `--layout-profile` and moves the code
+/// of the entry label to address 0 with `--entry-first`
+fn place_entry(
+    parsed: ir::IR,
+    debug_info: Option<&mut DebugInfo>,
+    args: &AssemblerArgs,
+    reporter: &Reporter,
+) -> ir::IR {
+    if !args.entry_first && args.layout_profile.is_none() {
+        return parsed;
+    }
+    let labels = parsed.label_definitions.clone();
+    let mut placed = Ok(parsed);
+    if let Some(path) = &args.layout_profile {
+        let profile = std::fs::read_to_string(path).unwrap_or_else(|err| {
+            eprintln!("Error: Could not read profile '{}':", path.display());
+            eprintln!("{err}");
+            process::exit(EXIT_IO);
+        });
+        let profile = profile::parse_profile(&profile).unwrap_or_else(|err| {
+            eprintln!("Error: {err} of '{}'", path.display());
+            process::exit(EXIT_FAILURE);
+        });
+        placed = placed.and_then(|parsed| layout::place_hot_first(parsed, &profile));
+    }
+    if args.entry_first {
+        placed = placed.and_then(layout::place_entry_first);
+    }
+    let parsed = placed.unwrap_or_else(|err| {
+        reporter.error("Layout", err.code(), &err, None);
+        reporter.exit(EXIT_GENERATOR);
     });
-    let mut writer = BufWriter::new(output);
-    writer
-        .write_all("v3.0 hex words plain\n".as_bytes())
-        .and_then(|_| {
-            for instr_line in binary.chunks(8) {
-                let mut line = String::new();
-                for instr_word in instr_line {
-                    line = format!("{line} {instr_word}");
-                }
-                writer.write_all(format!("{}\n", line.trim()).as_bytes())?;
+    if let Some(debug_info) = debug_info {
+        debug_info.relocate(&labels, &parsed.label_definitions);
+    }
+    parsed
+}
+
+/// One keyword per line, `LINE:COLUMN` with the line counted from 1 like in
+/// the diagnostics, kind and text separated by tabs
+fn print_tokens(keywords: &[lexer::Keyword]) {
+    let mut stdout = io::stdout().lock();
+    for keyword in keywords {
+        let text = match keyword {
+            lexer::Keyword::Label { name, .. } => name.clone(),
+            keyword => keyword.get_original_string(),
+        };
+        let line = format!(
+            "{}:{}\t{}\t{}",
+            diagnostic::display_line(keyword.get_line_number()),
+            keyword.get_column(),
+            keyword.kind(),
+            text
+        );
+        if writeln!(stdout, "{}", line).is_err() {
+            // e.g. piped into `head`
+            return;
+        }
+    }
+}
+
+/// Reads the program stored with `--ir` from the input instead of lexing
+/// and parsing it
+fn read_ir(reporter: &Reporter) -> ir::IR {
+    let Some(source) = &reporter.source else {
+        let message = format!(
+            "Could not read input file '{}'",
+            reporter.input_path.display()
+        );
+        reporter.io_error(&message);
+    };
+    ir_json::from_json(source).unwrap_or_else(|err| {
+        reporter.error("IR", err.code(), &err, err.position());
+        reporter.exit(EXIT_PARSER);
+    })
+}
+
+fn patch(args: PatchArgs) {
+    let mut binary = loader::loader(&args.image_path).unwrap_or_else(|err| exit_loader(err));
+
+    let word = if let Some(word) = args.value.word {
+        parse_word(&word).unwrap_or_else(|| {
+            eprintln!("Error: '{word}' is not a valid 20 bit hex word");
+            process::exit(EXIT_FAILURE);
+        })
+    } else {
+        assemble_instruction(&args.value.instruction.unwrap_or_default())
+    };
+
+    // recomputed if the patched word is covered by the checksum
+    let checksum = match output::find_checksum(&binary) {
+        Ok(record) => Some(record),
+        Err(output::ChecksumError::MissingMarker) if !args.checksum => None,
+        Err(err) => {
+            eprintln!("Error: {err}");
+            process::exit(EXIT_FAILURE);
+        }
+    };
+    let image_size = binary.len();
+    let target = binary
+        .get_mut(usize::from(args.address))
+        .unwrap_or_else(|| {
+            eprintln!(
+                "Error: Address {:#x} is outside of the image ({} words)",
+                args.address, image_size
+            );
+            process::exit(EXIT_FAILURE);
+        });
+    *target = word;
+    if let Some((words, checksum)) =
+        checksum.filter(|(words, _)| usize::from(args.address) < *words)
+    {
+        output::update_checksum(&mut binary, words, checksum);
+    }
+
+    // written in the format it was read in unless the output is named otherwise
+    let output_path = args.output_path.unwrap_or(args.image_path);
+    let context = OutputContext::default();
+    let format = loader::detect_format(&output_path).unwrap_or("hex");
+    let backend = output::backend(format, &context).expect("loadable formats are backends");
+    write_file(&output_path, backend.as_ref(), &binary).unwrap_or_else(|err| {
+        eprintln!("Error: {err}");
+        process::exit(EXIT_IO);
+    });
+}
+
+/// Prints an error of reading an image and exits
+fn exit_loader(err: loader::LoaderError) -> ! {
+    eprintln!("Loader: {err}");
+    process::exit(match err {
+        loader::LoaderError::IoError(_) => EXIT_IO,
+        _ => EXIT_FAILURE,
+    });
+}
+
+fn disassemble(args: DisasmArgs) {
+    let format = args
+        .format
+        .unwrap_or_else(|| String::from(loader::detect_format(&args.image_path).unwrap_or("hex")));
+    let loaded = if args.image_path == Path::new("-") {
+        loader::load(&format, io::stdin().lock())
+    } else {
+        File::open(&args.image_path)
+            .map_err(loader::LoaderError::IoError)
+            .and_then(|file| loader::load(&format, io::BufReader::new(file)))
+    };
+    let binary = loaded.unwrap_or_else(|err| exit_loader(err));
+
+    let listing = disassembler::disassemble(&binary, args.labels).to_string();
+    write_text(&args.output_path, &listing).unwrap_or_else(|err| {
+        eprintln!("Error: {err}");
+        process::exit(EXIT_IO);
+    });
+}
+
+fn format_programs(args: FmtArgs) {
+    let options = formatter::FormatterOptions { radix: args.radix };
+    let mut unformatted = false;
+    for input_path in args.input_paths {
+        let stdin = input_path == Path::new("-");
+        let source = if stdin {
+            let mut source = String::new();
+            io::stdin().read_to_string(&mut source).map(|_| source)
+        } else {
+            std::fs::read_to_string(&input_path)
+        };
+        let source = source.unwrap_or_else(|err| {
+            eprintln!("Error: Could not read '{}':", input_path.display());
+            eprintln!("{err}");
+            process::exit(EXIT_IO);
+        });
+        let reporter = Reporter {
+            renderer: diagnostic::Renderer {
+                color: use_color("auto"),
+            },
+            json: false,
+            source: Some(source),
+            input_path: input_path.clone(),
+            errors: Cell::new(0),
+            warnings: Cell::new(0),
+        };
+        let source = reporter.source.as_deref().unwrap_or_default();
+        let formatted = formatter::format(source, &options).unwrap_or_else(|errors| {
+            for err in errors {
+                reporter.error("Lexer", err.code(), &err, err.position());
+            }
+            reporter.exit(EXIT_LEXER);
+        });
+
+        if args.check {
+            if let Some(line_number) = formatter::first_difference(source, &formatted) {
+                let expected = formatted.lines().nth(line_number).unwrap_or_default();
+                eprintln!(
+                    "{}:{}: not formatted, expected `{}`",
+                    input_path.display(),
+                    line_number + 1,
+                    expected
+                );
+                unformatted = true;
+            }
+        } else if stdin || formatted != source {
+            write_text(&input_path, &formatted).unwrap_or_else(|err| reporter.io_error(&err));
+        }
+    }
+    if unformatted {
+        process::exit(EXIT_FAILURE);
+    }
+}
+
+fn run(args: RunArgs) {
+    let image = loader::detect_format(&args.input_path).is_some();
+    if args.watch && (image || args.input_path == Path::new("-")) {
+        eprintln!("Error: --watch needs a source file to re-assemble");
+        process::exit(EXIT_FAILURE);
+    }
+    if args.profile.is_some() && image {
+        eprintln!("Error: --profile needs a source file with labels");
+        process::exit(EXIT_FAILURE);
+    }
+    // labels of the program the `--profile` counts the cycles of
+    let mut profiled = None;
+    let binary = if image {
+        loader::loader(&args.input_path).unwrap_or_else(|err| exit_loader(err))
+    } else {
+        let stdin = args.input_path == Path::new("-");
+        let (input_path, source) = read_program(&args.input_path);
+        let reporter = Reporter::new(&args.assembler, input_path, source);
+        let (parsed, _) = parse_program(lex_program(&reporter, stdin), &args.assembler, &reporter);
+        let parsed = place_entry(parsed, None, &args.assembler, &reporter);
+        if args.profile.is_some() {
+            profiled = Some(parsed.clone());
+        }
+        let binary = generator::generator(parsed).unwrap_or_else(|err| {
+            reporter.error("Generator", err.code(), &err, None);
+            reporter.exit(EXIT_GENERATOR);
+        });
+        reporter.summary();
+        binary
+    };
+
+    let mut emulator = emulator::Emulator::new(binary);
+    emulator.bus = machine(&args.machine);
+    if let Some(recording) = replay(&args.machine) {
+        emulator.replay(recording);
+    }
+    if args.record_path.is_some() {
+        emulator.record();
+    }
+    if args.watch {
+        watch(emulator, &args);
+    }
+    let mut trace: Option<Box<dyn Write>> = match args.trace {
+        None => None,
+        Some(None) => Some(Box::new(io::stderr())),
+        Some(Some(path)) => Some(Box::new(BufWriter::new(
+            File::create(&path).unwrap_or_else(|err| {
+                eprintln!(
+                    "Error: Could not open trace file '{}' for writing:",
+                    path.display()
+                );
+                eprintln!("{err}");
+                process::exit(EXIT_IO);
+            }),
+        ))),
+    };
+    let mut vcd = args.vcd.map(|path| {
+        File::create(&path)
+            .map(BufWriter::new)
+            .and_then(|file| vcd::Vcd::new(file, &emulator, args.vcd_period))
+            .unwrap_or_else(|err| {
+                eprintln!("Error: Could not write waveform file '{}':", path.display());
+                eprintln!("{err}");
+                process::exit(EXIT_IO);
+            })
+    });
+    let mut logged = Ok(());
+    let mut dumped = Ok(());
+    let mut diverged = false;
+    let mut executed: HashMap<u16, u64> = HashMap::new();
+    let result = if let Some(path) = &args.compare_path {
+        let hardware = std::fs::read_to_string(path).unwrap_or_else(|err| {
+            eprintln!("Error: Could not read trace '{}':", path.display());
+            eprintln!("{err}");
+            process::exit(EXIT_IO);
+        });
+        let hardware = cosim::parse_trace(&hardware).unwrap_or_else(|err| {
+            eprintln!("Error: {err} of '{}'", path.display());
+            process::exit(EXIT_FAILURE);
+        });
+        cosim::compare(&mut emulator, &hardware).map(|divergence| match divergence {
+            Some(divergence) => {
+                eprintln!("Divergence: {divergence}");
+                diverged = true;
             }
-            Ok(())
+            None if !args.quiet => println!("{} cycles agree with the trace", hardware.len()),
+            None => {}
         })
-        .and_then(|_| writer.flush())
-        .unwrap_or_else(|err| {
-            eprintln!("Error: Could not write to file:");
+    } else if trace.is_none() && vcd.is_none() && profiled.is_none() {
+        emulator.run(args.max_cycles)
+    } else {
+        emulator.run_traced(args.max_cycles, |emulator, entry| {
+            *executed.entry(entry.address).or_default() += 1;
+            if let (Some(trace), Ok(())) = (trace.as_mut(), &logged) {
+                logged = writeln!(trace, "{entry}");
+            }
+            if let (Some(vcd), Ok(())) = (vcd.as_mut(), &dumped) {
+                dumped = vcd.cycle(emulator, entry);
+            }
+        })
+    };
+    if let Err(err) = logged.and_then(|()| trace.map_or(Ok(()), |mut trace| trace.flush())) {
+        eprintln!("Error: Could not write the trace:");
+        eprintln!("{err}");
+        process::exit(EXIT_IO);
+    }
+    if let Err(err) = dumped.and_then(|()| vcd.map_or(Ok(()), |vcd| vcd.finish().map(drop))) {
+        eprintln!("Error: Could not write the waveform:");
+        eprintln!("{err}");
+        process::exit(EXIT_IO);
+    }
+    // failed runs are recorded as well, to reproduce the failure
+    if let (Some(path), Some(recording)) = (&args.record_path, emulator.recording()) {
+        std::fs::write(path, recording.to_bytes()).unwrap_or_else(|err| {
+            eprintln!("Error: Could not write recording '{}':", path.display());
             eprintln!("{err}");
-            process::exit(1);
+            process::exit(EXIT_IO);
+        });
+    }
+    if let (Some(path), Some(ir)) = (&args.profile, &profiled) {
+        let profile = profile::to_text(&profile::profile(ir, &executed));
+        write_text(path, &profile).unwrap_or_else(|err| {
+            eprintln!("Error: {err}");
+            process::exit(EXIT_IO);
+        });
+    }
+    if let (Some(path), true) = (&args.dump, emulator.halted) {
+        let dump = emulator.to_json(args.dump_ram.unwrap_or(0..0));
+        write_text(path, &dump).unwrap_or_else(|err| {
+            eprintln!("Error: {err}");
+            process::exit(EXIT_IO);
+        });
+    }
+    if !args.quiet {
+        print!("{}", emulator);
+    }
+    if let Err(err) = result {
+        eprintln!("Emulator: {err}");
+        process::exit(EXIT_EMULATOR);
+    }
+    if diverged {
+        process::exit(EXIT_FAILURE);
+    }
+}
+
+/// Cycles `--watch` runs between two checks of the source
+const WATCH_CYCLES: u64 = 10_000;
+/// Time between two checks of the source once the program stopped
+const WATCH_INTERVAL: std::time::Duration = std::time::Duration::from_millis(200);
+
+/// Runs the program of `emulator` and loads the re-assembled program into it
+/// whenever the source of `args` is modified. A program which halted, failed
+/// or ran out of cycles starts again from address 0 after the next reload.
+fn watch(mut emulator: emulator::Emulator, args: &RunArgs) -> ! {
+    let modified = || {
+        std::fs::metadata(&args.input_path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    };
+    let mut stamp = modified();
+    let mut stopped = false;
+    loop {
+        if stopped {
+            std::thread::sleep(WATCH_INTERVAL);
+        } else {
+            let cycles = WATCH_CYCLES.min(args.max_cycles.saturating_sub(emulator.cycles));
+            match emulator.run(cycles) {
+                Ok(()) => {
+                    if !args.quiet {
+                        print!("{}", emulator);
+                    }
+                    eprintln!("Halted, waiting for changes of the source");
+                    stopped = true;
+                }
+                Err(emulator::EmulatorError::CycleLimit { .. })
+                    if emulator.cycles < args.max_cycles => {}
+                Err(err) => {
+                    let err = match err {
+                        emulator::EmulatorError::CycleLimit { .. } => {
+                            emulator::EmulatorError::CycleLimit {
+                                cycles: args.max_cycles,
+                            }
+                        }
+                        err => err,
+                    };
+                    eprintln!("Emulator: {err}");
+                    eprintln!("Stopped, waiting for changes of the source");
+                    stopped = true;
+                }
+            }
+        }
+
+        if modified() == stamp {
+            continue;
+        }
+        stamp = modified();
+        match reassemble(&args.input_path, &args.assembler) {
+            Ok(binary) if args.reset || stopped => {
+                let bus = std::mem::take(&mut emulator.bus);
+                emulator = emulator::Emulator::new(binary);
+                emulator.bus = bus;
+                eprintln!("Reloaded {}, restarted", args.input_path.display());
+                stopped = false;
+            }
+            Ok(binary) => {
+                emulator.load(binary);
+                eprintln!(
+                    "Reloaded {}, continuing at {:#06x}",
+                    args.input_path.display(),
+                    emulator.pc
+                );
+            }
+            // the old program keeps running until the source is fixed
+            Err(err) => eprintln!("{err}"),
+        }
+    }
+}
+
+fn debug(args: DebugArgs) {
+    let stdin = args.input_path == Path::new("-");
+    let (input_path, source) = read_program(&args.input_path);
+    let reporter = Reporter::new(&args.assembler, input_path, source);
+    let lexed = lex_program(&reporter, stdin);
+    let (parsed, mut debug_info) = parse_program(lexed, &args.assembler, &reporter);
+    let parsed = place_entry(parsed, Some(&mut debug_info), &args.assembler, &reporter);
+    let labels = parsed.label_definitions.clone();
+    let binary = generator::generator(parsed).unwrap_or_else(|err| {
+        reporter.error("Generator", err.code(), &err, None);
+        reporter.exit(EXIT_GENERATOR);
+    });
+    reporter.summary();
+    let directory = reporter.input_path.parent().unwrap_or(Path::new(""));
+    let mut debugger = debugger::Debugger::new(
+        binary,
+        reporter.source.as_deref().unwrap_or_default(),
+        debug_info,
+        &labels,
+        args.max_cycles,
+        |file| std::fs::read_to_string(directory.join(file)).ok(),
+    );
+    debugger.emulator.bus = machine(&args.machine);
+    if let Some(recording) = replay(&args.machine) {
+        debugger.emulator.replay(recording);
+    }
+    let terminal = io::stdout().is_terminal();
+    let color = terminal && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty());
+    let mut line = String::new();
+    loop {
+        if terminal {
+            print!("\x1b[2J\x1b[H");
+        }
+        print!("{}(masm) ", debugger.render(color));
+        let _ = io::stdout().flush();
+        line.clear();
+        match io::stdin().read_line(&mut line) {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(err) => {
+                eprintln!("Error: Could not read command from stdin:");
+                eprintln!("{err}");
+                process::exit(EXIT_IO);
+            }
+        }
+        if !debugger.prompt(&line) {
+            break;
+        }
+    }
+}
+
+/// Recording of `--replay`
+fn replay(args: &MachineArgs) -> Option<recording::Recording> {
+    let path = args.replay_path.as_ref()?;
+    let bytes = std::fs::read(path).unwrap_or_else(|err| {
+        eprintln!("Error: Could not read recording '{}':", path.display());
+        eprintln!("{err}");
+        process::exit(EXIT_IO);
+    });
+    let recording = recording::Recording::from_bytes(&bytes).unwrap_or_else(|err| {
+        eprintln!("Error: {err}: '{}'", path.display());
+        process::exit(EXIT_FAILURE);
+    });
+    Some(recording)
+}
+
+/// Devices of `--machine` and every `--device`
+fn machine(args: &MachineArgs) -> device::Bus {
+    let mut bus = match &args.machine_path {
+        Some(path) => {
+            let description = std::fs::read_to_string(path).unwrap_or_else(|err| {
+                eprintln!(
+                    "Error: Could not read machine description '{}':",
+                    path.display()
+                );
+                eprintln!("{err}");
+                process::exit(EXIT_IO);
+            });
+            machine::parse_machine(&description).unwrap_or_else(|err| {
+                eprintln!("Machine: {err}");
+                process::exit(EXIT_FAILURE);
+            })
+        }
+        None => device::Bus::new(),
+    };
+    for spec in &args.devices {
+        let (address, device) = device::parse_spec(spec).expect("checked by parse_device");
+        bus.attach(address, device).unwrap_or_else(|err| {
+            eprintln!("Machine: {err}");
+            process::exit(EXIT_FAILURE);
         });
+    }
+    bus
+}
+
+/// Assembles a single instruction, e.g. `ldc %reg0 0x10`, into exactly one
+/// instruction word.
+fn assemble_instruction(instruction: &str) -> InstructionWord {
+    let mut lexed = Vec::with_capacity(4);
+    lexer::lex_line(&mut lexed, format!(" {instruction}"), 0).unwrap_or_else(|err| {
+        eprintln!("Lexer: {err}");
+        process::exit(EXIT_LEXER);
+    });
+
+    let parsed = parser::parser(lexed).unwrap_or_else(|errors| {
+        for err in errors {
+            eprintln!("Parser: {err}");
+        }
+        process::exit(EXIT_PARSER);
+    });
+
+    let binary = generator::generator(parsed).unwrap_or_else(|err| {
+        eprintln!("Generator: {err}");
+        process::exit(EXIT_GENERATOR);
+    });
+
+    match binary.as_slice() {
+        [word] => word.clone(),
+        _ => {
+            eprintln!("Error: '{instruction}' does not assemble to a single instruction word");
+            process::exit(EXIT_FAILURE);
+        }
+    }
+}
+
+/// Colors are used if forced with `always` or, for `auto`, stderr is a
+/// terminal and the NO_COLOR environment variable is unset or empty
+fn use_color(when: &str) -> bool {
+    match when {
+        "always" => true,
+        "never" => false,
+        _ => {
+            std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+                && io::stderr().is_terminal()
+        }
+    }
+}
+
+/// Reports errors and warnings about the input file
+struct Reporter {
+    renderer: diagnostic::Renderer,
+    /// one JSON object per diagnostic instead of the human readable form
+    json: bool,
+    input_path: PathBuf,
+    /// the source lines shown with the diagnostics
+    source: Option<String>,
+    /// number of reported errors and warnings for the summary
+    errors: Cell<usize>,
+    warnings: Cell<usize>,
+}
+
+impl Reporter {
+    /// Reports diagnostics about the program at `input_path` as selected by
+    /// `--color` and `--diagnostics-format`
+    fn new(args: &AssemblerArgs, input_path: PathBuf, source: Option<String>) -> Self {
+        Reporter {
+            renderer: diagnostic::Renderer {
+                color: use_color(&args.color),
+            },
+            json: args.diagnostics_format == "json",
+            input_path,
+            source,
+            errors: Cell::new(0),
+            warnings: Cell::new(0),
+        }
+    }
+
+    /// Prints `error` found by `stage`
+    fn error(
+        &self,
+        stage: &str,
+        code: &str,
+        error: &dyn std::fmt::Display,
+        position: Option<diagnostic::Position>,
+    ) {
+        self.print(diagnostic::Severity::Error, stage, code, error, position);
+    }
+
+    /// Prints `warning`, which is an error if its category is denied
+    fn report(
+        &self,
+        severity: diagnostic::Severity,
+        code: &str,
+        warning: &dyn std::fmt::Display,
+        position: Option<diagnostic::Position>,
+    ) {
+        let label = match severity {
+            diagnostic::Severity::Error => "Error",
+            diagnostic::Severity::Warning => "Warning",
+        };
+        self.print(severity, label, code, warning, position);
+    }
+
+    /// Prints an error reading or writing files and exits
+    fn io_error(&self, message: &str) -> ! {
+        self.error("Error", "io-error", &message, None);
+        self.exit(EXIT_IO);
+    }
+
+    /// Prints the summary and exits with `code`
+    fn exit(&self, code: i32) -> ! {
+        self.summary();
+        process::exit(code);
+    }
+
+    /// `N errors, M warnings` unless nothing was reported, not part of the
+    /// JSON diagnostics
+    fn summary(&self) {
+        let (errors, warnings) = (self.errors.get(), self.warnings.get());
+        if self.json || errors + warnings == 0 {
+            return;
+        }
+        let plural = |count: usize, noun: &str| {
+            format!("{} {}{}", count, noun, if count == 1 { "" } else { "s" })
+        };
+        eprintln!(
+            "{}, {}",
+            plural(errors, "error"),
+            plural(warnings, "warning")
+        );
+    }
+
+    /// Prints `message` with the line of the source at `position`
+    fn print(
+        &self,
+        severity: diagnostic::Severity,
+        label: &str,
+        code: &str,
+        message: &dyn std::fmt::Display,
+        position: Option<diagnostic::Position>,
+    ) {
+        let count = match severity {
+            diagnostic::Severity::Error => &self.errors,
+            diagnostic::Severity::Warning => &self.warnings,
+        };
+        count.set(count.get() + 1);
+        if self.json {
+            let diagnostic = diagnostic::Diagnostic {
+                severity,
+                code,
+                message: message.to_string(),
+                file: Some(&self.input_path),
+                position,
+            };
+            eprintln!("{}", diagnostic.to_json(self.source.as_deref()));
+            return;
+        }
+        eprintln!("{}", self.renderer.header(severity, label, message));
+        if let Some(snippet) = self
+            .source
+            .as_deref()
+            .zip(position)
+            .and_then(|(source, position)| self.renderer.snippet(severity, source, position))
+        {
+            eprintln!("{snippet}");
+        }
+    }
+}
+
+/// Parses a 20 bit hex word with optional `0x` prefix
+fn parse_word(word: &str) -> Option<InstructionWord> {
+    u32::from_str_radix(word.trim_start_matches("0x"), 16)
+        .ok()
+        .filter(|&raw| raw <= 0xfffff)
+        .map(InstructionWord::from_raw)
+}
+
+/// Fill values are hex words or, if they aren't, single instructions
+fn parse_fill_value(fill_value: &str) -> InstructionWord {
+    parse_word(fill_value).unwrap_or_else(|| assemble_instruction(fill_value))
+}
+
+fn parse_define(define: &str) -> Result<(String, u16), String> {
+    let (name, value) = match define.split_once('=') {
+        Some((name, value)) => (name, parse_address(value)?),
+        None => (define, 1),
+    };
+    if name.is_empty()
+        || name.starts_with(|c: char| c.is_ascii_digit())
+        || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    {
+        return Err(format!("invalid symbol name '{name}'"));
+    }
+    Ok((name.to_string(), value))
+}
+
+/// `WARNING` or `error=WARNING`, a plain `error` denies all warnings
+fn parse_warning(flag: &str) -> Result<(String, warning::Level), String> {
+    match flag.strip_prefix("error") {
+        Some("") => Ok((String::from("all"), warning::Level::Deny)),
+        Some(category) if category.starts_with('=') => Ok((
+            parse_warning_category(&category[1..])?,
+            warning::Level::Deny,
+        )),
+        _ => Ok((parse_warning_category(flag)?, warning::Level::Warn)),
+    }
+}
+
+fn parse_warning_category(category: &str) -> Result<String, String> {
+    if category == "all" || warning::CATEGORIES.contains(&category) {
+        Ok(category.to_string())
+    } else {
+        Err(format!(
+            "unknown warning '{category}', expected all, {}",
+            warning::CATEGORIES.join(", ")
+        ))
+    }
+}
+
+fn parse_alias(alias: &str) -> Result<(String, ir::RegisterAddress), String> {
+    let (name, register) = alias
+        .split_once('=')
+        .ok_or_else(|| String::from("expected NAME=REGISTER"))?;
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(format!("invalid alias name '{name}'"));
+    }
+    if parser::register_address(name).is_some() {
+        return Err(format!(
+            "alias '{name}' shadows the register of the same name"
+        ));
+    }
+    let register = register.strip_prefix('%').unwrap_or(register);
+    let address =
+        parser::register_address(register).ok_or(format!("invalid register '{register}'"))?;
+    Ok((name.to_string(), address))
+}
+
+fn parse_device(spec: &str) -> Result<String, String> {
+    device::parse_spec(spec)
+        .map(|_| spec.to_string())
+        .map_err(|err| err.to_string())
+}
+
+fn parse_address(address: &str) -> Result<u16, String> {
+    if let Some(hex_address) = address.strip_prefix("0x") {
+        u16::from_str_radix(hex_address, 16)
+    } else {
+        address.parse::<u16>()
+    }
+    .map_err(|err| err.to_string())
+}
+
+/// `START..END` with addresses as accepted by [`parse_address`]
+fn parse_address_range(range: &str) -> Result<std::ops::Range<u16>, String> {
+    let (start, end) = range
+        .split_once("..")
+        .ok_or_else(|| String::from("expected START..END"))?;
+    let (start, end) = (parse_address(start)?, parse_address(end)?);
+    if end < start {
+        return Err(format!("end {end:#06x} lies before start {start:#06x}"));
+    }
+    Ok(start..end)
+}
+
+/// Writes `binary` with `backend`, one file per lane if `lanes` is given.
+/// Every lane is written by the backend of the same name for its lane of
+/// `context`.
+fn write_output(
+    output_path: &Path,
+    backend: &dyn OutputBackend,
+    context: &OutputContext,
+    binary: &[InstructionWord],
+    lanes: Option<u8>,
+) -> Result<(), String> {
+    let Some(lanes) = lanes else {
+        return write_file(output_path, backend, binary);
+    };
+    let width = output::lane_width(lanes);
+    for (index, lane_binary) in output::split_lanes(binary, lanes).iter().enumerate() {
+        let context = OutputContext {
+            lane: Some(output::Lane { index, width }),
+            ..context.clone()
+        };
+        let backend = output::backend(backend.name(), &context).expect("backends have a name");
+        write_file(
+            &output::lane_path(output_path, index),
+            backend.as_ref(),
+            lane_binary,
+        )?;
+    }
+    Ok(())
+}
+
+/// `-` as output path selects stdout
+fn is_stdout(output_path: &Path) -> bool {
+    output_path == Path::new("-")
+}
+
+/// Buffered writer of the file at `output_path` or stdout for `-`
+fn open_output(output_path: &Path) -> Result<BufWriter<Box<dyn Write>>, String> {
+    let output: Box<dyn Write> = if is_stdout(output_path) {
+        Box::new(io::stdout().lock())
+    } else {
+        Box::new(File::create(output_path).map_err(|err| {
+            format!(
+                "Could not open output file '{}' for writing: {}",
+                output_path.display(),
+                err
+            )
+        })?)
+    };
+    Ok(BufWriter::new(output))
+}
+
+fn write_text(output_path: &Path, text: &str) -> Result<(), String> {
+    let mut writer = open_output(output_path)?;
+    writer
+        .write_all(text.as_bytes())
+        .and_then(|_| writer.flush())
+        .map_err(|err| {
+            format!(
+                "Could not write to file '{}': {}",
+                output_path.display(),
+                err
+            )
+        })
+}
+
+fn write_file(
+    output_path: &Path,
+    backend: &dyn OutputBackend,
+    binary: &[InstructionWord],
+) -> Result<(), String> {
+    let mut writer = open_output(output_path)?;
+    backend
+        .write(binary, &mut writer)
+        .and_then(|_| writer.flush().map_err(output::OutputError::IoError))
+        .map_err(|err| {
+            format!(
+                "Could not write to file '{}': {}",
+                output_path.display(),
+                err
+            )
+        })
 }
@@ -0,0 +1,1412 @@
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    error::MasmError,
+    generator::{self, InstructionWord, WordSink, LOGISIM_HEADER},
+    ir,
+};
+
+/// Errors a backend can run into while writing an image
+#[derive(Debug)]
+pub enum OutputError {
+    ProgramTooLarge {
+        size: usize,
+        depth: usize,
+    },
+    MemoryExceeded {
+        size: usize,
+        memory_size: usize,
+        label: Option<String>,
+    },
+    IoError(io::Error),
+}
+
+impl std::fmt::Display for OutputError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self {
+            OutputError::IoError(io_error) => write!(f, "IO error '{}'", io_error),
+            OutputError::ProgramTooLarge { size, depth } => write!(
+                f,
+                "Program with {} words does not fit into memory depth {}",
+                size, depth
+            ),
+            OutputError::MemoryExceeded {
+                size,
+                memory_size,
+                label,
+            } => {
+                write!(
+                    f,
+                    "Program with {} words exceeds the memory of {} words by {} words",
+                    size,
+                    memory_size,
+                    size - memory_size
+                )?;
+                match label {
+                    Some(label) => write!(f, ", starting in label '{}'", label),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+impl std::error::Error for OutputError {}
+
+impl OutputError {
+    /// Stable name of the error kind, e.g. for machine readable diagnostics
+    pub fn code(&self) -> &'static str {
+        match self {
+            OutputError::ProgramTooLarge { .. } => "program-too-large",
+            OutputError::MemoryExceeded { .. } => "memory-exceeded",
+            OutputError::IoError(_) => "io-error",
+        }
+    }
+}
+
+/// Information about the assembled program besides its instruction words,
+/// used by backends that embed names, addresses or memory parameters.
+#[derive(Clone)]
+pub struct OutputContext {
+    pub input_path: PathBuf,
+    pub label_definitions: ir::LabelLUT,
+    /// number of words of the code of every label, see [`ir::IR::block_size`]
+    pub block_sizes: HashMap<ir::LabelReference, u16>,
+    pub entry_address: u16,
+    /// Memory depth in words declared in MIF images, defaults to program size
+    pub mif_depth: Option<usize>,
+    /// Word width in bits declared in MIF images
+    pub mif_width: u8,
+    pub hex_format: HexFormat,
+    /// Lane of a split image the backend writes, `None` for whole words
+    pub lane: Option<Lane>,
+}
+
+/// Lane of an image split with [`split_lanes`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lane {
+    /// 0 holds the least significant bits
+    pub index: usize,
+    /// bits of every word, see [`lane_width`]
+    pub width: u32,
+}
+
+/// Layout of the words in Logisim hex images
+#[derive(Debug, Clone, Copy)]
+pub struct HexFormat {
+    pub words_per_line: usize,
+    pub uppercase: bool,
+    /// Number of digits every word is zero padded to
+    pub digits: usize,
+    /// Append the address of the first word to every line as comment
+    pub addresses: bool,
+    /// Start a new line at every label, preceded by a comment with its name
+    pub labels: bool,
+}
+
+impl Default for HexFormat {
+    fn default() -> Self {
+        HexFormat {
+            words_per_line: 8,
+            uppercase: false,
+            digits: 5,
+            addresses: false,
+            labels: false,
+        }
+    }
+}
+
+impl Default for OutputContext {
+    fn default() -> Self {
+        OutputContext {
+            input_path: PathBuf::new(),
+            label_definitions: ir::LabelLUT::new(),
+            block_sizes: HashMap::new(),
+            entry_address: 0,
+            mif_depth: None,
+            mif_width: 20,
+            hex_format: HexFormat::default(),
+            lane: None,
+        }
+    }
+}
+
+impl OutputContext {
+    fn file_name(&self) -> String {
+        self.input_path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned()
+    }
+    /// Input file stem reduced to characters valid in C identifiers
+    fn c_identifier(&self) -> String {
+        self.input_path
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .replace(|c: char| !c.is_ascii_alphanumeric(), "_")
+    }
+    fn sorted_labels(&self) -> Vec<&ir::LabelDefinition> {
+        let mut labels: Vec<&ir::LabelDefinition> = self.label_definitions.0.values().collect();
+        labels.sort_by_key(|label| label.address);
+        labels
+    }
+}
+
+/// Output format an assembled program can be written in
+pub trait OutputBackend {
+    /// Name used to select the backend, e.g. with `--format`
+    fn name(&self) -> &'static str;
+    /// One-line summary shown in the usage
+    fn description(&self) -> &'static str;
+    /// File extension without the leading dot
+    fn extension(&self) -> &'static str;
+    /// File name of the artifact for an input named `stem`
+    fn file_name(&self, stem: &str) -> String {
+        format!("{stem}.{}", self.extension())
+    }
+    fn write(&self, binary: &[InstructionWord], writer: &mut dyn Write) -> Result<(), OutputError>;
+    /// Encoder writing every word to `writer` as soon as it is generated,
+    /// `None` if the format needs the whole image up front, e.g. its size
+    fn stream<'w>(&self, _writer: &'w mut dyn Write) -> Option<Box<dyn WordStream + 'w>> {
+        None
+    }
+}
+
+/// Encoder of a backend receiving the words one at a time, see
+/// [`OutputBackend::stream`]
+pub trait WordStream: WordSink {
+    /// Writes what follows the last word
+    fn finish(self: Box<Self>) -> Result<(), OutputError>;
+}
+
+/// Generates `ir` straight into `writer` in the format of `backend`. Images
+/// of backends with a [`OutputBackend::stream`] are never kept in memory,
+/// the others are generated first and written afterwards.
+pub fn write_program(
+    ir: ir::IR,
+    gap_fill: &InstructionWord,
+    backend: &dyn OutputBackend,
+    writer: &mut dyn Write,
+) -> Result<(), MasmError> {
+    if let Some(mut stream) = backend.stream(writer) {
+        generator::generate_into(ir, gap_fill, stream.as_mut())?;
+        return Ok(stream.finish()?);
+    }
+    let binary = generator::generator_with_gap_fill(ir, gap_fill)?;
+    Ok(backend.write(&binary, writer)?)
+}
+
+/// All available backends in the order they are listed in the usage
+pub fn backends(context: &OutputContext) -> Vec<Box<dyn OutputBackend + '_>> {
+    vec![
+        Box::new(Hex(context)),
+        Box::new(Bin),
+        Box::new(Mif(context)),
+        Box::new(Coe(context)),
+        Box::new(Vhdl(context)),
+        Box::new(CArray(context)),
+        Box::new(RustArray(context)),
+        Box::new(SymbolsHeader(context)),
+        Box::new(Reference(context)),
+        Box::new(Map(context)),
+    ]
+}
+
+/// Looks up the backend called `name`
+pub fn backend<'a>(name: &str, context: &'a OutputContext) -> Option<Box<dyn OutputBackend + 'a>> {
+    backends(context)
+        .into_iter()
+        .find(|backend| backend.name() == name)
+}
+
+/// Picks the backend matching the extension of `path`. If several backends
+/// share an extension the one listed first by [`backends`] wins.
+pub fn detect_backend<'a>(
+    path: &Path,
+    context: &'a OutputContext,
+) -> Option<Box<dyn OutputBackend + 'a>> {
+    let extension = path.extension()?.to_string_lossy().to_lowercase();
+    backends(context)
+        .into_iter()
+        .find(|backend| backend.extension() == extension)
+}
+
+/// Algorithm of the checksum record appended by [`append_checksum`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Checksum {
+    /// Sum of all words modulo 2^16
+    Sum16,
+    /// All words xor-ed together
+    Xor,
+    /// CRC-16/CCITT-FALSE over the three little endian bytes of every word
+    Crc16,
+}
+
+impl std::str::FromStr for Checksum {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "sum16" => Ok(Checksum::Sum16),
+            "xor" => Ok(Checksum::Xor),
+            "crc16" => Ok(Checksum::Crc16),
+            _ => Err(format!("unknown checksum '{name}'")),
+        }
+    }
+}
+
+impl Checksum {
+    fn marker(&self) -> u32 {
+        CHECKSUM_MARKER
+            | match self {
+                Checksum::Sum16 => 0,
+                Checksum::Xor => 1,
+                Checksum::Crc16 => 2,
+            }
+    }
+
+    pub fn compute(&self, binary: &[InstructionWord]) -> u32 {
+        match self {
+            Checksum::Sum16 => {
+                binary
+                    .iter()
+                    .fold(0u32, |sum, instr_word| sum.wrapping_add(instr_word.raw()))
+                    & 0xffff
+            }
+            Checksum::Xor => binary
+                .iter()
+                .fold(0, |xor, instr_word| xor ^ instr_word.raw()),
+            Checksum::Crc16 => {
+                let bytes: Vec<u8> = binary
+                    .iter()
+                    .flat_map(|instr_word| instr_word.raw().to_le_bytes()[..3].to_vec())
+                    .collect();
+                u32::from(crc16(&bytes))
+            }
+        }
+    }
+}
+
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0xffff, |crc, &byte| {
+        (0..8).fold(crc ^ (u16::from(byte) << 8), |crc, _| {
+            if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+/// First word of a checksum record, the algorithm is stored in the lowest
+/// nibble
+pub const CHECKSUM_MARKER: u32 = 0xc5c00;
+
+/// Errors of finding the checksum record of an image
+#[derive(Debug, PartialEq, Eq)]
+pub enum ChecksumError {
+    /// No word of the image is the marker of a checksum record
+    MissingMarker,
+    /// The marker is not followed by the number of words in front of it
+    WordCount { address: usize, words: u32 },
+    /// The marker names no known algorithm
+    UnknownAlgorithm { address: usize, marker: u32 },
+}
+
+impl std::fmt::Display for ChecksumError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChecksumError::MissingMarker => write!(f, "Image has no checksum record"),
+            ChecksumError::WordCount { address, words } => write!(
+                f,
+                "Checksum record at {:#06x} counts {} words instead of {}",
+                address, words, address
+            ),
+            ChecksumError::UnknownAlgorithm { address, marker } => write!(
+                f,
+                "Checksum record at {:#06x} has unknown marker {:#07x}",
+                address, marker
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ChecksumError {}
+
+/// Appends a record of three words directly behind the program: the marker
+/// [`CHECKSUM_MARKER`] with the algorithm, the number of program words and
+/// their checksum.
+pub fn append_checksum(binary: &mut Vec<InstructionWord>, checksum: Checksum) {
+    let words = binary.len() as u32;
+    let marker = checksum.marker();
+    let checksum = checksum.compute(binary);
+    binary.push(InstructionWord::from_raw(marker));
+    binary.push(InstructionWord::from_raw(words));
+    binary.push(InstructionWord::from_raw(checksum));
+}
+
+/// Finds the record of [`append_checksum`] in an image, which may be padded
+/// behind it. The record is the first marker word followed by its own
+/// address. Returns the number of program words in front of the record and
+/// the algorithm of its checksum.
+pub fn find_checksum(binary: &[InstructionWord]) -> Result<(usize, Checksum), ChecksumError> {
+    let is_marker = |raw: u32| raw & !0xf == CHECKSUM_MARKER;
+    let mut markers = (0..binary.len().saturating_sub(2))
+        .filter(|&address| is_marker(binary[address].raw()))
+        .peekable();
+    let first = *markers.peek().ok_or(ChecksumError::MissingMarker)?;
+    let address = markers
+        .find(|&address| binary[address + 1].raw() as usize == address)
+        .ok_or(ChecksumError::WordCount {
+            address: first,
+            words: binary[first + 1].raw(),
+        })?;
+    let marker = binary[address].raw();
+    let checksum = [Checksum::Sum16, Checksum::Xor, Checksum::Crc16]
+        .into_iter()
+        .find(|checksum| checksum.marker() == marker)
+        .ok_or(ChecksumError::UnknownAlgorithm { address, marker })?;
+    Ok((address, checksum))
+}
+
+/// Recomputes the checksum of the record found by [`find_checksum`] after
+/// the first `words` words of the image changed
+pub fn update_checksum(binary: &mut [InstructionWord], words: usize, checksum: Checksum) {
+    binary[words + 2] = InstructionWord::from_raw(checksum.compute(&binary[..words]));
+}
+
+/// Fills the image up to `size` words with `fill`
+pub fn pad(
+    binary: &mut Vec<InstructionWord>,
+    size: usize,
+    fill: &InstructionWord,
+) -> Result<(), OutputError> {
+    if size < binary.len() {
+        return Err(OutputError::ProgramTooLarge {
+            size: binary.len(),
+            depth: size,
+        });
+    }
+    binary.resize(size, fill.clone());
+    Ok(())
+}
+
+/// Checks that the image fits into a memory of `memory_size` words. The
+/// error names the label whose code holds the first word not fitting, which
+/// may start behind a gap of `.org`.
+pub fn check_memory_size(
+    size: usize,
+    memory_size: usize,
+    context: &OutputContext,
+) -> Result<(), OutputError> {
+    if size <= memory_size {
+        return Ok(());
+    }
+    let label = context
+        .sorted_labels()
+        .into_iter()
+        .filter_map(|label| {
+            let start = usize::from(label.address.0);
+            let words = context
+                .block_sizes
+                .get(&label.clone().into())
+                .copied()
+                .unwrap_or_default();
+            let first = start.max(memory_size);
+            (start + usize::from(words) > first).then_some((first, label))
+        })
+        .min_by_key(|(first, _)| *first)
+        .map(|(_, label)| label.name.clone());
+    Err(OutputError::MemoryExceeded {
+        size,
+        memory_size,
+        label,
+    })
+}
+
+/// Bits of every word of a lane if words are split into `lanes` lanes
+pub fn lane_width(lanes: u8) -> u32 {
+    20u32.div_ceil(u32::from(lanes))
+}
+
+/// Splits every word into `lanes` lanes of equal width, starting with the
+/// least significant bits. If 20 bits don't divide evenly the most
+/// significant lane is zero padded.
+pub fn split_lanes(binary: &[InstructionWord], lanes: u8) -> Vec<Vec<InstructionWord>> {
+    let lane_width = lane_width(lanes);
+    let mask = (1 << lane_width) - 1;
+    (0..u32::from(lanes))
+        .map(|lane| {
+            binary
+                .iter()
+                .map(|instr_word| {
+                    InstructionWord::from_raw((instr_word.raw() >> (lane * lane_width)) & mask)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Path of the file storing `lane`, e.g. `rom_lane1.hex` for `rom.hex`
+pub fn lane_path(path: &Path, lane: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let file_name = match path.extension() {
+        Some(extension) => format!("{stem}_lane{lane}.{}", extension.to_string_lossy()),
+        None => format!("{stem}_lane{lane}"),
+    };
+    path.with_file_name(file_name)
+}
+
+fn write_string(writer: &mut dyn Write, contents: String) -> Result<(), OutputError> {
+    writer
+        .write_all(contents.as_bytes())
+        .map_err(OutputError::IoError)
+}
+
+/// Logisim `v3.0 hex words plain` image
+pub struct Hex<'a>(pub &'a OutputContext);
+
+impl OutputBackend for Hex<'_> {
+    fn name(&self) -> &'static str {
+        "hex"
+    }
+    fn description(&self) -> &'static str {
+        "Logisim `v3.0 hex words plain` image"
+    }
+    fn extension(&self) -> &'static str {
+        "hex"
+    }
+    fn write(&self, binary: &[InstructionWord], writer: &mut dyn Write) -> Result<(), OutputError> {
+        write_stream(self, binary, writer)
+    }
+    fn stream<'w>(&self, writer: &'w mut dyn Write) -> Option<Box<dyn WordStream + 'w>> {
+        Some(Box::new(HexWriter::new(writer, self.0)))
+    }
+}
+
+/// Writes `binary` with the [`OutputBackend::stream`] of `backend`
+fn write_stream(
+    backend: &dyn OutputBackend,
+    binary: &[InstructionWord],
+    writer: &mut dyn Write,
+) -> Result<(), OutputError> {
+    let mut stream = backend
+        .stream(writer)
+        .expect("backend writes a stream of words");
+    for word in binary {
+        stream.push(word.clone()).map_err(OutputError::IoError)?;
+    }
+    stream.finish()
+}
+
+/// Encoder of [`Hex`] images, writes every line as soon as it is complete
+pub struct HexWriter<'w> {
+    writer: &'w mut dyn Write,
+    format: HexFormat,
+    /// addresses and names of the labels starting a new line
+    labels: Vec<(usize, String)>,
+    header_written: bool,
+    address: usize,
+    /// address and words of the current line
+    line_address: usize,
+    line: Vec<String>,
+}
+
+impl<'w> HexWriter<'w> {
+    pub fn new(writer: &'w mut dyn Write, context: &OutputContext) -> Self {
+        let labels = match context.hex_format.labels {
+            true => context
+                .sorted_labels()
+                .into_iter()
+                .map(|label| (usize::from(label.address.0), label.name.clone()))
+                .collect(),
+            false => Vec::new(),
+        };
+        HexWriter {
+            writer,
+            format: context.hex_format,
+            labels,
+            header_written: false,
+            address: 0,
+            line_address: 0,
+            line: Vec::with_capacity(context.hex_format.words_per_line),
+        }
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        if !self.header_written {
+            writeln!(self.writer, "{}", LOGISIM_HEADER)?;
+            self.header_written = true;
+        }
+        Ok(())
+    }
+
+    fn write_line(&mut self) -> io::Result<()> {
+        write!(self.writer, "{}", self.line.join(" "))?;
+        if self.format.addresses {
+            write!(self.writer, " # {:#06x}", self.line_address)?;
+        }
+        writeln!(self.writer)?;
+        self.line.clear();
+        Ok(())
+    }
+}
+
+impl WordSink for HexWriter<'_> {
+    fn address(&self) -> usize {
+        self.address
+    }
+    fn push(&mut self, word: InstructionWord) -> io::Result<()> {
+        self.write_header()?;
+        let at_label = self.labels.iter().any(|(a, _)| *a == self.address);
+        if !self.line.is_empty() && (at_label || self.line.len() == self.format.words_per_line) {
+            self.write_line()?;
+        }
+        if self.line.is_empty() {
+            for (_, name) in self.labels.iter().filter(|(a, _)| *a == self.address) {
+                writeln!(self.writer, "# {name}:")?;
+            }
+            self.line_address = self.address;
+        }
+        let digits = self.format.digits;
+        self.line.push(match self.format.uppercase {
+            true => format!("{:0digits$X}", word.raw()),
+            false => format!("{:0digits$x}", word.raw()),
+        });
+        self.address += 1;
+        Ok(())
+    }
+}
+
+impl WordStream for HexWriter<'_> {
+    fn finish(mut self: Box<Self>) -> Result<(), OutputError> {
+        self.write_header().map_err(OutputError::IoError)?;
+        if !self.line.is_empty() {
+            self.write_line().map_err(OutputError::IoError)?;
+        }
+        Ok(())
+    }
+}
+
+/// Raw image with one little endian 32 bit integer per word
+pub struct Bin;
+
+impl OutputBackend for Bin {
+    fn name(&self) -> &'static str {
+        "bin"
+    }
+    fn description(&self) -> &'static str {
+        "Raw image with one little endian 32 bit integer per word"
+    }
+    fn extension(&self) -> &'static str {
+        "bin"
+    }
+    fn write(&self, binary: &[InstructionWord], writer: &mut dyn Write) -> Result<(), OutputError> {
+        write_stream(self, binary, writer)
+    }
+    fn stream<'w>(&self, writer: &'w mut dyn Write) -> Option<Box<dyn WordStream + 'w>> {
+        Some(Box::new(BinWriter { writer, address: 0 }))
+    }
+}
+
+/// Encoder of [`Bin`] images
+pub struct BinWriter<'w> {
+    writer: &'w mut dyn Write,
+    address: usize,
+}
+
+impl WordSink for BinWriter<'_> {
+    fn address(&self) -> usize {
+        self.address
+    }
+    fn push(&mut self, word: InstructionWord) -> io::Result<()> {
+        self.address += 1;
+        self.writer.write_all(&word.raw().to_le_bytes())
+    }
+}
+
+impl WordStream for BinWriter<'_> {
+    fn finish(self: Box<Self>) -> Result<(), OutputError> {
+        Ok(())
+    }
+}
+
+/// Altera/Quartus memory initialization file
+pub struct Mif<'a>(pub &'a OutputContext);
+
+impl OutputBackend for Mif<'_> {
+    fn name(&self) -> &'static str {
+        "mif"
+    }
+    fn description(&self) -> &'static str {
+        "Altera/Quartus memory initialization file"
+    }
+    fn extension(&self) -> &'static str {
+        "mif"
+    }
+    fn write(&self, binary: &[InstructionWord], writer: &mut dyn Write) -> Result<(), OutputError> {
+        let depth = self.0.mif_depth.unwrap_or(binary.len());
+        if depth < binary.len() {
+            return Err(OutputError::ProgramTooLarge {
+                size: binary.len(),
+                depth,
+            });
+        }
+
+        // a lane declares its own width
+        let width = self
+            .0
+            .lane
+            .map_or(u32::from(self.0.mif_width), |lane| lane.width);
+        let mut mif = format!(
+            "-- {} generated by masm\nDEPTH = {};\nWIDTH = {};\nADDRESS_RADIX = HEX;\nDATA_RADIX = HEX;\n\nCONTENT\nBEGIN\n",
+            self.0.file_name(),
+            depth,
+            width
+        );
+        for (address, instr_word) in binary.iter().enumerate() {
+            mif.push_str(&format!("    {:x} : {};\n", address, instr_word));
+        }
+        if depth > binary.len() {
+            mif.push_str(&format!("    [{:x}..{:x}] : 0;\n", binary.len(), depth - 1));
+        }
+        mif.push_str("END;\n");
+
+        write_string(writer, mif)
+    }
+}
+
+/// Xilinx coefficient file for block memory generators
+pub struct Coe<'a>(pub &'a OutputContext);
+
+impl OutputBackend for Coe<'_> {
+    fn name(&self) -> &'static str {
+        "coe"
+    }
+    fn description(&self) -> &'static str {
+        "Xilinx coefficient file for block memory generators"
+    }
+    fn extension(&self) -> &'static str {
+        "coe"
+    }
+    fn write(&self, binary: &[InstructionWord], writer: &mut dyn Write) -> Result<(), OutputError> {
+        let mut coe = String::new();
+        writeln!(coe, "; {} generated by masm", self.0.file_name()).unwrap();
+        writeln!(coe, "memory_initialization_radix=16;").unwrap();
+        writeln!(coe, "memory_initialization_vector=").unwrap();
+        let words: Vec<String> = binary.iter().map(InstructionWord::to_string).collect();
+        writeln!(coe, "{};", words.join(",\n")).unwrap();
+
+        write_string(writer, coe)
+    }
+}
+
+/// Synthesizable VHDL ROM entity
+pub struct Vhdl<'a>(pub &'a OutputContext);
+
+impl OutputBackend for Vhdl<'_> {
+    fn name(&self) -> &'static str {
+        "vhdl"
+    }
+    fn description(&self) -> &'static str {
+        "Synthesizable VHDL ROM entity"
+    }
+    fn extension(&self) -> &'static str {
+        "vhd"
+    }
+    fn write(&self, binary: &[InstructionWord], writer: &mut dyn Write) -> Result<(), OutputError> {
+        let mut entity = format!(
+            "{}_rom",
+            self.0
+                .c_identifier()
+                .to_lowercase()
+                .trim_start_matches(|c: char| c.is_ascii_digit() || c == '_')
+        );
+        // the lanes of a split image are instantiated side by side
+        if let Some(lane) = self.0.lane {
+            entity += &format!("_lane{}", lane.index);
+        }
+        let msb = self.0.lane.map_or(19, |lane| lane.width - 1);
+        // lanes are not as wide as the hex digits of a word
+        let literal = |instr_word: &InstructionWord| match self.0.lane {
+            Some(lane) => format!(
+                "\"{:0width$b}\"",
+                instr_word.raw(),
+                width = lane.width as usize
+            ),
+            None => format!("x\"{instr_word}\""),
+        };
+
+        let mut vhdl = String::new();
+        writeln!(vhdl, "-- {} generated by masm", self.0.file_name()).unwrap();
+        writeln!(vhdl, "library ieee;").unwrap();
+        writeln!(vhdl, "use ieee.std_logic_1164.all;").unwrap();
+        writeln!(vhdl, "use ieee.numeric_std.all;").unwrap();
+        writeln!(vhdl).unwrap();
+        writeln!(vhdl, "entity {entity} is").unwrap();
+        writeln!(vhdl, "    port (").unwrap();
+        writeln!(vhdl, "        address : in  std_logic_vector(15 downto 0);").unwrap();
+        writeln!(
+            vhdl,
+            "        data    : out std_logic_vector({msb} downto 0)"
+        )
+        .unwrap();
+        writeln!(vhdl, "    );").unwrap();
+        writeln!(vhdl, "end entity {entity};").unwrap();
+        writeln!(vhdl).unwrap();
+        writeln!(vhdl, "architecture rtl of {entity} is").unwrap();
+        writeln!(vhdl, "    constant ROM_SIZE : natural := {};", binary.len()).unwrap();
+        writeln!(
+            vhdl,
+            "    type rom_type is array (0 to ROM_SIZE - 1) of std_logic_vector({msb} downto 0);"
+        )
+        .unwrap();
+        writeln!(vhdl, "    constant ROM : rom_type := (").unwrap();
+        for (address, instr_word) in binary.iter().enumerate() {
+            writeln!(vhdl, "        {address} => {},", literal(instr_word)).unwrap();
+        }
+        writeln!(vhdl, "        others => (others => '0')").unwrap();
+        writeln!(vhdl, "    );").unwrap();
+        writeln!(vhdl, "begin").unwrap();
+        writeln!(vhdl, "    data <= ROM(to_integer(unsigned(address)))").unwrap();
+        writeln!(
+            vhdl,
+            "        when to_integer(unsigned(address)) < ROM_SIZE"
+        )
+        .unwrap();
+        writeln!(vhdl, "        else (others => '0');").unwrap();
+        writeln!(vhdl, "end architecture rtl;").unwrap();
+
+        write_string(writer, vhdl)
+    }
+}
+
+/// C header with the program as `uint32_t` array
+pub struct CArray<'a>(pub &'a OutputContext);
+
+impl OutputBackend for CArray<'_> {
+    fn name(&self) -> &'static str {
+        "c"
+    }
+    fn description(&self) -> &'static str {
+        "C header with the program as `uint32_t` array"
+    }
+    fn extension(&self) -> &'static str {
+        "h"
+    }
+    fn write(&self, binary: &[InstructionWord], writer: &mut dyn Write) -> Result<(), OutputError> {
+        let guard = format!("{}_PROGRAM_H", self.0.c_identifier().to_uppercase());
+
+        let mut header = String::new();
+        writeln!(header, "/* {} generated by masm */", self.0.file_name()).unwrap();
+        writeln!(header, "#ifndef {guard}").unwrap();
+        writeln!(header, "#define {guard}").unwrap();
+        writeln!(header).unwrap();
+        writeln!(header, "#include <stdint.h>").unwrap();
+        writeln!(header).unwrap();
+        writeln!(header, "#define PROGRAM_LENGTH {}u", binary.len()).unwrap();
+        writeln!(
+            header,
+            "#define PROGRAM_ENTRY {:#06x}u",
+            self.0.entry_address
+        )
+        .unwrap();
+        writeln!(header).unwrap();
+        writeln!(header, "static const uint32_t program[PROGRAM_LENGTH] = {{").unwrap();
+        for instr_line in binary.chunks(8) {
+            let line: Vec<String> = instr_line
+                .iter()
+                .map(|instr_word| format!("{:#010x}", instr_word.raw()))
+                .collect();
+            writeln!(header, "    {},", line.join(", ")).unwrap();
+        }
+        writeln!(header, "}};").unwrap();
+        writeln!(header).unwrap();
+        writeln!(header, "#endif /* {guard} */").unwrap();
+
+        write_string(writer, header)
+    }
+}
+
+/// Rust source with the program as `u32` array and label constants
+pub struct RustArray<'a>(pub &'a OutputContext);
+
+impl OutputBackend for RustArray<'_> {
+    fn name(&self) -> &'static str {
+        "rs"
+    }
+    fn description(&self) -> &'static str {
+        "Rust source with the program as `u32` array and label constants"
+    }
+    fn extension(&self) -> &'static str {
+        "rs"
+    }
+    fn write(&self, binary: &[InstructionWord], writer: &mut dyn Write) -> Result<(), OutputError> {
+        let mut source = String::new();
+        writeln!(source, "// {} generated by masm", self.0.file_name()).unwrap();
+        writeln!(source).unwrap();
+        writeln!(source, "pub const PROGRAM: [u32; {}] = [", binary.len()).unwrap();
+        for instr_line in binary.chunks(8) {
+            let line: Vec<String> = instr_line
+                .iter()
+                .map(|instr_word| format!("{:#07x}", instr_word.raw()))
+                .collect();
+            writeln!(source, "    {},", line.join(", ")).unwrap();
+        }
+        writeln!(source, "];").unwrap();
+        writeln!(source).unwrap();
+        writeln!(
+            source,
+            "pub const ENTRY: u16 = {:#06x};",
+            self.0.entry_address
+        )
+        .unwrap();
+        writeln!(source).unwrap();
+        writeln!(source, "pub mod symbols {{").unwrap();
+        for label in self.0.sorted_labels() {
+            for line in label.doc.iter().flat_map(|doc| doc.lines()) {
+                writeln!(source, "    /// {line}").unwrap();
+            }
+            writeln!(
+                source,
+                "    pub const {}: u16 = {:#06x};",
+                label.name.to_uppercase().replace('.', "_"),
+                label.address.0
+            )
+            .unwrap();
+        }
+        writeln!(source, "}}").unwrap();
+
+        write_string(writer, source)
+    }
+}
+
+/// C header with a define for every label address
+pub struct SymbolsHeader<'a>(pub &'a OutputContext);
+
+impl OutputBackend for SymbolsHeader<'_> {
+    fn name(&self) -> &'static str {
+        "symbols-h"
+    }
+    fn description(&self) -> &'static str {
+        "C header with a define for every label address"
+    }
+    fn extension(&self) -> &'static str {
+        "h"
+    }
+    fn file_name(&self, stem: &str) -> String {
+        format!("{stem}_symbols.h")
+    }
+    fn write(
+        &self,
+        _binary: &[InstructionWord],
+        writer: &mut dyn Write,
+    ) -> Result<(), OutputError> {
+        let guard = format!("{}_SYMBOLS_H", self.0.c_identifier().to_uppercase());
+        let mut header = format!(
+            "/* Label addresses of {} generated by masm */\n#ifndef {guard}\n#define {guard}\n\n",
+            self.0.file_name()
+        );
+        for label in self.0.sorted_labels() {
+            for line in label.doc.iter().flat_map(|doc| doc.lines()) {
+                let line = format!("/* {} */\n", line.replace("*/", "* /"));
+                header.push_str(&line);
+            }
+            header.push_str(&format!(
+                "#define {} {:#06x}\n",
+                label.name.to_uppercase().replace('.', "_"),
+                label.address.0
+            ));
+        }
+        header.push_str(&format!("\n#endif /* {guard} */\n"));
+
+        write_string(writer, header)
+    }
+}
+
+/// Markdown reference of all documented labels
+pub struct Reference<'a>(pub &'a OutputContext);
+
+impl OutputBackend for Reference<'_> {
+    fn name(&self) -> &'static str {
+        "reference"
+    }
+    fn description(&self) -> &'static str {
+        "Markdown reference of all documented labels"
+    }
+    fn extension(&self) -> &'static str {
+        "md"
+    }
+    fn write(
+        &self,
+        _binary: &[InstructionWord],
+        writer: &mut dyn Write,
+    ) -> Result<(), OutputError> {
+        let mut reference = format!("# {}\n", self.0.file_name());
+        for label in self
+            .0
+            .sorted_labels()
+            .into_iter()
+            .filter(|label| label.doc.is_some())
+        {
+            reference.push_str(&format!(
+                "\n## `{}` ({:#06x})\n\n{}\n",
+                label.name,
+                label.address.0,
+                label.doc.as_deref().unwrap_or_default()
+            ));
+        }
+
+        write_string(writer, reference)
+    }
+}
+
+/// Table of all labels sorted by address with the number of words of their
+/// code, like the map of a linker. The doc comment of a label follows its
+/// row as comment below its name.
+pub struct Map<'a>(pub &'a OutputContext);
+
+impl OutputBackend for Map<'_> {
+    fn name(&self) -> &'static str {
+        "map"
+    }
+    fn description(&self) -> &'static str {
+        "Table of all labels with their address and size"
+    }
+    fn extension(&self) -> &'static str {
+        "map"
+    }
+    fn write(
+        &self,
+        _binary: &[InstructionWord],
+        writer: &mut dyn Write,
+    ) -> Result<(), OutputError> {
+        let mut labels = self.0.sorted_labels();
+        labels.sort_by_key(|label| (label.address, &label.name));
+        let mut map = format!(
+            "; Symbol map of {} generated by masm\n; address  words  label\n",
+            self.0.file_name()
+        );
+        for label in labels {
+            let size = self
+                .0
+                .block_sizes
+                .get(&label.clone().into())
+                .copied()
+                .unwrap_or_default();
+            map.push_str(&format!(
+                "{:#06x}    {:>5}  {}\n",
+                label.address.0, size, label.name
+            ));
+            for line in label.doc.iter().flat_map(|doc| doc.lines()) {
+                let line = format!("{:17}; {}", "", line);
+                map.push_str(line.trim_end());
+                map.push('\n');
+            }
+        }
+
+        write_string(writer, map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{generator, lexer, parser};
+
+    fn assemble(path: &str) -> (Vec<InstructionWord>, OutputContext) {
+        let lexed = lexer::lexer(Path::new(path)).unwrap();
+        let parsed = parser::parser(lexed).unwrap();
+        let context = OutputContext {
+            input_path: PathBuf::from(path),
+            label_definitions: parsed.label_definitions.clone(),
+            block_sizes: parsed
+                .instructions
+                .keys()
+                .map(|label| (label.clone(), parsed.block_size(label)))
+                .collect(),
+            ..OutputContext::default()
+        };
+        (generator::generator(parsed).ok().unwrap(), context)
+    }
+
+    #[test]
+    fn streamed_program() {
+        let gap_fill = InstructionWord::from_raw(0x6c);
+        for path in [
+            "tests/test.s",
+            "tests/long_jump.s",
+            "tests/load_immediate.s",
+            "tests/attributes.s",
+        ] {
+            let parse = || parser::parser(lexer::lexer(Path::new(path)).unwrap()).unwrap();
+            let (binary, context) = assemble(path);
+            assert_eq!(binary.len(), parse().size(), "size of {}", path);
+
+            // hex is streamed, mif is generated first
+            for backend in [&Hex(&context) as &dyn OutputBackend, &Mif(&context)] {
+                let mut expected = Vec::new();
+                backend.write(&binary, &mut expected).unwrap();
+                let mut found = Vec::new();
+                write_program(parse(), &gap_fill, backend, &mut found).unwrap();
+                assert_eq!(
+                    String::from_utf8(expected).unwrap(),
+                    String::from_utf8(found).unwrap()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn hex_backend() {
+        let (binary, context) = assemble("tests/test.s");
+        let mut found = Vec::new();
+
+        backend("hex", &context)
+            .unwrap()
+            .write(&binary, &mut found)
+            .unwrap();
+
+        let expected = std::fs::read("tests/test.hex").unwrap();
+        assert_eq!(expected, found);
+    }
+
+    #[test]
+    fn hex_format() {
+        let context = OutputContext {
+            hex_format: HexFormat {
+                words_per_line: 2,
+                uppercase: true,
+                digits: 8,
+                ..HexFormat::default()
+            },
+            ..OutputContext::default()
+        };
+        let binary = vec![
+            InstructionWord::from_raw(0xfffff),
+            InstructionWord::from_raw(0x12a45),
+            InstructionWord::from_raw(0x7f),
+        ];
+        let mut found = Vec::new();
+
+        Hex(&context).write(&binary, &mut found).unwrap();
+
+        assert_eq!(
+            "v3.0 hex words plain\n000FFFFF 00012A45\n0000007F\n",
+            String::from_utf8(found).unwrap()
+        );
+    }
+
+    #[test]
+    fn annotated_hex() {
+        let (binary, mut context) = assemble("tests/all_instructions.s");
+        context.hex_format.addresses = true;
+        context.hex_format.labels = true;
+        let mut found = Vec::new();
+
+        Hex(&context).write(&binary, &mut found).unwrap();
+
+        let found = String::from_utf8(found).unwrap();
+        let lines: Vec<&str> = found.lines().collect();
+        assert_eq!("# main:", lines[1]);
+        assert!(lines[2].ends_with(" # 0x0000"));
+        assert_eq!("00052 00053 7fe58 80058 # 0x0018", lines[5]);
+        assert_eq!("# jump_0:", lines[6]);
+        assert_eq!(
+            "fff59 0045a 0045b 00168 a2069 0006c 0007f # 0x001c",
+            lines[7]
+        );
+        assert_eq!(binary, crate::loader::load_hex(found.as_bytes()).unwrap());
+    }
+
+    #[test]
+    fn backend_names() {
+        let context = OutputContext::default();
+        let names: Vec<&str> = backends(&context).iter().map(|b| b.name()).collect();
+
+        assert_eq!(
+            vec![
+                "hex",
+                "bin",
+                "mif",
+                "coe",
+                "vhdl",
+                "c",
+                "rs",
+                "symbols-h",
+                "reference",
+                "map"
+            ],
+            names
+        );
+        assert!(backend("srec", &context).is_none());
+        assert_eq!(
+            "program_symbols.h",
+            backend("symbols-h", &context).unwrap().file_name("program")
+        );
+    }
+
+    #[test]
+    fn symbol_map() {
+        let (binary, context) = assemble("tests/attributes.s");
+        let mut found = Vec::new();
+        Map(&context).write(&binary, &mut found).unwrap();
+
+        assert_eq!(
+            "; Symbol map of attributes.s generated by masm
+; address  words  label
+0x0000        3  main
+0x0004        1  aligned
+0x0005        1  done
+                 ; Stops the program
+",
+            String::from_utf8(found).unwrap()
+        );
+
+        let (binary, context) = assemble("tests/docs.s");
+        let mut found = Vec::new();
+        Map(&context).write(&binary, &mut found).unwrap();
+        assert_eq!(
+            "; Symbol map of docs.s generated by masm
+; address  words  label
+0x0000        2  main
+                 ; Entry point of the program
+0x0002        1  add
+                 ; Adds register 0 and register 1
+                 ; and stores the result in register 2
+0x0003        1  done
+",
+            String::from_utf8(found).unwrap()
+        );
+    }
+
+    #[test]
+    fn documented_symbols() {
+        let (binary, context) = assemble("tests/docs.s");
+        let mut found = Vec::new();
+        SymbolsHeader(&context).write(&binary, &mut found).unwrap();
+        let found = String::from_utf8(found).unwrap();
+        assert!(found.contains(
+            "/* Entry point of the program */
+#define MAIN 0x0000
+/* Adds register 0 and register 1 */
+/* and stores the result in register 2 */
+#define ADD 0x0002
+#define DONE 0x0003
+"
+        ));
+
+        let mut found = Vec::new();
+        RustArray(&context).write(&binary, &mut found).unwrap();
+        let found = String::from_utf8(found).unwrap();
+        assert!(found.contains(
+            "    /// Entry point of the program
+    pub const MAIN: u16 = 0x0000;
+    /// Adds register 0 and register 1
+    /// and stores the result in register 2
+    pub const ADD: u16 = 0x0002;
+    pub const DONE: u16 = 0x0003;
+"
+        ));
+    }
+
+    #[test]
+    fn detect_from_extension() {
+        let context = OutputContext::default();
+        let detected = |path: &str| detect_backend(Path::new(path), &context).map(|b| b.name());
+
+        assert_eq!(Some("mif"), detected("build/rom.mif"));
+        assert_eq!(Some("coe"), detected("rom.COE"));
+        assert_eq!(Some("bin"), detected("rom.bin"));
+        assert_eq!(Some("c"), detected("program.h"));
+        assert_eq!(None, detected("rom.txt"));
+        assert_eq!(None, detected("rom"));
+    }
+
+    #[test]
+    fn padding() {
+        let mut binary = vec![InstructionWord::from_raw(0x12345)];
+
+        pad(&mut binary, 3, &InstructionWord::from_raw(0x7f)).unwrap();
+
+        let raw: Vec<u32> = binary.iter().map(InstructionWord::raw).collect();
+        assert_eq!(vec![0x12345, 0x7f, 0x7f], raw);
+        assert!(matches!(
+            pad(&mut binary, 2, &InstructionWord::from_raw(0)),
+            Err(OutputError::ProgramTooLarge { size: 3, depth: 2 })
+        ));
+    }
+
+    #[test]
+    fn memory_size() {
+        let exceeded_label = |size, memory_size, context: &OutputContext| match check_memory_size(
+            size,
+            memory_size,
+            context,
+        ) {
+            Err(OutputError::MemoryExceeded { label, .. }) => label,
+            found => panic!("expected exceeded memory, found {:?}", found),
+        };
+        let mut context = OutputContext::default();
+        for (name, address, words) in [("main", 0, 3), ("table", 3, 3), ("end", 6, 0)] {
+            context.label_definitions.0.insert(
+                ir::LabelReference::new(name),
+                ir::LabelDefinition::new(name, address),
+            );
+            context
+                .block_sizes
+                .insert(ir::LabelReference::new(name), words);
+        }
+
+        assert!(check_memory_size(6, 6, &context).is_ok());
+        assert_eq!(Some(String::from("table")), exceeded_label(6, 4, &context));
+        assert_eq!(Some(String::from("main")), exceeded_label(6, 2, &context));
+        // checksum and padding words belong to no label
+        assert_eq!(None, exceeded_label(8, 6, &context));
+
+        // the first words not fitting follow a gap of `.org`
+        let source = "main:\n    ldc %reg0 0x1\n.org 0x10\nfar:\n    hlt\n";
+        let parsed =
+            parser::parser(lexer::lexer_from_str(source, &Default::default()).unwrap()).unwrap();
+        let context = OutputContext {
+            label_definitions: parsed.label_definitions.clone(),
+            block_sizes: parsed
+                .instructions
+                .keys()
+                .map(|label| (label.clone(), parsed.block_size(label)))
+                .collect(),
+            ..OutputContext::default()
+        };
+        assert_eq!(
+            Some(String::from("far")),
+            exceeded_label(parsed.size(), 8, &context)
+        );
+    }
+
+    #[test]
+    fn lanes() {
+        let binary = vec![
+            InstructionWord::from_raw(0xfffff),
+            InstructionWord::from_raw(0x12345),
+        ];
+
+        let found: Vec<Vec<u32>> = split_lanes(&binary, 3)
+            .iter()
+            .map(|lane| lane.iter().map(InstructionWord::raw).collect())
+            .collect();
+
+        assert_eq!(
+            vec![vec![0x7f, 0x45], vec![0x7f, 0x46], vec![0x3f, 0x04]],
+            found
+        );
+        assert_eq!(
+            PathBuf::from("build/rom_lane2.hex"),
+            lane_path(Path::new("build/rom.hex"), 2)
+        );
+    }
+
+    #[test]
+    fn lane_headers() {
+        let binary = vec![InstructionWord::from_raw(0x0007f)];
+        let context = OutputContext {
+            input_path: PathBuf::from("rom.s"),
+            ..OutputContext::default()
+        };
+        let literals = ["\"0001111111\"", "\"0000000000\""];
+        for (index, lane_binary) in split_lanes(&binary, 2).iter().enumerate() {
+            let context = OutputContext {
+                lane: Some(Lane {
+                    index,
+                    width: lane_width(2),
+                }),
+                ..context.clone()
+            };
+            let mut mif = Vec::new();
+            Mif(&context).write(lane_binary, &mut mif).unwrap();
+            assert!(String::from_utf8(mif).unwrap().contains("\nWIDTH = 10;\n"));
+
+            let mut vhdl = Vec::new();
+            Vhdl(&context).write(lane_binary, &mut vhdl).unwrap();
+            let vhdl = String::from_utf8(vhdl).unwrap();
+            assert!(vhdl.contains(&format!("\nentity rom_rom_lane{index} is\n")));
+            assert!(vhdl.contains("data    : out std_logic_vector(9 downto 0)"));
+            assert!(vhdl.contains("of std_logic_vector(9 downto 0);"));
+            assert!(vhdl.contains(&format!("        0 => {},\n", literals[index])));
+        }
+    }
+
+    #[test]
+    fn checksums() {
+        let binary = vec![
+            InstructionWord::from_raw(0xfffff),
+            InstructionWord::from_raw(0x12345),
+        ];
+
+        assert_eq!(0x2344, Checksum::Sum16.compute(&binary));
+        assert_eq!(0xedcba, Checksum::Xor.compute(&binary));
+        assert_eq!(0x29b1, crc16(b"123456789"));
+
+        let mut found = binary.clone();
+        append_checksum(&mut found, Checksum::Sum16);
+        let raw: Vec<u32> = found.iter().map(InstructionWord::raw).collect();
+        assert_eq!(vec![0xfffff, 0x12345, 0xc5c00, 2, 0x2344], raw);
+        assert_eq!(Ok((2, Checksum::Sum16)), find_checksum(&found));
+
+        // patching a padded image
+        let mut found = binary.clone();
+        append_checksum(&mut found, Checksum::Crc16);
+        pad(&mut found, 8, &InstructionWord::from_raw(0)).unwrap();
+        assert_eq!(Ok((2, Checksum::Crc16)), find_checksum(&found));
+        found[1] = InstructionWord::from_raw(0x7f);
+        let checksum = found[4].raw();
+        update_checksum(&mut found, 2, Checksum::Crc16);
+        assert_ne!(checksum, found[4].raw());
+        assert_eq!(Checksum::Crc16.compute(&found[..2]), found[4].raw());
+
+        // marker words inside the program are skipped
+        let mut found = vec![InstructionWord::from_raw(0xc5c01); 3];
+        append_checksum(&mut found, Checksum::Xor);
+        assert_eq!(Ok((3, Checksum::Xor)), find_checksum(&found));
+
+        assert_eq!(Err(ChecksumError::MissingMarker), find_checksum(&binary));
+        let broken = [0x1, 0xc5c00, 0x7, 0x0].map(InstructionWord::from_raw);
+        assert_eq!(
+            Err(ChecksumError::WordCount {
+                address: 1,
+                words: 7
+            }),
+            find_checksum(&broken)
+        );
+        let unknown = [0x1, 0xc5c0f, 0x1, 0x0].map(InstructionWord::from_raw);
+        assert_eq!(
+            Err(ChecksumError::UnknownAlgorithm {
+                address: 1,
+                marker: 0xc5c0f
+            }),
+            find_checksum(&unknown)
+        );
+    }
+
+    #[test]
+    fn mif_depth_too_small() {
+        let (binary, mut context) = assemble("tests/test.s");
+        context.mif_depth = Some(1);
+
+        let found = backend("mif", &context)
+            .unwrap()
+            .write(&binary, &mut Vec::new());
+
+        assert!(matches!(
+            found,
+            Err(OutputError::ProgramTooLarge { depth: 1, .. })
+        ));
+    }
+}
@@ -1,8 +1,14 @@
 use std::collections::HashMap;
+use std::iter::Peekable;
+use std::path::Path;
 use std::slice::Iter;
 
+use crate::debug_info::{DebugInfo, Span};
+use crate::diagnostic::{self, display_line};
 use crate::ir;
-use crate::lexer::{Keyword, LineNumber};
+use crate::lexer::{is_numeric_label, Keyword, LineNumber, INCLUDE_END};
+
+type Keywords<'a> = Peekable<Iter<'a, Keyword>>;
 
 pub enum ParserError {
     EndOfStream,
@@ -10,6 +16,9 @@ pub enum ParserError {
     UnknownCommand {
         command: String,
         line_number: u16,
+        column: u16,
+        /// known mnemonics closest to `command`
+        suggestions: Vec<String>,
     },
     MissingArgument {
         command: String,
@@ -21,11 +30,110 @@ pub enum ParserError {
         arg_name: String,
         arg_value: String,
         line_number: u16,
+        column: u16,
+    },
+    UnexpectedArgument {
+        command: String,
+        arg_value: String,
+        line_number: u16,
+        column: u16,
     },
     ExpectedFound {
         expected: String,
         found: String,
         line_number: u16,
+        column: u16,
+    },
+    InvalidRegister {
+        register: String,
+        line_number: u16,
+        column: u16,
+        /// register names and aliases closest to `register`
+        suggestions: Vec<String>,
+    },
+    InvalidAttribute {
+        attribute: String,
+        line_number: u16,
+        column: u16,
+    },
+    UnknownDirective {
+        directive: String,
+        line_number: u16,
+        column: u16,
+    },
+    ReservedRegister {
+        register: String,
+        reason: String,
+        line_number: u16,
+        column: u16,
+    },
+    InvalidOrigin {
+        origin: u16,
+        location: u16,
+        line_number: u16,
+    },
+    Requires32BitMode {
+        command: String,
+        line_number: u16,
+        column: u16,
+    },
+    NoHardwareSupport {
+        command: String,
+        line_number: u16,
+        column: u16,
+    },
+    ClobberedRegister {
+        command: String,
+        register: String,
+        line_number: u16,
+        column: u16,
+    },
+    ShadowedRegister {
+        alias: String,
+        line_number: u16,
+        column: u16,
+    },
+    LocalLabelWithoutScope {
+        label: String,
+        line_number: u16,
+        column: u16,
+    },
+    DuplicateAlias {
+        alias: String,
+        line_number: u16,
+        previous: u16,
+    },
+    UndefinedNumericLabel {
+        label: String,
+        line_number: u16,
+        column: u16,
+    },
+    DuplicateEntry {
+        line_number: u16,
+        previous: u16,
+    },
+    UndefinedEntry {
+        label: String,
+        line_number: Option<u16>,
+    },
+    UndefinedLabels {
+        labels: Vec<(String, u16)>,
+    },
+    ConstantOutOfRange {
+        command: String,
+        value: u32,
+        line_number: u16,
+        column: u16,
+    },
+    LabelOutOfRange {
+        label: String,
+        line_number: u16,
+        column: u16,
+    },
+    LocationOutOfRange {
+        command: String,
+        line_number: u16,
+        column: u16,
     },
 }
 
@@ -37,7 +145,12 @@ impl std::fmt::Display for ParserError {
             ParserError::UnknownCommand {
                 command,
                 line_number,
-            } => write!(f, "Unknown command: '{}' at line {}", command, line_number),
+                column,
+                suggestions,
+            } => {
+                write!(f, "Unknown command: '{}' at line {}, column {}", command, display_line(*line_number), column)?;
+                write_suggestions(f, suggestions)
+            }
 
             ParserError::MissingArgument {
                 command,
@@ -46,26 +159,208 @@ impl std::fmt::Display for ParserError {
             } => write!(
                 f,
                 "Missing argument '{}' in command '{}' at line {}",
-                arg_name, command, line_number
+                arg_name, command, display_line(*line_number)
             ),
             ParserError::CouldNotParseArgument {
                 command,
                 arg_name,
                 arg_value,
                 line_number,
+                column,
+            } => write!(
+                f,
+                "Invalid value '{}' for argument '{}' in command '{}' at line {}, column {}",
+                arg_value, arg_name, command, display_line(*line_number), column
+            ),
+            ParserError::UnexpectedArgument {
+                command,
+                arg_value,
+                line_number,
+                column,
             } => write!(
                 f,
-                "Invalid value '{}' for argument '{}' in command '{}' at line {}",
-                arg_value, arg_name, command, line_number
+                "Unexpected argument '{}' for command '{}' at line {}, column {}",
+                arg_value, command, display_line(*line_number), column
             ),
+            ParserError::InvalidRegister {
+                register,
+                line_number,
+                column,
+                suggestions,
+            } => {
+                write!(
+                    f,
+                    "Invalid register '%{}' at line {}, column {}, registers are %reg0..%reg7 or %regA..%regH",
+                    register, display_line(*line_number), column
+                )?;
+                let suggestions: Vec<String> =
+                    suggestions.iter().map(|name| format!("%{}", name)).collect();
+                write_suggestions(f, &suggestions)
+            }
             ParserError::ExpectedFound {
                 expected,
                 found,
                 line_number,
+                column,
+            } => write!(
+                f,
+                "Expected '{}' found '{}' at line {}, column {}",
+                expected, found, display_line(*line_number), column
+            ),
+            ParserError::InvalidAttribute {
+                attribute,
+                line_number,
+                column,
+            } => write!(
+                f,
+                "Invalid label attribute '{}' at line {}, column {}",
+                attribute, display_line(*line_number), column
+            ),
+            ParserError::UnknownDirective {
+                directive,
+                line_number,
+                column,
+            } => write!(
+                f,
+                "Unknown directive: '{}' at line {}, column {}",
+                directive, display_line(*line_number), column
+            ),
+            ParserError::ReservedRegister {
+                register,
+                reason,
+                line_number,
+                column,
+            } if reason.is_empty() => write!(
+                f,
+                "Use of reserved register '{}' at line {}, column {}",
+                register, display_line(*line_number), column
+            ),
+            ParserError::ReservedRegister {
+                register,
+                reason,
+                line_number,
+                column,
+            } => write!(
+                f,
+                "Use of register '{}' reserved as '{}' at line {}, column {}",
+                register, reason, display_line(*line_number), column
+            ),
+            ParserError::InvalidOrigin {
+                origin,
+                location,
+                line_number,
+            } => write!(
+                f,
+                "Origin {:#06x} at line {} lies before the current location {:#06x}",
+                origin, display_line(*line_number), location
+            ),
+            ParserError::Requires32BitMode {
+                command,
+                line_number,
+                column,
+            } => write!(
+                f,
+                "Command '{}' at line {}, column {} requires 32 bit mode, enable it with 's32b true' first",
+                command, display_line(*line_number), column
+            ),
+            ParserError::NoHardwareSupport {
+                command,
+                line_number,
+                column,
+            } => write!(
+                f,
+                "Command '{}' at line {}, column {} has no hardware support, enable the software expansion with '--soft-div'",
+                command, display_line(*line_number), column
+            ),
+            ParserError::ClobberedRegister {
+                command,
+                register,
+                line_number,
+                column,
+            } => write!(
+                f,
+                "Register '{}' is overwritten before command '{}' at line {}, column {} is done reading it",
+                register, command, display_line(*line_number), column
+            ),
+            ParserError::LocalLabelWithoutScope { label, line_number, column } => write!(
+                f,
+                "Local label '{}' at line {}, column {} has no global label in front of it",
+                label, display_line(*line_number), column
+            ),
+            ParserError::ShadowedRegister { alias, line_number, column } => write!(
+                f,
+                "Alias '{}' at line {}, column {} shadows the register of the same name",
+                alias, display_line(*line_number), column
+            ),
+            ParserError::DuplicateAlias {
+                alias,
+                line_number,
+                previous,
+            } => write!(
+                f,
+                "Alias '{}' at line {} is already declared at line {}",
+                alias, display_line(*line_number), display_line(*previous)
+            ),
+            ParserError::UndefinedNumericLabel { label, line_number, column } => write!(
+                f,
+                "Numeric label '{}' referenced at line {}, column {} is not defined in its scope",
+                label, display_line(*line_number), column
+            ),
+            ParserError::DuplicateEntry {
+                line_number,
+                previous,
+            } => write!(
+                f,
+                "Entry point declared at line {} is already declared at line {}",
+                display_line(*line_number), display_line(*previous)
+            ),
+            ParserError::UndefinedEntry {
+                label,
+                line_number: Some(line_number),
+            } => write!(
+                f,
+                "Entry label '{}' declared at line {} is not defined",
+                label, display_line(*line_number)
+            ),
+            ParserError::UndefinedEntry {
+                label,
+                line_number: None,
+            } => write!(f, "Entry label '{}' is not defined", label),
+            ParserError::UndefinedLabels { labels } => {
+                write!(f, "Undefined labels:")?;
+                for (label, line_number) in labels {
+                    write!(f, "\n    '{}' referenced at line {}", label, display_line(*line_number))?;
+                }
+                Ok(())
+            }
+            ParserError::ConstantOutOfRange {
+                command,
+                value,
+                line_number,
+                column,
+            } => write!(
+                f,
+                "Constant {:#x} of '{}' at line {}, column {} does not fit into 16 bits, \
+                 use 'li' for 32 bit values",
+                value, command, display_line(*line_number), column
+            ),
+            ParserError::LabelOutOfRange {
+                label,
+                line_number,
+                column,
+            } => write!(
+                f,
+                "Label address out of range: '{}' at line {}, column {} lies beyond address 0xffff",
+                label, display_line(*line_number), column
+            ),
+            ParserError::LocationOutOfRange {
+                command,
+                line_number,
+                column,
             } => write!(
                 f,
-                "Expected '{}' found '{}' at line {}",
-                expected, found, line_number
+                "Words of '{}' at line {}, column {} extend beyond address 0xffff",
+                command, display_line(*line_number), column
             ),
         }
     }
@@ -77,740 +372,2527 @@ impl std::fmt::Debug for ParserError {
     }
 }
 
-impl std::error::Error for ParserError {}
-
-pub fn parser(keywords: Vec<Keyword>) -> Result<ir::IR, ParserError> {
-    let mut known_labels = ir::LabelLUT::with_capacity(10);
-    let mut parsed: HashMap<ir::LabelReference, Vec<ir::Instruction>> = HashMap::with_capacity(10);
-    let mut iter = keywords.iter();
-    let default_label = ir::LabelDefinition::new("main", 0);
-
-    let start_label: ir::LabelDefinition;
-    let mut instructions_since_label = 0;
-
-    if let Some(first_keyword) = iter.next() {
-        if let Ok(parsed_start_label) = try_parse_label_definition(first_keyword, 0, 0) {
-            start_label = parsed_start_label;
-        } else {
-            start_label = default_label;
-            match try_parse_instruction(first_keyword, &mut iter) {
-                Ok(instruction) => {
-                    if let Some(vec) = parsed.get_mut(&start_label.clone().into()) {
-                        vec.push(instruction);
-                    } else {
-                        parsed.insert(start_label.clone().into(), vec![instruction]);
-                    }
-                    instructions_since_label += 1;
-                }
-                Err(ParserError::EndOfStream) => {
-                    return Err(ParserError::EmptyStream);
-                }
-                Err(parser_error) => return Err(parser_error),
-            }
-        }
-    } else {
-        return Err(ParserError::EmptyStream);
-    }
-
-    known_labels
-        .0
-        .insert(start_label.clone().into(), start_label.clone());
-    let mut last_label: ir::LabelDefinition = start_label.clone();
-
-    loop {
-        if let Some(next_keyword) = iter.next() {
-            if let Ok(label) = try_parse_label_definition(
-                next_keyword,
-                last_label.address.0,
-                instructions_since_label,
-            ) {
-                parsed.insert(label.clone().into(), Vec::new());
-                known_labels.0.insert(label.clone().into(), label.clone());
-                last_label = label;
-                instructions_since_label = 0;
-            } else {
-                match try_parse_instruction(next_keyword, &mut iter) {
-                    Ok(instruction) => {
-                        if let Some(vec) = parsed.get_mut(&last_label.clone().into()) {
-                            vec.push(instruction);
-                        } else {
-                            parsed.insert(last_label.clone().into(), vec![instruction]);
-                        }
-                        instructions_since_label += 1;
-                    }
-                    Err(ParserError::EndOfStream) => {
-                        return Ok(ir::IR {
-                            start_label: start_label.into(),
-                            label_definitions: known_labels,
-                            instructions: parsed,
-                        })
-                    }
-                    Err(parser_error) => return Err(parser_error),
-                }
-            }
-        } else {
-            return Ok(ir::IR {
-                start_label: start_label.into(),
-                label_definitions: known_labels,
-                instructions: parsed,
-            });
+/// `, did you mean 'a', 'b' or 'c'?` unless there are no `suggestions`
+fn write_suggestions(f: &mut std::fmt::Formatter<'_>, suggestions: &[String]) -> std::fmt::Result {
+    if let Some((last, first)) = suggestions.split_last() {
+        write!(f, ", did you mean ")?;
+        for (i, suggestion) in first.iter().enumerate() {
+            let separator = if i + 1 == first.len() { " or " } else { ", " };
+            write!(f, "'{}'{}", suggestion, separator)?;
         }
+        write!(f, "'{}'?", last)?;
     }
+    Ok(())
 }
 
-fn try_parse_instruction(
-    next_keyword: &Keyword,
-    keywords: &mut Iter<Keyword>,
-) -> Result<ir::Instruction, ParserError> {
-    match next_keyword {
-        Keyword::Mmenonic { name, line_number } => match name.as_str() {
-            "ldc" => try_parse_ldc(keywords, *line_number),
-            "add" => Ok(ir::Instruction::Add(try_parse_binary_expression(
-                "add",
-                keywords,
-                *line_number,
-            )?)),
-            "add3" => Ok(ir::Instruction::Add3(try_parse_ternary_expression(
-                "add3",
-                keywords,
-                *line_number,
-            )?)),
-            "addc" => Ok(ir::Instruction::AddWithCarry(try_parse_binary_expression(
-                "addc",
-                keywords,
-                *line_number,
-            )?)),
-            "sub" => Ok(ir::Instruction::Subtract(try_parse_binary_expression(
-                "sub",
-                keywords,
-                *line_number,
-            )?)),
-            "subc" => Ok(ir::Instruction::SubtractWithCarry(
-                try_parse_binary_expression("subc", keywords, *line_number)?,
-            )),
-            "inc" => {
-                let unary_statement = try_parse_unary_statement("inc", keywords, *line_number)?;
-                Ok(ir::Instruction::Increment(ir::UnaryExpression::new(
-                    unary_statement.source_a,
-                    unary_statement.source_a,
-                )))
+impl std::error::Error for ParserError {}
+
+impl ParserError {
+    /// Position of the token the error points at, errors concerning more
+    /// than one line or the whole program have none
+    pub fn position(&self) -> Option<diagnostic::Position> {
+        match *self {
+            ParserError::UnknownCommand {
+                line_number,
+                column,
+                ..
             }
-            "dec" => {
-                let unary_statement = try_parse_unary_statement("dec", keywords, *line_number)?;
-                Ok(ir::Instruction::Decrement(ir::UnaryExpression::new(
-                    unary_statement.source_a,
-                    unary_statement.source_a,
-                )))
+            | ParserError::CouldNotParseArgument {
+                line_number,
+                column,
+                ..
             }
-            "mul" => Ok(ir::Instruction::Multiply(try_parse_binary_expression(
-                "mul",
-                keywords,
-                *line_number,
-            )?)),
-            "and" => Ok(ir::Instruction::AND(try_parse_binary_expression(
-                "and",
-                keywords,
-                *line_number,
-            )?)),
-            "or" => Ok(ir::Instruction::OR(try_parse_binary_expression(
-                "or",
-                keywords,
-                *line_number,
-            )?)),
-            "not" => Ok(ir::Instruction::NOT(try_parse_unary_expression(
-                "not",
-                keywords,
-                *line_number,
-            )?)),
-            "neg" => Ok(ir::Instruction::Negate(try_parse_unary_expression(
-                "neg",
-                keywords,
-                *line_number,
-            )?)),
-            "xor" => Ok(ir::Instruction::XOR(try_parse_binary_expression(
-                "xor",
-                keywords,
-                *line_number,
-            )?)),
-            "xnor" => Ok(ir::Instruction::XNOR(try_parse_binary_expression(
-                "xnor",
-                keywords,
-                *line_number,
-            )?)),
-            "shl" => Ok(ir::Instruction::ShiftLeft(try_parse_binary_expression(
-                "shl",
-                keywords,
-                *line_number,
-            )?)),
-            "shr" => Ok(ir::Instruction::ShiftRight(try_parse_binary_expression(
-                "shr",
-                keywords,
-                *line_number,
-            )?)),
-            "tst" => Ok(ir::Instruction::Test(try_parse_binary_statement(
-                "tst",
-                keywords,
-                *line_number,
-            )?)),
-            "mov" => Ok(ir::Instruction::Move(try_parse_unary_expression(
-                "mov",
-                keywords,
-                *line_number,
-            )?)),
-            "s32b" => {
-                if let Some(maybe_bool) = keywords.next() {
-                    if let Ok(boolean) = try_parse_bool(maybe_bool) {
-                        Ok(ir::Instruction::Set32BitMode { enable: boolean })
-                    } else {
-                        Err(ParserError::CouldNotParseArgument {
-                            command: String::from("s32b"),
-                            arg_name: String::from("EnableBoolean"),
-                            arg_value: maybe_bool.get_original_string(),
-                            line_number: *line_number,
-                        })
-                    }
-                } else {
-                    Err(ParserError::MissingArgument {
-                        command: String::from("s32b"),
-                        arg_name: String::from("EnableBoolean"),
-                        line_number: *line_number,
-                    })
-                }
+            | ParserError::UnexpectedArgument {
+                line_number,
+                column,
+                ..
             }
-            "hlt" => Ok(ir::Instruction::Halt),
-            "dbg" => Ok(ir::Instruction::Debug),
-            "jmp" => try_parse_jmp(
-                next_keyword,
-                keywords,
-                *line_number,
-                ir::JumpCondition::True,
-            ),
-            "jz" => try_parse_jmp(
-                next_keyword,
-                keywords,
-                *line_number,
-                ir::JumpCondition::Zero,
-            ),
-            "jnz" => try_parse_jmp(
-                next_keyword,
-                keywords,
-                *line_number,
-                ir::JumpCondition::NotZero,
-            ),
-            "jc" => try_parse_jmp(
-                next_keyword,
-                keywords,
-                *line_number,
-                ir::JumpCondition::Less,
-            ),
-            "jo" => try_parse_jmp(
-                next_keyword,
-                keywords,
-                *line_number,
-                ir::JumpCondition::Overflow,
-            ),
-            "jrcon" => try_parse_jr(
-                next_keyword,
-                keywords,
-                *line_number,
-                ir::JumpCondition::True,
-            ),
-            "jr" => try_parse_jr(
-                next_keyword,
-                keywords,
-                *line_number,
-                ir::JumpCondition::True,
-            ),
-            "jzr" => try_parse_jr(
-                next_keyword,
-                keywords,
-                *line_number,
-                ir::JumpCondition::Zero,
-            ),
-            "jnzr" => try_parse_jr(
-                next_keyword,
-                keywords,
-                *line_number,
-                ir::JumpCondition::NotZero,
-            ),
-            "jcr" => try_parse_jr(
-                next_keyword,
-                keywords,
-                *line_number,
-                ir::JumpCondition::Less,
-            ),
-            "jor" => try_parse_jr(
-                next_keyword,
-                keywords,
-                *line_number,
-                ir::JumpCondition::Overflow,
-            ),
-            "st" => {
-                let u_expr = try_parse_unary_expression("st", keywords, *line_number)?;
-                Ok(ir::Instruction::StoreRAM {
-                    address_register: u_expr.target.address,
-                    data_register: u_expr.source_a.address,
-                })
+            | ParserError::ExpectedFound {
+                line_number,
+                column,
+                ..
             }
-            "ld" => {
-                let u_expr = try_parse_unary_expression("ld", keywords, *line_number)?;
-                Ok(ir::Instruction::Load {
-                    address: u_expr.target.address,
-                    source: ir::LoadSource::RAM {
-                        address_register: u_expr.source_a,
-                    },
-                })
+            | ParserError::InvalidRegister {
+                line_number,
+                column,
+                ..
             }
-            "nop" => Ok(ir::Instruction::Noop),
-            unknown => Err(ParserError::UnknownCommand {
-                command: unknown.to_string(),
-                line_number: *line_number,
-            }),
-        },
-        Keyword::Constant {
-            value,
-            line_number,
-            origin: _,
-        } => Err(ParserError::UnknownCommand {
-            command: format!("{}", value),
-            line_number: *line_number,
-        }),
-        Keyword::Boolean {
-            value,
-            line_number,
-            origin: _,
-        } => Err(ParserError::UnknownCommand {
-            command: format!("{}", value),
-            line_number: *line_number,
-        }),
-        Keyword::Label { name, line_number } => Err(ParserError::UnknownCommand {
-            command: name.to_string(),
-            line_number: *line_number,
-        }),
-        Keyword::RegisterAddress { name, line_number } => Err(ParserError::UnknownCommand {
-            command: name.to_string(),
-            line_number: *line_number,
-        }),
+            | ParserError::InvalidAttribute {
+                line_number,
+                column,
+                ..
+            }
+            | ParserError::UnknownDirective {
+                line_number,
+                column,
+                ..
+            }
+            | ParserError::ReservedRegister {
+                line_number,
+                column,
+                ..
+            }
+            | ParserError::Requires32BitMode {
+                line_number,
+                column,
+                ..
+            }
+            | ParserError::NoHardwareSupport {
+                line_number,
+                column,
+                ..
+            }
+            | ParserError::ClobberedRegister {
+                line_number,
+                column,
+                ..
+            }
+            | ParserError::ShadowedRegister {
+                line_number,
+                column,
+                ..
+            }
+            | ParserError::LocalLabelWithoutScope {
+                line_number,
+                column,
+                ..
+            }
+            | ParserError::UndefinedNumericLabel {
+                line_number,
+                column,
+                ..
+            }
+            | ParserError::ConstantOutOfRange {
+                line_number,
+                column,
+                ..
+            }
+            | ParserError::LabelOutOfRange {
+                line_number,
+                column,
+                ..
+            }
+            | ParserError::LocationOutOfRange {
+                line_number,
+                column,
+                ..
+            } => Some((line_number, Some(column))),
+            ParserError::MissingArgument { line_number, .. }
+            | ParserError::InvalidOrigin { line_number, .. }
+            | ParserError::DuplicateAlias { line_number, .. }
+            | ParserError::DuplicateEntry { line_number, .. }
+            | ParserError::UndefinedEntry {
+                line_number: Some(line_number),
+                ..
+            } => Some((line_number, None)),
+            ParserError::EndOfStream
+            | ParserError::EmptyStream
+            | ParserError::UndefinedEntry {
+                line_number: None, ..
+            }
+            | ParserError::UndefinedLabels { .. } => None,
+        }
     }
-}
 
-/// **ldc** `$TargetRegister` `Constant16`
-fn try_parse_ldc(
-    keywords: &mut Iter<Keyword>,
-    line_number: u16,
-) -> Result<ir::Instruction, ParserError> {
-    if let Some(maybe_target_register) = keywords.next() {
-        let target_register = try_parse_register(maybe_target_register)?;
-        if let Some(maybe_constant) = keywords.next() {
-            let constant = try_parse_constant(maybe_constant)?;
-            Ok(ir::Instruction::Load {
-                address: target_register,
-                source: ir::LoadSource::Constant(constant.0),
-            })
-        } else {
-            Err(ParserError::MissingArgument {
-                command: String::from("ldc"),
-                arg_name: String::from("Constant16"),
-                line_number,
-            })
+    /// Stable name of the error kind, e.g. for machine readable diagnostics
+    pub fn code(&self) -> &'static str {
+        match self {
+            ParserError::EndOfStream => "end-of-stream",
+            ParserError::EmptyStream => "empty-stream",
+            ParserError::UnknownCommand { .. } => "unknown-command",
+            ParserError::MissingArgument { .. } => "missing-argument",
+            ParserError::CouldNotParseArgument { .. } => "could-not-parse-argument",
+            ParserError::UnexpectedArgument { .. } => "unexpected-argument",
+            ParserError::ExpectedFound { .. } => "expected-found",
+            ParserError::InvalidRegister { .. } => "invalid-register",
+            ParserError::InvalidAttribute { .. } => "invalid-attribute",
+            ParserError::UnknownDirective { .. } => "unknown-directive",
+            ParserError::ReservedRegister { .. } => "reserved-register",
+            ParserError::InvalidOrigin { .. } => "invalid-origin",
+            ParserError::Requires32BitMode { .. } => "requires-32-bit-mode",
+            ParserError::NoHardwareSupport { .. } => "no-hardware-support",
+            ParserError::ClobberedRegister { .. } => "clobbered-register",
+            ParserError::ShadowedRegister { .. } => "shadowed-register",
+            ParserError::LocalLabelWithoutScope { .. } => "local-label-without-scope",
+            ParserError::DuplicateAlias { .. } => "duplicate-alias",
+            ParserError::UndefinedNumericLabel { .. } => "undefined-numeric-label",
+            ParserError::DuplicateEntry { .. } => "duplicate-entry",
+            ParserError::UndefinedEntry { .. } => "undefined-entry",
+            ParserError::UndefinedLabels { .. } => "undefined-labels",
+            ParserError::ConstantOutOfRange { .. } => "constant-out-of-range",
+            ParserError::LabelOutOfRange { .. } => "label-out-of-range",
+            ParserError::LocationOutOfRange { .. } => "location-out-of-range",
         }
-    } else {
-        Err(ParserError::MissingArgument {
-            command: String::from("ldc"),
-            arg_name: String::from("TargetRegister"),
-            line_number,
-        })
     }
 }
 
-/// **instruction** `$TargetRegister` `$SourceRegister`
-fn try_parse_unary_expression(
-    instruction: &str,
-    keywords: &mut Iter<Keyword>,
-    line_number: u16,
-) -> Result<ir::UnaryExpression, ParserError> {
-    if let Some(maybe_target_register) = keywords.next() {
-        let target = ir::Register::new(try_parse_register(maybe_target_register)?);
-        if let Some(maybe_source_register) = keywords.next() {
-            let source = ir::Register::new(try_parse_register(maybe_source_register)?);
-            Ok(ir::UnaryExpression::new(target, source))
-        } else {
-            Err(ParserError::MissingArgument {
-                command: String::from(instruction),
-                arg_name: String::from("SourceRegister"),
-                line_number,
-            })
+/// Settings of the parser which are not part of the source
+#[derive(Debug, Clone, Default)]
+pub struct ParserOptions {
+    /// Pack two characters of `.ascii`/`.asciiz` strings into every data
+    /// word, with the first character in the upper byte. By default every
+    /// character gets its own word.
+    pub pack_strings: bool,
+    /// Expand `div` and `mod`, which the hardware does not implement, to
+    /// loops of repeated subtraction. Without it they are an error.
+    pub soft_division: bool,
+    /// Register names like `.alias` ones, replacing the defaults of
+    /// [`CONVENTIONAL_REGISTERS`] with the same name
+    pub register_aliases: HashMap<String, ir::RegisterAddress>,
+    /// Accept mnemonics, directives and register names in any case, like
+    /// `LDC %REG0 0x10`
+    pub case_insensitive: bool,
+    /// Label the execution starts at, replacing the one of `.start`
+    pub entry: Option<String>,
+}
+
+/// Names of the calling convention registers, available in every program
+/// unless redefined with `.alias` or [`ParserOptions::register_aliases`]
+pub const CONVENTIONAL_REGISTERS: &[(&str, ir::RegisterAddress)] = &[
+    ("sp", ir::RegisterAddress(7)),
+    ("lr", ir::RegisterAddress(5)),
+    ("zero", ir::RegisterAddress(0)),
+];
+
+/// Register used by pseudo-instructions like `ljmp` unless `.scratch`
+/// selects another one
+pub const DEFAULT_SCRATCH_REGISTER: ir::RegisterAddress = ir::RegisterAddress(6);
+
+/// State set up by directives which affects the following keywords
+struct Directives {
+    /// registers reserved with `.reserve` and the reason for it
+    reserved_registers: HashMap<ir::RegisterAddress, String>,
+    /// register names declared with `.alias`, collected before parsing
+    register_aliases: HashMap<String, ir::RegisterAddress>,
+    /// address and line of a `.org` waiting for the next label definition
+    origin: Option<(u16, u16)>,
+    /// symbols defined with `.equ` whose value depends on addresses
+    symbols: HashMap<String, ir::Expression>,
+    /// address of the next instruction, the value of `$`
+    location: u16,
+    /// register selected with `.scratch`
+    scratch_register: ir::RegisterAddress,
+    /// 32 bit mode enabled by the last `s32b` in front of the keyword
+    wide_mode: bool,
+    /// label and line of the `.start` directive
+    entry: Option<(String, u16)>,
+    options: ParserOptions,
+}
+
+impl Default for Directives {
+    fn default() -> Self {
+        Directives {
+            reserved_registers: HashMap::new(),
+            register_aliases: HashMap::new(),
+            origin: None,
+            symbols: HashMap::new(),
+            location: 0,
+            scratch_register: DEFAULT_SCRATCH_REGISTER,
+            wide_mode: false,
+            entry: None,
+            options: ParserOptions::default(),
         }
-    } else {
-        Err(ParserError::MissingArgument {
-            command: String::from(instruction),
-            arg_name: String::from("TargetRegister"),
-            line_number,
-        })
     }
 }
 
-/// **instruction** $SourceRegister`
-fn try_parse_unary_statement(
-    instruction: &str,
-    keywords: &mut Iter<Keyword>,
-    line_number: u16,
-) -> Result<ir::UnaryStatement, ParserError> {
-    if let Some(maybe_source_register) = keywords.next() {
-        let source = ir::Register::new(try_parse_register(maybe_source_register)?);
-        Ok(ir::UnaryStatement::new(source))
-    } else {
-        Err(ParserError::MissingArgument {
-            command: String::from(instruction),
-            arg_name: String::from("SourceRegister"),
-            line_number,
-        })
-    }
+/// Parser state carried from one statement to the next
+struct Program<'a> {
+    known_labels: ir::LabelLUT,
+    parsed: HashMap<ir::LabelReference, Vec<ir::Instruction>>,
+    /// first label, `main` if the program starts with an instruction
+    start_label: Option<ir::LabelDefinition>,
+    last_label: ir::LabelDefinition,
+    /// global label local labels are defined under
+    scope: Option<String>,
+    instructions_since_label: u16,
+    doc: Option<String>,
+    attributes: Vec<&'a Keyword>,
+    /// (label, index) of the instructions referencing labels and their line
+    references: Vec<(ir::LabelReference, usize, u16)>,
+    directives: Directives,
+    debug_info: DebugInfo,
+    /// indices in `debug_info.files` of the files currently being included
+    includes: Vec<usize>,
 }
 
-/// **instruction** `$TargetRegister` `$SourceRegisterA` `$SourceRegisterB`
-fn try_parse_binary_expression(
-    instruction: &str,
-    keywords: &mut Iter<Keyword>,
-    line_number: u16,
-) -> Result<ir::BinaryExpression, ParserError> {
-    if let Some(maybe_target_register) = keywords.next() {
-        let target = ir::Register::new(try_parse_register(maybe_target_register)?);
-        if let Some(maybe_source_a) = keywords.next() {
-            let source_a = ir::Register::new(try_parse_register(maybe_source_a)?);
-            if let Some(maybe_source_b) = keywords.next() {
-                let source_b = ir::Register::new(try_parse_register(maybe_source_b)?);
-                Ok(ir::BinaryExpression::new(target, source_a, source_b))
-            } else {
-                Err(ParserError::MissingArgument {
-                    command: String::from(instruction),
-                    arg_name: String::from("SourceRegisterB"),
-                    line_number,
-                })
-            }
-        } else {
-            Err(ParserError::MissingArgument {
-                command: String::from(instruction),
-                arg_name: String::from("SourceRegisterA"),
-                line_number,
+impl Program<'_> {
+    /// Records the words of the statement starting with `keyword`, which
+    /// follow the words parsed so far
+    fn record_span(&mut self, keyword: &Keyword, size: u16) {
+        self.debug_info.spans.push(Span {
+            address: self
+                .last_label
+                .address
+                .0
+                .wrapping_add(self.instructions_since_label),
+            size,
+            line_number: keyword.get_line_number(),
+            column: keyword.get_column(),
+            file: self.includes.last().copied(),
+        });
+    }
+
+    /// Places the `size` words of the statement starting with `keyword`
+    /// behind the words parsed so far
+    fn advance(&mut self, keyword: &Keyword, size: usize) -> Result<(), ParserError> {
+        let out_of_range = || ParserError::LocationOutOfRange {
+            command: keyword.get_original_string(),
+            line_number: keyword.get_line_number(),
+            column: keyword.get_column(),
+        };
+        let size = u16::try_from(size).map_err(|_| out_of_range())?;
+        let since_label = self
+            .instructions_since_label
+            .checked_add(size)
+            .filter(|&since_label| {
+                usize::from(self.last_label.address.0) + usize::from(since_label) <= 0x10000
             })
+            .ok_or_else(out_of_range)?;
+        self.record_span(keyword, size);
+        self.instructions_since_label = since_label;
+        Ok(())
+    }
+
+    /// Records the line of the definition of the label `name`
+    fn record_label(&mut self, name: &str, keyword: &Keyword) {
+        self.debug_info.labels.push((
+            name.to_string(),
+            self.includes.last().copied(),
+            keyword.get_line_number(),
+        ));
+    }
+
+    /// Tracks the file the following keywords come from, `.include` is
+    /// followed by the keywords of the file until its `INCLUDE_END`
+    fn enter_file(&mut self, directive: &str, operand: Option<&&Keyword>) {
+        match (directive, operand) {
+            (
+                "include",
+                Some(Keyword::StringLiteral {
+                    value, line_number, ..
+                }),
+            ) => {
+                let directory = self
+                    .includes
+                    .last()
+                    .and_then(|&file| self.debug_info.files[file].parent())
+                    .unwrap_or(Path::new(""));
+                let path = directory.join(value);
+                let file = match self.debug_info.files.iter().position(|file| *file == path) {
+                    Some(file) => file,
+                    None => {
+                        self.debug_info.files.push(path);
+                        let parent = self.includes.last().copied();
+                        self.debug_info.included_at.push((parent, *line_number));
+                        self.debug_info.files.len() - 1
+                    }
+                };
+                self.includes.push(file);
+            }
+            (INCLUDE_END, _) => {
+                self.includes.pop();
+            }
+            _ => {}
         }
-    } else {
-        Err(ParserError::MissingArgument {
-            command: String::from(instruction),
-            arg_name: String::from("TargetRegister"),
-            line_number,
-        })
     }
-}
 
-/// **instruction** $SourceRegisterA` `$SourceRegisterB`
-fn try_parse_binary_statement(
-    instruction: &str,
-    keywords: &mut Iter<Keyword>,
-    line_number: u16,
-) -> Result<ir::BinaryStatement, ParserError> {
-    if let Some(maybe_source_a) = keywords.next() {
-        let source_a = ir::Register::new(try_parse_register(maybe_source_a)?);
-        if let Some(maybe_source_b) = keywords.next() {
-            let source_b = ir::Register::new(try_parse_register(maybe_source_b)?);
-            Ok(ir::BinaryStatement::new(source_a, source_b))
-        } else {
-            Err(ParserError::MissingArgument {
-                command: String::from(instruction),
-                arg_name: String::from("SourceRegisterB"),
-                line_number,
-            })
+    /// Defines the default label for code in front of the first label
+    fn start(&mut self) {
+        if self.start_label.is_none() {
+            let label = self.last_label.clone();
+            self.scope = Some(label.name.clone());
+            self.known_labels
+                .0
+                .insert(label.clone().into(), label.clone());
+            self.start_label = Some(label);
         }
-    } else {
-        Err(ParserError::MissingArgument {
-            command: String::from(instruction),
-            arg_name: String::from("SourceRegisterA"),
-            line_number,
-        })
     }
 }
 
-/// **instruction** `$TargetRegister` `$SourceRegisterA` `$SourceRegisterB` `$SourceRegisterC`
-fn try_parse_ternary_expression(
-    instruction: &str,
-    keywords: &mut Iter<Keyword>,
-    line_number: u16,
-) -> Result<ir::TernaryExpression, ParserError> {
-    if let Some(maybe_target_register) = keywords.next() {
-        let target = ir::Register::new(try_parse_register(maybe_target_register)?);
-        if let Some(maybe_source_a) = keywords.next() {
-            let source_a = ir::Register::new(try_parse_register(maybe_source_a)?);
-            if let Some(maybe_source_b) = keywords.next() {
-                let source_b = ir::Register::new(try_parse_register(maybe_source_b)?);
-                if let Some(maybe_source_c) = keywords.next() {
-                    let source_c = ir::Register::new(try_parse_register(maybe_source_c)?);
-                    Ok(ir::TernaryExpression::new(
-                        target, source_a, source_b, source_c,
-                    ))
-                } else {
-                    Err(ParserError::MissingArgument {
-                        command: String::from(instruction),
-                        arg_name: String::from("SourceRegisterC"),
-                        line_number,
-                    })
-                }
+pub fn parser(keywords: impl IntoIterator<Item = Keyword>) -> Result<ir::IR, Vec<ParserError>> {
+    parser_with_options(keywords, &ParserOptions::default())
+}
+
+/// Parses the whole program. After an error the parser continues with the
+/// next line, so all errors of the program are reported at once.
+///
+/// `keywords` may come straight from a [`crate::lexer::Tokens`] stream. They
+/// are collected once, with their case folded on the way, as register aliases
+/// and numeric labels are resolved for the whole program before its
+/// statements are parsed. A `Vec` is collected in place.
+pub fn parser_with_options(
+    keywords: impl IntoIterator<Item = Keyword>,
+    options: &ParserOptions,
+) -> Result<ir::IR, Vec<ParserError>> {
+    parser_with_debug_info(keywords, options).map(|(ir, _)| ir)
+}
+
+/// Like [`parser_with_options`], but also returns the line every generated
+/// word comes from
+pub fn parser_with_debug_info(
+    keywords: impl IntoIterator<Item = Keyword>,
+    options: &ParserOptions,
+) -> Result<(ir::IR, DebugInfo), Vec<ParserError>> {
+    let mut fold = fold_case();
+    let keywords: Vec<Keyword> = keywords
+        .into_iter()
+        .map(|keyword| {
+            if options.case_insensitive {
+                fold(keyword)
             } else {
-                Err(ParserError::MissingArgument {
-                    command: String::from(instruction),
-                    arg_name: String::from("SourceRegisterB"),
-                    line_number,
-                })
+                keyword
             }
-        } else {
-            Err(ParserError::MissingArgument {
-                command: String::from(instruction),
-                arg_name: String::from("SourceRegisterA"),
-                line_number,
-            })
+        })
+        .collect();
+    let keywords = resolve_numeric_labels(keywords).map_err(|error| vec![error])?;
+    let register_aliases =
+        collect_register_aliases(&keywords, options).map_err(|error| vec![error])?;
+    let mut iter = keywords.iter().peekable();
+    let mut program = Program {
+        known_labels: ir::LabelLUT::with_capacity(10),
+        parsed: HashMap::with_capacity(10),
+        start_label: None,
+        last_label: ir::LabelDefinition::new("main", 0),
+        scope: None,
+        instructions_since_label: 0,
+        doc: None,
+        attributes: Vec::new(),
+        references: Vec::new(),
+        directives: Directives {
+            register_aliases,
+            options: options.clone(),
+            ..Directives::default()
+        },
+        debug_info: DebugInfo::default(),
+        includes: Vec::new(),
+    };
+    let mut errors = Vec::new();
+
+    while let Some(next_keyword) = iter.next() {
+        program.directives.location = program
+            .last_label
+            .address
+            .0
+            .wrapping_add(program.instructions_since_label);
+        match try_parse_statement(next_keyword, &mut iter, &mut program) {
+            Ok(()) => (),
+            Err(ParserError::EndOfStream) => break,
+            Err(parser_error) => {
+                errors.push(parser_error);
+                // synchronize at the next line
+                let line_number = next_keyword.get_line_number();
+                while iter
+                    .next_if(|keyword| keyword.get_line_number() == line_number)
+                    .is_some()
+                {}
+            }
+        }
+    }
+
+    let Some(start_label) = program.start_label else {
+        if errors.is_empty() {
+            errors.push(ParserError::EmptyStream);
+        }
+        return Err(errors);
+    };
+    let entry = match (&options.entry, program.directives.entry) {
+        (Some(entry), _) => try_resolve_entry(entry, None, &program.known_labels),
+        (None, Some((entry, line_number))) => {
+            try_resolve_entry(&entry, Some(line_number), &program.known_labels)
         }
+        (None, None) => Ok(start_label.into()),
+    };
+    let start_label = match entry {
+        Ok(entry) => entry,
+        Err(parser_error) => {
+            errors.push(parser_error);
+            return Err(errors);
+        }
+    };
+    let mut ir = ir::IR {
+        start_label,
+        label_definitions: program.known_labels,
+        instructions: program.parsed,
+    };
+    resolve_local_labels(&mut ir);
+    if let Err(parser_error) = check_undefined_labels(&mut ir, &program.references) {
+        errors.push(parser_error);
+    }
+    if errors.is_empty() {
+        Ok((ir, program.debug_info))
     } else {
-        Err(ParserError::MissingArgument {
-            command: String::from(instruction),
-            arg_name: String::from("TargetRegister"),
-            line_number,
-        })
+        Err(errors)
     }
 }
 
-/// **jmp** `%DestinationRegister`
-fn try_parse_jmp(
-    jump_instruction: &Keyword,
-    keywords: &mut Iter<Keyword>,
-    line_number: u16,
-    condition: ir::JumpCondition,
-) -> Result<ir::Instruction, ParserError> {
-    if let Some(maybe_target) = keywords.next() {
-        if let Ok(register) = try_parse_register(maybe_target) {
-            Ok(ir::Instruction::Jump {
-                target: ir::JumpTarget::Register(ir::Register::new(register)),
-                condition,
-            })
-        } else {
-            Err(ParserError::CouldNotParseArgument {
-                command: jump_instruction.get_original_string(),
-                arg_name: String::from("DestinationRegister"),
-                arg_value: maybe_target.get_original_string(),
-                line_number,
-            })
+/// Parses the label definition, directive or instruction starting with
+/// `next_keyword` into `program`
+fn try_parse_statement<'a>(
+    next_keyword: &'a Keyword,
+    iter: &mut Keywords<'a>,
+    program: &mut Program<'a>,
+) -> Result<(), ParserError> {
+    if let Keyword::Doc { text, .. } = next_keyword {
+        program.doc = Some(text.clone());
+    } else if let Keyword::Attribute { .. } = next_keyword {
+        program.attributes.push(next_keyword);
+    } else if let Keyword::Directive {
+        name,
+        line_number,
+        column,
+    } = next_keyword
+    {
+        program.enter_file(name, iter.peek());
+        let data = try_parse_directive(name, iter, *line_number, *column, &mut program.directives)?;
+        if !data.is_empty() {
+            check_no_pending_attributes(&program.attributes, next_keyword)?;
+            check_no_pending_origin(&program.directives, next_keyword)?;
+            program.start();
+            program.advance(next_keyword, data.len())?;
+            program
+                .parsed
+                .entry(program.last_label.clone().into())
+                .or_default()
+                .extend(data);
+        }
+    } else if let Keyword::Label { .. } = next_keyword {
+        let mut label = try_parse_label_definition(
+            next_keyword,
+            program.last_label.address.0,
+            program.instructions_since_label,
+        )?;
+        qualify_local_label(&mut label, &mut program.scope, next_keyword)?;
+        apply_origin(&mut label, &mut program.directives)?;
+        label.doc = program.doc.take();
+        // with invalid attributes the label is still defined, so only the
+        // attributes are reported
+        let invalid_attributes = match try_parse_label_attributes(&program.attributes) {
+            Ok(attributes) => {
+                label.attributes = attributes;
+                None
+            }
+            Err(parser_error) => Some(parser_error),
+        };
+        program.attributes.clear();
+        if let Some(align) = label.attributes.align {
+            label.address.0 = label
+                .address
+                .0
+                .checked_next_multiple_of(align)
+                .ok_or_else(|| ParserError::LabelOutOfRange {
+                    label: label.name.clone(),
+                    line_number: next_keyword.get_line_number(),
+                    column: next_keyword.get_column(),
+                })?;
+        }
+        // consecutive labels alias the block of the last one
+        if label.address == program.last_label.address
+            && program
+                .parsed
+                .get(&program.last_label.clone().into())
+                .is_some_and(Vec::is_empty)
+        {
+            program.parsed.remove(&program.last_label.clone().into());
+        }
+        program.parsed.insert(label.clone().into(), Vec::new());
+        program
+            .known_labels
+            .0
+            .insert(label.clone().into(), label.clone());
+        if program.start_label.is_none() {
+            program.start_label = Some(label.clone());
+        }
+        program.record_label(&label.name, next_keyword);
+        program.last_label = label;
+        program.instructions_since_label = 0;
+        if let Some(parser_error) = invalid_attributes {
+            return Err(parser_error);
         }
     } else {
-        Err(ParserError::MissingArgument {
-            command: jump_instruction.get_original_string(),
-            arg_name: String::from("DestinationRegister"),
-            line_number,
-        })
+        check_no_pending_attributes(&program.attributes, next_keyword)?;
+        check_no_pending_origin(&program.directives, next_keyword)?;
+        let mut instruction = try_parse_instruction(next_keyword, iter, &mut program.directives)?;
+        check_reserved_registers(
+            &instruction,
+            &program.directives.reserved_registers,
+            next_keyword,
+        )?;
+        program.start();
+        program.advance(next_keyword, usize::from(instruction.size()))?;
+        let group: ir::LabelReference = program.last_label.clone().into();
+        if !instruction.label_references_mut().is_empty() {
+            let idx = program.parsed.get(&group).map_or(0, Vec::len);
+            program
+                .references
+                .push((group.clone(), idx, next_keyword.get_line_number()));
+        }
+        program.parsed.entry(group).or_default().push(instruction);
     }
+    Ok(())
 }
 
-/// **jr** `ConstantSigned12`
-fn try_parse_jr(
-    jump_instruction: &Keyword,
-    keywords: &mut Iter<Keyword>,
-    line_number: u16,
-    condition: ir::JumpCondition,
-) -> Result<ir::Instruction, ParserError> {
-    if let Some(maybe_target) = keywords.next() {
-        if let Ok(constant) = try_parse_constant(maybe_target) {
-            Ok(ir::Instruction::Jump {
-                target: ir::JumpTarget::Constant(constant.0),
-                condition,
-            })
-        } else if let Ok(label) = try_parse_label_reference(maybe_target) {
-            Ok(ir::Instruction::Jump {
-                target: ir::JumpTarget::Label(label),
-                condition,
-            })
-        } else {
-            Err(ParserError::CouldNotParseArgument {
-                command: jump_instruction.get_original_string(),
-                arg_name: String::from("ConstantSigned12 or JumpLabel"),
-                arg_value: maybe_target.get_original_string(),
-                line_number,
-            })
-        }
+/// Entry label selected with `.start` at `line_number` or the `entry`
+/// option, by default the program starts at its first label
+fn try_resolve_entry(
+    entry: &str,
+    line_number: Option<u16>,
+    known_labels: &ir::LabelLUT,
+) -> Result<ir::LabelReference, ParserError> {
+    let reference = ir::LabelReference::new(entry);
+    if known_labels.0.contains_key(&reference) {
+        Ok(reference)
     } else {
-        Err(ParserError::MissingArgument {
-            command: jump_instruction.get_original_string(),
-            arg_name: String::from("ConstantSigned12 or JumpLabel"),
+        Err(ParserError::UndefinedEntry {
+            label: String::from(entry),
             line_number,
         })
     }
 }
 
-fn try_parse_bool(keyword: &Keyword) -> Result<ir::Boolean, ParserError> {
-    match keyword {
-        &Keyword::Boolean { value, .. } => Ok(ir::Boolean(value)),
-        _ => Err(ParserError::ExpectedFound {
-            expected: String::from("Keyword::Boolean"),
-            found: format!("{:?}", keyword),
+/// Label defined as `.name:` by `keyword` becomes `scope.name` with the
+/// most recent global label as `scope`
+fn qualify_local_label(
+    label: &mut ir::LabelDefinition,
+    scope: &mut Option<String>,
+    keyword: &Keyword,
+) -> Result<(), ParserError> {
+    if label.name.starts_with('.') {
+        let global = scope.as_ref().ok_or(ParserError::LocalLabelWithoutScope {
+            label: label.name.clone(),
             line_number: keyword.get_line_number(),
-        }),
+            column: keyword.get_column(),
+        })?;
+        label.name = format!("{}{}", global, label.name);
+    } else {
+        *scope = Some(label.name.clone());
     }
+    Ok(())
 }
 
-fn try_parse_constant(keyword: &Keyword) -> Result<ir::Constant, ParserError> {
-    match keyword {
-        &Keyword::Constant { value, .. } => Ok(ir::Constant(value)),
-        _ => Err(ParserError::ExpectedFound {
-            expected: String::from("Keyword::Constant"),
-            found: format!("{:?}", keyword),
-            line_number: keyword.get_line_number(),
-        }),
+/// References to `name` resolve to the local label `scope.name` of the
+/// global label the reference is written under, if there is one
+fn resolve_local_labels(ir: &mut ir::IR) {
+    let labels = &ir.label_definitions;
+    for (group, instructions) in ir.instructions.iter_mut() {
+        let scope = group.name().split('.').next().unwrap_or_default();
+        for instruction in instructions.iter_mut() {
+            for reference in instruction.label_references_mut() {
+                let local = ir::LabelReference::new(format!("{}.{}", scope, reference.name()));
+                if labels.0.contains_key(&local) {
+                    *reference = local;
+                }
+            }
+        }
     }
 }
 
-fn try_parse_label_definition(
-    keyword: &Keyword,
-    last_label_address: u16,
-    instructions_since_label: u16,
-) -> Result<ir::LabelDefinition, ParserError> {
-    match keyword {
-        Keyword::Label { name, .. } => Ok(ir::LabelDefinition::new(
+/// Numeric labels like `1:` may be defined any number of times. A
+/// reference `1b` refers to the closest definition of `1` in front of it
+/// and `1f` to the closest one after it.
+///
+/// The K-th definition of `N` becomes the local label `.N_K` and its
+/// references `N_K`, so numeric labels never reach across a global label.
+fn resolve_numeric_labels(mut keywords: Vec<Keyword>) -> Result<Vec<Keyword>, ParserError> {
+    // (keyword index, scope, name) of every numeric label definition
+    let mut definitions: Vec<(usize, usize, String)> = Vec::new();
+    // global label each keyword is written under
+    let mut scopes: Vec<usize> = Vec::with_capacity(keywords.len());
+    let mut scope = 0;
+    let mut is_definition: Vec<bool> = Vec::with_capacity(keywords.len());
+    for (idx, keyword) in keywords.iter().enumerate() {
+        let first_on_line =
+            idx == 0 || keywords[idx - 1].get_line_number() != keyword.get_line_number();
+        if let Keyword::Label { name, .. } = keyword {
+            if first_on_line && is_numeric_label(name) {
+                definitions.push((idx, scope, name.clone()));
+            } else if first_on_line && !name.starts_with('.') {
+                scope += 1;
+            }
+        }
+        scopes.push(scope);
+        is_definition.push(first_on_line);
+    }
+    if definitions.is_empty() {
+        return Ok(keywords);
+    }
+
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    let unique_names: Vec<String> = definitions
+        .iter()
+        .map(|(_, _, name)| {
+            let count = counts.entry(name).or_default();
+            *count += 1;
+            format!("{}_{}", name, *count - 1)
+        })
+        .collect();
+
+    for (idx, keyword) in keywords.iter_mut().enumerate() {
+        let Keyword::Label {
             name,
-            last_label_address + instructions_since_label,
-        )),
-        _ => Err(ParserError::ExpectedFound {
-            expected: String::from("Keyword::Label"),
-            found: format!("{:?}", keyword),
-            line_number: keyword.get_line_number(),
-        }),
+            line_number,
+            column,
+        } = keyword
+        else {
+            continue;
+        };
+        if is_definition[idx] {
+            if let Some(position) = definitions
+                .iter()
+                .position(|(definition, ..)| *definition == idx)
+            {
+                *name = format!(".{}", unique_names[position]);
+            }
+            continue;
+        }
+        let (number, forward) = match name.split_at(name.len().saturating_sub(1)) {
+            (number, "f") if is_numeric_label(number) => (number, true),
+            (number, "b") if is_numeric_label(number) => (number, false),
+            _ => continue,
+        };
+        let mut matching =
+            definitions
+                .iter()
+                .enumerate()
+                .filter(|(_, (_, definition_scope, definition))| {
+                    *definition_scope == scopes[idx] && definition == number
+                });
+        let position = if forward {
+            matching.find(|(_, (definition, ..))| *definition > idx)
+        } else {
+            matching.rfind(|(_, (definition, ..))| *definition < idx)
+        }
+        .map(|(position, _)| position)
+        .ok_or(ParserError::UndefinedNumericLabel {
+            label: name.clone(),
+            line_number: *line_number,
+            column: *column,
+        })?;
+        *name = unique_names[position].clone();
     }
+    Ok(keywords)
 }
 
-fn try_parse_label_reference(keyword: &Keyword) -> Result<ir::LabelReference, ParserError> {
-    match &keyword {
-        Keyword::Label { name, .. } => Ok(ir::LabelReference::new(name)),
-        _ => Err(ParserError::ExpectedFound {
-            expected: String::from("Keyword::Label"),
-            found: format!("{:?}", keyword),
-            line_number: keyword.get_line_number(),
-        }),
+/// Reports every label referenced by the instructions at `references`
+/// that is not defined, together with the line of the reference
+fn check_undefined_labels(
+    ir: &mut ir::IR,
+    references: &[(ir::LabelReference, usize, u16)],
+) -> Result<(), ParserError> {
+    let mut undefined: Vec<(String, u16)> = Vec::new();
+    for (group, idx, line_number) in references {
+        let Some(instruction) = ir
+            .instructions
+            .get_mut(group)
+            .and_then(|instructions| instructions.get_mut(*idx))
+        else {
+            continue;
+        };
+        for reference in instruction.label_references_mut() {
+            if !ir.label_definitions.0.contains_key(reference) {
+                undefined.push((String::from(reference.name()), *line_number));
+            }
+        }
+    }
+    if undefined.is_empty() {
+        Ok(())
+    } else {
+        Err(ParserError::UndefinedLabels { labels: undefined })
     }
 }
 
-fn try_parse_register(keyword: &Keyword) -> Result<ir::RegisterAddress, ParserError> {
-    match keyword {
-        Keyword::RegisterAddress { name, line_number } => {
-            if let Some(register_number) = name.strip_prefix("reg") {
-                if register_number.is_empty() {
-                    None
-                } else {
-                    let char = register_number.chars().next().unwrap();
-                    match register_number.chars().next().unwrap() {
-                        '0'..='7' => Some(char.to_digit(8).unwrap()),
-                        'A'..='H' => Some(u32::from(char) - u32::from('A')),
-                        _ => None,
-                    }
-                }
-                .ok_or(ParserError::ExpectedFound {
-                    expected: String::from("valid register number (0..7 | A..H)"),
-                    found: register_number.to_string(),
-                    line_number: *line_number,
-                })
-            } else {
-                Err(ParserError::ExpectedFound {
-                    expected: String::from("valid register identifier"),
-                    found: name.to_string(),
-                    line_number: *line_number,
-                })
-            }
-            .and_then(|address_u32| {
-                u8::try_from(address_u32).or(Err(ParserError::ExpectedFound {
-                    expected: String::from("valid register identifier"),
-                    found: name.to_string(),
-                    line_number: *line_number,
-                }))
-            })
-            .map(ir::RegisterAddress)
+/// Kind of an operand in an instruction signature
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OperandKind {
+    /// `%reg0` .. `%reg7` / `%regA` .. `%regH`
+    Register,
+    /// 16 bit constant, label address or expression like `lo(label)`
+    Constant,
+    /// constant whose value is known while parsing, e.g. a rotation amount
+    Count,
+    /// `true` / `false`
+    Boolean,
+    /// constant offset or label of a relative jump
+    JumpTarget,
+}
+
+/// Operand read by `try_parse_operands`, its variant always
+/// matches the `OperandKind` of the signature
+#[derive(Debug)]
+enum Operand {
+    Register(ir::Register),
+    Constant(u16),
+    Expression(ir::Expression),
+    Boolean(ir::Boolean),
+    JumpTarget(ir::JumpTarget),
+}
+
+/// Operands of one instruction in the order of its signature
+struct Operands {
+    operands: Vec<Operand>,
+    /// register pseudo-instructions may use for intermediate values
+    scratch: ir::Register,
+    /// 32 bit mode is enabled
+    wide_mode: bool,
+}
+
+impl Operands {
+    fn register(&self, idx: usize) -> ir::Register {
+        match self.operands[idx] {
+            Operand::Register(register) => register,
+            _ => unreachable!("operand {} is no register", idx),
         }
-        _ => Err(ParserError::ExpectedFound {
-            expected: String::from("Keyword::RegisterAddress"),
-            found: format!("{:?}", keyword),
-            line_number: keyword.get_line_number(),
-        }),
+    }
+    fn load_source(&self, idx: usize) -> ir::LoadSource {
+        match &self.operands[idx] {
+            Operand::Constant(constant) => ir::LoadSource::Constant(*constant),
+            Operand::Expression(expression) => ir::LoadSource::Expression(expression.clone()),
+            _ => unreachable!("operand {} is no constant", idx),
+        }
+    }
+    fn expression(&self, idx: usize) -> ir::Expression {
+        match &self.operands[idx] {
+            Operand::Constant(constant) => ir::Expression::Constant(u32::from(*constant)),
+            Operand::Expression(expression) => expression.clone(),
+            _ => unreachable!("operand {} is no constant", idx),
+        }
+    }
+    fn scratch(&self) -> ir::Register {
+        self.scratch
+    }
+    fn constant(&self, idx: usize) -> u16 {
+        match self.operands[idx] {
+            Operand::Constant(constant) => constant,
+            _ => unreachable!("operand {} is no constant", idx),
+        }
+    }
+    fn rotate(&self, direction: ir::Direction) -> ir::Instruction {
+        ir::Instruction::Rotate {
+            direction,
+            target: self.register(0),
+            source: self.register(1),
+            amount: self.constant(2),
+            width: if self.wide_mode { 32 } else { 16 },
+            scratch: self.scratch(),
+        }
+    }
+    fn boolean(&self, idx: usize) -> ir::Boolean {
+        match self.operands[idx] {
+            Operand::Boolean(boolean) => boolean,
+            _ => unreachable!("operand {} is no boolean", idx),
+        }
+    }
+    fn jump_target(&self, idx: usize) -> ir::JumpTarget {
+        match &self.operands[idx] {
+            Operand::JumpTarget(jump_target) => jump_target.clone(),
+            _ => unreachable!("operand {} is no jump target", idx),
+        }
+    }
+    fn unary_expression(&self) -> ir::UnaryExpression {
+        ir::UnaryExpression::new(self.register(0), self.register(1))
+    }
+    fn binary_expression(&self) -> ir::BinaryExpression {
+        ir::BinaryExpression::new(self.register(0), self.register(1), self.register(2))
+    }
+    fn immediate(&self, operation: ir::Operation) -> ir::Instruction {
+        ir::Instruction::Immediate {
+            operation,
+            target: self.register(0),
+            source: self.register(0),
+            value: self.expression(1),
+            scratch: self.scratch(),
+        }
+    }
+    fn shift_immediate(&self, operation: ir::Operation) -> ir::Instruction {
+        ir::Instruction::Immediate {
+            operation,
+            target: self.register(0),
+            source: self.register(1),
+            value: self.expression(2),
+            scratch: self.scratch(),
+        }
+    }
+    fn binary_statement(&self) -> ir::BinaryStatement {
+        ir::BinaryStatement::new(self.register(0), self.register(1))
+    }
+    fn ternary_expression(&self) -> ir::TernaryExpression {
+        ir::TernaryExpression::new(
+            self.register(0),
+            self.register(1),
+            self.register(2),
+            self.register(3),
+        )
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Declares the operands of a mnemonic and how they are turned into an
+/// `ir::Instruction`
+pub struct Signature {
+    pub mnemonic: &'static str,
+    operands: &'static [(&'static str, OperandKind)],
+    build: fn(&Operands) -> ir::Instruction,
+}
 
-    #[test]
-    fn parse_all_instructions() {
-        let lexed = vec![
-            Keyword::mmenonic("ldc", 0),
-            Keyword::register_address("reg0", 0),
-            Keyword::constant("0x00", 0, 0),
-            Keyword::mmenonic("ldc", 1),
-            Keyword::register_address("reg7", 1),
-            Keyword::constant("-0x01", 1u16.wrapping_neg(), 1),
-            Keyword::mmenonic("ldc", 2),
-            Keyword::register_address("regA", 2),
-            Keyword::constant("42", 42, 2),
-            Keyword::mmenonic("ldc", 3),
-            Keyword::register_address("regH", 3),
-            Keyword::constant("-1337", 1337u16.wrapping_neg(), 3),
-            Keyword::mmenonic("add", 4),
-            Keyword::register_address("reg0", 4),
-            Keyword::register_address("reg1", 4),
-            Keyword::register_address("reg2", 4),
-            Keyword::mmenonic("add3", 5),
-            Keyword::register_address("reg3", 5),
-            Keyword::register_address("reg4", 5),
-            Keyword::register_address("reg5", 5),
-            Keyword::register_address("reg6", 5),
-            Keyword::mmenonic("addc", 6),
-            Keyword::register_address("regB", 6),
-            Keyword::register_address("regC", 6),
-            Keyword::register_address("regD", 6),
-            Keyword::mmenonic("sub", 7),
-            Keyword::register_address("regE", 7),
-            Keyword::register_address("regF", 7),
-            Keyword::register_address("regG", 7),
-            Keyword::mmenonic("subc", 8),
-            Keyword::register_address("reg0", 8),
-            Keyword::register_address("reg0", 8),
-            Keyword::register_address("reg0", 8),
-            Keyword::mmenonic("inc", 9),
-            Keyword::register_address("reg0", 9),
-            Keyword::mmenonic("dec", 10),
-            Keyword::register_address("reg0", 10),
-            Keyword::mmenonic("mul", 11),
-            Keyword::register_address("reg0", 11),
-            Keyword::register_address("reg0", 11),
-            Keyword::register_address("reg0", 11),
-            Keyword::mmenonic("and", 12),
-            Keyword::register_address("reg0", 12),
-            Keyword::register_address("reg0", 12),
-            Keyword::register_address("reg0", 12),
-            Keyword::mmenonic("or", 13),
-            Keyword::register_address("reg0", 13),
-            Keyword::register_address("reg0", 13),
-            Keyword::register_address("reg0", 13),
-            Keyword::mmenonic("not", 14),
-            Keyword::register_address("reg0", 14),
-            Keyword::register_address("reg0", 14),
-            Keyword::mmenonic("neg", 15),
-            Keyword::register_address("reg0", 15),
-            Keyword::register_address("reg0", 15),
-            Keyword::mmenonic("xor", 16),
-            Keyword::register_address("reg0", 16),
-            Keyword::register_address("reg0", 16),
-            Keyword::register_address("reg0", 16),
-            Keyword::mmenonic("xnor", 17),
-            Keyword::register_address("reg0", 17),
-            Keyword::register_address("reg0", 17),
-            Keyword::register_address("reg0", 17),
-            Keyword::mmenonic("shl", 18),
-            Keyword::register_address("reg0", 18),
-            Keyword::register_address("reg0", 18),
-            Keyword::register_address("reg0", 18),
+/// **instruction**
+const NO_OPERANDS: &[(&str, OperandKind)] = &[];
+/// **instruction** `$SourceRegister`
+const UNARY_STATEMENT: &[(&str, OperandKind)] = &[("SourceRegister", OperandKind::Register)];
+/// **instruction** `$TargetRegister` `$SourceRegister`
+const UNARY_EXPRESSION: &[(&str, OperandKind)] = &[
+    ("TargetRegister", OperandKind::Register),
+    ("SourceRegister", OperandKind::Register),
+];
+/// **instruction** `$SourceRegisterA` `$SourceRegisterB`
+const BINARY_STATEMENT: &[(&str, OperandKind)] = &[
+    ("SourceRegisterA", OperandKind::Register),
+    ("SourceRegisterB", OperandKind::Register),
+];
+/// **instruction** `$TargetRegister` `$SourceRegisterA` `$SourceRegisterB`
+const BINARY_EXPRESSION: &[(&str, OperandKind)] = &[
+    ("TargetRegister", OperandKind::Register),
+    ("SourceRegisterA", OperandKind::Register),
+    ("SourceRegisterB", OperandKind::Register),
+];
+/// **instruction** `$TargetRegister` `$SourceRegisterA` `$SourceRegisterB` `$SourceRegisterC`
+const TERNARY_EXPRESSION: &[(&str, OperandKind)] = &[
+    ("TargetRegister", OperandKind::Register),
+    ("SourceRegisterA", OperandKind::Register),
+    ("SourceRegisterB", OperandKind::Register),
+    ("SourceRegisterC", OperandKind::Register),
+];
+/// **ldc** `$TargetRegister` `Constant16`
+const LOAD_CONSTANT: &[(&str, OperandKind)] = &[
+    ("TargetRegister", OperandKind::Register),
+    ("Constant16", OperandKind::Constant),
+];
+/// **s32b** `EnableBoolean`
+const BOOLEAN: &[(&str, OperandKind)] = &[("EnableBoolean", OperandKind::Boolean)];
+/// **jmp** `%DestinationRegister`
+const ABSOLUTE_JUMP: &[(&str, OperandKind)] = &[("DestinationRegister", OperandKind::Register)];
+/// **li** `$TargetRegister` `Constant32`
+const LOAD_IMMEDIATE: &[(&str, OperandKind)] = &[
+    ("TargetRegister", OperandKind::Register),
+    ("Constant32", OperandKind::Constant),
+];
+/// **addi** `$TargetRegister` `Constant16` / **subi**, **andi**, **ori**, **xori**
+const IMMEDIATE: &[(&str, OperandKind)] = &[
+    ("TargetRegister", OperandKind::Register),
+    ("Constant16", OperandKind::Constant),
+];
+/// **shl** `$TargetRegister` `$SourceRegister` `ShiftAmount` / **shr**
+const SHIFT_IMMEDIATE: &[(&str, OperandKind)] = &[
+    ("TargetRegister", OperandKind::Register),
+    ("SourceRegister", OperandKind::Register),
+    ("ShiftAmount", OperandKind::Constant),
+];
+/// **rol** `$TargetRegister` `$SourceRegister` `RotateAmount` / **ror**
+const ROTATE: &[(&str, OperandKind)] = &[
+    ("TargetRegister", OperandKind::Register),
+    ("SourceRegister", OperandKind::Register),
+    ("RotateAmount", OperandKind::Count),
+];
+/// **div** `$TargetRegister` `$DividendRegister` `$DivisorRegister` / **mod**
+const DIVISION: &[(&str, OperandKind)] = &[
+    ("TargetRegister", OperandKind::Register),
+    ("DividendRegister", OperandKind::Register),
+    ("DivisorRegister", OperandKind::Register),
+];
+/// **ljmp** `JumpLabel`
+const LONG_JUMP: &[(&str, OperandKind)] = &[("JumpLabel", OperandKind::Constant)];
+/// **jr** `ConstantSigned12` | `JumpLabel`
+const RELATIVE_JUMP: &[(&str, OperandKind)] =
+    &[("ConstantSigned12 or JumpLabel", OperandKind::JumpTarget)];
+
+/// All mnemonics known to the parser
+pub const SIGNATURES: &[Signature] = &[
+    Signature {
+        mnemonic: "ldc",
+        operands: LOAD_CONSTANT,
+        build: |ops| ir::Instruction::Load {
+            address: ops.register(0).address,
+            source: ops.load_source(1),
+        },
+    },
+    Signature {
+        mnemonic: "add",
+        operands: BINARY_EXPRESSION,
+        build: |ops| ir::Instruction::Add(ops.binary_expression()),
+    },
+    Signature {
+        mnemonic: "add3",
+        operands: TERNARY_EXPRESSION,
+        build: |ops| ir::Instruction::Add3(ops.ternary_expression()),
+    },
+    Signature {
+        mnemonic: "addc",
+        operands: BINARY_EXPRESSION,
+        build: |ops| ir::Instruction::AddWithCarry(ops.binary_expression()),
+    },
+    Signature {
+        mnemonic: "sub",
+        operands: BINARY_EXPRESSION,
+        build: |ops| ir::Instruction::Subtract(ops.binary_expression()),
+    },
+    Signature {
+        mnemonic: "subc",
+        operands: BINARY_EXPRESSION,
+        build: |ops| ir::Instruction::SubtractWithCarry(ops.binary_expression()),
+    },
+    Signature {
+        mnemonic: "inc",
+        operands: UNARY_STATEMENT,
+        build: |ops| {
+            ir::Instruction::Increment(ir::UnaryExpression::new(ops.register(0), ops.register(0)))
+        },
+    },
+    Signature {
+        mnemonic: "dec",
+        operands: UNARY_STATEMENT,
+        build: |ops| {
+            ir::Instruction::Decrement(ir::UnaryExpression::new(ops.register(0), ops.register(0)))
+        },
+    },
+    Signature {
+        mnemonic: "mul",
+        operands: BINARY_EXPRESSION,
+        build: |ops| ir::Instruction::Multiply(ops.binary_expression()),
+    },
+    Signature {
+        mnemonic: "and",
+        operands: BINARY_EXPRESSION,
+        build: |ops| ir::Instruction::AND(ops.binary_expression()),
+    },
+    Signature {
+        mnemonic: "or",
+        operands: BINARY_EXPRESSION,
+        build: |ops| ir::Instruction::OR(ops.binary_expression()),
+    },
+    Signature {
+        mnemonic: "not",
+        operands: UNARY_EXPRESSION,
+        build: |ops| ir::Instruction::NOT(ops.unary_expression()),
+    },
+    Signature {
+        mnemonic: "neg",
+        operands: UNARY_EXPRESSION,
+        build: |ops| ir::Instruction::Negate(ops.unary_expression()),
+    },
+    Signature {
+        mnemonic: "xor",
+        operands: BINARY_EXPRESSION,
+        build: |ops| ir::Instruction::XOR(ops.binary_expression()),
+    },
+    Signature {
+        mnemonic: "xnor",
+        operands: BINARY_EXPRESSION,
+        build: |ops| ir::Instruction::XNOR(ops.binary_expression()),
+    },
+    Signature {
+        mnemonic: "shl",
+        operands: BINARY_EXPRESSION,
+        build: |ops| ir::Instruction::ShiftLeft(ops.binary_expression()),
+    },
+    Signature {
+        mnemonic: "shr",
+        operands: BINARY_EXPRESSION,
+        build: |ops| ir::Instruction::ShiftRight(ops.binary_expression()),
+    },
+    Signature {
+        mnemonic: "tst",
+        operands: BINARY_STATEMENT,
+        build: |ops| ir::Instruction::Test(ops.binary_statement()),
+    },
+    Signature {
+        mnemonic: "mov",
+        operands: UNARY_EXPRESSION,
+        build: |ops| ir::Instruction::Move(ops.unary_expression()),
+    },
+    Signature {
+        mnemonic: "s32b",
+        operands: BOOLEAN,
+        build: |ops| ir::Instruction::Set32BitMode {
+            enable: ops.boolean(0),
+        },
+    },
+    Signature {
+        mnemonic: "hlt",
+        operands: NO_OPERANDS,
+        build: |_| ir::Instruction::Halt,
+    },
+    Signature {
+        mnemonic: "dbg",
+        operands: NO_OPERANDS,
+        build: |_| ir::Instruction::Debug,
+    },
+    Signature {
+        mnemonic: "jmp",
+        operands: ABSOLUTE_JUMP,
+        build: |ops| ir::Instruction::Jump {
+            target: ir::JumpTarget::Register(ops.register(0)),
+            condition: ir::JumpCondition::True,
+        },
+    },
+    Signature {
+        mnemonic: "jz",
+        operands: ABSOLUTE_JUMP,
+        build: |ops| ir::Instruction::Jump {
+            target: ir::JumpTarget::Register(ops.register(0)),
+            condition: ir::JumpCondition::Zero,
+        },
+    },
+    Signature {
+        mnemonic: "jnz",
+        operands: ABSOLUTE_JUMP,
+        build: |ops| ir::Instruction::Jump {
+            target: ir::JumpTarget::Register(ops.register(0)),
+            condition: ir::JumpCondition::NotZero,
+        },
+    },
+    Signature {
+        mnemonic: "jc",
+        operands: ABSOLUTE_JUMP,
+        build: |ops| ir::Instruction::Jump {
+            target: ir::JumpTarget::Register(ops.register(0)),
+            condition: ir::JumpCondition::Less,
+        },
+    },
+    Signature {
+        mnemonic: "jo",
+        operands: ABSOLUTE_JUMP,
+        build: |ops| ir::Instruction::Jump {
+            target: ir::JumpTarget::Register(ops.register(0)),
+            condition: ir::JumpCondition::Overflow,
+        },
+    },
+    Signature {
+        mnemonic: "jge",
+        operands: ABSOLUTE_JUMP,
+        build: |ops| ir::Instruction::Jump {
+            target: ir::JumpTarget::Register(ops.register(0)),
+            condition: ir::JumpCondition::GreaterEqual,
+        },
+    },
+    Signature {
+        mnemonic: "jle",
+        operands: ABSOLUTE_JUMP,
+        build: |ops| ir::Instruction::Jump {
+            target: ir::JumpTarget::Register(ops.register(0)),
+            condition: ir::JumpCondition::LessEqual,
+        },
+    },
+    Signature {
+        mnemonic: "jgt",
+        operands: ABSOLUTE_JUMP,
+        build: |ops| ir::Instruction::Jump {
+            target: ir::JumpTarget::Register(ops.register(0)),
+            condition: ir::JumpCondition::Greater,
+        },
+    },
+    Signature {
+        mnemonic: "jrcon",
+        operands: RELATIVE_JUMP,
+        build: |ops| ir::Instruction::Jump {
+            target: ops.jump_target(0),
+            condition: ir::JumpCondition::True,
+        },
+    },
+    Signature {
+        mnemonic: "jr",
+        operands: RELATIVE_JUMP,
+        build: |ops| ir::Instruction::Jump {
+            target: ops.jump_target(0),
+            condition: ir::JumpCondition::True,
+        },
+    },
+    Signature {
+        mnemonic: "jzr",
+        operands: RELATIVE_JUMP,
+        build: |ops| ir::Instruction::Jump {
+            target: ops.jump_target(0),
+            condition: ir::JumpCondition::Zero,
+        },
+    },
+    Signature {
+        mnemonic: "jnzr",
+        operands: RELATIVE_JUMP,
+        build: |ops| ir::Instruction::Jump {
+            target: ops.jump_target(0),
+            condition: ir::JumpCondition::NotZero,
+        },
+    },
+    Signature {
+        mnemonic: "jcr",
+        operands: RELATIVE_JUMP,
+        build: |ops| ir::Instruction::Jump {
+            target: ops.jump_target(0),
+            condition: ir::JumpCondition::Less,
+        },
+    },
+    Signature {
+        mnemonic: "jor",
+        operands: RELATIVE_JUMP,
+        build: |ops| ir::Instruction::Jump {
+            target: ops.jump_target(0),
+            condition: ir::JumpCondition::Overflow,
+        },
+    },
+    Signature {
+        mnemonic: "jger",
+        operands: RELATIVE_JUMP,
+        build: |ops| ir::Instruction::Jump {
+            target: ops.jump_target(0),
+            condition: ir::JumpCondition::GreaterEqual,
+        },
+    },
+    Signature {
+        mnemonic: "jler",
+        operands: RELATIVE_JUMP,
+        build: |ops| ir::Instruction::Jump {
+            target: ops.jump_target(0),
+            condition: ir::JumpCondition::LessEqual,
+        },
+    },
+    Signature {
+        mnemonic: "jgtr",
+        operands: RELATIVE_JUMP,
+        build: |ops| ir::Instruction::Jump {
+            target: ops.jump_target(0),
+            condition: ir::JumpCondition::Greater,
+        },
+    },
+    Signature {
+        mnemonic: "st",
+        operands: UNARY_EXPRESSION,
+        build: |ops| ir::Instruction::StoreRAM {
+            address_register: ops.register(0).address,
+            data_register: ops.register(1).address,
+        },
+    },
+    Signature {
+        mnemonic: "ld",
+        operands: UNARY_EXPRESSION,
+        build: |ops| ir::Instruction::Load {
+            address: ops.register(0).address,
+            source: ir::LoadSource::RAM {
+                address_register: ops.register(1),
+            },
+        },
+    },
+    Signature {
+        mnemonic: "nop",
+        operands: NO_OPERANDS,
+        build: |_| ir::Instruction::Noop,
+    },
+    Signature {
+        mnemonic: "ljmp",
+        operands: LONG_JUMP,
+        build: |ops| ir::Instruction::LongJump {
+            target: ops.expression(0),
+            scratch: ops.scratch(),
+        },
+    },
+    Signature {
+        mnemonic: "li",
+        operands: LOAD_IMMEDIATE,
+        build: |ops| ir::Instruction::LoadImmediate {
+            address: ops.register(0).address,
+            value: ops.expression(1),
+            scratch: ops.scratch(),
+        },
+    },
+    Signature {
+        mnemonic: "addi",
+        operands: IMMEDIATE,
+        build: |ops| ops.immediate(ir::Operation::Add),
+    },
+    Signature {
+        mnemonic: "subi",
+        operands: IMMEDIATE,
+        build: |ops| ops.immediate(ir::Operation::Subtract),
+    },
+    Signature {
+        mnemonic: "andi",
+        operands: IMMEDIATE,
+        build: |ops| ops.immediate(ir::Operation::AND),
+    },
+    Signature {
+        mnemonic: "ori",
+        operands: IMMEDIATE,
+        build: |ops| ops.immediate(ir::Operation::OR),
+    },
+    Signature {
+        mnemonic: "xori",
+        operands: IMMEDIATE,
+        build: |ops| ops.immediate(ir::Operation::XOR),
+    },
+    Signature {
+        mnemonic: "shl",
+        operands: SHIFT_IMMEDIATE,
+        build: |ops| ops.shift_immediate(ir::Operation::ShiftLeft),
+    },
+    Signature {
+        mnemonic: "shr",
+        operands: SHIFT_IMMEDIATE,
+        build: |ops| ops.shift_immediate(ir::Operation::ShiftRight),
+    },
+    Signature {
+        mnemonic: "rol",
+        operands: ROTATE,
+        build: |ops| ops.rotate(ir::Direction::Left),
+    },
+    Signature {
+        mnemonic: "ror",
+        operands: ROTATE,
+        build: |ops| ops.rotate(ir::Direction::Right),
+    },
+    Signature {
+        mnemonic: "div",
+        operands: DIVISION,
+        build: |ops| ir::Instruction::Divide {
+            expression: ops.binary_expression(),
+            scratch: ops.scratch(),
+        },
+    },
+    Signature {
+        mnemonic: "mod",
+        operands: DIVISION,
+        build: |ops| ir::Instruction::Modulo(ops.binary_expression()),
+    },
+];
+
+fn try_parse_instruction(
+    next_keyword: &Keyword,
+    keywords: &mut Keywords,
+    directives: &mut Directives,
+) -> Result<ir::Instruction, ParserError> {
+    match next_keyword {
+        Keyword::Mmenonic {
+            name, line_number, ..
+        } => {
+            // a mnemonic may have several signatures, e.g. `shl` with a
+            // register or a constant shift amount, the first matching wins
+            let mut first_error = None;
+            let mut parsed = None;
+            for signature in SIGNATURES
+                .iter()
+                .filter(|signature| signature.mnemonic == name)
+            {
+                let mut attempt = keywords.clone();
+                match try_parse_operands(
+                    name,
+                    signature.operands,
+                    &mut attempt,
+                    *line_number,
+                    directives,
+                ) {
+                    Ok(operands) => {
+                        *keywords = attempt;
+                        parsed = Some((signature.build)(&operands));
+                        break;
+                    }
+                    Err(err) => {
+                        first_error.get_or_insert(err);
+                    }
+                }
+            }
+            let instruction = match (parsed, first_error) {
+                (Some(instruction), _) => instruction,
+                (None, Some(err)) => return Err(err),
+                (None, None) => {
+                    return Err(ParserError::UnknownCommand {
+                        command: name.to_string(),
+                        line_number: *line_number,
+                        column: next_keyword.get_column(),
+                        suggestions: similar_mnemonics(name),
+                    })
+                }
+            };
+            match &instruction {
+                ir::Instruction::Set32BitMode { enable } => directives.wide_mode = enable.0,
+                ir::Instruction::LoadImmediate { .. } if !directives.wide_mode => {
+                    return Err(ParserError::Requires32BitMode {
+                        command: name.to_string(),
+                        line_number: *line_number,
+                        column: next_keyword.get_column(),
+                    })
+                }
+                ir::Instruction::LoadImmediate {
+                    address, scratch, ..
+                }
+                | ir::Instruction::Immediate {
+                    source: ir::Register { address },
+                    scratch,
+                    ..
+                } if *address == scratch.address => {
+                    return Err(ParserError::ReservedRegister {
+                        register: format!("%reg{}", address.0),
+                        reason: String::from("scratch register"),
+                        line_number: *line_number,
+                        column: next_keyword.get_column(),
+                    })
+                }
+                ir::Instruction::Divide { .. } | ir::Instruction::Modulo(_)
+                    if !directives.options.soft_division =>
+                {
+                    return Err(ParserError::NoHardwareSupport {
+                        command: name.to_string(),
+                        line_number: *line_number,
+                        column: next_keyword.get_column(),
+                    })
+                }
+                // the divisor is read in every iteration
+                ir::Instruction::Divide { expression, .. }
+                | ir::Instruction::Modulo(expression)
+                    if expression.target == expression.source_b =>
+                {
+                    return Err(ParserError::ClobberedRegister {
+                        command: name.to_string(),
+                        register: format!("%reg{}", expression.target.address.0),
+                        line_number: *line_number,
+                        column: next_keyword.get_column(),
+                    })
+                }
+                ir::Instruction::Divide {
+                    expression,
+                    scratch,
+                } if [expression.target, expression.source_a, expression.source_b]
+                    .contains(scratch) =>
+                {
+                    return Err(ParserError::ReservedRegister {
+                        register: format!("%reg{}", scratch.address.0),
+                        reason: String::from("scratch register"),
+                        line_number: *line_number,
+                        column: next_keyword.get_column(),
+                    })
+                }
+                ir::Instruction::Rotate {
+                    target,
+                    source,
+                    scratch,
+                    ..
+                } if *target == *scratch || *source == *scratch => {
+                    return Err(ParserError::ReservedRegister {
+                        register: format!("%reg{}", scratch.address.0),
+                        reason: String::from("scratch register"),
+                        line_number: *line_number,
+                        column: next_keyword.get_column(),
+                    })
+                }
+                // values depending on label addresses are checked by the generator
+                ir::Instruction::Load {
+                    source: ir::LoadSource::Expression(value),
+                    ..
+                }
+                | ir::Instruction::Immediate { value, .. } => {
+                    match value.evaluate(&ir::LabelLUT::new(), directives.location) {
+                        Ok(value) if !ir::fits_16_bits(value) => {
+                            return Err(ParserError::ConstantOutOfRange {
+                                command: name.to_string(),
+                                value,
+                                line_number: *line_number,
+                                column: next_keyword.get_column(),
+                            })
+                        }
+                        _ => (),
+                    }
+                }
+                _ => (),
+            }
+            Ok(instruction)
+        }
+        Keyword::Constant {
+            value, line_number, ..
+        } => Err(ParserError::UnknownCommand {
+            command: format!("{}", value),
+            line_number: *line_number,
+            column: next_keyword.get_column(),
+            suggestions: Vec::new(),
+        }),
+        Keyword::Boolean {
+            value, line_number, ..
+        } => Err(ParserError::UnknownCommand {
+            command: format!("{}", value),
+            line_number: *line_number,
+            column: next_keyword.get_column(),
+            suggestions: Vec::new(),
+        }),
+        Keyword::Label {
+            name, line_number, ..
+        } => Err(ParserError::UnknownCommand {
+            command: name.to_string(),
+            line_number: *line_number,
+            column: next_keyword.get_column(),
+            suggestions: Vec::new(),
+        }),
+        Keyword::RegisterAddress {
+            name, line_number, ..
+        } => Err(ParserError::UnknownCommand {
+            command: name.to_string(),
+            line_number: *line_number,
+            column: next_keyword.get_column(),
+            suggestions: Vec::new(),
+        }),
+        Keyword::Doc { line_number, .. }
+        | Keyword::Attribute { line_number, .. }
+        | Keyword::Directive { line_number, .. }
+        | Keyword::StringLiteral { line_number, .. }
+        | Keyword::Expression { line_number, .. }
+        | Keyword::Comment { line_number, .. }
+        | Keyword::BlankLine { line_number, .. } => Err(ParserError::UnknownCommand {
+            command: next_keyword.get_original_string(),
+            line_number: *line_number,
+            column: next_keyword.get_column(),
+            suggestions: Vec::new(),
+        }),
+    }
+}
+
+/// Most names suggested for an unknown command or register
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Mnemonics of [`SIGNATURES`] with the smallest edit distance to `name`
+fn similar_mnemonics(name: &str) -> Vec<String> {
+    closest(name, SIGNATURES.iter().map(|signature| signature.mnemonic))
+}
+
+/// `candidates` with the smallest edit distance to `name` ignoring case,
+/// none if all of them differ in more than a third of their characters
+fn closest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let name = name.to_ascii_lowercase();
+    let mut closest: Vec<String> = Vec::new();
+    let mut closest_distance = usize::MAX;
+    for candidate in candidates {
+        let distance = edit_distance(&name, &candidate.to_ascii_lowercase());
+        if distance > candidate.len().div_ceil(3) || distance > closest_distance {
+            continue;
+        }
+        if distance < closest_distance {
+            closest.clear();
+            closest_distance = distance;
+        }
+        if closest.len() < MAX_SUGGESTIONS && !closest.iter().any(|known| known == candidate) {
+            closest.push(String::from(candidate));
+        }
+    }
+    closest
+}
+
+/// Number of inserted, removed, replaced or swapped adjacent characters
+/// turning `from` into `to`
+fn edit_distance(from: &str, to: &str) -> usize {
+    let from: Vec<char> = from.chars().collect();
+    let to: Vec<char> = to.chars().collect();
+    // distances[i][j] between the first i characters of `from` and the
+    // first j characters of `to`
+    let mut distances = vec![vec![0; to.len() + 1]; from.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+    for i in 1..=from.len() {
+        for j in 1..=to.len() {
+            let replace = distances[i - 1][j - 1] + usize::from(from[i - 1] != to[j - 1]);
+            let mut distance = replace
+                .min(distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1);
+            if i > 1 && j > 1 && from[i - 1] == to[j - 2] && from[i - 2] == to[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+    distances[from.len()][to.len()]
+}
+
+/// Reads exactly the operands declared in `signature` from the line of `command`
+fn try_parse_operands(
+    command: &str,
+    signature: &[(&str, OperandKind)],
+    keywords: &mut Keywords,
+    line_number: u16,
+    directives: &Directives,
+) -> Result<Operands, ParserError> {
+    let mut operands = Vec::with_capacity(signature.len());
+    for &(arg_name, kind) in signature {
+        let keyword = next_operand(keywords, line_number).ok_or(ParserError::MissingArgument {
+            command: String::from(command),
+            arg_name: String::from(arg_name),
+            line_number,
+        })?;
+        operands.push(try_parse_operand(keyword, kind, directives).map_err(|err| {
+            match (kind, keyword) {
+                // keep the detailed message for malformed register identifiers
+                (OperandKind::Register, Keyword::RegisterAddress { .. }) => err,
+                _ => ParserError::CouldNotParseArgument {
+                    command: String::from(command),
+                    arg_name: String::from(arg_name),
+                    arg_value: keyword.get_original_string(),
+                    line_number,
+                    column: keyword.get_column(),
+                },
+            }
+        })?);
+    }
+    if let Some(keyword) = next_operand(keywords, line_number) {
+        return Err(ParserError::UnexpectedArgument {
+            command: String::from(command),
+            arg_value: keyword.get_original_string(),
+            line_number,
+            column: keyword.get_column(),
+        });
+    }
+    Ok(Operands {
+        operands,
+        scratch: ir::Register::new(directives.scratch_register),
+        wide_mode: directives.wide_mode,
+    })
+}
+
+/// Returns the next keyword if it is an operand on the line of the command
+fn next_operand<'a>(keywords: &mut Keywords<'a>, line_number: u16) -> Option<&'a Keyword> {
+    keywords.next_if(|keyword| {
+        keyword.get_line_number() == line_number
+            && !matches!(
+                keyword,
+                Keyword::Mmenonic { .. }
+                    | Keyword::Directive { .. }
+                    | Keyword::Doc { .. }
+                    | Keyword::Attribute { .. }
+            )
+    })
+}
+
+fn try_parse_operand(
+    keyword: &Keyword,
+    kind: OperandKind,
+    directives: &Directives,
+) -> Result<Operand, ParserError> {
+    let symbols = &directives.symbols;
+    match kind {
+        OperandKind::Register => try_parse_register(keyword, &directives.register_aliases)
+            .map(|address| Operand::Register(ir::Register::new(address))),
+        OperandKind::Constant => match keyword {
+            Keyword::Expression { expression, .. } => Ok(Operand::Expression(resolve_symbols(
+                expression, symbols, None,
+            ))),
+            Keyword::Label { name, .. } if symbols.contains_key(name) => Ok(match &symbols[name] {
+                &ir::Expression::Constant(value) if value <= 0xffff => {
+                    Operand::Constant(value as u16)
+                }
+                expression => Operand::Expression(expression.clone()),
+            }),
+            // label addresses are resolved by the generator
+            Keyword::Label { name, .. } => Ok(Operand::Expression(ir::Expression::Label(
+                ir::LabelReference::new(name),
+            ))),
+            _ => try_parse_constant(keyword).map(|constant| Operand::Constant(constant.0)),
+        },
+        OperandKind::Count => {
+            match try_parse_operand(keyword, OperandKind::Constant, directives)? {
+                Operand::Constant(value) => Ok(Operand::Constant(value)),
+                _ => Err(ParserError::ExpectedFound {
+                    expected: String::from("constant"),
+                    found: keyword.get_original_string(),
+                    line_number: keyword.get_line_number(),
+                    column: keyword.get_column(),
+                }),
+            }
+        }
+        OperandKind::Boolean => try_parse_bool(keyword).map(Operand::Boolean),
+        OperandKind::JumpTarget => try_parse_constant(keyword)
+            .map(|constant| ir::JumpTarget::Constant(constant.0))
+            .or_else(|_| try_parse_label_reference(keyword).map(ir::JumpTarget::Label))
+            .or_else(|_| try_parse_jump_expression(keyword))
+            .map(Operand::JumpTarget),
+    }
+}
+
+/// Replaces the labels in `expression` named like a symbol by the value of
+/// the symbol and `$` by `location` if given.
+fn resolve_symbols(
+    expression: &ir::Expression,
+    symbols: &HashMap<String, ir::Expression>,
+    location: Option<u16>,
+) -> ir::Expression {
+    let resolve =
+        |expression: &ir::Expression| Box::new(resolve_symbols(expression, symbols, location));
+    match expression {
+        ir::Expression::Label(reference) if symbols.contains_key(reference.name()) => {
+            symbols[reference.name()].clone()
+        }
+        ir::Expression::Location => match location {
+            Some(location) => ir::Expression::Constant(u32::from(location)),
+            None => ir::Expression::Location,
+        },
+        ir::Expression::Low(inner) => ir::Expression::Low(resolve(inner)),
+        ir::Expression::High(inner) => ir::Expression::High(resolve(inner)),
+        ir::Expression::Sum(a, b) => ir::Expression::Sum(resolve(a), resolve(b)),
+        ir::Expression::Difference(a, b) => ir::Expression::Difference(resolve(a), resolve(b)),
+        ir::Expression::Constant(_) | ir::Expression::Label(_) => expression.clone(),
+    }
+}
+
+fn try_parse_directive(
+    directive: &str,
+    keywords: &mut Keywords,
+    line_number: u16,
+    column: u16,
+    directives: &mut Directives,
+) -> Result<Vec<ir::Instruction>, ParserError> {
+    match directive {
+        "reserve" => {
+            let (register, reason) =
+                try_parse_reserve(keywords, line_number, &directives.register_aliases)?;
+            directives.reserved_registers.insert(register, reason);
+            Ok(Vec::new())
+        }
+        // collected by `collect_register_aliases` before parsing
+        "alias" => {
+            while next_operand(keywords, line_number).is_some() {}
+            Ok(Vec::new())
+        }
+        "scratch" => {
+            let register =
+                next_operand(keywords, line_number).ok_or(ParserError::MissingArgument {
+                    command: String::from(".scratch"),
+                    arg_name: String::from("Register"),
+                    line_number,
+                })?;
+            directives.scratch_register =
+                try_parse_register(register, &directives.register_aliases)?;
+            if let Some(keyword) = next_operand(keywords, line_number) {
+                return Err(ParserError::UnexpectedArgument {
+                    command: String::from(".scratch"),
+                    arg_value: keyword.get_original_string(),
+                    line_number,
+                    column: keyword.get_column(),
+                });
+            }
+            Ok(Vec::new())
+        }
+        "start" => {
+            let label = match next_operand(keywords, line_number) {
+                Some(Keyword::Label { name, .. }) => name.clone(),
+                Some(keyword) => {
+                    return Err(ParserError::CouldNotParseArgument {
+                        command: String::from(".start"),
+                        arg_name: String::from("Label"),
+                        arg_value: keyword.get_original_string(),
+                        line_number,
+                        column: keyword.get_column(),
+                    })
+                }
+                None => {
+                    return Err(ParserError::MissingArgument {
+                        command: String::from(".start"),
+                        arg_name: String::from("Label"),
+                        line_number,
+                    })
+                }
+            };
+            if let Some(keyword) = next_operand(keywords, line_number) {
+                return Err(ParserError::UnexpectedArgument {
+                    command: String::from(".start"),
+                    arg_value: keyword.get_original_string(),
+                    line_number,
+                    column: keyword.get_column(),
+                });
+            }
+            if let Some((_, previous)) = directives.entry {
+                return Err(ParserError::DuplicateEntry {
+                    line_number,
+                    previous,
+                });
+            }
+            directives.entry = Some((label, line_number));
+            Ok(Vec::new())
+        }
+        "equ" => {
+            let (name, value) = try_parse_equ(keywords, line_number, directives)?;
+            directives.symbols.insert(name, value);
+            Ok(Vec::new())
+        }
+        "org" => {
+            let origin = try_parse_org(keywords, line_number)?;
+            directives.origin = Some((origin, line_number));
+            Ok(Vec::new())
+        }
+        // expanded by the lexer, the keywords of the file follow
+        "include" => {
+            match next_operand(keywords, line_number) {
+                Some(Keyword::StringLiteral { .. }) => Ok(()),
+                Some(keyword) => Err(ParserError::CouldNotParseArgument {
+                    command: String::from(".include"),
+                    arg_name: String::from("Path"),
+                    arg_value: keyword.get_original_string(),
+                    line_number,
+                    column: keyword.get_column(),
+                }),
+                None => Err(ParserError::MissingArgument {
+                    command: String::from(".include"),
+                    arg_name: String::from("Path"),
+                    line_number,
+                }),
+            }?;
+            Ok(Vec::new())
+        }
+        INCLUDE_END => Ok(Vec::new()),
+        "word" => try_parse_data(".word", keywords, line_number, |value, _| Some(value)),
+        "space" => try_parse_space(".space", keywords, line_number),
+        "fill" => try_parse_space(".fill", keywords, line_number),
+        "ascii" => try_parse_string(".ascii", keywords, line_number, false, directives),
+        "asciiz" => try_parse_string(".asciiz", keywords, line_number, true, directives),
+        "byte" => try_parse_data(".byte", keywords, line_number, |value, origin| {
+            if value <= 0xff || (origin.starts_with('-') && value as i16 >= -128) {
+                Some(value & 0xff)
+            } else {
+                None
+            }
+        }),
+        unknown => Err(ParserError::UnknownDirective {
+            directive: format!(".{}", unknown),
+            line_number,
+            column,
+        }),
+    }
+}
+
+/// **.alias** `Name` `%Register`
+///
+/// Aliases are valid in the whole program, also in front of their
+/// declaration. An alias may refer to an alias declared before it and may
+/// redefine the conventional names and the `predefined` ones.
+fn collect_register_aliases(
+    keywords: &[Keyword],
+    options: &ParserOptions,
+) -> Result<HashMap<String, ir::RegisterAddress>, ParserError> {
+    let mut aliases: HashMap<String, ir::RegisterAddress> = CONVENTIONAL_REGISTERS
+        .iter()
+        .map(|&(name, register)| (String::from(name), register))
+        .collect();
+    aliases.extend(options.register_aliases.iter().map(|(name, &register)| {
+        if options.case_insensitive {
+            (fold_register_name(name), register)
+        } else {
+            (name.clone(), register)
+        }
+    }));
+    let mut declarations: HashMap<String, u16> = HashMap::new();
+    let mut iter = keywords.iter().peekable();
+    while let Some(keyword) = iter.next() {
+        let Keyword::Directive {
+            name, line_number, ..
+        } = keyword
+        else {
+            continue;
+        };
+        if name != "alias" {
+            continue;
+        }
+        let line_number = *line_number;
+        let missing = |arg_name: &str| ParserError::MissingArgument {
+            command: String::from(".alias"),
+            arg_name: String::from(arg_name),
+            line_number,
+        };
+        let (alias, column) = match next_operand(&mut iter, line_number).ok_or(missing("Name"))? {
+            Keyword::Label { name, column, .. } => (name.clone(), *column),
+            keyword => {
+                return Err(ParserError::CouldNotParseArgument {
+                    command: String::from(".alias"),
+                    arg_name: String::from("Name"),
+                    arg_value: keyword.get_original_string(),
+                    line_number,
+                    column: keyword.get_column(),
+                })
+            }
+        };
+        let register = try_parse_register(
+            next_operand(&mut iter, line_number).ok_or(missing("Register"))?,
+            &aliases,
+        )?;
+        if let Some(keyword) = next_operand(&mut iter, line_number) {
+            return Err(ParserError::UnexpectedArgument {
+                command: String::from(".alias"),
+                arg_value: keyword.get_original_string(),
+                line_number,
+                column: keyword.get_column(),
+            });
+        }
+        if register_address(&alias).is_some() {
+            return Err(ParserError::ShadowedRegister {
+                alias,
+                line_number,
+                column,
+            });
+        }
+        if let Some(&previous) = declarations.get(&alias) {
+            return Err(ParserError::DuplicateAlias {
+                alias,
+                line_number,
+                previous,
+            });
+        }
+        declarations.insert(alias.clone(), line_number);
+        aliases.insert(alias, register);
+    }
+    Ok(aliases)
+}
+
+/// Converts mnemonics, directives and register names of one keyword after
+/// the other to the case the parser expects, also the names declared with
+/// `.alias`
+fn fold_case() -> impl FnMut(Keyword) -> Keyword {
+    let mut in_alias = false;
+    move |keyword| {
+        let alias_name = std::mem::take(&mut in_alias);
+        match keyword {
+            Keyword::Mmenonic {
+                name,
+                line_number,
+                column,
+            } => Keyword::Mmenonic {
+                name: name.to_ascii_lowercase(),
+                line_number,
+                column,
+            },
+            Keyword::Directive {
+                name,
+                line_number,
+                column,
+            } => {
+                let name = name.to_ascii_lowercase();
+                in_alias = name == "alias";
+                Keyword::Directive {
+                    name,
+                    line_number,
+                    column,
+                }
+            }
+            Keyword::RegisterAddress {
+                name,
+                line_number,
+                column,
+            } => Keyword::RegisterAddress {
+                name: fold_register_name(&name),
+                line_number,
+                column,
+            },
+            Keyword::Label {
+                name,
+                line_number,
+                column,
+            } if alias_name => Keyword::Label {
+                name: fold_register_name(&name),
+                line_number,
+                column,
+            },
+            keyword => keyword,
+        }
+    }
+}
+
+/// `REG0` becomes `reg0`, `rega` becomes `regA` and other names lowercase
+fn fold_register_name(name: &str) -> String {
+    match name.get(..3) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("reg") => {
+            format!("reg{}", name[3..].to_ascii_uppercase())
+        }
+        _ => name.to_ascii_lowercase(),
+    }
+}
+
+/// **.reserve** `%Register` `"Reason"`
+///
+/// The reason is optional.
+fn try_parse_reserve(
+    keywords: &mut Keywords,
+    line_number: u16,
+    aliases: &HashMap<String, ir::RegisterAddress>,
+) -> Result<(ir::RegisterAddress, String), ParserError> {
+    let maybe_register =
+        next_operand(keywords, line_number).ok_or(ParserError::MissingArgument {
+            command: String::from(".reserve"),
+            arg_name: String::from("Register"),
+            line_number,
+        })?;
+    let register = try_parse_register(maybe_register, aliases)?;
+    let reason = match next_operand(keywords, line_number) {
+        Some(Keyword::StringLiteral { value, .. }) => value.clone(),
+        Some(keyword) => {
+            return Err(ParserError::CouldNotParseArgument {
+                command: String::from(".reserve"),
+                arg_name: String::from("Reason"),
+                arg_value: keyword.get_original_string(),
+                line_number,
+                column: keyword.get_column(),
+            })
+        }
+        None => String::new(),
+    };
+    Ok((register, reason))
+}
+
+/// **.word** `Constant16` ... / **.byte** `Constant8` ...
+///
+/// Every value becomes one data word, `convert` returns `None` for values
+/// out of range.
+fn try_parse_data(
+    command: &str,
+    keywords: &mut Keywords,
+    line_number: u16,
+    convert: fn(u16, &str) -> Option<u16>,
+) -> Result<Vec<ir::Instruction>, ParserError> {
+    let mut data = Vec::new();
+    while let Some(keyword) = next_operand(keywords, line_number) {
+        let value = match keyword {
+            Keyword::Constant { value, origin, .. } => convert(*value, origin),
+            _ => None,
+        }
+        .ok_or(ParserError::CouldNotParseArgument {
+            command: String::from(command),
+            arg_name: String::from("Value"),
+            arg_value: keyword.get_original_string(),
+            line_number,
+            column: keyword.get_column(),
+        })?;
+        data.push(ir::Instruction::Data(ir::Constant(value)));
+    }
+    if data.is_empty() {
+        return Err(ParserError::MissingArgument {
+            command: String::from(command),
+            arg_name: String::from("Value"),
+            line_number,
+        });
+    }
+    Ok(data)
+}
+
+/// **.space** `Count` `Fill` / **.fill** `Count` `Fill`
+///
+/// Reserves `Count` data words with the value `Fill`, which is optional
+/// and defaults to zero.
+fn try_parse_space(
+    command: &str,
+    keywords: &mut Keywords,
+    line_number: u16,
+) -> Result<Vec<ir::Instruction>, ParserError> {
+    let count = next_operand(keywords, line_number).ok_or(ParserError::MissingArgument {
+        command: String::from(command),
+        arg_name: String::from("Count"),
+        line_number,
+    })?;
+    let count = try_parse_constant(count).map_err(|_| ParserError::CouldNotParseArgument {
+        command: String::from(command),
+        arg_name: String::from("Count"),
+        arg_value: count.get_original_string(),
+        line_number,
+        column: count.get_column(),
+    })?;
+    let fill = match next_operand(keywords, line_number) {
+        Some(fill) => try_parse_constant(fill).map_err(|_| ParserError::CouldNotParseArgument {
+            command: String::from(command),
+            arg_name: String::from("Fill"),
+            arg_value: fill.get_original_string(),
+            line_number,
+            column: fill.get_column(),
+        })?,
+        None => ir::Constant(0),
+    };
+    if let Some(keyword) = next_operand(keywords, line_number) {
+        return Err(ParserError::UnexpectedArgument {
+            command: String::from(command),
+            arg_value: keyword.get_original_string(),
+            line_number,
+            column: keyword.get_column(),
+        });
+    }
+    Ok((0..count.0).map(|_| ir::Instruction::Data(fill)).collect())
+}
+
+/// **.ascii** `"String"` ... / **.asciiz** `"String"` ...
+///
+/// `.asciiz` terminates every string with a zero character.
+fn try_parse_string(
+    command: &str,
+    keywords: &mut Keywords,
+    line_number: u16,
+    zero_terminated: bool,
+    directives: &Directives,
+) -> Result<Vec<ir::Instruction>, ParserError> {
+    let mut data = Vec::new();
+    let mut strings = 0;
+    while let Some(keyword) = next_operand(keywords, line_number) {
+        strings += 1;
+        let mut bytes = match keyword {
+            Keyword::StringLiteral { value, .. } if value.is_ascii() => value.clone().into_bytes(),
+            _ => {
+                return Err(ParserError::CouldNotParseArgument {
+                    command: String::from(command),
+                    arg_name: String::from("String"),
+                    arg_value: keyword.get_original_string(),
+                    line_number,
+                    column: keyword.get_column(),
+                })
+            }
+        };
+        if zero_terminated {
+            bytes.push(0);
+        }
+        if directives.options.pack_strings {
+            data.extend(bytes.chunks(2).map(|pair| {
+                let low = pair.get(1).copied().unwrap_or_default();
+                ir::Instruction::Data(ir::Constant(u16::from_be_bytes([pair[0], low])))
+            }));
+        } else {
+            data.extend(
+                bytes
+                    .into_iter()
+                    .map(|byte| ir::Instruction::Data(ir::Constant(u16::from(byte)))),
+            );
+        }
+    }
+    if strings == 0 {
+        return Err(ParserError::MissingArgument {
+            command: String::from(command),
+            arg_name: String::from("String"),
+            line_number,
+        });
+    }
+    Ok(data)
+}
+
+/// **.org** `Address`
+fn try_parse_org(keywords: &mut Keywords, line_number: u16) -> Result<u16, ParserError> {
+    let maybe_address =
+        next_operand(keywords, line_number).ok_or(ParserError::MissingArgument {
+            command: String::from(".org"),
+            arg_name: String::from("Address"),
+            line_number,
+        })?;
+    try_parse_constant(maybe_address)
+        .map(|address| address.0)
+        .map_err(|_| ParserError::CouldNotParseArgument {
+            command: String::from(".org"),
+            arg_name: String::from("Address"),
+            arg_value: maybe_address.get_original_string(),
+            line_number,
+            column: maybe_address.get_column(),
+        })
+}
+
+/// Moves `label` to the address of a preceding `.org`, which must not lie
+/// before the address the label would get otherwise
+fn apply_origin(
+    label: &mut ir::LabelDefinition,
+    directives: &mut Directives,
+) -> Result<(), ParserError> {
+    if let Some((origin, line_number)) = directives.origin.take() {
+        if origin < label.address.0 {
+            return Err(ParserError::InvalidOrigin {
+                origin,
+                location: label.address.0,
+                line_number,
+            });
+        }
+        label.address.0 = origin;
+        label.fixed = true;
+    }
+    Ok(())
+}
+
+/// `.org` places the label definition following it
+fn check_no_pending_origin(
+    directives: &Directives,
+    next_keyword: &Keyword,
+) -> Result<(), ParserError> {
+    match directives.origin {
+        Some(_) => Err(ParserError::ExpectedFound {
+            expected: String::from("label definition after .org"),
+            found: next_keyword.get_original_string(),
+            line_number: next_keyword.get_line_number(),
+            column: next_keyword.get_column(),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Registers reserved with `.reserve` must not be used explicitly
+/// by the instructions following the directive
+fn check_reserved_registers(
+    instruction: &ir::Instruction,
+    reserved_registers: &HashMap<ir::RegisterAddress, String>,
+    command: &Keyword,
+) -> Result<(), ParserError> {
+    for register in instruction.registers() {
+        if let Some(reason) = reserved_registers.get(&register) {
+            return Err(ParserError::ReservedRegister {
+                register: format!("%reg{}", register.0),
+                reason: reason.clone(),
+                line_number: command.get_line_number(),
+                column: command.get_column(),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn try_parse_bool(keyword: &Keyword) -> Result<ir::Boolean, ParserError> {
+    match keyword {
+        &Keyword::Boolean { value, .. } => Ok(ir::Boolean(value)),
+        _ => Err(ParserError::ExpectedFound {
+            expected: String::from("Keyword::Boolean"),
+            found: format!("{:?}", keyword),
+            line_number: keyword.get_line_number(),
+            column: keyword.get_column(),
+        }),
+    }
+}
+
+fn try_parse_constant(keyword: &Keyword) -> Result<ir::Constant, ParserError> {
+    match keyword {
+        &Keyword::Constant { value, .. } => Ok(ir::Constant(value)),
+        _ => Err(ParserError::ExpectedFound {
+            expected: String::from("Keyword::Constant"),
+            found: format!("{:?}", keyword),
+            line_number: keyword.get_line_number(),
+            column: keyword.get_column(),
+        }),
+    }
+}
+
+fn try_parse_label_definition(
+    keyword: &Keyword,
+    last_label_address: u16,
+    instructions_since_label: u16,
+) -> Result<ir::LabelDefinition, ParserError> {
+    match keyword {
+        Keyword::Label {
+            name,
+            line_number,
+            column,
+        } => last_label_address
+            .checked_add(instructions_since_label)
+            .map(|address| ir::LabelDefinition::new(name, address))
+            .ok_or_else(|| ParserError::LabelOutOfRange {
+                label: name.clone(),
+                line_number: *line_number,
+                column: *column,
+            }),
+        _ => Err(ParserError::ExpectedFound {
+            expected: String::from("Keyword::Label"),
+            found: format!("{:?}", keyword),
+            line_number: keyword.get_line_number(),
+            column: keyword.get_column(),
+        }),
+    }
+}
+
+/// `[[noreturn]]`, `[[interrupt]]` or `[[align=N]]`
+fn try_parse_label_attributes(attributes: &[&Keyword]) -> Result<ir::LabelAttributes, ParserError> {
+    let mut label_attributes = ir::LabelAttributes::default();
+    for attribute in attributes {
+        match attribute {
+            Keyword::Attribute {
+                name, value: None, ..
+            } if name == "noreturn" => label_attributes.noreturn = true,
+            Keyword::Attribute {
+                name, value: None, ..
+            } if name == "interrupt" => label_attributes.interrupt = true,
+            Keyword::Attribute {
+                name,
+                value: Some(value),
+                ..
+            } if name == "align" => {
+                label_attributes.align = Some(
+                    if let Some(hex_value) = value.strip_prefix("0x") {
+                        u16::from_str_radix(hex_value, 16)
+                    } else {
+                        value.parse::<u16>()
+                    }
+                    .ok()
+                    .filter(|&align| align > 0)
+                    .ok_or(ParserError::InvalidAttribute {
+                        attribute: attribute.get_original_string(),
+                        line_number: attribute.get_line_number(),
+                        column: attribute.get_column(),
+                    })?,
+                )
+            }
+            _ => {
+                return Err(ParserError::InvalidAttribute {
+                    attribute: attribute.get_original_string(),
+                    line_number: attribute.get_line_number(),
+                    column: attribute.get_column(),
+                })
+            }
+        }
+    }
+    Ok(label_attributes)
+}
+
+/// Label attributes are only allowed directly in front of a label definition
+fn check_no_pending_attributes(
+    attributes: &[&Keyword],
+    next_keyword: &Keyword,
+) -> Result<(), ParserError> {
+    if attributes.is_empty() {
+        Ok(())
+    } else {
+        Err(ParserError::ExpectedFound {
+            expected: String::from("label definition after label attribute"),
+            found: next_keyword.get_original_string(),
+            line_number: next_keyword.get_line_number(),
+            column: next_keyword.get_column(),
+        })
+    }
+}
+
+fn try_parse_label_reference(keyword: &Keyword) -> Result<ir::LabelReference, ParserError> {
+    match &keyword {
+        Keyword::Label { name, .. } => Ok(ir::LabelReference::new(name)),
+        _ => Err(ParserError::ExpectedFound {
+            expected: String::from("Keyword::Label"),
+            found: format!("{:?}", keyword),
+            line_number: keyword.get_line_number(),
+            column: keyword.get_column(),
+        }),
+    }
+}
+
+/// `label+offset` / `label-offset` or `$`, `$+offset` / `$-offset` as jump target
+fn try_parse_jump_expression(keyword: &Keyword) -> Result<ir::JumpTarget, ParserError> {
+    let offset = |expression: &ir::Expression, offset: u32| {
+        let offset = offset as i16;
+        if matches!(expression, ir::Expression::Sum(..)) {
+            offset
+        } else {
+            offset.wrapping_neg()
+        }
+    };
+    match keyword {
+        Keyword::Expression {
+            expression: ir::Expression::Location,
+            ..
+        } => Some(ir::JumpTarget::Constant(0)),
+        Keyword::Expression { expression, .. } => match expression {
+            ir::Expression::Sum(base, value) | ir::Expression::Difference(base, value) => {
+                match (base.as_ref(), value.as_ref()) {
+                    (ir::Expression::Label(label), &ir::Expression::Constant(value)) => {
+                        Some(ir::JumpTarget::LabelOffset {
+                            label: label.clone(),
+                            offset: offset(expression, value),
+                        })
+                    }
+                    (ir::Expression::Location, &ir::Expression::Constant(value)) => {
+                        Some(ir::JumpTarget::Constant(offset(expression, value) as u16))
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+    .ok_or(ParserError::ExpectedFound {
+        expected: String::from("label+offset"),
+        found: keyword.get_original_string(),
+        line_number: keyword.get_line_number(),
+        column: keyword.get_column(),
+    })
+}
+
+/// **.equ** `Name` `Value`
+///
+/// Symbols with constant values are defined by the preprocessor, the
+/// parser defines the symbols whose value depends on addresses like
+/// `.equ SIZE $-start`. `$` is the address of the next instruction.
+fn try_parse_equ(
+    keywords: &mut Keywords,
+    line_number: u16,
+    directives: &Directives,
+) -> Result<(String, ir::Expression), ParserError> {
+    let missing = |arg_name: &str| ParserError::MissingArgument {
+        command: String::from(".equ"),
+        arg_name: String::from(arg_name),
+        line_number,
+    };
+    let name = next_operand(keywords, line_number).ok_or(missing("Name"))?;
+    let name = try_parse_label_reference(name).map_err(|_| ParserError::CouldNotParseArgument {
+        command: String::from(".equ"),
+        arg_name: String::from("Name"),
+        arg_value: name.get_original_string(),
+        line_number,
+        column: name.get_column(),
+    })?;
+    let value = match next_operand(keywords, line_number).ok_or(missing("Value"))? {
+        Keyword::Constant { value, .. } => ir::Expression::Constant(u32::from(*value)),
+        Keyword::Label { name, .. } => ir::Expression::Label(ir::LabelReference::new(name)),
+        Keyword::Expression { expression, .. } => expression.clone(),
+        keyword => {
+            return Err(ParserError::CouldNotParseArgument {
+                command: String::from(".equ"),
+                arg_name: String::from("Value"),
+                arg_value: keyword.get_original_string(),
+                line_number,
+                column: keyword.get_column(),
+            })
+        }
+    };
+    if let Some(keyword) = next_operand(keywords, line_number) {
+        return Err(ParserError::UnexpectedArgument {
+            command: String::from(".equ"),
+            arg_value: keyword.get_original_string(),
+            line_number,
+            column: keyword.get_column(),
+        });
+    }
+    let value = resolve_symbols(&value, &directives.symbols, Some(directives.location));
+    Ok((name.name().to_string(), value))
+}
+
+/// Address of the register named `name` without the leading `%`, like
+/// `reg3` or `regD`. Aliases are not resolved.
+pub fn register_address(name: &str) -> Option<ir::RegisterAddress> {
+    let keyword = Keyword::register_address(name, 0);
+    try_parse_register(&keyword, &HashMap::new()).ok()
+}
+
+/// Parses `%reg0` .. `%reg7`, `%regA` .. `%regH` and names declared with
+/// `.alias`
+fn try_parse_register(
+    keyword: &Keyword,
+    aliases: &HashMap<String, ir::RegisterAddress>,
+) -> Result<ir::RegisterAddress, ParserError> {
+    match keyword {
+        Keyword::RegisterAddress { name, .. } if aliases.contains_key(name) => Ok(aliases[name]),
+        Keyword::RegisterAddress {
+            name, line_number, ..
+        } => if let Some(register_number) = name.strip_prefix("reg") {
+            if register_number.chars().count() != 1 {
+                None
+            } else {
+                let char = register_number.chars().next().unwrap();
+                match register_number.chars().next().unwrap() {
+                    '0'..='7' => Some(char.to_digit(8).unwrap()),
+                    'A'..='H' => Some(u32::from(char) - u32::from('A')),
+                    _ => None,
+                }
+            }
+            .and_then(|address| u8::try_from(address).ok())
+        } else {
+            None
+        }
+        .map(ir::RegisterAddress)
+        .ok_or_else(|| ParserError::InvalidRegister {
+            register: name.to_string(),
+            line_number: *line_number,
+            column: keyword.get_column(),
+            suggestions: similar_registers(name, aliases),
+        }),
+        _ => Err(ParserError::ExpectedFound {
+            expected: String::from("Keyword::RegisterAddress"),
+            found: format!("{:?}", keyword),
+            line_number: keyword.get_line_number(),
+            column: keyword.get_column(),
+        }),
+    }
+}
+
+/// Register names and `aliases` with the smallest edit distance to `name`.
+/// Out of range numbers like `reg9` are as close to several registers, the
+/// error lists the valid range instead.
+fn similar_registers(name: &str, aliases: &HashMap<String, ir::RegisterAddress>) -> Vec<String> {
+    let out_of_range = name
+        .strip_prefix("reg")
+        .is_some_and(|number| number.chars().all(|c| c.is_ascii_digit()));
+    if out_of_range {
+        return Vec::new();
+    }
+    let mut aliases: Vec<&str> = aliases.keys().map(String::as_str).collect();
+    aliases.sort_unstable();
+    let registers: Vec<String> = ('0'..='7')
+        .chain('A'..='H')
+        .map(|number| format!("reg{}", number))
+        .collect();
+    closest(name, registers.iter().map(String::as_str).chain(aliases))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_all_instructions() {
+        let lexed = vec![
+            Keyword::mmenonic("ldc", 0),
+            Keyword::register_address("reg0", 0),
+            Keyword::constant("0x00", 0, 0),
+            Keyword::mmenonic("ldc", 1),
+            Keyword::register_address("reg7", 1),
+            Keyword::constant("-0x01", 1u16.wrapping_neg(), 1),
+            Keyword::mmenonic("ldc", 2),
+            Keyword::register_address("regA", 2),
+            Keyword::constant("42", 42, 2),
+            Keyword::mmenonic("ldc", 3),
+            Keyword::register_address("regH", 3),
+            Keyword::constant("-1337", 1337u16.wrapping_neg(), 3),
+            Keyword::mmenonic("add", 4),
+            Keyword::register_address("reg0", 4),
+            Keyword::register_address("reg1", 4),
+            Keyword::register_address("reg2", 4),
+            Keyword::mmenonic("add3", 5),
+            Keyword::register_address("reg3", 5),
+            Keyword::register_address("reg4", 5),
+            Keyword::register_address("reg5", 5),
+            Keyword::register_address("reg6", 5),
+            Keyword::mmenonic("addc", 6),
+            Keyword::register_address("regB", 6),
+            Keyword::register_address("regC", 6),
+            Keyword::register_address("regD", 6),
+            Keyword::mmenonic("sub", 7),
+            Keyword::register_address("regE", 7),
+            Keyword::register_address("regF", 7),
+            Keyword::register_address("regG", 7),
+            Keyword::mmenonic("subc", 8),
+            Keyword::register_address("reg0", 8),
+            Keyword::register_address("reg0", 8),
+            Keyword::register_address("reg0", 8),
+            Keyword::mmenonic("inc", 9),
+            Keyword::register_address("reg0", 9),
+            Keyword::mmenonic("dec", 10),
+            Keyword::register_address("reg0", 10),
+            Keyword::mmenonic("mul", 11),
+            Keyword::register_address("reg0", 11),
+            Keyword::register_address("reg0", 11),
+            Keyword::register_address("reg0", 11),
+            Keyword::mmenonic("and", 12),
+            Keyword::register_address("reg0", 12),
+            Keyword::register_address("reg0", 12),
+            Keyword::register_address("reg0", 12),
+            Keyword::mmenonic("or", 13),
+            Keyword::register_address("reg0", 13),
+            Keyword::register_address("reg0", 13),
+            Keyword::register_address("reg0", 13),
+            Keyword::mmenonic("not", 14),
+            Keyword::register_address("reg0", 14),
+            Keyword::register_address("reg0", 14),
+            Keyword::mmenonic("neg", 15),
+            Keyword::register_address("reg0", 15),
+            Keyword::register_address("reg0", 15),
+            Keyword::mmenonic("xor", 16),
+            Keyword::register_address("reg0", 16),
+            Keyword::register_address("reg0", 16),
+            Keyword::register_address("reg0", 16),
+            Keyword::mmenonic("xnor", 17),
+            Keyword::register_address("reg0", 17),
+            Keyword::register_address("reg0", 17),
+            Keyword::register_address("reg0", 17),
+            Keyword::mmenonic("shl", 18),
+            Keyword::register_address("reg0", 18),
+            Keyword::register_address("reg0", 18),
+            Keyword::register_address("reg0", 18),
             Keyword::mmenonic("shr", 19),
             Keyword::register_address("reg0", 19),
             Keyword::register_address("reg0", 19),
@@ -1011,88 +3093,1612 @@ mod tests {
                 ],
             ),
         ];
-        let expected_label_definitions = vec![
+        let expected_label_definitions = vec![
+            (
+                ir::LabelReference::new("main"),
+                ir::LabelDefinition::new("main", 0),
+            ),
+            (
+                ir::LabelReference::new("jump"),
+                ir::LabelDefinition::new("jump", 28),
+            ),
+        ];
+
+        let expected = ir::IR {
+            start_label: ir::LabelReference::new("main"),
+            label_definitions: ir::LabelLUT(expected_label_definitions.into_iter().collect()),
+            instructions: expected_instructions.into_iter().collect(),
+        };
+
+        let found = parser(lexed).unwrap();
+
+        assert_eq!(
+            expected.label_definitions.0.len(),
+            found.label_definitions.0.len(),
+            "found too few label definitions",
+        );
+        let mut exp_ld_vec = expected
+            .label_definitions
+            .0
+            .iter()
+            .collect::<Vec<(&ir::LabelReference, &ir::LabelDefinition)>>();
+
+        let mut found_ld_vec = found
+            .label_definitions
+            .0
+            .iter()
+            .collect::<Vec<(&ir::LabelReference, &ir::LabelDefinition)>>();
+
+        found_ld_vec.sort_by_key(|a| a.1.address);
+        exp_ld_vec.sort_by_key(|a| a.1.address);
+
+        for (expected_label_definition, found_label_definition) in
+            exp_ld_vec.iter().zip(found_ld_vec.iter())
+        {
+            assert_eq!(expected_label_definition.0, found_label_definition.0);
+            assert_eq!(
+                expected_label_definition.1.address, found_label_definition.1.address,
+                "label definitions addresses do not match",
+            );
+        }
+
+        assert_eq!(
+            expected.instructions.len(),
+            found.instructions.len(),
+            "found too few label references in instructions",
+        );
+
+        let mut exp_instr_keys = expected.instructions.keys().collect::<Vec<_>>();
+        let mut found_instr_keys = found.instructions.keys().collect::<Vec<_>>();
+
+        exp_instr_keys.sort_by(|a, b| a.name().cmp(b.name()));
+        found_instr_keys.sort_by(|a, b| a.name().cmp(b.name()));
+
+        for (expected_key_label, found_key_label) in
+            exp_instr_keys.iter().zip(found_instr_keys.iter())
+        {
+            assert_eq!(expected_key_label, found_key_label);
+            for (expected_instruction, found_instruction) in expected
+                .instructions
+                .get(expected_key_label)
+                .unwrap()
+                .iter()
+                .zip(found.instructions.get(found_key_label).unwrap().iter())
+            {
+                assert_eq!(
+                    expected_instruction, found_instruction,
+                    "instructions do not match"
+                );
+            }
+        }
+
+        assert_eq!(
+            expected.start_label, found.start_label,
+            "start label do not match"
+        );
+    }
+
+    #[test]
+    fn doc_comments() {
+        let lexed = vec![
+            Keyword::doc("Entry point", 1),
+            Keyword::label("main", 1),
+            Keyword::mmenonic("nop", 2),
+            Keyword::doc("Stops the program", 3),
+            Keyword::label("done", 3),
+            Keyword::mmenonic("hlt", 4),
+            Keyword::label("undocumented", 5),
+            Keyword::mmenonic("hlt", 6),
+        ];
+
+        let found = parser(lexed).unwrap();
+
+        let doc = |name: &str| {
+            found
+                .label_definitions
+                .0
+                .get(&ir::LabelReference::new(name))
+                .unwrap()
+                .doc
+                .clone()
+        };
+        assert_eq!(Some(String::from("Entry point")), doc("main"));
+        assert_eq!(Some(String::from("Stops the program")), doc("done"));
+        assert_eq!(None, doc("undocumented"));
+    }
+
+    #[test]
+    fn label_attributes() {
+        let lexed = vec![
+            Keyword::mmenonic("nop", 0),
+            Keyword::attribute("align", Some("4"), 1),
+            Keyword::label("aligned", 2),
+            Keyword::mmenonic("nop", 3),
+            Keyword::attribute("noreturn", None, 4),
+            Keyword::label("done", 5),
+            Keyword::mmenonic("hlt", 6),
+        ];
+
+        let found = parser(lexed).unwrap();
+
+        let aligned = found
+            .label_definitions
+            .0
+            .get(&ir::LabelReference::new("aligned"))
+            .unwrap();
+        assert_eq!(ir::MemoryAddress(4), aligned.address);
+        assert_eq!(Some(4), aligned.attributes.align);
+        let done = found
+            .label_definitions
+            .0
+            .get(&ir::LabelReference::new("done"))
+            .unwrap();
+        assert_eq!(ir::MemoryAddress(5), done.address);
+        assert!(done.attributes.noreturn);
+        assert!(!done.attributes.interrupt);
+
+        assert!(matches!(
+            parser(vec![
+                Keyword::attribute("inline", None, 0),
+                Keyword::label("main", 1),
+            ])
+            .as_ref()
+            .map_err(Vec::as_slice),
+            Err([ParserError::InvalidAttribute { .. }])
+        ));
+        // the label is still defined, the instruction after it is no error
+        assert!(matches!(
+            parser(vec![
+                Keyword::attribute("align", Some("0"), 0),
+                Keyword::label("main", 1),
+                Keyword::mmenonic("hlt", 2),
+            ])
+            .as_ref()
+            .map_err(Vec::as_slice),
+            Err([ParserError::InvalidAttribute { line_number: 0, .. }])
+        ));
+        assert!(matches!(
+            parser(vec![
+                Keyword::attribute("noreturn", None, 0),
+                Keyword::mmenonic("hlt", 1),
+            ])
+            .as_ref()
+            .map_err(Vec::as_slice),
+            Err([ParserError::ExpectedFound { .. }])
+        ));
+        let align = |line_number| Keyword::attribute("align", Some("0x8000"), line_number);
+        match parser(vec![
+            Keyword::mmenonic("nop", 0),
+            align(1),
+            Keyword::label("high", 2),
+            Keyword::mmenonic("nop", 3),
+            align(4),
+            Keyword::label("beyond", 5),
+            Keyword::mmenonic("hlt", 6),
+        ])
+        .as_ref()
+        .map_err(Vec::as_slice)
+        {
+            Err(
+                [ParserError::LabelOutOfRange {
+                    label, line_number, ..
+                }],
+            ) => assert_eq!(("beyond", 5), (label.as_str(), *line_number)),
+            found => panic!("expected label out of range, found {:?}", found.err()),
+        }
+    }
+
+    #[test]
+    fn reserved_registers() {
+        let reserve = || {
+            vec![
+                Keyword::directive("reserve", 0),
+                Keyword::register_address("reg7", 0),
+                Keyword::string_literal("\"stack pointer\"", "stack pointer", 0),
+            ]
+        };
+
+        let mut allowed = reserve();
+        allowed.append(&mut vec![
+            Keyword::mmenonic("mov", 1),
+            Keyword::register_address("reg0", 1),
+            Keyword::register_address("reg1", 1),
+        ]);
+        assert!(parser(allowed).is_ok());
+
+        let mut forbidden = reserve();
+        forbidden.append(&mut vec![
+            Keyword::mmenonic("nop", 1),
+            Keyword::mmenonic("mov", 2),
+            Keyword::register_address("reg7", 2),
+            Keyword::register_address("reg1", 2),
+        ]);
+        match parser(forbidden).as_ref().map_err(Vec::as_slice) {
+            Err(
+                [ParserError::ReservedRegister {
+                    register,
+                    reason,
+                    line_number,
+                    ..
+                }],
+            ) => {
+                assert_eq!("%reg7", register);
+                assert_eq!("stack pointer", reason);
+                assert_eq!(2, *line_number);
+            }
+            found => panic!("expected reserved register error, found {:?}", found.err()),
+        }
+
+        assert!(matches!(
+            parser(vec![Keyword::directive("unknown", 0)])
+                .as_ref()
+                .map_err(Vec::as_slice),
+            Err([ParserError::UnknownDirective { .. }])
+        ));
+    }
+
+    #[test]
+    fn origin() {
+        let lexed = vec![
+            Keyword::mmenonic("nop", 0),
+            Keyword::directive("org", 1),
+            Keyword::constant("0x10", 0x10, 1),
+            Keyword::label("isr", 2),
+            Keyword::mmenonic("hlt", 3),
+        ];
+
+        let found = parser(lexed).unwrap();
+
+        let isr = found
+            .label_definitions
+            .0
+            .get(&ir::LabelReference::new("isr"))
+            .unwrap();
+        assert_eq!(ir::MemoryAddress(0x10), isr.address);
+
+        match parser(vec![
+            Keyword::mmenonic("nop", 0),
+            Keyword::mmenonic("nop", 1),
+            Keyword::directive("org", 2),
+            Keyword::constant("1", 1, 2),
+            Keyword::label("isr", 3),
+        ])
+        .as_ref()
+        .map_err(Vec::as_slice)
+        {
+            Err(
+                [ParserError::InvalidOrigin {
+                    origin,
+                    location,
+                    line_number,
+                }],
+            ) => {
+                assert_eq!((1, 2, 2), (*origin, *location, *line_number));
+            }
+            found => panic!("expected invalid origin, found {:?}", found.err()),
+        }
+        match parser(vec![
+            Keyword::directive("org", 0),
+            Keyword::constant("0xffff", 0xffff, 0),
+            Keyword::label("last", 1),
+            Keyword::mmenonic("nop", 2),
+            Keyword::label("end", 4),
+        ])
+        .as_ref()
+        .map_err(Vec::as_slice)
+        {
+            Err(
+                [ParserError::LabelOutOfRange {
+                    label, line_number, ..
+                }],
+            ) => assert_eq!(("end", 4), (label.as_str(), *line_number)),
+            found => panic!("expected label out of range, found {:?}", found.err()),
+        }
+        assert!(matches!(
+            parser(vec![
+                Keyword::directive("org", 0),
+                Keyword::constant("0x10", 0x10, 0),
+                Keyword::mmenonic("hlt", 1),
+            ])
+            .as_ref()
+            .map_err(Vec::as_slice),
+            Err([ParserError::ExpectedFound { .. }])
+        ));
+    }
+
+    #[test]
+    fn data_directives() {
+        let lexed = vec![
+            Keyword::directive("word", 0),
+            Keyword::constant("0x1234", 0x1234, 0),
+            Keyword::constant("-1", 1u16.wrapping_neg(), 0),
+            Keyword::label("table", 1),
+            Keyword::directive("byte", 2),
+            Keyword::constant("0xab", 0xab, 2),
+            Keyword::constant("-128", 128u16.wrapping_neg(), 2),
+            Keyword::label("done", 3),
+            Keyword::mmenonic("hlt", 4),
+        ];
+
+        let found = parser(lexed).unwrap();
+
+        let data = |name: &str| {
+            let reference = ir::LabelReference::new(name);
             (
-                ir::LabelReference::new("main"),
-                ir::LabelDefinition::new("main", 0),
+                found.label_definitions.0.get(&reference).unwrap().address,
+                found.instructions.get(&reference).unwrap(),
+            )
+        };
+        assert_eq!(
+            (
+                ir::MemoryAddress(0),
+                &vec![
+                    ir::Instruction::Data(ir::Constant(0x1234)),
+                    ir::Instruction::Data(ir::Constant(0xffff)),
+                ]
             ),
+            data("main")
+        );
+        assert_eq!(
             (
-                ir::LabelReference::new("jump"),
-                ir::LabelDefinition::new("jump", 28),
+                ir::MemoryAddress(2),
+                &vec![
+                    ir::Instruction::Data(ir::Constant(0xab)),
+                    ir::Instruction::Data(ir::Constant(0x80)),
+                ]
+            ),
+            data("table")
+        );
+        assert_eq!(ir::MemoryAddress(4), data("done").0);
+
+        assert!(matches!(
+            parser(vec![
+                Keyword::directive("byte", 0),
+                Keyword::constant("256", 256, 0),
+            ])
+            .as_ref()
+            .map_err(Vec::as_slice),
+            Err([ParserError::CouldNotParseArgument { .. }])
+        ));
+        assert!(matches!(
+            parser(vec![Keyword::directive("word", 0)])
+                .as_ref()
+                .map_err(Vec::as_slice),
+            Err([ParserError::MissingArgument { .. }])
+        ));
+    }
+
+    #[test]
+    fn space_directive() {
+        let lexed = vec![
+            Keyword::label("scratch", 0),
+            Keyword::directive("space", 1),
+            Keyword::constant("3", 3, 1),
+            Keyword::label("ones", 2),
+            Keyword::directive("fill", 3),
+            Keyword::constant("2", 2, 3),
+            Keyword::constant("0xffff", 0xffff, 3),
+            Keyword::label("done", 4),
+            Keyword::mmenonic("hlt", 5),
+        ];
+
+        let found = parser(lexed).unwrap();
+
+        let instructions = |name: &str| {
+            found
+                .instructions
+                .get(&ir::LabelReference::new(name))
+                .unwrap()
+        };
+        let data = |value: u16, count: usize| {
+            (0..count)
+                .map(|_| ir::Instruction::Data(ir::Constant(value)))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(&data(0, 3), instructions("scratch"));
+        assert_eq!(&data(0xffff, 2), instructions("ones"));
+        assert_eq!(
+            ir::MemoryAddress(5),
+            found
+                .label_definitions
+                .0
+                .get(&ir::LabelReference::new("done"))
+                .unwrap()
+                .address
+        );
+
+        assert!(matches!(
+            parser(vec![Keyword::directive("space", 0)])
+                .as_ref()
+                .map_err(Vec::as_slice),
+            Err([ParserError::MissingArgument { .. }])
+        ));
+        assert!(matches!(
+            parser(vec![
+                Keyword::directive("space", 0),
+                Keyword::constant("1", 1, 0),
+                Keyword::constant("0", 0, 0),
+                Keyword::constant("0", 0, 0),
+            ])
+            .as_ref()
+            .map_err(Vec::as_slice),
+            Err([ParserError::UnexpectedArgument { .. }])
+        ));
+        match parser(vec![
+            Keyword::label("main", 0),
+            Keyword::directive("space", 1),
+            Keyword::constant("40000", 40000, 1),
+            Keyword::directive("space", 2),
+            Keyword::constant("40000", 40000, 2),
+        ])
+        .as_ref()
+        .map_err(Vec::as_slice)
+        {
+            Err(
+                [ParserError::LocationOutOfRange {
+                    command,
+                    line_number,
+                    ..
+                }],
+            ) => assert_eq!((".space", 2), (command.as_str(), *line_number)),
+            found => panic!("expected location out of range, found {:?}", found.err()),
+        }
+    }
+
+    #[test]
+    fn string_directives() {
+        let lexed = || {
+            vec![
+                Keyword::directive("asciiz", 0),
+                Keyword::string_literal("\"Hi!\"", "Hi!", 0),
+                Keyword::directive("ascii", 1),
+                Keyword::string_literal("\"\\n\"", "\n", 1),
+            ]
+        };
+        let data = |ir: ir::IR| -> Vec<u16> {
+            ir.instructions
+                .get(&ir::LabelReference::new("main"))
+                .unwrap()
+                .iter()
+                .map(|instruction| match instruction {
+                    ir::Instruction::Data(constant) => constant.0,
+                    _ => panic!("expected data, found {:?}", instruction),
+                })
+                .collect()
+        };
+
+        assert_eq!(
+            vec![0x48, 0x69, 0x21, 0x00, 0x0a],
+            data(parser(lexed()).unwrap())
+        );
+        let packed = ParserOptions {
+            pack_strings: true,
+            ..ParserOptions::default()
+        };
+        assert_eq!(
+            vec![0x4869, 0x2100, 0x0a00],
+            data(parser_with_options(lexed(), &packed).unwrap())
+        );
+
+        assert!(matches!(
+            parser(vec![
+                Keyword::directive("ascii", 0),
+                Keyword::constant("42", 42, 0),
+            ])
+            .as_ref()
+            .map_err(Vec::as_slice),
+            Err([ParserError::CouldNotParseArgument { .. }])
+        ));
+    }
+
+    #[test]
+    fn includes() {
+        let lexed = crate::lexer::lexer(std::path::Path::new("tests/include/main.s")).unwrap();
+
+        let found = parser(lexed).unwrap();
+
+        let lib = found
+            .label_definitions
+            .0
+            .get(&ir::LabelReference::new("lib"))
+            .unwrap();
+        assert_eq!(ir::MemoryAddress(1), lib.address);
+        assert_eq!(Some(String::from("Increments register 0")), lib.doc);
+        assert_eq!(
+            3,
+            found
+                .instructions
+                .get(&ir::LabelReference::new("lib"))
+                .unwrap()
+                .len()
+        );
+    }
+
+    #[test]
+    fn half_words() {
+        let lexed = crate::lexer::lexer(std::path::Path::new("tests/halves.s")).unwrap();
+
+        let found = parser(lexed).unwrap();
+        let binary = crate::generator::generator(found).ok().unwrap();
+
+        let constants: Vec<u32> = binary[..4]
+            .iter()
+            .map(|word| {
+                let raw = word.raw();
+                (raw & 0xf) | ((raw >> 8) << 4)
+            })
+            .collect();
+        assert_eq!(vec![0x5678, 0x1234, 4, 0], constants);
+
+        let undefined = vec![
+            Keyword::mmenonic("ldc", 0),
+            Keyword::register_address("reg0", 0),
+            Keyword::expression(
+                "lo(nowhere)",
+                ir::Expression::Low(Box::new(ir::Expression::Label(ir::LabelReference::new(
+                    "nowhere",
+                )))),
+                0,
             ),
         ];
+        match parser(undefined).as_ref().map_err(Vec::as_slice) {
+            Err([ParserError::UndefinedLabels { labels }]) => {
+                assert_eq!(vec![(String::from("nowhere"), 0)], *labels)
+            }
+            found => panic!("expected undefined labels, found {:?}", found.err()),
+        }
+    }
+
+    #[test]
+    fn keyword_stream() {
+        let source = "main:\n    ldc %reg0 0x1\n    jr  main\n";
+        let tokens = crate::lexer::Tokens::new(source.as_bytes(), std::path::Path::new(""));
+
+        let parsed = parser(tokens.map(Result::unwrap)).unwrap();
+        assert_eq!(
+            2,
+            parsed.instructions[&ir::LabelReference::new("main")].len()
+        );
+
+        // folded while the stream is collected
+        let source = "main:
+    .ALIAS Count %REG1
+    LDC %count 0x1
+";
+        let tokens = crate::lexer::Tokens::new(source.as_bytes(), std::path::Path::new(""));
+        let options = ParserOptions {
+            case_insensitive: true,
+            ..ParserOptions::default()
+        };
+        let folded = parser_with_options(tokens.map(Result::unwrap), &options).unwrap();
+        assert_eq!(
+            "ldc %reg1 0x1",
+            folded.instructions[&ir::LabelReference::new("main")][0].to_string()
+        );
+    }
+
+    #[test]
+    fn error_recovery() {
+        match parser(vec![
+            Keyword::label("main", 0),
+            Keyword::mmenonic("foo", 1),
+            Keyword::constant("1", 1, 1),
+            Keyword::mmenonic("nop", 2),
+            Keyword::mmenonic("bar", 3),
+            Keyword::mmenonic("jr", 4),
+            Keyword::label("nowhere", 4),
+            Keyword::mmenonic("hlt", 5),
+        ])
+        .as_ref()
+        .map_err(Vec::as_slice)
+        {
+            Err(
+                [ParserError::UnknownCommand { line_number: 1, .. }, ParserError::UnknownCommand { line_number: 3, .. }, ParserError::UndefinedLabels { labels }],
+            ) => assert_eq!(vec![(String::from("nowhere"), 4)], *labels),
+            found => panic!("expected three errors, found {:?}", found.err()),
+        }
+    }
+
+    #[test]
+    fn error_column() {
+        let mut lexed = Vec::new();
+        crate::lexer::lex_line(&mut lexed, String::from("    add %reg0 %reg1 true"), 0).unwrap();
+
+        match parser(lexed).as_ref().map_err(Vec::as_slice) {
+            Err(
+                [ParserError::CouldNotParseArgument {
+                    arg_value,
+                    line_number,
+                    column,
+                    ..
+                }],
+            ) => assert_eq!(("true", 0, 20), (arg_value.as_str(), *line_number, *column)),
+            found => panic!("expected invalid argument, found {:?}", found.err()),
+        }
+    }
+
+    #[test]
+    fn suggestions() {
+        let mut lexed = Vec::new();
+        crate::lexer::lex_line(&mut lexed, String::from("    ladc %reg0 0x1"), 0).unwrap();
+
+        match parser(lexed).as_ref().map_err(Vec::as_slice) {
+            Err([err @ ParserError::UnknownCommand { suggestions, .. }]) => {
+                assert_eq!(vec![String::from("ldc")], *suggestions);
+                assert_eq!(
+                    "Unknown command: 'ladc' at line 1, column 4, did you mean 'ldc'?",
+                    err.to_string()
+                );
+            }
+            found => panic!("expected unknown command, found {:?}", found.err()),
+        }
+        assert_eq!(vec![String::from("sub")], similar_mnemonics("sbu"));
+        assert_eq!(
+            vec![String::from("jmp"), String::from("jz"), String::from("jc")],
+            similar_mnemonics("jm")
+        );
+        assert!(similar_mnemonics("xyzzy").is_empty());
+    }
+
+    #[test]
+    fn undefined_labels() {
+        match parser(vec![
+            Keyword::label("main", 0),
+            Keyword::mmenonic("jr", 1),
+            Keyword::label("first", 1),
+            Keyword::label(".loop", 2),
+            Keyword::mmenonic("jr", 3),
+            Keyword::label("loop", 3),
+            Keyword::mmenonic("ljmp", 4),
+            Keyword::label("second", 4),
+        ])
+        .as_ref()
+        .map_err(Vec::as_slice)
+        {
+            Err([ParserError::UndefinedLabels { labels }]) => assert_eq!(
+                vec![(String::from("first"), 1), (String::from("second"), 4)],
+                *labels
+            ),
+            found => panic!("expected undefined labels, found {:?}", found.err()),
+        }
+    }
+
+    #[test]
+    fn label_offsets() {
+        let lexed = crate::lexer::lexer(std::path::Path::new("tests/offsets.s")).unwrap();
+
+        let found = parser(lexed).unwrap();
+
+        assert_eq!(
+            Some(&ir::Instruction::Jump {
+                target: ir::JumpTarget::LabelOffset {
+                    label: ir::LabelReference::new("end"),
+                    offset: -1,
+                },
+                condition: ir::JumpCondition::True,
+            }),
+            found
+                .instructions
+                .get(&ir::LabelReference::new("loop"))
+                .unwrap()
+                .get(2)
+        );
+        let binary = crate::generator::generator(found).ok().unwrap();
+        assert_eq!(0xfff58, binary[2].raw());
+        assert_eq!(0x00058, binary[3].raw());
+
+        assert!(matches!(
+            parser(vec![
+                Keyword::mmenonic("jr", 0),
+                Keyword::expression(
+                    "lo(end)",
+                    ir::Expression::Low(Box::new(ir::Expression::Label(ir::LabelReference::new(
+                        "end"
+                    )))),
+                    0,
+                ),
+            ])
+            .as_ref()
+            .map_err(Vec::as_slice),
+            Err([ParserError::CouldNotParseArgument { .. }])
+        ));
+    }
+
+    #[test]
+    fn label_addresses() {
+        let lexed = crate::lexer::lexer(std::path::Path::new("tests/label_address.s")).unwrap();
+
+        let found = parser(lexed).unwrap();
+
+        assert_eq!(
+            Some(&ir::Instruction::Load {
+                address: ir::RegisterAddress(0),
+                source: ir::LoadSource::Expression(ir::Expression::Label(ir::LabelReference::new(
+                    "handler"
+                ))),
+            }),
+            found
+                .instructions
+                .get(&ir::LabelReference::new("main"))
+                .unwrap()
+                .first()
+        );
+        let binary = crate::generator::generator(found).ok().unwrap();
+        assert_eq!(0x83, binary[0].raw());
+        assert_eq!(0x90, binary[3].raw());
+    }
+
+    #[test]
+    fn long_jump() {
+        let lexed = crate::lexer::lexer(std::path::Path::new("tests/long_jump.s")).unwrap();
+
+        let found = parser(lexed).unwrap();
+        assert_eq!(
+            Some(&ir::Instruction::LongJump {
+                target: ir::Expression::Label(ir::LabelReference::new("main")),
+                scratch: ir::Register::new(ir::RegisterAddress(5)),
+            }),
+            found
+                .instructions
+                .get(&ir::LabelReference::new("far"))
+                .unwrap()
+                .first()
+        );
+        let binary = crate::generator::generator(found).ok().unwrap();
+        let raw: Vec<u32> = binary.iter().map(|word| word.raw()).collect();
+        // ldc %reg6 far, jmp %reg6, hlt
+        assert_eq!(vec![0x100e0, 0x650, 0x7f], raw[..3]);
+        // ldc %reg5 main, jmp %reg5
+        assert_eq!(vec![0xd0, 0x550], raw[0x1000..0x1002]);
+    }
+
+    #[test]
+    fn load_immediate() {
+        let lexed = crate::lexer::lexer(std::path::Path::new("tests/load_immediate.s")).unwrap();
+
+        let found = parser(lexed).unwrap();
+        assert_eq!(
+            Some(&ir::Instruction::LoadImmediate {
+                address: ir::RegisterAddress(0),
+                value: ir::Expression::Constant(0x12345678),
+                scratch: ir::Register::new(ir::RegisterAddress(6)),
+            }),
+            found
+                .instructions
+                .get(&ir::LabelReference::new("main"))
+                .unwrap()
+                .get(1)
+        );
+        let binary = crate::generator::generator(found).ok().unwrap();
+        let raw: Vec<u32> = binary.iter().map(|word| word.raw()).collect();
+        // ldc %reg0 0x1234, ldc %reg6 16, shl, ldc %reg6 0x5678, or
+        assert_eq!(vec![0x12384, 0x1e0, 0x300f, 0x567e8, 0x300a], raw[1..6]);
+        assert_eq!(12, raw.len());
 
-        let expected = ir::IR {
-            start_label: ir::LabelReference::new("main"),
-            label_definitions: ir::LabelLUT(expected_label_definitions.into_iter().collect()),
-            instructions: expected_instructions.into_iter().collect(),
+        match parser(vec![
+            Keyword::mmenonic("li", 0),
+            Keyword::register_address("reg0", 0),
+            Keyword::constant("1", 1, 0),
+        ])
+        .as_ref()
+        .map_err(Vec::as_slice)
+        {
+            Err([ParserError::Requires32BitMode { command, .. }]) => assert_eq!("li", command),
+            found => panic!("expected missing 32 bit mode, found {:?}", found.err()),
+        }
+        match parser(vec![
+            Keyword::mmenonic("s32b", 0),
+            Keyword::boolean("true", true, 0),
+            Keyword::mmenonic("li", 1),
+            Keyword::register_address("reg6", 1),
+            Keyword::constant("1", 1, 1),
+        ])
+        .as_ref()
+        .map_err(Vec::as_slice)
+        {
+            Err([ParserError::ReservedRegister { register, .. }]) => assert_eq!("%reg6", register),
+            found => panic!("expected scratch register error, found {:?}", found.err()),
+        }
+    }
+
+    #[test]
+    fn constant_range() {
+        let load = |origin: &str, value: u32| {
+            parser(vec![
+                Keyword::mmenonic("ldc", 0),
+                Keyword::register_address("reg0", 0),
+                Keyword::expression(origin, ir::Expression::Constant(value), 0),
+            ])
         };
+        assert!(load("0xffff", 0xffff).is_ok());
+        assert!(load("-32768", 0xffff_8000).is_ok());
+        match load("0x10000", 0x10000).as_ref().map_err(Vec::as_slice) {
+            Err([ParserError::ConstantOutOfRange { command, value, .. }]) => {
+                assert_eq!(("ldc", 0x10000), (command.as_str(), *value))
+            }
+            found => panic!("expected constant out of range, found {:?}", found.err()),
+        }
+        assert!(matches!(
+            load("-32769", 0xffff_7fff).as_ref().map_err(Vec::as_slice),
+            Err([ParserError::ConstantOutOfRange { .. }])
+        ));
+    }
 
-        let found = parser(lexed).unwrap();
+    #[test]
+    fn immediates() {
+        let lexed = crate::lexer::lexer(std::path::Path::new("tests/immediate.s")).unwrap();
 
+        let found = parser(lexed).unwrap();
+        let instructions = found
+            .instructions
+            .get(&ir::LabelReference::new("main"))
+            .unwrap();
         assert_eq!(
-            expected.label_definitions.0.len(),
-            found.label_definitions.0.len(),
-            "found too few label definitions",
+            ir::Instruction::Immediate {
+                operation: ir::Operation::Add,
+                target: ir::Register::new(ir::RegisterAddress(0)),
+                source: ir::Register::new(ir::RegisterAddress(0)),
+                value: ir::Expression::Constant(5),
+                scratch: ir::Register::new(ir::RegisterAddress(6)),
+            },
+            instructions[0]
+        );
+        assert_eq!(
+            ir::Instruction::Immediate {
+                operation: ir::Operation::XOR,
+                target: ir::Register::new(ir::RegisterAddress(4)),
+                source: ir::Register::new(ir::RegisterAddress(4)),
+                value: ir::Expression::Constant(0xffff),
+                scratch: ir::Register::new(ir::RegisterAddress(7)),
+            },
+            instructions[4]
+        );
+        let binary = crate::generator::generator(found).ok().unwrap();
+        let raw: Vec<u32> = binary.iter().map(|word| word.raw()).collect();
+        assert_eq!(
+            vec![
+                0xe5, 0x3000, 0xe1, 0x23103, 0xfef, 0x43209, 0xe0, 0x6330a, 0xfffff, 0x83c0d, 0x7f
+            ],
+            raw
         );
-        let mut exp_ld_vec = expected
-            .label_definitions
-            .0
-            .iter()
-            .collect::<Vec<(&ir::LabelReference, &ir::LabelDefinition)>>();
 
-        let mut found_ld_vec = found
-            .label_definitions
-            .0
-            .iter()
-            .collect::<Vec<(&ir::LabelReference, &ir::LabelDefinition)>>();
+        match parser(vec![
+            Keyword::mmenonic("addi", 0),
+            Keyword::register_address("reg6", 0),
+            Keyword::constant("1", 1, 0),
+        ])
+        .as_ref()
+        .map_err(Vec::as_slice)
+        {
+            Err([ParserError::ReservedRegister { register, .. }]) => assert_eq!("%reg6", register),
+            found => panic!("expected scratch register error, found {:?}", found.err()),
+        }
+    }
 
-        found_ld_vec.sort_by(|a, b| a.1.address.cmp(&b.1.address));
-        exp_ld_vec.sort_by(|a, b| a.1.address.cmp(&b.1.address));
+    #[test]
+    fn shift_immediates() {
+        let lexed = crate::lexer::lexer(std::path::Path::new("tests/shift.s")).unwrap();
 
-        for (expected_label_definition, found_label_definition) in
-            exp_ld_vec.iter().zip(found_ld_vec.iter())
+        let found = parser(lexed).unwrap();
+        let register = |address| ir::Register::new(ir::RegisterAddress(address));
+        let instructions = found
+            .instructions
+            .get(&ir::LabelReference::new("main"))
+            .unwrap();
+        assert_eq!(
+            ir::Instruction::ShiftLeft(ir::BinaryExpression::new(
+                register(0),
+                register(1),
+                register(2)
+            )),
+            instructions[0]
+        );
+        assert_eq!(
+            ir::Instruction::Immediate {
+                operation: ir::Operation::ShiftLeft,
+                target: register(0),
+                source: register(1),
+                value: ir::Expression::Constant(3),
+                scratch: register(6),
+            },
+            instructions[1]
+        );
+        let binary = crate::generator::generator(found).ok().unwrap();
+        let raw: Vec<u32> = binary.iter().map(|word| word.raw()).collect();
+        assert_eq!(vec![0x110f, 0xe3, 0x310f, 0xe4, 0x43210, 0x7f], raw);
+
+        match parser(vec![
+            Keyword::mmenonic("shl", 0),
+            Keyword::register_address("reg0", 0),
+            Keyword::register_address("reg1", 0),
+        ])
+        .as_ref()
+        .map_err(Vec::as_slice)
         {
-            assert_eq!(expected_label_definition.0, found_label_definition.0);
-            assert_eq!(
-                expected_label_definition.1.address, found_label_definition.1.address,
-                "label definitions addresses do not match",
-            );
+            Err([ParserError::MissingArgument { arg_name, .. }]) => {
+                assert_eq!("SourceRegisterB", arg_name)
+            }
+            found => panic!("expected missing argument, found {:?}", found.err()),
         }
+    }
 
+    #[test]
+    fn rotations() {
+        let lexed = crate::lexer::lexer(std::path::Path::new("tests/rotate.s")).unwrap();
+
+        let found = parser(lexed).unwrap();
+        let register = |address| ir::Register::new(ir::RegisterAddress(address));
         assert_eq!(
-            expected.instructions.len(),
-            found.instructions.len(),
-            "found too few label references in instructions",
+            Some(&ir::Instruction::Rotate {
+                direction: ir::Direction::Right,
+                target: register(2),
+                source: register(2),
+                amount: 14,
+                width: 16,
+                scratch: register(6),
+            }),
+            found
+                .instructions
+                .get(&ir::LabelReference::new("main"))
+                .unwrap()
+                .get(1)
         );
+        let binary = crate::generator::generator(found).ok().unwrap();
+        let raw: Vec<u32> = binary.iter().map(|word| word.raw()).collect();
+        // rol %reg0 %reg1 4
+        assert_eq!(vec![0xec, 0xc3110, 0x84, 0x10f, 0x300a], raw[..5]);
+        // ror %reg2 %reg2 14 shifts left by doubling
+        assert_eq!(vec![0xee, 0xc3210, 0x41200, 0x41200, 0x4320a], raw[5..10]);
+        // ror %reg3 %reg4 16 is a plain move
+        assert_eq!(vec![0x60448, 0x7f], raw[10..]);
 
-        let mut exp_instr_keys = expected.instructions.keys().collect::<Vec<_>>();
-        let mut found_instr_keys = found.instructions.keys().collect::<Vec<_>>();
+        match parser(vec![
+            Keyword::mmenonic("rol", 0),
+            Keyword::register_address("reg0", 0),
+            Keyword::register_address("reg0", 0),
+            Keyword::label("far", 0),
+        ])
+        .as_ref()
+        .map_err(Vec::as_slice)
+        {
+            Err([ParserError::CouldNotParseArgument { arg_name, .. }]) => {
+                assert_eq!("RotateAmount", arg_name)
+            }
+            found => panic!("expected invalid rotate amount, found {:?}", found.err()),
+        }
+    }
 
-        exp_instr_keys.sort_by(|a, b| a.name().cmp(b.name()));
-        found_instr_keys.sort_by(|a, b| a.name().cmp(b.name()));
+    #[test]
+    fn division() {
+        let lexed = || crate::lexer::lexer(std::path::Path::new("tests/division.s")).unwrap();
 
-        for (expected_key_label, found_key_label) in
-            exp_instr_keys.iter().zip(found_instr_keys.iter())
+        match parser(lexed()).as_ref().map_err(Vec::as_slice) {
+            Err([ParserError::NoHardwareSupport { command, .. }, ..]) => assert_eq!("div", command),
+            found => panic!("expected missing hardware support, found {:?}", found.err()),
+        }
+        let options = ParserOptions {
+            soft_division: true,
+            ..ParserOptions::default()
+        };
+        let found = parser_with_options(lexed(), &options).unwrap();
+        let binary = crate::generator::generator(found).ok().unwrap();
+        let raw: Vec<u32> = binary.iter().map(|word| word.raw()).collect();
+        // mov, xor, tst, jcr 4, sub, inc, jr -4
+        assert_eq!(
+            vec![0xc0148, 0xd, 0x1608, 0x35b, 0xc1603, 0x5, 0xffb58],
+            raw[..7]
+        );
+        // mov, tst, jcr 3, sub, jr -3
+        assert_eq!(vec![0x20148, 0x1108, 0x25b, 0x21103, 0xffc58], raw[7..12]);
+
+        let keywords = |target| {
+            vec![
+                Keyword::mmenonic("div", 0),
+                Keyword::register_address(target, 0),
+                Keyword::register_address("reg1", 0),
+                Keyword::register_address("reg2", 0),
+            ]
+        };
+        match parser_with_options(keywords("reg2"), &options)
+            .as_ref()
+            .map_err(Vec::as_slice)
         {
-            assert_eq!(expected_key_label, found_key_label);
-            for (expected_instruction, found_instruction) in expected
+            Err([ParserError::ClobberedRegister { register, .. }]) => assert_eq!("%reg2", register),
+            found => panic!("expected clobbered divisor, found {:?}", found.err()),
+        }
+        match parser_with_options(keywords("reg6"), &options)
+            .as_ref()
+            .map_err(Vec::as_slice)
+        {
+            Err([ParserError::ReservedRegister { register, .. }]) => assert_eq!("%reg6", register),
+            found => panic!("expected scratch register error, found {:?}", found.err()),
+        }
+    }
+
+    #[test]
+    fn comparison_jumps() {
+        let lexed = crate::lexer::lexer(std::path::Path::new("tests/conditions.s")).unwrap();
+
+        let found = parser(lexed).unwrap();
+        assert_eq!(
+            Some(&ir::Instruction::Jump {
+                target: ir::JumpTarget::Label(ir::LabelReference::new("done")),
+                condition: ir::JumpCondition::LessEqual,
+            }),
+            found
                 .instructions
-                .get(expected_key_label)
+                .get(&ir::LabelReference::new("main"))
                 .unwrap()
+                .get(2)
+        );
+        assert_eq!(
+            9,
+            found.label_definitions.0[&ir::LabelReference::new("done")]
+                .address
+                .0
+        );
+        let binary = crate::generator::generator(found).ok().unwrap();
+        let raw: Vec<u32> = binary.iter().map(|word| word.raw()).collect();
+        // jge %reg2: jcr 2, jmp %reg2
+        assert_eq!(vec![0x15b, 0x250], raw[1..3]);
+        // jler done: jzr done, jcr done
+        assert_eq!(vec![0x559, 0x45b], raw[3..5]);
+        // jgtr $+4: jzr 3, jcr 2, jr 2
+        assert_eq!(vec![0x259, 0x15b, 0x158], raw[5..8]);
+    }
+
+    #[test]
+    fn debug_breakpoint() {
+        let found = parser(vec![
+            Keyword::mmenonic("dbg", 0),
+            Keyword::mmenonic("hlt", 1),
+        ])
+        .unwrap();
+        assert_eq!(
+            Some(&vec![ir::Instruction::Debug, ir::Instruction::Halt]),
+            found.instructions.get(&ir::LabelReference::new("main"))
+        );
+        let binary = crate::generator::generator(found).ok().unwrap();
+        assert_eq!(0x7e, binary[0].raw());
+    }
+
+    #[test]
+    fn register_numbers() {
+        let aliases = HashMap::new();
+        let register = |name| try_parse_register(&Keyword::register_address(name, 0), &aliases);
+        assert_eq!(ir::RegisterAddress(7), register("reg7").unwrap());
+        assert_eq!(ir::RegisterAddress(2), register("regC").unwrap());
+        for name in ["reg8", "reg10", "regI", "reg"] {
+            assert!(register(name).is_err(), "{} is not a register", name);
+        }
+    }
+
+    #[test]
+    fn register_suggestions() {
+        let aliases = HashMap::from([(String::from("counter"), ir::RegisterAddress(3))]);
+        let suggestions =
+            |name| match try_parse_register(&Keyword::register_address(name, 0), &aliases) {
+                Err(ParserError::InvalidRegister { suggestions, .. }) => suggestions,
+                found => panic!("expected invalid register, found {:?}", found),
+            };
+        assert_eq!(vec![String::from("reg0")], suggestions("rg0"));
+        assert_eq!(vec![String::from("regB")], suggestions("regb"));
+        assert_eq!(vec![String::from("counter")], suggestions("countr"));
+        assert!(suggestions("reg9").is_empty());
+        assert_eq!(
+            "Invalid register '%rg0' at line 1, column 0, registers are %reg0..%reg7 or %regA..%regH, did you mean '%reg0'?",
+            try_parse_register(&Keyword::register_address("rg0", 0), &aliases)
+                .unwrap_err()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn register_aliases() {
+        let lexed = crate::lexer::lexer(std::path::Path::new("tests/alias.s")).unwrap();
+
+        let found = parser(lexed).unwrap();
+        let register = |address| ir::Register::new(ir::RegisterAddress(address));
+        assert_eq!(
+            Some(&vec![
+                ir::Instruction::Increment(ir::UnaryExpression::new(register(3), register(3))),
+                ir::Instruction::Test(ir::BinaryStatement::new(register(3), register(1))),
+                ir::Instruction::Jump {
+                    target: ir::JumpTarget::Label(ir::LabelReference::new("loop")),
+                    condition: ir::JumpCondition::NotZero,
+                },
+                ir::Instruction::Move(ir::UnaryExpression::new(register(0), register(3))),
+                ir::Instruction::Halt,
+            ]),
+            found.instructions.get(&ir::LabelReference::new("loop"))
+        );
+
+        let alias = |name, register, line_number| {
+            vec![
+                Keyword::directive("alias", line_number),
+                Keyword::label(name, line_number),
+                Keyword::register_address(register, line_number),
+            ]
+        };
+        let program = |mut keywords: Vec<Keyword>| {
+            keywords.push(Keyword::mmenonic("hlt", 2));
+            parser(keywords)
+        };
+        match program(alias("regC", "reg0", 0))
+            .as_ref()
+            .map_err(Vec::as_slice)
+        {
+            Err([ParserError::ShadowedRegister { alias, .. }]) => assert_eq!("regC", alias),
+            found => panic!("expected shadowed register, found {:?}", found.err()),
+        }
+        match program([alias("tmp", "reg0", 0), alias("tmp", "reg1", 1)].concat())
+            .as_ref()
+            .map_err(Vec::as_slice)
+        {
+            Err(
+                [ParserError::DuplicateAlias {
+                    alias, previous, ..
+                }],
+            ) => {
+                assert_eq!("tmp", alias);
+                assert_eq!(0, *previous);
+            }
+            found => panic!("expected duplicate alias, found {:?}", found.err()),
+        }
+    }
+
+    #[test]
+    fn conventional_registers() {
+        let keywords = || {
+            vec![
+                Keyword::mmenonic("mov", 0),
+                Keyword::register_address("sp", 0),
+                Keyword::register_address("lr", 0),
+                Keyword::mmenonic("mov", 1),
+                Keyword::register_address("zero", 1),
+                Keyword::register_address("fp", 1),
+            ]
+        };
+        let moves = |found: ir::IR| {
+            found.instructions[&ir::LabelReference::new("main")]
                 .iter()
-                .zip(found.instructions.get(found_key_label).unwrap().iter())
-            {
-                assert_eq!(
-                    expected_instruction, found_instruction,
-                    "instructions do not match"
-                );
+                .filter_map(|instruction| match instruction {
+                    ir::Instruction::Move(expression) => {
+                        Some((expression.target.address.0, expression.source_a.address.0))
+                    }
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let options = ParserOptions {
+            register_aliases: HashMap::from([
+                (String::from("fp"), ir::RegisterAddress(4)),
+                (String::from("sp"), ir::RegisterAddress(6)),
+            ]),
+            ..ParserOptions::default()
+        };
+        let found = parser_with_options(keywords(), &options).unwrap();
+        assert_eq!(vec![(6, 5), (0, 4)], moves(found));
+
+        // `.alias` in the source takes precedence
+        let mut redefined = vec![
+            Keyword::directive("alias", 0),
+            Keyword::label("lr", 0),
+            Keyword::register_address("reg1", 0),
+        ];
+        redefined.extend(
+            keywords()
+                .into_iter()
+                .map(|keyword| keyword.with_line_number(1)),
+        );
+        let found = parser_with_options(redefined, &options).unwrap();
+        assert_eq!(vec![(6, 1), (0, 4)], moves(found));
+        assert!(parser(keywords()).is_err());
+    }
+
+    #[test]
+    fn case_insensitive() {
+        let lexed = || crate::lexer::lexer(std::path::Path::new("tests/uppercase.s")).unwrap();
+
+        match parser(lexed()).as_ref().map_err(Vec::as_slice) {
+            Err([ParserError::UnknownDirective { directive, .. }, ..]) => {
+                assert_eq!(".ALIAS", directive)
             }
+            found => panic!("expected unknown directive, found {:?}", found.err()),
         }
+        let options = ParserOptions {
+            case_insensitive: true,
+            ..ParserOptions::default()
+        };
+        let found = parser_with_options(lexed(), &options).unwrap();
+        let register = |address| ir::Register::new(ir::RegisterAddress(address));
+        assert_eq!(
+            Some(&vec![
+                ir::Instruction::Load {
+                    address: ir::RegisterAddress(0),
+                    source: ir::LoadSource::Constant(0x10),
+                },
+                ir::Instruction::Move(ir::UnaryExpression::new(register(0), register(7))),
+                ir::Instruction::Increment(ir::UnaryExpression::new(register(3), register(3))),
+                ir::Instruction::Data(ir::Constant(1)),
+                ir::Instruction::Halt,
+            ]),
+            found.instructions.get(&ir::LabelReference::new("MAIN"))
+        );
+    }
+
+    #[test]
+    fn local_labels() {
+        let lexed = crate::lexer::lexer(std::path::Path::new("tests/local_labels.s")).unwrap();
 
+        let found = parser(lexed).unwrap();
+        let address = |name| {
+            found.label_definitions.0[&ir::LabelReference::new(name)]
+                .address
+                .0
+        };
+        assert_eq!(1, address("main.loop"));
+        assert_eq!(5, address("main.done"));
+        assert_eq!(8, address("other.loop"));
+        let jump_target =
+            |label, idx| match &found.instructions[&ir::LabelReference::new(label)][idx] {
+                ir::Instruction::Jump { target, .. } => target.clone(),
+                instruction => panic!("expected jump, found {:?}", instruction),
+            };
         assert_eq!(
-            expected.start_label, found.start_label,
-            "start label do not match"
+            ir::JumpTarget::Label(ir::LabelReference::new("main.loop")),
+            jump_target("main.loop", 1)
+        );
+        assert_eq!(
+            ir::JumpTarget::Label(ir::LabelReference::new("other.loop")),
+            jump_target("other.loop", 1)
+        );
+        assert_eq!(
+            ir::JumpTarget::Label(ir::LabelReference::new("main.done")),
+            jump_target("other.loop", 2)
+        );
+
+        match parser(vec![
+            Keyword::label(".loop", 0),
+            Keyword::mmenonic("hlt", 1),
+        ])
+        .as_ref()
+        .map_err(Vec::as_slice)
+        {
+            Err([ParserError::LocalLabelWithoutScope { label, .. }]) => assert_eq!(".loop", label),
+            found => panic!(
+                "expected local label without scope, found {:?}",
+                found.err()
+            ),
+        }
+    }
+
+    #[test]
+    fn consecutive_labels() {
+        let found = parser(vec![
+            Keyword::label("start", 0),
+            Keyword::label("reset", 1),
+            Keyword::mmenonic("nop", 2),
+            Keyword::label("end", 3),
+            Keyword::mmenonic("hlt", 4),
+        ])
+        .unwrap();
+
+        let address = |name| {
+            found.label_definitions.0[&ir::LabelReference::new(name)]
+                .address
+                .0
+        };
+        assert_eq!(0, address("start"));
+        assert_eq!(0, address("reset"));
+        assert_eq!(1, address("end"));
+        assert_eq!(ir::LabelReference::new("start"), found.start_label);
+        assert!(!found
+            .instructions
+            .contains_key(&ir::LabelReference::new("start")));
+        assert_eq!(
+            vec![ir::Instruction::Noop],
+            found.instructions[&ir::LabelReference::new("reset")]
+        );
+    }
+
+    #[test]
+    fn entry_point() {
+        let keywords = vec![
+            Keyword::directive("start", 0),
+            Keyword::label("reset", 0),
+            Keyword::label("main", 1),
+            Keyword::mmenonic("nop", 2),
+            Keyword::label("reset", 3),
+            Keyword::mmenonic("hlt", 4),
+        ];
+
+        let found = parser(keywords.clone()).unwrap();
+        assert_eq!(ir::LabelReference::new("reset"), found.start_label);
+
+        let options = ParserOptions {
+            entry: Some(String::from("main")),
+            ..ParserOptions::default()
+        };
+        let found = parser_with_options(keywords.clone(), &options).unwrap();
+        assert_eq!(ir::LabelReference::new("main"), found.start_label);
+
+        let options = ParserOptions {
+            entry: Some(String::from("missing")),
+            ..ParserOptions::default()
+        };
+        match parser_with_options(keywords, &options)
+            .as_ref()
+            .map_err(Vec::as_slice)
+        {
+            Err([ParserError::UndefinedEntry { label, line_number }]) => {
+                assert_eq!("missing", label);
+                assert_eq!(None, *line_number);
+            }
+            found => panic!("expected undefined entry, found {:?}", found.err()),
+        }
+
+        match parser(vec![
+            Keyword::directive("start", 0),
+            Keyword::label("main", 0),
+            Keyword::directive("start", 1),
+            Keyword::label("main", 1),
+            Keyword::label("main", 2),
+            Keyword::mmenonic("hlt", 3),
+        ])
+        .as_ref()
+        .map_err(Vec::as_slice)
+        {
+            Err(
+                [ParserError::DuplicateEntry {
+                    line_number,
+                    previous,
+                }],
+            ) => assert_eq!((1, 0), (*line_number, *previous)),
+            found => panic!("expected duplicate entry, found {:?}", found.err()),
+        }
+    }
+
+    #[test]
+    fn numeric_labels() {
+        let lexed = crate::lexer::lexer(std::path::Path::new("tests/numeric_labels.s")).unwrap();
+
+        let found = parser(crate::preprocessor::preprocessor(lexed).unwrap()).unwrap();
+        let address = |name| {
+            found.label_definitions.0[&ir::LabelReference::new(name)]
+                .address
+                .0
+        };
+        assert_eq!(1, address("main.1_0"));
+        assert_eq!(4, address("main.1_1"));
+        assert_eq!(8, address("main.1_2"));
+        let jump_target =
+            |label, idx| match &found.instructions[&ir::LabelReference::new(label)][idx] {
+                ir::Instruction::Jump { target, .. } => target.clone(),
+                instruction => panic!("expected jump, found {:?}", instruction),
+            };
+        // every expansion of the macro jumps back to its own definition
+        assert_eq!(
+            ir::JumpTarget::Label(ir::LabelReference::new("main.1_0")),
+            jump_target("main.1_0", 1)
+        );
+        assert_eq!(
+            ir::JumpTarget::Label(ir::LabelReference::new("main.1_1")),
+            jump_target("main.1_1", 1)
+        );
+        assert_eq!(
+            ir::JumpTarget::Label(ir::LabelReference::new("main.1_2")),
+            jump_target("main.1_1", 2)
+        );
+        assert_eq!(
+            ir::JumpTarget::Label(ir::LabelReference::new("main.1_2")),
+            jump_target("main.1_2", 0)
+        );
+
+        match parser(vec![
+            Keyword::label("main", 0),
+            Keyword::label("1", 1),
+            Keyword::mmenonic("jr", 2),
+            Keyword::label("1f", 2),
+        ])
+        .as_ref()
+        .map_err(Vec::as_slice)
+        {
+            Err(
+                [ParserError::UndefinedNumericLabel {
+                    label, line_number, ..
+                }],
+            ) => {
+                assert_eq!("1f", label);
+                assert_eq!(2, *line_number);
+            }
+            found => panic!("expected undefined numeric label, found {:?}", found.err()),
+        }
+    }
+
+    #[test]
+    fn location_counter() {
+        let lexed = crate::lexer::lexer(std::path::Path::new("tests/location.s")).unwrap();
+
+        let found = parser(lexed).unwrap();
+        let binary = crate::generator::generator(found).ok().unwrap();
+
+        let raw: Vec<u32> = binary.iter().map(|word| word.raw()).collect();
+        assert_eq!(
+            vec![0x6c, 0xfff58, 0x00158, 0x6c, 0x84, 0x95, 0xa6, 0x7f],
+            raw
+        );
+    }
+
+    #[test]
+    fn operand_errors() {
+        match parser(vec![
+            Keyword::mmenonic("inc", 0),
+            Keyword::mmenonic("hlt", 1),
+        ])
+        .as_ref()
+        .map_err(Vec::as_slice)
+        {
+            Err(
+                [ParserError::MissingArgument {
+                    command, arg_name, ..
+                }],
+            ) => {
+                assert_eq!("inc", command);
+                assert_eq!("SourceRegister", arg_name);
+            }
+            found => panic!("expected missing argument, found {:?}", found.err()),
+        }
+
+        assert!(matches!(
+            parser(vec![
+                Keyword::mmenonic("nop", 0),
+                Keyword::register_address("reg0", 0),
+            ])
+            .as_ref()
+            .map_err(Vec::as_slice),
+            Err([ParserError::UnexpectedArgument { .. }])
+        ));
+
+        match parser(vec![
+            Keyword::mmenonic("ldc", 0),
+            Keyword::register_address("reg0", 0),
+            Keyword::register_address("reg1", 0),
+        ])
+        .as_ref()
+        .map_err(Vec::as_slice)
+        {
+            Err(
+                [ParserError::CouldNotParseArgument {
+                    arg_name,
+                    arg_value,
+                    ..
+                }],
+            ) => {
+                assert_eq!("Constant16", arg_name);
+                assert_eq!("%reg1", arg_value);
+            }
+            found => panic!("expected unparsable argument, found {:?}", found.err()),
+        }
+    }
+
+    #[test]
+    fn canonical_syntax() {
+        let source = ";; Entry
+[[noreturn]]
+main:
+    s32b true
+    ldc %regA -1
+    li %reg1 hi(far)
+.scratch %reg5
+    addi %reg0 5
+    shr %reg2 %reg3 2
+    jr main-3
+    jnzr -2
+    jz %reg4
+.org 0x0100
+far:
+    ror %reg2 %reg2 14
+    .word 42
+    hlt
+";
+        let options = crate::lexer::LexerOptions { auto_halt: false };
+        let lexed = crate::lexer::lexer_from_str(source, &options).unwrap();
+        let found = parser(lexed).unwrap();
+
+        let printed = found.to_string();
+        assert_eq!(
+            ";; Entry
+[[noreturn]]
+main:
+    s32b true
+    ldc %reg0 0xffff
+    li %reg1 hi(far)
+.scratch %reg5
+    addi %reg0 0x5
+    shr %reg2 %reg3 0x2
+    jr main-0x3
+    jnzr -0x2
+    jz %reg4
+.org 0x0100
+far:
+    ror %reg2 %reg2 14
+    .word 0x2a
+    hlt
+",
+            printed
+        );
+        let annotated = format!("{:#}", crate::lowering::lower(found.clone()));
+        assert!(annotated.starts_with("; 0x0000\n;; Entry\n"));
+        assert!(annotated.contains("\n; 0x0100\n.org 0x0100\nfar:\n"));
+        assert_eq!(
+            crate::generator::generator(found.clone()).unwrap(),
+            crate::assemble_str(&annotated).unwrap()
+        );
+        let reparsed = parser(crate::lexer::lexer_from_str(&printed, &options).unwrap()).unwrap();
+        assert_eq!(found.instructions, reparsed.instructions);
+
+        let lexed = crate::lexer::lexer(std::path::Path::new("tests/all_instructions.s")).unwrap();
+        let found = parser(lexed).unwrap();
+        let lexed = crate::lexer::lexer_from_str(&found.to_string(), &options).unwrap();
+        assert_eq!(found.instructions, parser(lexed).unwrap().instructions);
+    }
+
+    #[test]
+    fn display_round_trip() {
+        for file in [
+            "tests/location.s",
+            "tests/local_labels.s",
+            "tests/numeric_labels.s",
+            "tests/macros.s",
+            "tests/halves.s",
+        ] {
+            let lexed = crate::lexer::lexer(std::path::Path::new(file)).unwrap();
+            let ir = parser(crate::preprocessor::preprocessor(lexed).unwrap()).unwrap();
+            let binary = crate::generator::generator(ir.clone()).unwrap();
+            assert_eq!(
+                binary,
+                crate::assemble_str(&ir.to_string()).unwrap(),
+                "{file}"
+            );
+            let annotated = format!("{:#}", crate::lowering::lower(ir));
+            assert_eq!(binary, crate::assemble_str(&annotated).unwrap(), "{file}");
+        }
+
+        let lexed = crate::lexer::lexer(std::path::Path::new("tests/location.s")).unwrap();
+        let printed = parser(lexed).unwrap().to_string();
+        assert!(printed.contains("    ldc %reg1 0x5-start\n    ldc %reg2 0x6\n"));
+        let lexed = crate::lexer::lexer(std::path::Path::new("tests/local_labels.s")).unwrap();
+        let printed = parser(lexed).unwrap().to_string();
+        assert!(printed.starts_with("main:\n    ldc %reg0 0x3\n.loop:\n"));
+    }
+
+    #[test]
+    fn debug_info() {
+        let source = "main:\n    ldc %reg0 0x1\n.org 0x10\nfar:\n    s32b true\n    li %reg1 0x12345\n    .word 0x1 0x2\n";
+        let lexed = crate::lexer::lexer_from_str(source, &Default::default()).unwrap();
+        let (_, debug_info) = parser_with_debug_info(lexed, &ParserOptions::default()).unwrap();
+
+        let span = |address, size, line_number, column| Span {
+            address,
+            size,
+            line_number,
+            column,
+            file: None,
+        };
+        assert_eq!(
+            vec![
+                span(0x0, 1, 1, 4),
+                span(0x10, 1, 4, 4),
+                span(0x11, 5, 5, 4),
+                span(0x16, 2, 6, 4),
+                span(0x18, 1, 7, 0),
+            ],
+            debug_info.spans
         );
+        assert_eq!(Some(&span(0x11, 5, 5, 4)), debug_info.span(0x15));
+        assert_eq!(None, debug_info.span(0x5));
+        assert_eq!(Some(0x16), debug_info.address(None, 6));
     }
 }
@@ -0,0 +1,1052 @@
+use std::collections::HashMap;
+use std::iter::Peekable;
+use std::vec::IntoIter;
+
+use crate::diagnostic::{self, display_line};
+use crate::ir;
+use crate::lexer::{is_numeric_label, Keyword, LineNumber};
+
+type Keywords = Peekable<IntoIter<Keyword>>;
+
+/// Maximum nesting depth of macro invocations, deeper expansions are
+/// reported as recursive macros
+const MAX_MACRO_DEPTH: usize = 64;
+
+/// Errors the preprocessor can run into while expanding macros and
+/// conditional blocks
+#[derive(Debug)]
+pub enum PreprocessorError {
+    MissingMacroName {
+        line_number: u16,
+    },
+    InvalidMacroParameter {
+        parameter: String,
+        line_number: u16,
+    },
+    DuplicateMacro {
+        name: String,
+        line_number: u16,
+    },
+    NestedMacro {
+        line_number: u16,
+    },
+    UnterminatedMacro {
+        name: String,
+        line_number: u16,
+    },
+    UnexpectedEndMacro {
+        line_number: u16,
+    },
+    LabelInMacro {
+        name: String,
+        label: String,
+        line_number: u16,
+    },
+    MacroArguments {
+        name: String,
+        expected: usize,
+        found: usize,
+        line_number: u16,
+    },
+    RecursiveMacro {
+        name: String,
+        line_number: u16,
+    },
+    UndefinedSymbol {
+        symbol: String,
+        line_number: u16,
+    },
+    InvalidCondition {
+        directive: String,
+        condition: String,
+        line_number: u16,
+    },
+    UnexpectedConditional {
+        directive: String,
+        line_number: u16,
+    },
+    UnterminatedConditional {
+        line_number: u16,
+    },
+    InvalidRepeatCount {
+        count: String,
+        line_number: u16,
+    },
+    UnterminatedRepeat {
+        line_number: u16,
+    },
+    UnexpectedEndRepeat {
+        line_number: u16,
+    },
+    LabelInRepeat {
+        label: String,
+        line_number: u16,
+    },
+}
+
+impl std::fmt::Display for PreprocessorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self {
+            PreprocessorError::MissingMacroName { line_number } => {
+                write!(
+                    f,
+                    "Expected macro name after '.macro' at line {}",
+                    display_line(*line_number)
+                )
+            }
+            PreprocessorError::InvalidMacroParameter {
+                parameter,
+                line_number,
+            } => write!(
+                f,
+                "Invalid macro parameter '{}' found at line {}",
+                parameter,
+                display_line(*line_number)
+            ),
+            PreprocessorError::DuplicateMacro { name, line_number } => {
+                write!(
+                    f,
+                    "Macro '{}' defined again at line {}",
+                    name,
+                    display_line(*line_number)
+                )
+            }
+            PreprocessorError::NestedMacro { line_number } => write!(
+                f,
+                "Found macro definition inside of a macro at line {}",
+                display_line(*line_number)
+            ),
+            PreprocessorError::UnterminatedMacro { name, line_number } => write!(
+                f,
+                "Macro '{}' defined at line {} is missing '.endm'",
+                name,
+                display_line(*line_number)
+            ),
+            PreprocessorError::UnexpectedEndMacro { line_number } => {
+                write!(
+                    f,
+                    "Found '.endm' without macro at line {}",
+                    display_line(*line_number)
+                )
+            }
+            PreprocessorError::LabelInMacro {
+                name,
+                label,
+                line_number,
+            } => write!(
+                f,
+                "Found label definition '{}' in macro '{}' at line {}",
+                label,
+                name,
+                display_line(*line_number)
+            ),
+            PreprocessorError::MacroArguments {
+                name,
+                expected,
+                found,
+                line_number,
+            } => write!(
+                f,
+                "Macro '{}' expects {} arguments but found {} at line {}",
+                name,
+                expected,
+                found,
+                display_line(*line_number)
+            ),
+            PreprocessorError::RecursiveMacro { name, line_number } => write!(
+                f,
+                "Recursive expansion of macro '{}' found at line {}",
+                name,
+                display_line(*line_number)
+            ),
+            PreprocessorError::UndefinedSymbol {
+                symbol,
+                line_number,
+            } => write!(
+                f,
+                "Undefined symbol '{}' used at line {}",
+                symbol,
+                display_line(*line_number)
+            ),
+            PreprocessorError::InvalidCondition {
+                directive,
+                condition,
+                line_number,
+            } => write!(
+                f,
+                "Invalid condition '{}' for '.{}' found at line {}",
+                condition,
+                directive,
+                display_line(*line_number)
+            ),
+            PreprocessorError::UnexpectedConditional {
+                directive,
+                line_number,
+            } => write!(
+                f,
+                "Found '.{}' without '.if' at line {}",
+                directive,
+                display_line(*line_number)
+            ),
+            PreprocessorError::UnterminatedConditional { line_number } => write!(
+                f,
+                "Conditional block started at line {} is missing '.endif'",
+                display_line(*line_number)
+            ),
+            PreprocessorError::InvalidRepeatCount { count, line_number } => write!(
+                f,
+                "Invalid repeat count '{}' found at line {}",
+                count,
+                display_line(*line_number)
+            ),
+            PreprocessorError::UnterminatedRepeat { line_number } => write!(
+                f,
+                "Repeated block started at line {} is missing '.endr'",
+                display_line(*line_number)
+            ),
+            PreprocessorError::UnexpectedEndRepeat { line_number } => {
+                write!(
+                    f,
+                    "Found '.endr' without '.rept' at line {}",
+                    display_line(*line_number)
+                )
+            }
+            PreprocessorError::LabelInRepeat { label, line_number } => write!(
+                f,
+                "Found label definition '{}' in repeated block at line {}",
+                label,
+                display_line(*line_number)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PreprocessorError {}
+
+impl PreprocessorError {
+    /// Position of the line the error points at
+    pub fn position(&self) -> Option<diagnostic::Position> {
+        match *self {
+            PreprocessorError::MissingMacroName { line_number, .. }
+            | PreprocessorError::InvalidMacroParameter { line_number, .. }
+            | PreprocessorError::DuplicateMacro { line_number, .. }
+            | PreprocessorError::NestedMacro { line_number, .. }
+            | PreprocessorError::UnterminatedMacro { line_number, .. }
+            | PreprocessorError::UnexpectedEndMacro { line_number, .. }
+            | PreprocessorError::LabelInMacro { line_number, .. }
+            | PreprocessorError::MacroArguments { line_number, .. }
+            | PreprocessorError::RecursiveMacro { line_number, .. }
+            | PreprocessorError::UndefinedSymbol { line_number, .. }
+            | PreprocessorError::InvalidCondition { line_number, .. }
+            | PreprocessorError::UnexpectedConditional { line_number, .. }
+            | PreprocessorError::UnterminatedConditional { line_number, .. }
+            | PreprocessorError::InvalidRepeatCount { line_number, .. }
+            | PreprocessorError::UnterminatedRepeat { line_number, .. }
+            | PreprocessorError::UnexpectedEndRepeat { line_number, .. }
+            | PreprocessorError::LabelInRepeat { line_number, .. } => Some((line_number, None)),
+        }
+    }
+
+    /// Stable name of the error kind, e.g. for machine readable diagnostics
+    pub fn code(&self) -> &'static str {
+        match self {
+            PreprocessorError::MissingMacroName { .. } => "missing-macro-name",
+            PreprocessorError::InvalidMacroParameter { .. } => "invalid-macro-parameter",
+            PreprocessorError::DuplicateMacro { .. } => "duplicate-macro",
+            PreprocessorError::NestedMacro { .. } => "nested-macro",
+            PreprocessorError::UnterminatedMacro { .. } => "unterminated-macro",
+            PreprocessorError::UnexpectedEndMacro { .. } => "unexpected-end-macro",
+            PreprocessorError::LabelInMacro { .. } => "label-in-macro",
+            PreprocessorError::MacroArguments { .. } => "macro-arguments",
+            PreprocessorError::RecursiveMacro { .. } => "recursive-macro",
+            PreprocessorError::UndefinedSymbol { .. } => "undefined-symbol",
+            PreprocessorError::InvalidCondition { .. } => "invalid-condition",
+            PreprocessorError::UnexpectedConditional { .. } => "unexpected-conditional",
+            PreprocessorError::UnterminatedConditional { .. } => "unterminated-conditional",
+            PreprocessorError::InvalidRepeatCount { .. } => "invalid-repeat-count",
+            PreprocessorError::UnterminatedRepeat { .. } => "unterminated-repeat",
+            PreprocessorError::UnexpectedEndRepeat { .. } => "unexpected-end-repeat",
+            PreprocessorError::LabelInRepeat { .. } => "label-in-repeat",
+        }
+    }
+}
+
+/// Macro defined with `.macro NAME PARAMETERS...` and `.endm`
+#[derive(Debug)]
+struct Macro {
+    parameters: Vec<String>,
+    body: Vec<Keyword>,
+}
+
+/// Constant symbols defined with `.equ NAME VALUE`
+type Symbols = HashMap<String, u16>;
+
+/// Settings of the preprocessor which are not part of the source
+#[derive(Debug, Clone, Default)]
+pub struct PreprocessorOptions {
+    /// Symbols defined in front of the source, e.g. with `-D NAME=VALUE`.
+    /// An `.equ` of the same name replaces them.
+    pub defines: HashMap<String, u16>,
+    /// Accept directives like `.MACRO` in any case
+    pub case_insensitive: bool,
+}
+
+/// The preprocessor runs between lexer and parser and rewrites the lexed
+/// keywords in three steps:
+///  1. conditional blocks (`.if`, `.ifdef`, `.ifndef`, `.else`, `.endif`)
+///     are resolved and `.equ` symbols with a constant value are defined,
+///     symbols whose value depends on addresses are left to the parser
+///  2. macros (`.macro` .. `.endm`) are expanded
+///  3. label operands named like a symbol are replaced by its value
+///  4. blocks between `.rept N` and `.endr` are repeated N times
+///
+/// Macros are defined with
+/// ```text
+/// .macro push reg
+///     st %reg7 reg
+///     dec %reg7
+/// .endm
+/// ```
+/// and invoked like an instruction (`push %reg0`). Every label operand in
+/// the body named like a parameter is replaced by the matching argument and
+/// all expanded keywords get the line number of the invocation. Only
+/// numeric labels (`1:`) may be defined in a macro body or a repeated
+/// block, every copy of them gets its own name in the parser.
+pub fn preprocessor(keywords: Vec<Keyword>) -> Result<Vec<Keyword>, PreprocessorError> {
+    preprocessor_with_options(keywords, &PreprocessorOptions::default())
+}
+
+pub fn preprocessor_with_options(
+    keywords: Vec<Keyword>,
+    options: &PreprocessorOptions,
+) -> Result<Vec<Keyword>, PreprocessorError> {
+    let mut symbols = options.defines.clone();
+    let keywords = if options.case_insensitive {
+        keywords
+            .into_iter()
+            .map(|keyword| match keyword {
+                Keyword::Directive {
+                    name,
+                    line_number,
+                    column,
+                } => Keyword::Directive {
+                    name: name.to_ascii_lowercase(),
+                    line_number,
+                    column,
+                },
+                keyword => keyword,
+            })
+            .collect()
+    } else {
+        keywords
+    };
+    let keywords = resolve_conditionals(keywords, &mut symbols)?;
+
+    let mut macros: HashMap<String, Macro> = HashMap::new();
+    let mut remaining: Vec<Keyword> = Vec::with_capacity(keywords.len());
+    let mut iter = keywords.into_iter().peekable();
+
+    while let Some(keyword) = iter.next() {
+        match &keyword {
+            Keyword::Directive {
+                name, line_number, ..
+            } if name == "macro" => {
+                let (name, definition) = try_parse_macro(&mut iter, *line_number)?;
+                if macros.contains_key(&name) {
+                    return Err(PreprocessorError::DuplicateMacro {
+                        name,
+                        line_number: *line_number,
+                    });
+                }
+                macros.insert(name, definition);
+            }
+            Keyword::Directive {
+                name, line_number, ..
+            } if name == "endm" => {
+                return Err(PreprocessorError::UnexpectedEndMacro {
+                    line_number: *line_number,
+                })
+            }
+            _ => remaining.push(keyword),
+        }
+    }
+
+    let expanded = expand_macros(remaining, &macros, 0)?;
+    expand_repeats(substitute_symbols(expanded, &symbols))
+}
+
+/// Next keyword on `line_number` that is an operand of the command in front
+fn next_operand(keywords: &mut Keywords, line_number: u16) -> Option<Keyword> {
+    keywords.next_if(|keyword| {
+        keyword.get_line_number() == line_number
+            && !matches!(
+                keyword,
+                Keyword::Mmenonic { .. }
+                    | Keyword::Directive { .. }
+                    | Keyword::Doc { .. }
+                    | Keyword::Attribute { .. }
+            )
+    })
+}
+
+/// Conditional block opened by `.if`, `.ifdef` or `.ifndef`
+struct Conditional {
+    line_number: u16,
+    condition: bool,
+    in_else: bool,
+    /// whether the block around this one is assembled
+    enclosing: bool,
+}
+
+impl Conditional {
+    fn active(&self) -> bool {
+        self.enclosing && self.condition != self.in_else
+    }
+}
+
+/// Drops the keywords of conditional blocks whose condition does not hold
+/// and collects the `.equ` symbols defined outside of them.
+fn resolve_conditionals(
+    keywords: Vec<Keyword>,
+    symbols: &mut Symbols,
+) -> Result<Vec<Keyword>, PreprocessorError> {
+    let mut resolved: Vec<Keyword> = Vec::with_capacity(keywords.len());
+    let mut conditionals: Vec<Conditional> = Vec::new();
+    let mut iter = keywords.into_iter().peekable();
+
+    while let Some(keyword) = iter.next() {
+        let active = conditionals.last().is_none_or(Conditional::active);
+        let (directive, line_number) = match &keyword {
+            Keyword::Directive {
+                name, line_number, ..
+            } => (name.as_str(), *line_number),
+            _ => {
+                if active {
+                    resolved.push(keyword);
+                }
+                continue;
+            }
+        };
+        match directive {
+            "if" | "ifdef" | "ifndef" => {
+                let operand = next_operand(&mut iter, line_number);
+                let condition = if active {
+                    try_parse_condition(
+                        directive,
+                        operand,
+                        next_operand(&mut iter, line_number),
+                        symbols,
+                        line_number,
+                    )?
+                } else {
+                    while next_operand(&mut iter, line_number).is_some() {}
+                    false
+                };
+                conditionals.push(Conditional {
+                    line_number,
+                    condition,
+                    in_else: false,
+                    enclosing: active,
+                });
+            }
+            "else" => match conditionals.last_mut() {
+                Some(conditional) if !conditional.in_else => conditional.in_else = true,
+                _ => {
+                    return Err(PreprocessorError::UnexpectedConditional {
+                        directive: directive.to_string(),
+                        line_number,
+                    })
+                }
+            },
+            "endif" => {
+                conditionals
+                    .pop()
+                    .ok_or(PreprocessorError::UnexpectedConditional {
+                        directive: directive.to_string(),
+                        line_number,
+                    })?;
+            }
+            "equ" if active => {
+                let mut operands = Vec::new();
+                while let Some(operand) = next_operand(&mut iter, line_number) {
+                    operands.push(operand);
+                }
+                if !try_define_symbol(&operands, symbols) {
+                    resolved.push(keyword);
+                    resolved.extend(operands);
+                }
+            }
+            "equ" => while next_operand(&mut iter, line_number).is_some() {},
+            _ if active => resolved.push(keyword),
+            _ => (),
+        }
+    }
+
+    match conditionals.first() {
+        Some(conditional) => Err(PreprocessorError::UnterminatedConditional {
+            line_number: conditional.line_number,
+        }),
+        None => Ok(resolved),
+    }
+}
+
+/// Evaluates the condition of an `.if` (constant, boolean or symbol that
+/// is not zero) or of an `.ifdef` / `.ifndef` (name of a symbol).
+fn try_parse_condition(
+    directive: &str,
+    operand: Option<Keyword>,
+    extra: Option<Keyword>,
+    symbols: &Symbols,
+    line_number: u16,
+) -> Result<bool, PreprocessorError> {
+    let invalid = |condition: String| PreprocessorError::InvalidCondition {
+        directive: directive.to_string(),
+        condition,
+        line_number,
+    };
+    if let Some(extra) = extra {
+        return Err(invalid(extra.get_original_string()));
+    }
+    match (directive, operand) {
+        ("ifdef", Some(Keyword::Label { name, .. })) => Ok(symbols.contains_key(&name)),
+        ("ifndef", Some(Keyword::Label { name, .. })) => Ok(!symbols.contains_key(&name)),
+        ("if", Some(Keyword::Constant { value, .. })) => Ok(value != 0),
+        ("if", Some(Keyword::Boolean { value, .. })) => Ok(value),
+        ("if", Some(Keyword::Label { name, .. })) => symbols
+            .get(&name)
+            .map(|value| *value != 0)
+            .ok_or(PreprocessorError::UndefinedSymbol {
+                symbol: name,
+                line_number,
+            }),
+        (_, Some(operand)) => Err(invalid(operand.get_original_string())),
+        (_, None) => Err(invalid(String::new())),
+    }
+}
+
+/// Defines the symbol of an `.equ NAME VALUE` whose value is a constant
+/// or a symbol defined before. Any other value depends on addresses, the
+/// directive is handed to the parser then.
+fn try_define_symbol(operands: &[Keyword], symbols: &mut Symbols) -> bool {
+    let value = match operands {
+        [Keyword::Label { .. }, Keyword::Constant { value, .. }] => *value,
+        [Keyword::Label { .. }, Keyword::Label { name, .. }] if symbols.contains_key(name) => {
+            symbols[name]
+        }
+        _ => return false,
+    };
+    if let Keyword::Label { name, .. } = &operands[0] {
+        symbols.insert(name.clone(), value);
+    }
+    true
+}
+
+/// Replaces label operands named like a symbol by a constant keyword with
+/// the value of the symbol. Labels starting a line are label definitions
+/// and stay untouched.
+fn substitute_symbols(keywords: Vec<Keyword>, symbols: &Symbols) -> Vec<Keyword> {
+    if symbols.is_empty() {
+        return keywords;
+    }
+    let mut substituted: Vec<Keyword> = Vec::with_capacity(keywords.len());
+    for keyword in keywords {
+        let is_operand = substituted.last().is_some_and(|previous: &Keyword| {
+            previous.get_line_number() == keyword.get_line_number()
+                && !matches!(previous, Keyword::Doc { .. })
+        });
+        match keyword {
+            Keyword::Label {
+                name,
+                line_number,
+                column,
+            } if is_operand && symbols.contains_key(&name) => substituted.push(Keyword::Constant {
+                value: symbols[&name],
+                line_number,
+                column,
+                origin: name,
+            }),
+            Keyword::Expression {
+                mut expression,
+                line_number,
+                column,
+                origin,
+            } => {
+                substitute_expression(&mut expression, symbols);
+                substituted.push(Keyword::Expression {
+                    expression,
+                    line_number,
+                    column,
+                    origin,
+                })
+            }
+            keyword => substituted.push(keyword),
+        }
+    }
+    substituted
+}
+
+/// Replaces the labels in `expression` named like a symbol by its value
+fn substitute_expression(expression: &mut ir::Expression, symbols: &Symbols) {
+    match expression {
+        ir::Expression::Label(reference) => {
+            if let Some(value) = symbols.get(reference.name()) {
+                *expression = ir::Expression::Constant(u32::from(*value));
+            }
+        }
+        ir::Expression::Low(inner) | ir::Expression::High(inner) => {
+            substitute_expression(inner, symbols)
+        }
+        ir::Expression::Sum(a, b) | ir::Expression::Difference(a, b) => {
+            substitute_expression(a, symbols);
+            substitute_expression(b, symbols);
+        }
+        ir::Expression::Constant(_) | ir::Expression::Location => (),
+    }
+}
+
+/// Reads the macro definition following a `.macro` directive up to and
+/// including its `.endm`.
+fn try_parse_macro(
+    keywords: &mut Keywords,
+    line_number: u16,
+) -> Result<(String, Macro), PreprocessorError> {
+    let name = match keywords.next_if(|keyword| keyword.get_line_number() == line_number) {
+        Some(Keyword::Label { name, .. }) => name,
+        _ => return Err(PreprocessorError::MissingMacroName { line_number }),
+    };
+    let mut parameters: Vec<String> = Vec::new();
+    while let Some(keyword) = keywords.next_if(|keyword| keyword.get_line_number() == line_number) {
+        match keyword {
+            Keyword::Label { name, .. } if !parameters.contains(&name) => parameters.push(name),
+            keyword => {
+                return Err(PreprocessorError::InvalidMacroParameter {
+                    parameter: keyword.get_original_string(),
+                    line_number,
+                })
+            }
+        }
+    }
+
+    let mut body: Vec<Keyword> = Vec::new();
+    let mut last_line = None;
+    for keyword in keywords.by_ref() {
+        let first_on_line = last_line != Some(keyword.get_line_number());
+        last_line = Some(keyword.get_line_number());
+        match keyword {
+            Keyword::Directive {
+                name: directive, ..
+            } if directive == "endm" => return Ok((name, Macro { parameters, body })),
+            Keyword::Directive {
+                name: directive,
+                line_number,
+                ..
+            } if directive == "macro" => {
+                return Err(PreprocessorError::NestedMacro { line_number })
+            }
+            Keyword::Label {
+                name: label,
+                line_number,
+                ..
+            } if first_on_line && !is_numeric_label(&label) => {
+                return Err(PreprocessorError::LabelInMacro {
+                    name,
+                    label,
+                    line_number,
+                })
+            }
+            keyword => body.push(keyword),
+        }
+    }
+
+    Err(PreprocessorError::UnterminatedMacro { name, line_number })
+}
+
+/// Replaces every invocation of one of `macros` in `keywords` by the
+/// macro body, macros used inside of a body are expanded as well.
+fn expand_macros(
+    keywords: Vec<Keyword>,
+    macros: &HashMap<String, Macro>,
+    depth: usize,
+) -> Result<Vec<Keyword>, PreprocessorError> {
+    let mut expanded: Vec<Keyword> = Vec::with_capacity(keywords.len());
+    let mut iter = keywords.into_iter().peekable();
+
+    while let Some(keyword) = iter.next() {
+        let (name, line_number, column) = match &keyword {
+            Keyword::Mmenonic {
+                name,
+                line_number,
+                column,
+            } if macros.contains_key(name) => (name.clone(), *line_number, *column),
+            _ => {
+                expanded.push(keyword);
+                continue;
+            }
+        };
+        if depth >= MAX_MACRO_DEPTH {
+            return Err(PreprocessorError::RecursiveMacro { name, line_number });
+        }
+        let definition = &macros[&name];
+        let mut arguments: Vec<Keyword> = Vec::new();
+        while let Some(argument) = next_operand(&mut iter, line_number) {
+            arguments.push(argument);
+        }
+        if arguments.len() != definition.parameters.len() {
+            return Err(PreprocessorError::MacroArguments {
+                name,
+                expected: definition.parameters.len(),
+                found: arguments.len(),
+                line_number,
+            });
+        }
+
+        let body = definition
+            .body
+            .iter()
+            .enumerate()
+            .map(|(idx, keyword)| {
+                let first_on_line = idx == 0
+                    || definition.body[idx - 1].get_line_number() != keyword.get_line_number();
+                let substituted = match keyword {
+                    // numeric label definitions keep their own line, the
+                    // parser would read them as operands otherwise
+                    Keyword::Label { .. } if first_on_line => return keyword.clone(),
+                    Keyword::Label { name, .. } => definition
+                        .parameters
+                        .iter()
+                        .position(|parameter| parameter == name)
+                        .map(|idx| arguments[idx].clone()),
+                    _ => None,
+                };
+                // keywords of the body point at the invocation
+                substituted
+                    .unwrap_or_else(|| keyword.clone().with_column(column))
+                    .with_line_number(line_number)
+            })
+            .collect();
+        expanded.extend(expand_macros(body, macros, depth + 1)?);
+    }
+
+    Ok(expanded)
+}
+
+/// Replaces every `.rept N` .. `.endr` block in `keywords` by N copies of
+/// its body, repeated blocks may be nested.
+fn expand_repeats(keywords: Vec<Keyword>) -> Result<Vec<Keyword>, PreprocessorError> {
+    let mut expanded: Vec<Keyword> = Vec::with_capacity(keywords.len());
+    let mut iter = keywords.into_iter().peekable();
+
+    while let Some(keyword) = iter.next() {
+        match keyword {
+            Keyword::Directive {
+                name, line_number, ..
+            } if name == "rept" => {
+                let count = match (
+                    next_operand(&mut iter, line_number),
+                    next_operand(&mut iter, line_number),
+                ) {
+                    (Some(Keyword::Constant { value, .. }), None) => value,
+                    (Some(count), _) => {
+                        return Err(PreprocessorError::InvalidRepeatCount {
+                            count: count.get_original_string(),
+                            line_number,
+                        })
+                    }
+                    (None, _) => {
+                        return Err(PreprocessorError::InvalidRepeatCount {
+                            count: String::new(),
+                            line_number,
+                        })
+                    }
+                };
+                let body = expand_repeats(try_parse_repeat(&mut iter, line_number)?)?;
+                for _ in 0..count {
+                    expanded.extend(body.iter().cloned());
+                }
+            }
+            Keyword::Directive {
+                name, line_number, ..
+            } if name == "endr" => {
+                return Err(PreprocessorError::UnexpectedEndRepeat { line_number })
+            }
+            keyword => expanded.push(keyword),
+        }
+    }
+
+    Ok(expanded)
+}
+
+/// Reads the body of the block opened by `.rept` at `line_number` up to
+/// and including its `.endr`. Nested blocks are kept in the body.
+fn try_parse_repeat(
+    keywords: &mut Keywords,
+    line_number: u16,
+) -> Result<Vec<Keyword>, PreprocessorError> {
+    let mut body: Vec<Keyword> = Vec::new();
+    let mut depth = 0;
+    let mut last_line = Some(line_number);
+    for keyword in keywords.by_ref() {
+        let first_on_line = last_line != Some(keyword.get_line_number());
+        last_line = Some(keyword.get_line_number());
+        match &keyword {
+            Keyword::Directive { name, .. } if name == "endr" && depth == 0 => return Ok(body),
+            Keyword::Directive { name, .. } if name == "endr" => depth -= 1,
+            Keyword::Directive { name, .. } if name == "rept" => depth += 1,
+            Keyword::Label {
+                name, line_number, ..
+            } if first_on_line && !is_numeric_label(name) => {
+                return Err(PreprocessorError::LabelInRepeat {
+                    label: name.clone(),
+                    line_number: *line_number,
+                })
+            }
+            _ => (),
+        }
+        body.push(keyword);
+    }
+
+    Err(PreprocessorError::UnterminatedRepeat { line_number })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expand_macro() {
+        let lexed = vec![
+            Keyword::directive("macro", 0),
+            Keyword::label("push", 0),
+            Keyword::label("reg", 0),
+            Keyword::mmenonic("st", 1),
+            Keyword::register_address("reg7", 1),
+            Keyword::label("reg", 1),
+            Keyword::mmenonic("dec", 2),
+            Keyword::register_address("reg7", 2),
+            Keyword::directive("endm", 3),
+            Keyword::label("main", 4),
+            Keyword::mmenonic("push", 5),
+            Keyword::register_address("reg0", 5),
+            Keyword::mmenonic("hlt", 6),
+        ];
+        let expected = vec![
+            Keyword::label("main", 4),
+            Keyword::mmenonic("st", 5),
+            Keyword::register_address("reg7", 5),
+            Keyword::register_address("reg0", 5),
+            Keyword::mmenonic("dec", 5),
+            Keyword::register_address("reg7", 5),
+            Keyword::mmenonic("hlt", 6),
+        ];
+
+        let found = preprocessor(lexed).unwrap();
+
+        assert_eq!(expected, found);
+        assert!(found[1..6]
+            .iter()
+            .all(|keyword| keyword.get_line_number() == 5));
+    }
+
+    #[test]
+    fn macro_fixture() {
+        let lexed = crate::lexer::lexer(std::path::Path::new("tests/macros.s")).unwrap();
+
+        let found = crate::parser::parser(preprocessor(lexed).unwrap()).unwrap();
+
+        assert_eq!(
+            5,
+            found
+                .instructions
+                .get(&crate::ir::LabelReference::new("main"))
+                .unwrap()
+                .len()
+        );
+    }
+
+    #[test]
+    fn conditionals() {
+        let lexed = crate::lexer::lexer(std::path::Path::new("tests/conditionals.s")).unwrap();
+        let expected = vec![
+            Keyword::label("main", 4),
+            Keyword::mmenonic("ldc", 6),
+            Keyword::register_address("reg0", 6),
+            Keyword::constant("LEDS", 0x0f, 6),
+            Keyword::mmenonic("inc", 16),
+            Keyword::register_address("reg0", 16),
+            Keyword::mmenonic("hlt", 18),
+        ];
+
+        let found = preprocessor(lexed).unwrap();
+
+        assert_eq!(expected, found);
+    }
+
+    #[test]
+    fn defines() {
+        let size = || Box::new(ir::Expression::Label(ir::LabelReference::new("SIZE")));
+        let lexed = vec![
+            Keyword::directive("ifdef", 0),
+            Keyword::label("DEBUG", 0),
+            Keyword::mmenonic("ldc", 1),
+            Keyword::register_address("reg0", 1),
+            Keyword::label("SIZE", 1),
+            Keyword::mmenonic("ldc", 2),
+            Keyword::register_address("reg1", 2),
+            Keyword::expression("hi(SIZE)", ir::Expression::High(size()), 2),
+            Keyword::directive("endif", 3),
+        ];
+        let options = PreprocessorOptions {
+            defines: HashMap::from([(String::from("DEBUG"), 1), (String::from("SIZE"), 64)]),
+            ..PreprocessorOptions::default()
+        };
+
+        let found = preprocessor_with_options(lexed, &options).unwrap();
+
+        assert_eq!(
+            vec![
+                Keyword::mmenonic("ldc", 1),
+                Keyword::register_address("reg0", 1),
+                Keyword::constant("SIZE", 64, 1),
+                Keyword::mmenonic("ldc", 2),
+                Keyword::register_address("reg1", 2),
+                Keyword::expression(
+                    "hi(SIZE)",
+                    ir::Expression::High(Box::new(ir::Expression::Constant(64))),
+                    2
+                ),
+            ],
+            found
+        );
+    }
+
+    #[test]
+    fn conditional_errors() {
+        assert!(matches!(
+            preprocessor(vec![
+                Keyword::directive("if", 0),
+                Keyword::constant("1", 1, 0)
+            ]),
+            Err(PreprocessorError::UnterminatedConditional { line_number: 0 })
+        ));
+        assert!(matches!(
+            preprocessor(vec![Keyword::directive("else", 3)]),
+            Err(PreprocessorError::UnexpectedConditional { line_number: 3, .. })
+        ));
+        assert!(matches!(
+            preprocessor(vec![
+                Keyword::directive("if", 0),
+                Keyword::label("DEBUG", 0),
+                Keyword::directive("endif", 1),
+            ]),
+            Err(PreprocessorError::UndefinedSymbol { line_number: 0, .. })
+        ));
+        assert!(matches!(
+            preprocessor(vec![
+                Keyword::directive("ifdef", 0),
+                Keyword::constant("1", 1, 0),
+                Keyword::directive("endif", 1),
+            ]),
+            Err(PreprocessorError::InvalidCondition { line_number: 0, .. })
+        ));
+        // conditions inside of skipped blocks are not evaluated
+        let skipped = vec![
+            Keyword::directive("if", 0),
+            Keyword::boolean("false", false, 0),
+            Keyword::directive("if", 1),
+            Keyword::label("UNDEFINED", 1),
+            Keyword::mmenonic("nop", 2),
+            Keyword::directive("endif", 3),
+            Keyword::directive("else", 4),
+            Keyword::mmenonic("hlt", 5),
+            Keyword::directive("endif", 6),
+        ];
+        assert_eq!(
+            vec![Keyword::mmenonic("hlt", 5)],
+            preprocessor(skipped).unwrap()
+        );
+    }
+
+    #[test]
+    fn repeats() {
+        let lexed = crate::lexer::lexer(std::path::Path::new("tests/repeat.s")).unwrap();
+
+        let found = crate::parser::parser(preprocessor(lexed).unwrap()).unwrap();
+        let binary = crate::generator::generator(found).ok().unwrap();
+
+        let raw: Vec<u32> = binary
+            .iter()
+            .map(crate::generator::InstructionWord::raw)
+            .collect();
+        assert_eq!(
+            vec![0, 0, 0, 0x6c, 0x6c, 0x6c, 0x6c, 0x6c, 0x6c, 0x7f],
+            raw[..10].to_vec()
+        );
+        assert!(matches!(
+            preprocessor(vec![Keyword::directive("rept", 0), Keyword::label("N", 0)]),
+            Err(PreprocessorError::InvalidRepeatCount { line_number: 0, .. })
+        ));
+        assert!(matches!(
+            preprocessor(vec![
+                Keyword::directive("rept", 0),
+                Keyword::constant("2", 2, 0),
+                Keyword::mmenonic("nop", 1),
+            ]),
+            Err(PreprocessorError::UnterminatedRepeat { line_number: 0 })
+        ));
+    }
+
+    #[test]
+    fn macro_errors() {
+        let unterminated = vec![
+            Keyword::directive("macro", 0),
+            Keyword::label("push", 0),
+            Keyword::mmenonic("nop", 1),
+        ];
+        assert!(matches!(
+            preprocessor(unterminated),
+            Err(PreprocessorError::UnterminatedMacro { line_number: 0, .. })
+        ));
+        assert!(matches!(
+            preprocessor(vec![Keyword::directive("endm", 2)]),
+            Err(PreprocessorError::UnexpectedEndMacro { line_number: 2 })
+        ));
+        let arguments = vec![
+            Keyword::directive("macro", 0),
+            Keyword::label("push", 0),
+            Keyword::label("reg", 0),
+            Keyword::mmenonic("nop", 1),
+            Keyword::directive("endm", 2),
+            Keyword::mmenonic("push", 3),
+        ];
+        assert!(matches!(
+            preprocessor(arguments),
+            Err(PreprocessorError::MacroArguments {
+                expected: 1,
+                found: 0,
+                line_number: 3,
+                ..
+            })
+        ));
+        let label = vec![
+            Keyword::directive("macro", 0),
+            Keyword::label("wait", 0),
+            Keyword::label("loop", 1),
+            Keyword::directive("endm", 2),
+        ];
+        assert!(matches!(
+            preprocessor(label),
+            Err(PreprocessorError::LabelInMacro { line_number: 1, .. })
+        ));
+        let recursive = vec![
+            Keyword::directive("macro", 0),
+            Keyword::label("forever", 0),
+            Keyword::mmenonic("forever", 1),
+            Keyword::directive("endm", 2),
+            Keyword::mmenonic("forever", 3),
+        ];
+        assert!(matches!(
+            preprocessor(recursive),
+            Err(PreprocessorError::RecursiveMacro { line_number: 3, .. })
+        ));
+    }
+}
@@ -0,0 +1,140 @@
+use std::{collections::HashMap, fmt};
+
+use crate::ir;
+
+/// Errors of reading a profile
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProfileError {
+    /// The line is not the number of cycles followed by a label
+    InvalidLine { line_number: usize, line: String },
+}
+
+impl fmt::Display for ProfileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProfileError::InvalidLine { line_number, line } => write!(
+                f,
+                "Line {} '{}' is not the number of cycles followed by a label",
+                line_number, line
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ProfileError {}
+
+/// Number of cycles spent in the code of every label of `ir`, counted from
+/// the number of times every address was executed. Labels without code
+/// count nothing, their cycles count for the label with code at the same
+/// address. Sorted from the most to the least cycles.
+pub fn profile(ir: &ir::IR, executed: &HashMap<u16, u64>) -> Vec<(String, u64)> {
+    let mut labels: Vec<(u16, &str)> = ir
+        .label_definitions
+        .0
+        .values()
+        .filter(|label| {
+            ir.instructions
+                .get(&ir::LabelReference::new(&label.name))
+                .is_some_and(|instructions| !instructions.is_empty())
+        })
+        .map(|label| (label.address.0, label.name.as_str()))
+        .collect();
+    labels.sort();
+
+    let mut cycles: HashMap<&str, u64> = HashMap::new();
+    for (address, count) in executed {
+        let label = labels.partition_point(|(start, _)| start <= address);
+        if let Some((_, name)) = label.checked_sub(1).map(|label| labels[label]) {
+            *cycles.entry(name).or_default() += count;
+        }
+    }
+    let mut profile: Vec<(String, u64)> = cycles
+        .into_iter()
+        .map(|(name, count)| (name.to_string(), count))
+        .collect();
+    profile.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    profile
+}
+
+/// One line per label with the number of cycles followed by the label,
+/// read by [`parse_profile`]
+pub fn to_text(profile: &[(String, u64)]) -> String {
+    let mut text = String::from("# cycles label\n");
+    for (label, cycles) in profile {
+        text += &format!("{} {}\n", cycles, label);
+    }
+    text
+}
+
+/// Reads the format of [`to_text`]. Empty lines and lines starting with `#`
+/// are skipped.
+pub fn parse_profile(text: &str) -> Result<HashMap<String, u64>, ProfileError> {
+    let mut profile = HashMap::new();
+    for (index, line) in text.lines().enumerate() {
+        let mut words = line.split_whitespace();
+        let cycles = match words.next() {
+            None => continue,
+            Some(word) if word.starts_with('#') => continue,
+            Some(word) => word.parse::<u64>().ok(),
+        };
+        match (cycles, words.next(), words.next()) {
+            (Some(cycles), Some(label), None) => {
+                *profile.entry(label.to_string()).or_default() += cycles;
+            }
+            _ => {
+                return Err(ProfileError::InvalidLine {
+                    line_number: index + 1,
+                    line: line.to_string(),
+                })
+            }
+        }
+    }
+    Ok(profile)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> ir::IR {
+        let options = crate::lexer::LexerOptions { auto_halt: false };
+        crate::parser::parser(crate::lexer::lexer_from_str(source, &options).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn count_labels() {
+        let ir = parse(
+            "main:
+    ldc %reg1 2
+loop:
+again:
+    dec %reg1
+    jnzr loop
+    hlt
+",
+        );
+        let executed = HashMap::from([(0, 1), (1, 2), (2, 2), (3, 1)]);
+        let profile = profile(&ir, &executed);
+        assert_eq!(
+            vec![(String::from("again"), 5), (String::from("main"), 1)],
+            profile
+        );
+
+        let text = to_text(&profile);
+        assert_eq!("# cycles label\n5 again\n1 main\n", text);
+        assert_eq!(
+            Ok(HashMap::from([
+                (String::from("again"), 5),
+                (String::from("main"), 1)
+            ])),
+            parse_profile(&text)
+        );
+        assert_eq!(
+            Err(ProfileError::InvalidLine {
+                line_number: 2,
+                line: String::from("main 1")
+            }),
+            parse_profile("\nmain 1\n")
+        );
+    }
+}
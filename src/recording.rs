@@ -0,0 +1,129 @@
+use std::fmt;
+
+/// First bytes of a recording file
+const MAGIC: &[u8; 8] = b"masmrec1";
+
+/// Errors of reading a recording file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecordingError {
+    /// The file does not start with the magic bytes of a recording
+    NoRecording,
+    /// The file ends inside of the read with the index
+    Truncated { read: usize },
+}
+
+impl fmt::Display for RecordingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecordingError::NoRecording => write!(f, "File is no masm recording"),
+            RecordingError::Truncated { read } => {
+                write!(f, "Recording ends inside of read {}", read)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RecordingError {}
+
+/// Values the devices answered to the `ld` instructions of a run, the only
+/// inputs of the emulator which are not part of the program. Recorded with
+/// [`crate::emulator::Emulator::record`], a run is reproduced by
+/// [`crate::emulator::Emulator::replay`], which answers the reads from the
+/// recording instead of the devices.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Recording {
+    /// address and value of every device read in the order of the reads
+    pub reads: Vec<(u16, u32)>,
+    /// number of reads answered by [`Recording::next`]
+    replayed: usize,
+}
+
+impl Recording {
+    pub fn new(reads: Vec<(u16, u32)>) -> Self {
+        Recording { reads, replayed: 0 }
+    }
+
+    /// Value of the next read if it is recorded for `address`
+    pub fn next(&mut self, address: u16) -> Option<u32> {
+        let (recorded, value) = *self.reads.get(self.replayed)?;
+        if recorded != address {
+            return None;
+        }
+        self.replayed += 1;
+        Some(value)
+    }
+
+    /// Answers the last read by [`Recording::next`] again
+    pub fn rewind(&mut self) {
+        self.replayed = self.replayed.saturating_sub(1);
+    }
+
+    /// Number of reads answered by [`Recording::next`]
+    pub fn replayed(&self) -> usize {
+        self.replayed
+    }
+
+    /// The magic bytes `masmrec1` followed by the little endian 16 bit
+    /// address and 32 bit value of every read
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        for (address, value) in &self.reads {
+            bytes.extend(address.to_le_bytes());
+            bytes.extend(value.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Reads the format of [`Recording::to_bytes`]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, RecordingError> {
+        let reads = bytes
+            .strip_prefix(MAGIC)
+            .ok_or(RecordingError::NoRecording)?;
+        let chunks = reads.chunks(6);
+        let reads = chunks
+            .enumerate()
+            .map(|(read, chunk)| match chunk {
+                [a0, a1, v0, v1, v2, v3] => Ok((
+                    u16::from_le_bytes([*a0, *a1]),
+                    u32::from_le_bytes([*v0, *v1, *v2, *v3]),
+                )),
+                _ => Err(RecordingError::Truncated { read }),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Recording::new(reads))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let recording = Recording::new(vec![(0xfff0, 0x68), (0xfff1, 1), (0xff00, u32::MAX)]);
+        let bytes = recording.to_bytes();
+        assert_eq!(8 + 3 * 6, bytes.len());
+        assert_eq!(Ok(recording), Recording::from_bytes(&bytes));
+
+        assert_eq!(
+            Err(RecordingError::Truncated { read: 2 }),
+            Recording::from_bytes(&bytes[..bytes.len() - 1])
+        );
+        assert_eq!(
+            Err(RecordingError::NoRecording),
+            Recording::from_bytes(b"v3.0 hex words plain\n")
+        );
+    }
+
+    #[test]
+    fn replay() {
+        let mut recording = Recording::new(vec![(0xfff0, 0x68), (0xfff0, 0x69)]);
+        assert_eq!(Some(0x68), recording.next(0xfff0));
+        assert_eq!(None, recording.next(0xfff1));
+        assert_eq!(Some(0x69), recording.next(0xfff0));
+        assert_eq!(None, recording.next(0xfff0));
+        recording.rewind();
+        assert_eq!(Some(0x69), recording.next(0xfff0));
+        assert_eq!(2, recording.replayed());
+    }
+}
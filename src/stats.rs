@@ -0,0 +1,285 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+use crate::diagnostic::json_string;
+use crate::ir;
+
+/// Numbers describing an assembled program, e.g. to track its code size.
+/// Instructions are counted after [`crate::lowering::lower`], so every
+/// pseudo-instruction counts as the machine instructions it expands to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stats {
+    /// size of the image without padding
+    pub words: usize,
+    /// name, address and number of words of the code of every label,
+    /// sorted by address
+    pub labels: Vec<(String, u16, u16)>,
+    /// number of instructions per mnemonic, the most frequent first
+    pub instructions: Vec<(String, usize)>,
+    /// number of `ldc` loading a constant or label address
+    pub constant_loads: usize,
+    /// number of different values loaded by `ldc`
+    pub distinct_constants: usize,
+    /// relative jump with the largest distance
+    pub longest_jump: Option<RelativeJump>,
+}
+
+/// Relative jump `distance` words from `address`, towards `target` unless
+/// it jumps by a constant offset
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelativeJump {
+    pub address: u16,
+    pub distance: i32,
+    pub target: Option<String>,
+}
+
+impl Stats {
+    /// Collects the statistics of `ir`, whose pseudo-instructions may
+    /// still be unexpanded
+    pub fn new(ir: &ir::IR) -> Self {
+        let lowered = crate::lowering::lower(ir.clone());
+        let mut labels: Vec<(String, u16, u16)> = lowered
+            .label_definitions
+            .0
+            .iter()
+            .map(|(reference, label)| {
+                let words = lowered.block_size(reference);
+                (label.name.clone(), label.address.0, words)
+            })
+            .collect();
+        labels.sort_by(|a, b| (a.1, &a.0).cmp(&(b.1, &b.0)));
+
+        let mut histogram: HashMap<String, usize> = HashMap::new();
+        let mut constant_loads = 0;
+        let mut constants = BTreeSet::new();
+        let mut longest_jump: Option<RelativeJump> = None;
+        // in the order of the addresses, so the first of equally long jumps wins
+        let mut blocks: Vec<(u16, &Vec<ir::Instruction>)> = lowered
+            .instructions
+            .iter()
+            .filter_map(|(reference, instructions)| {
+                let label = lowered.label_definitions.0.get(reference)?;
+                Some((label.address.0, instructions))
+            })
+            .collect();
+        blocks.sort_by_key(|(address, _)| *address);
+        for (mut address, instructions) in blocks {
+            for instruction in instructions {
+                let printed = instruction.to_string();
+                let mnemonic = printed.split_whitespace().next().unwrap_or_default();
+                *histogram.entry(mnemonic.to_string()).or_default() += 1;
+                match instruction {
+                    ir::Instruction::Load {
+                        source: ir::LoadSource::Constant(value),
+                        ..
+                    } => {
+                        constant_loads += 1;
+                        constants.insert(u32::from(*value));
+                    }
+                    ir::Instruction::Load {
+                        source: ir::LoadSource::Expression(expression),
+                        ..
+                    } => {
+                        constant_loads += 1;
+                        if let Ok(value) = expression.evaluate(&lowered.label_definitions, address)
+                        {
+                            constants.insert(value & 0xffff);
+                        }
+                    }
+                    ir::Instruction::Jump { target, .. } => {
+                        let jump = relative_jump(target, address, &lowered.label_definitions);
+                        if let Some(jump) = jump.filter(|jump| {
+                            longest_jump
+                                .as_ref()
+                                .is_none_or(|longest| jump.distance.abs() > longest.distance.abs())
+                        }) {
+                            longest_jump = Some(jump);
+                        }
+                    }
+                    _ => {}
+                }
+                address = address.wrapping_add(instruction.size());
+            }
+        }
+        let mut instructions: Vec<(String, usize)> = histogram.into_iter().collect();
+        instructions.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        Stats {
+            words: lowered.size(),
+            labels,
+            instructions,
+            constant_loads,
+            distinct_constants: constants.len(),
+            longest_jump,
+        }
+    }
+
+    /// Serializes the statistics as JSON, e.g.
+    /// ```json
+    /// {
+    ///   "words": 12,
+    ///   "labels": [{"name": "main", "address": 0, "words": 12}],
+    ///   "instructions": {"ldc": 4, "hlt": 1},
+    ///   "constants": {"loads": 4, "distinct": 3},
+    ///   "longest_jump": {"address": 9, "distance": -5, "target": "loop"}
+    /// }
+    /// ```
+    pub fn to_json(&self) -> String {
+        let labels: Vec<String> = self
+            .labels
+            .iter()
+            .map(|(name, address, words)| {
+                format!(
+                    "{{\"name\": {}, \"address\": {}, \"words\": {}}}",
+                    json_string(name),
+                    address,
+                    words
+                )
+            })
+            .collect();
+        let instructions: Vec<String> = self
+            .instructions
+            .iter()
+            .map(|(mnemonic, count)| format!("{}: {}", json_string(mnemonic), count))
+            .collect();
+        let longest_jump = self.longest_jump.as_ref().map_or_else(
+            || String::from("null"),
+            |jump| {
+                format!(
+                    "{{\"address\": {}, \"distance\": {}, \"target\": {}}}",
+                    jump.address,
+                    jump.distance,
+                    jump.target
+                        .as_deref()
+                        .map_or_else(|| String::from("null"), json_string)
+                )
+            },
+        );
+        format!(
+            "{{
+  \"words\": {},
+  \"labels\": [{}],
+  \"instructions\": {{{}}},
+  \"constants\": {{\"loads\": {}, \"distinct\": {}}},
+  \"longest_jump\": {}
+}}
+",
+            self.words,
+            labels.join(", "),
+            instructions.join(", "),
+            self.constant_loads,
+            self.distinct_constants,
+            longest_jump
+        )
+    }
+}
+
+/// Report with one statistic per line, labels and instructions in
+/// indented tables
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "words         {}", self.words)?;
+        writeln!(f, "labels")?;
+        let width = self
+            .labels
+            .iter()
+            .map(|(name, _, _)| name.len())
+            .max()
+            .unwrap_or_default();
+        for (name, address, words) in &self.labels {
+            writeln!(f, "  {:<width$}  {:#06x}  {:>5}", name, address, words)?;
+        }
+        writeln!(f, "instructions")?;
+        for (mnemonic, count) in &self.instructions {
+            writeln!(f, "  {:<8}  {:>5}", mnemonic, count)?;
+        }
+        writeln!(
+            f,
+            "constants     {} loads, {} distinct",
+            self.constant_loads, self.distinct_constants
+        )?;
+        match &self.longest_jump {
+            Some(jump) => {
+                write!(
+                    f,
+                    "longest jump  {:+} words at {:#06x}",
+                    jump.distance, jump.address
+                )?;
+                match &jump.target {
+                    Some(target) => writeln!(f, " to {}", target),
+                    None => writeln!(f),
+                }
+            }
+            None => writeln!(f, "longest jump  -"),
+        }
+    }
+}
+
+/// Distance of a jump at `address` to its target, `None` for absolute jumps
+/// through a register
+fn relative_jump(
+    target: &ir::JumpTarget,
+    address: u16,
+    labels: &ir::LabelLUT,
+) -> Option<RelativeJump> {
+    let (label, offset) = match target {
+        ir::JumpTarget::Register(_) => return None,
+        ir::JumpTarget::Constant(offset) => {
+            return Some(RelativeJump {
+                address,
+                distance: i32::from(*offset as i16),
+                target: None,
+            })
+        }
+        ir::JumpTarget::Label(label) => (label, 0),
+        ir::JumpTarget::LabelOffset { label, offset } => (label, *offset),
+    };
+    let target = labels.0.get(label)?.address.0;
+    Some(RelativeJump {
+        address,
+        distance: i32::from(target) + i32::from(offset) - i32::from(address),
+        target: Some(label.name().to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn program() {
+        let lexed = crate::lexer::lexer(std::path::Path::new("tests/local_labels.s")).unwrap();
+        let ir = crate::parser::parser(lexed).unwrap();
+        let stats = Stats::new(&ir);
+
+        assert_eq!(
+            "words         12
+labels
+  main        0x0000      1
+  main.loop   0x0001      4
+  main.done   0x0005      2
+  other       0x0007      1
+  other.loop  0x0008      4
+instructions
+  ldc           4
+  dec           2
+  jmp           2
+  jnzr          2
+  hlt           1
+  jr            1
+constants     4 loads, 4 distinct
+longest jump  -5 words at 0x000a to main.done
+",
+            stats.to_string()
+        );
+        let json = stats.to_json();
+        assert!(json.starts_with("{\n  \"words\": 12,\n  \"labels\": [{\"name\": \"main\", \"address\": 0, \"words\": 1}, "));
+        assert!(json.contains("\n  \"instructions\": {\"ldc\": 4, \"dec\": 2, \"jmp\": 2, \"jnzr\": 2, \"hlt\": 1, \"jr\": 1},\n"));
+        assert!(json.ends_with(
+            "  \"constants\": {\"loads\": 4, \"distinct\": 4},
+  \"longest_jump\": {\"address\": 10, \"distance\": -5, \"target\": \"main.done\"}
+}
+"
+        ));
+    }
+}
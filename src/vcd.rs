@@ -0,0 +1,172 @@
+use std::io::{self, Write};
+
+use crate::emulator::{Emulator, TraceEntry};
+use crate::ir;
+
+/// Name and width of every signal of the dump in the order of their
+/// identifier codes, which start at `!`
+const SIGNALS: [(&str, u8); 18] = [
+    ("pc", 16),
+    ("reg0", 32),
+    ("reg1", 32),
+    ("reg2", 32),
+    ("reg3", 32),
+    ("reg4", 32),
+    ("reg5", 32),
+    ("reg6", 32),
+    ("reg7", 32),
+    ("zero", 1),
+    ("carry", 1),
+    ("overflow", 1),
+    ("wide_mode", 1),
+    ("halted", 1),
+    ("bus_address", 16),
+    ("bus_data", 32),
+    ("bus_read", 1),
+    ("bus_write", 1),
+];
+
+/// Writes a Value Change Dump of the CPU, e.g. for GTKWave. Every cycle
+/// takes `period` nanoseconds, the values at the end of a cycle are
+/// dumped at its end. The bus signals show the `ld` or `st` of the cycle
+/// and are cleared in cycles without one.
+pub struct Vcd<W: Write> {
+    output: W,
+    period: u64,
+    time: u64,
+    /// last dumped value of every signal
+    values: [u32; SIGNALS.len()],
+}
+
+impl<W: Write> Vcd<W> {
+    /// Writes the header and the state of `emulator` at time 0
+    pub fn new(mut output: W, emulator: &Emulator, period: u64) -> io::Result<Self> {
+        writeln!(output, "$version masm {} $end", env!("CARGO_PKG_VERSION"))?;
+        writeln!(output, "$timescale 1ns $end")?;
+        writeln!(output, "$scope module cpu $end")?;
+        for (index, (name, width)) in SIGNALS.iter().enumerate() {
+            writeln!(output, "$var wire {} {} {} $end", width, code(index), name)?;
+        }
+        writeln!(output, "$upscope $end")?;
+        writeln!(output, "$enddefinitions $end")?;
+        let values = sample(emulator, None);
+        writeln!(output, "#0")?;
+        writeln!(output, "$dumpvars")?;
+        for (index, value) in values.iter().enumerate() {
+            write_value(&mut output, index, *value)?;
+        }
+        writeln!(output, "$end")?;
+        Ok(Vcd {
+            output,
+            period,
+            time: 0,
+            values,
+        })
+    }
+
+    /// Dumps the signals which changed in the cycle of `entry`, meant as
+    /// hook of [`Emulator::run_traced`]
+    pub fn cycle(&mut self, emulator: &Emulator, entry: &TraceEntry) -> io::Result<()> {
+        self.time += self.period;
+        let values = sample(emulator, Some(entry));
+        writeln!(self.output, "#{}", self.time)?;
+        for (index, value) in values.iter().enumerate() {
+            if self.values[index] != *value {
+                write_value(&mut self.output, index, *value)?;
+            }
+        }
+        self.values = values;
+        Ok(())
+    }
+
+    /// Ends the last cycle and returns the flushed output
+    pub fn finish(mut self) -> io::Result<W> {
+        writeln!(self.output, "#{}", self.time + self.period)?;
+        self.output.flush()?;
+        Ok(self.output)
+    }
+}
+
+/// Identifier code of signal `index`
+fn code(index: usize) -> char {
+    char::from(b'!' + index as u8)
+}
+
+fn write_value(output: &mut impl Write, index: usize, value: u32) -> io::Result<()> {
+    if SIGNALS[index].1 == 1 {
+        writeln!(output, "{}{}", value, code(index))
+    } else {
+        writeln!(output, "b{:b} {}", value, code(index))
+    }
+}
+
+fn sample(emulator: &Emulator, entry: Option<&TraceEntry>) -> [u32; SIGNALS.len()] {
+    let mut values = [0; SIGNALS.len()];
+    values[0] = u32::from(emulator.pc);
+    for register in 0..ir::REGISTER_COUNT {
+        values[1 + usize::from(register)] = emulator.register(register);
+    }
+    values[9] = u32::from(emulator.flags.zero);
+    values[10] = u32::from(emulator.flags.carry);
+    values[11] = u32::from(emulator.flags.overflow);
+    values[12] = u32::from(emulator.wide_mode);
+    values[13] = u32::from(emulator.halted);
+    let load = entry.and_then(|entry| entry.load);
+    let store = entry.and_then(|entry| entry.store);
+    if let Some((address, data)) = load.or(store) {
+        values[14] = u32::from(address);
+        values[15] = data;
+    }
+    values[16] = u32::from(load.is_some());
+    values[17] = u32::from(store.is_some());
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dump() {
+        let program = crate::assemble_str(
+            "main:
+    ldc %reg0 0x20
+    st %reg0 %reg0
+",
+        )
+        .unwrap();
+        let mut emulator = Emulator::new(program);
+        let mut vcd = Vcd::new(Vec::new(), &emulator, 10).unwrap();
+        let mut written = Ok(());
+        emulator
+            .run_traced(10, |emulator, entry| {
+                if written.is_ok() {
+                    written = vcd.cycle(emulator, entry);
+                }
+            })
+            .unwrap();
+        written.unwrap();
+        let dump = String::from_utf8(vcd.finish().unwrap()).unwrap();
+
+        assert!(dump.contains("$var wire 16 ! pc $end\n$var wire 32 \" reg0 $end\n"));
+        assert!(dump.contains("$var wire 1 2 bus_write $end\n$upscope $end\n"));
+        assert!(dump.contains("$dumpvars\nb0 !\nb0 \"\n"));
+        assert!(dump.ends_with(
+            "#10
+b1 !
+b100000 \"
+#20
+b10 !
+b100000 /
+b100000 0
+12
+#30
+1.
+b0 /
+b0 0
+02
+#40
+"
+        ));
+    }
+}
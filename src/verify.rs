@@ -0,0 +1,235 @@
+use std::fmt;
+
+use crate::{
+    disassembler,
+    error::MasmError,
+    generator::{self, GeneratorError, InstructionWord},
+    ir, lexer, lowering, parser, preprocessor,
+};
+
+/// Disagreement between the generator and the [`disassembler`]
+#[derive(Debug)]
+pub enum VerifyError {
+    /// The program could not be generated in the first place
+    Generator(GeneratorError),
+    /// The word generated for `expected` decodes to another instruction
+    Decoded {
+        address: u16,
+        word: InstructionWord,
+        expected: String,
+        found: Option<String>,
+    },
+    /// The disassembly of the image does not assemble
+    Listing(MasmError),
+    /// The disassembly of the image assembles to another word
+    Reassembled {
+        address: usize,
+        expected: Option<InstructionWord>,
+        found: Option<InstructionWord>,
+    },
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyError::Generator(error) => write!(f, "{}", error),
+            VerifyError::Decoded {
+                address,
+                word,
+                expected,
+                found: Some(found),
+            } => write!(
+                f,
+                "Word {} of '{}' at address {:#06x} decodes to '{}'",
+                word, expected, address, found
+            ),
+            VerifyError::Decoded {
+                address,
+                word,
+                expected,
+                found: None,
+            } => write!(
+                f,
+                "Word {} of '{}' at address {:#06x} decodes to no instruction",
+                word, expected, address
+            ),
+            VerifyError::Listing(error) => {
+                write!(f, "Disassembly of the image does not assemble: {}", error)
+            }
+            VerifyError::Reassembled {
+                address,
+                expected,
+                found,
+            } => {
+                let word = |word: &Option<InstructionWord>| {
+                    word.as_ref()
+                        .map_or(String::from("nothing"), InstructionWord::to_string)
+                };
+                write!(
+                    f,
+                    "Disassembly of word {} at address {:#06x} assembles to {}",
+                    word(expected),
+                    address,
+                    word(found)
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            VerifyError::Generator(error) => Some(error),
+            VerifyError::Listing(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl VerifyError {
+    /// Stable name of the error kind, e.g. for machine readable diagnostics
+    pub fn code(&self) -> &'static str {
+        match self {
+            VerifyError::Generator(error) => error.code(),
+            VerifyError::Decoded { .. } => "decode-mismatch",
+            VerifyError::Listing(_) => "invalid-disassembly",
+            VerifyError::Reassembled { .. } => "reassembly-mismatch",
+        }
+    }
+}
+
+/// Generates `ir` and checks that the [`disassembler`] agrees with the
+/// generator: every generated instruction word has to decode to the
+/// instruction it was generated for, and the disassembly of the image has
+/// to assemble to the same image again. Data words and gaps are only
+/// checked by the latter. Returns the verified image.
+pub fn verify(ir: &ir::IR) -> Result<Vec<InstructionWord>, VerifyError> {
+    let lowered = lowering::lower(ir.clone());
+    let image = generator::generator(lowered.clone()).map_err(VerifyError::Generator)?;
+
+    for label in lowered.label_definitions.0.values() {
+        let instructions = lowered.instructions.get(&label.clone().into());
+        for (address, instruction) in (label.address.0..).zip(instructions.into_iter().flatten()) {
+            let Some(expected) = resolved(instruction, address, &lowered.label_definitions) else {
+                continue;
+            };
+            let word = &image[usize::from(address)];
+            let found = disassembler::decode(word);
+            if found.as_ref() != Some(&expected) {
+                return Err(VerifyError::Decoded {
+                    address,
+                    word: word.clone(),
+                    expected: instruction.to_string(),
+                    found: found.as_ref().map(ir::Instruction::to_string),
+                });
+            }
+        }
+    }
+
+    let listing = disassembler::disassemble(&image, false).to_string();
+    let reassembled = reassemble(&listing).map_err(VerifyError::Listing)?;
+    if let Some(address) =
+        (0..image.len().max(reassembled.len())).find(|&i| image.get(i) != reassembled.get(i))
+    {
+        return Err(VerifyError::Reassembled {
+            address,
+            expected: image.get(address).cloned(),
+            found: reassembled.get(address).cloned(),
+        });
+    }
+    Ok(image)
+}
+
+/// `instruction` as the disassembler decodes it, with label and expression
+/// operands replaced by the values generated for them. Data words are no
+/// instruction and return `None`.
+fn resolved(
+    instruction: &ir::Instruction,
+    address: u16,
+    labels: &ir::LabelLUT,
+) -> Option<ir::Instruction> {
+    let address_of = |label: &ir::LabelReference| labels.0.get(label).map(|l| l.address.0);
+    match instruction {
+        ir::Instruction::Data(_) => None,
+        ir::Instruction::Load {
+            address: target,
+            source: ir::LoadSource::Expression(expression),
+        } => Some(ir::Instruction::Load {
+            address: *target,
+            source: ir::LoadSource::Constant(expression.evaluate(labels, address).ok()? as u16),
+        }),
+        ir::Instruction::Jump {
+            target: ir::JumpTarget::Label(label),
+            condition,
+        } => Some(ir::Instruction::Jump {
+            target: ir::JumpTarget::Constant(address_of(label)?.wrapping_sub(address)),
+            condition: *condition,
+        }),
+        ir::Instruction::Jump {
+            target: ir::JumpTarget::LabelOffset { label, offset },
+            condition,
+        } => Some(ir::Instruction::Jump {
+            target: ir::JumpTarget::Constant(
+                address_of(label)?
+                    .wrapping_add_signed(*offset)
+                    .wrapping_sub(address),
+            ),
+            condition: *condition,
+        }),
+        instruction => Some(instruction.clone()),
+    }
+}
+
+/// Assembles `listing` as it is, without appending a `hlt`
+fn reassemble(listing: &str) -> Result<Vec<InstructionWord>, MasmError> {
+    let options = lexer::LexerOptions { auto_halt: false };
+    let keywords = preprocessor::preprocessor(lexer::lexer_from_str(listing, &options)?)?;
+    Ok(generator::generator(parser::parser(keywords)?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> ir::IR {
+        let options = lexer::LexerOptions { auto_halt: false };
+        let keywords = lexer::lexer_from_str(source, &options).unwrap();
+        parser::parser(preprocessor::preprocessor(keywords).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn verify_programs() {
+        for path in [
+            "tests/all_instructions.s",
+            "tests/conditions.s",
+            "tests/offsets.s",
+        ] {
+            let source = std::fs::read_to_string(path).unwrap();
+            let ir = parse(&source);
+            assert_eq!(
+                crate::assemble_str(&source).unwrap(),
+                verify(&ir).unwrap_or_else(|err| panic!("{}: {}", path, err))
+            );
+        }
+    }
+
+    #[test]
+    fn data_and_errors() {
+        // encodes like `nop` with an unused bit set, which decodes to nothing
+        let ir = crate::builder::Program::builder()
+            .label("main")
+            .word(0x016c)
+            .hlt()
+            .build()
+            .unwrap();
+        assert!(verify(&ir).is_ok());
+
+        let ir = crate::builder::Program::builder()
+            .label("main")
+            .jump_label("missing")
+            .build()
+            .unwrap();
+        assert_eq!("undefined-label", verify(&ir).unwrap_err().code());
+    }
+}
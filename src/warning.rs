@@ -0,0 +1,265 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::diagnostic::{self, display_line};
+use crate::ir;
+use crate::lexer::Keyword;
+
+/// Mnemonics kept for old programs and the ones replacing them
+pub const DEPRECATED_MNEMONICS: &[(&str, &str)] = &[("jrcon", "jr")];
+
+/// Suspicious constructs that still assemble to a valid program
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    UnusedLabel {
+        label: String,
+    },
+    DeprecatedMnemonic {
+        mnemonic: String,
+        replacement: String,
+        line_number: u16,
+    },
+    AppendedHalt {
+        line_number: u16,
+    },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::UnusedLabel { label } => {
+                write!(f, "Label '{}' is defined but never referenced", label)
+            }
+            Warning::DeprecatedMnemonic {
+                mnemonic,
+                replacement,
+                line_number,
+            } => write!(
+                f,
+                "Mnemonic '{}' at line {} is deprecated, use '{}' instead",
+                mnemonic,
+                display_line(*line_number),
+                replacement
+            ),
+            Warning::AppendedHalt { line_number } => write!(
+                f,
+                "Program does not end with 'hlt', appended one at line {}",
+                display_line(*line_number)
+            ),
+        }
+    }
+}
+
+impl Warning {
+    /// Position of the line the warning points at
+    pub fn position(&self) -> Option<diagnostic::Position> {
+        match *self {
+            Warning::DeprecatedMnemonic { line_number, .. }
+            | Warning::AppendedHalt { line_number } => Some((line_number, None)),
+            Warning::UnusedLabel { .. } => None,
+        }
+    }
+
+    /// Stable name of the warning kind, e.g. for machine readable diagnostics
+    pub fn code(&self) -> &'static str {
+        match self {
+            Warning::UnusedLabel { .. } => "unused-label",
+            Warning::DeprecatedMnemonic { .. } => "deprecated-mnemonic",
+            Warning::AppendedHalt { .. } => "appended-halt",
+        }
+    }
+}
+
+/// Names of all warning categories, see [`Warning::code`]
+pub const CATEGORIES: &[&str] = &["unused-label", "deprecated-mnemonic", "appended-halt"];
+
+/// How the warnings of a category are reported
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Level {
+    /// not reported at all
+    Allow,
+    #[default]
+    Warn,
+    /// reported as error, failing the assembly
+    Deny,
+}
+
+/// Collects the warnings of all stages with the level of their category.
+/// Categories without a level of their own use the level set for `all`.
+#[derive(Debug, Clone, Default)]
+pub struct Diagnostics {
+    default: Level,
+    levels: HashMap<String, Level>,
+    warnings: Vec<(Warning, Level)>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the level of one of the [`CATEGORIES`] or of `all` of them
+    pub fn set_level(&mut self, category: &str, level: Level) {
+        if category == "all" {
+            self.default = level;
+        } else {
+            self.levels.insert(String::from(category), level);
+        }
+    }
+
+    pub fn level(&self, warning: &Warning) -> Level {
+        self.levels
+            .get(warning.code())
+            .copied()
+            .unwrap_or(self.default)
+    }
+
+    /// Keeps `warning` unless its category is allowed
+    pub fn push(&mut self, warning: Warning) {
+        let level = self.level(&warning);
+        if level != Level::Allow {
+            self.warnings.push((warning, level));
+        }
+    }
+
+    /// Reported warnings in the order they were found, with their level
+    pub fn warnings(&self) -> &[(Warning, Level)] {
+        &self.warnings
+    }
+
+    /// Whether a warning of a denied category was found
+    pub fn has_errors(&self) -> bool {
+        self.warnings.iter().any(|(_, level)| *level == Level::Deny)
+    }
+}
+
+impl Extend<Warning> for Diagnostics {
+    fn extend<T: IntoIterator<Item = Warning>>(&mut self, warnings: T) {
+        for warning in warnings {
+            self.push(warning);
+        }
+    }
+}
+
+/// Warns about every label no instruction refers to. The entry label,
+/// `[[interrupt]]` handlers and numeric labels are exempt, as they are
+/// entered without a reference.
+pub fn unused_labels(ir: &ir::IR) -> Vec<Warning> {
+    let referenced: HashSet<&str> = ir
+        .instructions
+        .values()
+        .flatten()
+        .flat_map(ir::Instruction::label_references)
+        .map(ir::LabelReference::name)
+        .collect();
+    let mut unused: Vec<&ir::LabelDefinition> = ir
+        .label_definitions
+        .0
+        .values()
+        .filter(|label| {
+            !referenced.contains(label.name.as_str())
+                && label.name != ir.start_label.name()
+                && !label.attributes.interrupt
+                && !is_numeric_label(&label.name)
+        })
+        .collect();
+    unused.sort_by_key(|label| (label.address, &label.name));
+    unused
+        .into_iter()
+        .map(|label| Warning::UnusedLabel {
+            label: label.name.clone(),
+        })
+        .collect()
+}
+
+/// Warns about every use of one of the [`DEPRECATED_MNEMONICS`]
+pub fn deprecated_mnemonics(keywords: &[Keyword]) -> Vec<Warning> {
+    keywords
+        .iter()
+        .filter_map(|keyword| match keyword {
+            Keyword::Mmenonic {
+                name, line_number, ..
+            } => DEPRECATED_MNEMONICS
+                .iter()
+                .find(|(deprecated, _)| name.eq_ignore_ascii_case(deprecated))
+                .map(|(_, replacement)| Warning::DeprecatedMnemonic {
+                    mnemonic: name.clone(),
+                    replacement: String::from(*replacement),
+                    line_number: *line_number,
+                }),
+            _ => None,
+        })
+        .collect()
+}
+
+/// `scope.N_K` the parser names the numeric label `N:` after
+fn is_numeric_label(name: &str) -> bool {
+    name.rsplit('.')
+        .next()
+        .is_some_and(|local| local.starts_with(|c: char| c.is_ascii_digit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Keyword;
+
+    #[test]
+    fn unused_label() {
+        let ir = crate::parser::parser(vec![
+            Keyword::label("main", 0),
+            Keyword::mmenonic("jr", 1),
+            Keyword::label("used", 1),
+            Keyword::label("used", 2),
+            Keyword::label("unused", 3),
+            Keyword::mmenonic("hlt", 4),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            vec![Warning::UnusedLabel {
+                label: String::from("unused"),
+            }],
+            unused_labels(&ir)
+        );
+    }
+
+    #[test]
+    fn levels() {
+        let unused = || Warning::UnusedLabel {
+            label: String::from("unused"),
+        };
+        let halt = || Warning::AppendedHalt { line_number: 3 };
+
+        let mut diagnostics = Diagnostics::new();
+        diagnostics.extend([unused(), halt()]);
+        assert_eq!(2, diagnostics.warnings().len());
+        assert!(!diagnostics.has_errors());
+
+        let mut diagnostics = Diagnostics::new();
+        diagnostics.set_level("all", Level::Deny);
+        diagnostics.set_level("unused-label", Level::Allow);
+        diagnostics.extend([unused(), halt()]);
+        assert_eq!(&[(halt(), Level::Deny)], diagnostics.warnings());
+        assert!(diagnostics.has_errors());
+    }
+
+    #[test]
+    fn deprecated_mnemonic() {
+        let found = deprecated_mnemonics(&[
+            Keyword::mmenonic("jr", 0),
+            Keyword::constant("1", 1, 0),
+            Keyword::mmenonic("jrcon", 1),
+            Keyword::constant("1", 1, 1),
+        ]);
+
+        assert_eq!(
+            vec![Warning::DeprecatedMnemonic {
+                mnemonic: String::from("jrcon"),
+                replacement: String::from("jr"),
+                line_number: 1,
+            }],
+            found
+        );
+    }
+}
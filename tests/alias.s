@@ -0,0 +1,11 @@
+.alias counter %reg3
+.alias limit %regB
+main:
+    ldc %limit 10
+    xor %counter %counter %counter
+loop:
+    inc %counter
+    tst %counter %limit
+    jnzr loop
+.alias index %counter
+    mov %reg0 %index
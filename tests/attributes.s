@@ -0,0 +1,10 @@
+    nop
+    nop
+    nop
+[[align=4]]
+aligned:
+    nop
+;; Stops the program
+[[noreturn, interrupt]] ; attributes can be combined
+done:
+    hlt
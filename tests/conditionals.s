@@ -0,0 +1,19 @@
+.equ DEBUG 1
+.equ RELEASE 0
+.equ LEDS 0x0f
+
+main:
+.if DEBUG
+    ldc %reg0 LEDS
+.else
+    ldc %reg0 0
+.endif
+.ifdef RELEASE
+.if RELEASE
+    nop
+.endif
+.endif
+.ifndef VERBOSE
+    inc %reg0
+.endif
+    hlt
@@ -0,0 +1,8 @@
+main:
+    tst %reg0 %reg1
+    jge %reg2
+    jler done
+    jgtr $+4
+    nop
+done:
+    hlt
@@ -0,0 +1,3 @@
+main:
+    div %reg0 %reg1 %reg2
+    mod %reg1 %reg1 %reg2
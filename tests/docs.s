@@ -0,0 +1,14 @@
+;;; this is the test file for doc comments
+
+;; Entry point of the program
+main:
+    ldc %reg0 0x1
+;; this comment is not attached to a label
+
+    ldc %reg1 0x2
+;; Adds register 0 and register 1
+;; and stores the result in register 2
+add:
+    add %reg2 %reg0 %reg1
+done:
+    hlt
@@ -0,0 +1,7 @@
+main:
+    ldc %reg0 lo(0x12345678)
+    ldc %reg1 hi(0x12345678)
+    ldc %reg2 lo(handler)
+    ldc %reg3 hi(handler)
+handler:
+    hlt
@@ -0,0 +1,7 @@
+main:
+    addi %reg0 5
+    subi %reg1 1
+    andi %reg2 0xff
+    ori %reg3 lo(main)
+.scratch %reg7
+    xori %reg4 -1
@@ -0,0 +1,3 @@
+main:
+.include "missing.s"
+.include "error.s"
@@ -0,0 +1,3 @@
+b:
+    nop
+.include "cycle_a.s"
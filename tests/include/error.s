@@ -0,0 +1,2 @@
+    nop
+    ldc %reg0 0x12$
@@ -0,0 +1,3 @@
+;; Increments register 0
+lib:
+    inc %reg0
@@ -0,0 +1,4 @@
+main:
+    nop
+.include "lib.s"
+    jr lib
@@ -0,0 +1,7 @@
+main:
+    ldc %reg0 .handler
+    jmp %reg0
+    nop
+handler:
+    ldc %reg1 main
+    hlt
@@ -0,0 +1,5 @@
+main:
+    s32b true
+    li %reg0 0x12345678
+    li %reg1 lo(-2)
+    hlt
@@ -0,0 +1,15 @@
+main:
+    ldc %reg0 3
+.loop:
+    dec %reg0
+    jnzr .loop
+    ldc %reg1 .done
+    jmp %reg1
+.done:
+    ljmp other
+other:
+    ldc %reg0 2
+.loop:
+    dec %reg0
+    jnzr loop
+    jr main.done
@@ -0,0 +1,10 @@
+start:
+    nop
+    jr $
+    jr $+2
+    nop
+    ldc %reg0 $
+.equ SIZE $-start
+    ldc %reg1 SIZE
+    ldc %reg2 lo(SIZE+1)
+    hlt
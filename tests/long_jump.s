@@ -0,0 +1,7 @@
+main:
+    ljmp far
+    hlt
+.org 0x1000
+far:
+.scratch %reg5
+    ljmp main
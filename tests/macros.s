@@ -0,0 +1,14 @@
+.macro push reg
+    st %reg7 reg
+    dec %reg7
+.endm
+
+.macro pop reg
+    inc %reg7
+    ld reg %reg7
+.endm
+
+main:
+    push %reg0
+    pop %reg1
+    hlt
@@ -0,0 +1,15 @@
+.macro wait reg
+1:
+    dec reg
+    jnzr 1b
+.endm
+
+main:
+    ldc %reg0 3
+    wait %reg0
+    ldc %reg1 2
+    wait %reg1
+    jzr 1f
+    nop
+1:
+    jr 1b
@@ -0,0 +1,9 @@
+main:
+    nop
+loop:
+    inc %reg0
+    jr .loop+1
+    jr end-1
+    nop
+end:
+    hlt
@@ -0,0 +1,12 @@
+.equ WAIT 2
+
+main:
+.rept 3
+    .word 0
+.endr
+.rept WAIT
+.rept 3
+    nop
+.endr
+.endr
+    hlt
@@ -0,0 +1,3 @@
+.reserve %reg7 "stack pointer" ; protects the stack pointer
+    .reserve %regG "link;register \"lr\""
+    ldc %reg0 0x1
@@ -0,0 +1,4 @@
+main:
+    rol %reg0 %reg1 4
+    ror %reg2 %reg2 14
+    ror %reg3 %reg4 16
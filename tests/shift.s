@@ -0,0 +1,4 @@
+main:
+    shl %reg0 %reg1 %reg2
+    shl %reg0 %reg1 3
+    shr %reg2 %reg2 0x4
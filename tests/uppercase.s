@@ -0,0 +1,7 @@
+.ALIAS Counter %REG3
+MAIN:
+    LDC %REG0 0x10
+    MOV %Rega %SP
+    INC %COUNTER
+    .WORD 1
+    HLT